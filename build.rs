@@ -1,3 +1,449 @@
+use std::{collections::BTreeMap, env, fmt::Write, fs, path::Path};
+
+use serde::Deserialize;
+
 fn main() {
-    embuild::espidf::sysenv::output();
+    // ESP-IDF 的链接参数只在编译到板子上时需要
+    if env::var("CARGO_CFG_TARGET_OS").as_deref() == Ok("espidf") {
+        embuild::espidf::sysenv::output();
+    }
+    generate_board_config();
+}
+
+/// 板子的引脚和外设配置，对应 boards/<板子>.toml
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct BoardConfig {
+    name: String,
+    display: Option<DisplayConfig>,
+    i2c: Option<I2cConfig>,
+    i2s: I2sConfig,
+    /// 麦克风单独用一组 I2S 时配置
+    i2s_mic: Option<I2sMicConfig>,
+    buttons: ButtonsConfig,
+    led: Option<LedConfig>,
+    pmic: Option<PmicConfig>,
+    camera: Option<CameraConfig>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct DisplayConfig {
+    width: u16,
+    height: u16,
+    offset_x: u16,
+    offset_y: u16,
+    rotation: u16,
+    mirror: bool,
+    sclk: u8,
+    mosi: u8,
+    cs: u8,
+    dc: u8,
+    backlight: u8,
+    backlight_inverted: bool,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct I2cConfig {
+    sda: u8,
+    scl: u8,
+    es8311_addr: u8,
+    es7210_addr: u8,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct I2sConfig {
+    mclk: Option<u8>,
+    bclk: u8,
+    ws: u8,
+    dout: u8,
+    din: Option<u8>,
+    sample_rate: u32,
+    bits: u8,
+    format: String,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct I2sMicConfig {
+    bclk: u8,
+    ws: u8,
+    din: u8,
+    bits: u8,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ButtonsConfig {
+    boot: u8,
+    volume: u8,
+    active_level: u8,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct LedConfig {
+    data: u8,
+    pixels: usize,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct PmicConfig {
+    irq: u8,
+}
+
+/// DVP 摄像头。没有配置 sccb_sda/sccb_scl 时和 [i2c] 共用一条总线
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct CameraConfig {
+    xclk: u8,
+    pclk: u8,
+    vsync: u8,
+    href: u8,
+    /// D0 ~ D7
+    data: [u8; 8],
+    sccb_sda: Option<u8>,
+    sccb_scl: Option<u8>,
+    pwdn: Option<u8>,
+    reset: Option<u8>,
+    xclk_freq_hz: u32,
+}
+
+/// 根据打开的 board-xxx feature 读取 boards/xxx.toml，检查后生成 $OUT_DIR/board_config.rs。
+/// `host-sim` 也算一块板子，它没有引脚配置，生成空文件
+fn generate_board_config() {
+    println!("cargo:rerun-if-changed=boards");
+
+    let mut boards: Vec<String> = env::vars()
+        .filter_map(|(key, _)| {
+            key.strip_prefix("CARGO_FEATURE_BOARD_")
+                .map(|name| format!("board-{}", name.to_lowercase().replace('_', "-")))
+        })
+        .collect();
+    let host_sim = env::var_os("CARGO_FEATURE_HOST_SIM").is_some();
+    if host_sim {
+        boards.push("host-sim".to_string());
+    }
+    match boards.len() {
+        0 => panic!("没有选择板子，请打开一个 board-xxx feature 或 host-sim"),
+        1 => {}
+        _ => panic!(
+            "只能选择一块板子，现在打开了 {}，用 --no-default-features 关掉默认的 board-jianglian-s3cam",
+            boards.join("、")
+        ),
+    }
+    // 真板子只能编译到 ESP-IDF，host-sim 只能编译成电脑上的程序
+    let espidf = env::var("CARGO_CFG_TARGET_OS").as_deref() == Ok("espidf");
+    if host_sim && espidf {
+        panic!("host-sim 要编译成电脑上的程序，加上 --target x86_64-unknown-linux-gnu");
+    }
+    if !host_sim && !espidf {
+        panic!("{} 只能编译到 ESP-IDF（xtensa-esp32s3-espidf）", boards[0]);
+    }
+
+    let out = Path::new(&env::var("OUT_DIR").unwrap()).join("board_config.rs");
+    if host_sim {
+        fs::write(&out, "").unwrap();
+        return;
+    }
+
+    let name = boards[0].trim_start_matches("board-");
+    let path = Path::new("boards").join(format!("{}.toml", name));
+    let content = fs::read_to_string(&path)
+        .unwrap_or_else(|e| panic!("读取板子配置 {} 失败: {}", path.display(), e));
+    let config: BoardConfig = toml::from_str(&content)
+        .unwrap_or_else(|e| panic!("解析板子配置 {} 失败: {}", path.display(), e));
+
+    if let Err(e) = validate(&config) {
+        panic!("板子配置 {} 有错误: {}", path.display(), e);
+    }
+
+    fs::write(&out, render(&config)).unwrap();
+}
+
+/// 检查引脚是否存在、是否重复，以及其他取值
+fn validate(config: &BoardConfig) -> Result<(), String> {
+    let mut pins: Vec<(&str, u8)> = vec![
+        ("buttons.boot", config.buttons.boot),
+        ("buttons.volume", config.buttons.volume),
+        ("i2s.bclk", config.i2s.bclk),
+        ("i2s.ws", config.i2s.ws),
+        ("i2s.dout", config.i2s.dout),
+    ];
+    if let Some(mclk) = config.i2s.mclk {
+        pins.push(("i2s.mclk", mclk));
+    }
+    if let Some(din) = config.i2s.din {
+        pins.push(("i2s.din", din));
+    }
+    if let Some(mic) = &config.i2s_mic {
+        pins.extend([
+            ("i2s_mic.bclk", mic.bclk),
+            ("i2s_mic.ws", mic.ws),
+            ("i2s_mic.din", mic.din),
+        ]);
+    }
+    if let Some(display) = &config.display {
+        pins.extend([
+            ("display.sclk", display.sclk),
+            ("display.mosi", display.mosi),
+            ("display.cs", display.cs),
+            ("display.dc", display.dc),
+            ("display.backlight", display.backlight),
+        ]);
+    }
+    if let Some(i2c) = &config.i2c {
+        pins.extend([("i2c.sda", i2c.sda), ("i2c.scl", i2c.scl)]);
+    }
+    if let Some(led) = &config.led {
+        pins.push(("led.data", led.data));
+    }
+    if let Some(pmic) = &config.pmic {
+        pins.push(("pmic.irq", pmic.irq));
+    }
+    if let Some(camera) = &config.camera {
+        const CAMERA_DATA: [&str; 8] = [
+            "camera.data[0]",
+            "camera.data[1]",
+            "camera.data[2]",
+            "camera.data[3]",
+            "camera.data[4]",
+            "camera.data[5]",
+            "camera.data[6]",
+            "camera.data[7]",
+        ];
+        pins.extend([
+            ("camera.xclk", camera.xclk),
+            ("camera.pclk", camera.pclk),
+            ("camera.vsync", camera.vsync),
+            ("camera.href", camera.href),
+        ]);
+        pins.extend(CAMERA_DATA.into_iter().zip(camera.data));
+        for (name, pin) in [
+            ("camera.sccb_sda", camera.sccb_sda),
+            ("camera.sccb_scl", camera.sccb_scl),
+            ("camera.pwdn", camera.pwdn),
+            ("camera.reset", camera.reset),
+        ] {
+            if let Some(pin) = pin {
+                pins.push((name, pin));
+            }
+        }
+    }
+
+    let mut used: BTreeMap<u8, &str> = BTreeMap::new();
+    for (name, pin) in pins {
+        match pin {
+            0..=21 | 38..=48 => {}
+            // 33~37 在八线 PSRAM 的模组上被占用
+            33..=37 => println!(
+                "cargo:warning={} 用了 GPIO{}，八线 PSRAM 的模组上这个引脚不能用",
+                name, pin
+            ),
+            26..=32 => return Err(format!("{} 用了 GPIO{}，这是 flash 的引脚", name, pin)),
+            _ => return Err(format!("{} 用了 GPIO{}，ESP32-S3 没有这个引脚", name, pin)),
+        }
+        if let Some(other) = used.insert(pin, name) {
+            return Err(format!("{} 和 {} 都用了 GPIO{}", other, name, pin));
+        }
+    }
+
+    if let Some(i2c) = &config.i2c {
+        if i2c.es8311_addr == i2c.es7210_addr {
+            return Err(format!(
+                "ES8311 和 ES7210 的 I2C 地址都是 {:#04x}",
+                i2c.es8311_addr
+            ));
+        }
+    }
+    if let Some(display) = &config.display {
+        if ![0, 90, 180, 270].contains(&display.rotation) {
+            return Err(format!(
+                "display.rotation 只能是 0、90、180、270，现在是 {}",
+                display.rotation
+            ));
+        }
+    }
+    if let Some(camera) = &config.camera {
+        match (camera.sccb_sda, camera.sccb_scl) {
+            (Some(_), Some(_)) => {}
+            (None, None) if config.i2c.is_some() => {}
+            (None, None) => {
+                return Err("camera 没有配置 sccb_sda/sccb_scl，也没有 [i2c] 可以共用".to_string())
+            }
+            _ => return Err("camera.sccb_sda 和 camera.sccb_scl 要一起配置".to_string()),
+        }
+        if !(6_000_000..=24_000_000).contains(&camera.xclk_freq_hz) {
+            return Err(format!(
+                "camera.xclk_freq_hz 要在 6MHz 到 24MHz 之间，现在是 {}",
+                camera.xclk_freq_hz
+            ));
+        }
+    }
+    if config.buttons.active_level > 1 {
+        return Err("buttons.active_level 只能是 0 或 1".to_string());
+    }
+    let mut bits = vec![("i2s.bits", config.i2s.bits)];
+    if let Some(mic) = &config.i2s_mic {
+        bits.push(("i2s_mic.bits", mic.bits));
+    }
+    for (name, bits) in bits {
+        if ![16, 24, 32].contains(&bits) {
+            return Err(format!("{} 只能是 16、24、32，现在是 {}", name, bits));
+        }
+    }
+    if !["philips", "msb", "pcm"].contains(&config.i2s.format.as_str()) {
+        return Err(format!(
+            "i2s.format 只能是 philips、msb、pcm，现在是 {}",
+            config.i2s.format
+        ));
+    }
+    Ok(())
+}
+
+/// 生成常量，每个段落一个 mod，可选的段落没配置时不生成
+fn render(config: &BoardConfig) -> String {
+    let mut s = String::new();
+    writeln!(
+        s,
+        "// 由 build.rs 根据 boards/{}.toml 生成，不要手改",
+        config.name
+    )
+    .unwrap();
+    writeln!(s, "pub const NAME: &str = {:?};", config.name).unwrap();
+
+    if let Some(display) = &config.display {
+        writeln!(s, "pub mod display {{").unwrap();
+        writeln!(s, "    pub const WIDTH: u16 = {};", display.width).unwrap();
+        writeln!(s, "    pub const HEIGHT: u16 = {};", display.height).unwrap();
+        writeln!(s, "    pub const OFFSET_X: u16 = {};", display.offset_x).unwrap();
+        writeln!(s, "    pub const OFFSET_Y: u16 = {};", display.offset_y).unwrap();
+        writeln!(s, "    pub const ROTATION: u16 = {};", display.rotation).unwrap();
+        writeln!(s, "    pub const MIRROR: bool = {};", display.mirror).unwrap();
+        writeln!(s, "    pub const SCLK: u8 = {};", display.sclk).unwrap();
+        writeln!(s, "    pub const MOSI: u8 = {};", display.mosi).unwrap();
+        writeln!(s, "    pub const CS: u8 = {};", display.cs).unwrap();
+        writeln!(s, "    pub const DC: u8 = {};", display.dc).unwrap();
+        writeln!(s, "    pub const BACKLIGHT: u8 = {};", display.backlight).unwrap();
+        writeln!(
+            s,
+            "    pub const BACKLIGHT_INVERTED: bool = {};",
+            display.backlight_inverted
+        )
+        .unwrap();
+        writeln!(s, "}}").unwrap();
+    }
+
+    if let Some(i2c) = &config.i2c {
+        writeln!(s, "pub mod i2c {{").unwrap();
+        writeln!(s, "    pub const SDA: u8 = {};", i2c.sda).unwrap();
+        writeln!(s, "    pub const SCL: u8 = {};", i2c.scl).unwrap();
+        writeln!(
+            s,
+            "    pub const ES8311_ADDR: u8 = {:#04x};",
+            i2c.es8311_addr
+        )
+        .unwrap();
+        writeln!(
+            s,
+            "    pub const ES7210_ADDR: u8 = {:#04x};",
+            i2c.es7210_addr
+        )
+        .unwrap();
+        writeln!(s, "}}").unwrap();
+    }
+
+    let i2s = &config.i2s;
+    let format = match i2s.format.as_str() {
+        "msb" => "Msb",
+        "pcm" => "Pcm",
+        _ => "Philips",
+    };
+    writeln!(s, "pub mod i2s {{").unwrap();
+    if let Some(mclk) = i2s.mclk {
+        writeln!(s, "    pub const MCLK: u8 = {};", mclk).unwrap();
+    }
+    writeln!(s, "    pub const BCLK: u8 = {};", i2s.bclk).unwrap();
+    writeln!(s, "    pub const WS: u8 = {};", i2s.ws).unwrap();
+    writeln!(s, "    pub const DOUT: u8 = {};", i2s.dout).unwrap();
+    if let Some(din) = i2s.din {
+        writeln!(s, "    pub const DIN: u8 = {};", din).unwrap();
+    }
+    writeln!(s, "    pub const SAMPLE_RATE: u32 = {};", i2s.sample_rate).unwrap();
+    writeln!(s, "    pub const BITS: u8 = {};", i2s.bits).unwrap();
+    writeln!(
+        s,
+        "    pub const FORMAT: super::I2sFormat = super::I2sFormat::{};",
+        format
+    )
+    .unwrap();
+    writeln!(s, "}}").unwrap();
+
+    if let Some(mic) = &config.i2s_mic {
+        writeln!(s, "pub mod i2s_mic {{").unwrap();
+        writeln!(s, "    pub const BCLK: u8 = {};", mic.bclk).unwrap();
+        writeln!(s, "    pub const WS: u8 = {};", mic.ws).unwrap();
+        writeln!(s, "    pub const DIN: u8 = {};", mic.din).unwrap();
+        writeln!(s, "    pub const BITS: u8 = {};", mic.bits).unwrap();
+        writeln!(s, "}}").unwrap();
+    }
+
+    writeln!(s, "pub mod buttons {{").unwrap();
+    writeln!(s, "    pub const BOOT: u8 = {};", config.buttons.boot).unwrap();
+    writeln!(s, "    pub const VOLUME: u8 = {};", config.buttons.volume).unwrap();
+    writeln!(
+        s,
+        "    pub const ACTIVE_LEVEL: u8 = {};",
+        config.buttons.active_level
+    )
+    .unwrap();
+    writeln!(s, "}}").unwrap();
+
+    if let Some(led) = &config.led {
+        writeln!(s, "pub mod led {{").unwrap();
+        writeln!(s, "    pub const DATA: u8 = {};", led.data).unwrap();
+        writeln!(s, "    pub const PIXELS: usize = {};", led.pixels).unwrap();
+        writeln!(s, "}}").unwrap();
+    }
+
+    if let Some(pmic) = &config.pmic {
+        writeln!(s, "pub mod pmic {{").unwrap();
+        writeln!(s, "    pub const IRQ: u8 = {};", pmic.irq).unwrap();
+        writeln!(s, "}}").unwrap();
+    }
+
+    if let Some(camera) = &config.camera {
+        writeln!(s, "pub mod camera {{").unwrap();
+        writeln!(s, "    pub const XCLK: u8 = {};", camera.xclk).unwrap();
+        writeln!(s, "    pub const PCLK: u8 = {};", camera.pclk).unwrap();
+        writeln!(s, "    pub const VSYNC: u8 = {};", camera.vsync).unwrap();
+        writeln!(s, "    pub const HREF: u8 = {};", camera.href).unwrap();
+        writeln!(s, "    pub const DATA: [u8; 8] = {:?};", camera.data).unwrap();
+        writeln!(
+            s,
+            "    pub const SCCB_SDA: Option<u8> = {:?};",
+            camera.sccb_sda
+        )
+        .unwrap();
+        writeln!(
+            s,
+            "    pub const SCCB_SCL: Option<u8> = {:?};",
+            camera.sccb_scl
+        )
+        .unwrap();
+        writeln!(s, "    pub const PWDN: Option<u8> = {:?};", camera.pwdn).unwrap();
+        writeln!(s, "    pub const RESET: Option<u8> = {:?};", camera.reset).unwrap();
+        writeln!(
+            s,
+            "    pub const XCLK_FREQ_HZ: u32 = {};",
+            camera.xclk_freq_hz
+        )
+        .unwrap();
+        writeln!(s, "}}").unwrap();
+    }
+
+    s
 }
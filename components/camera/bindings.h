@@ -0,0 +1,2 @@
+#include "esp_camera.h"
+#include "img_converters.h"
\ No newline at end of file
@@ -0,0 +1,61 @@
+#!/usr/bin/env python3
+"""host-sim 用的识图服务替身。
+
+收到照片后存到 sim/last_photo.jpg，回答里带上问题和照片大小，用来检查设备的上传是否正确：
+
+    python3 sim/vision_server.py 8088
+"""
+
+import json
+import sys
+from http.server import BaseHTTPRequestHandler, HTTPServer
+
+
+def parse_multipart(body, boundary):
+    fields = {}
+    for part in body.split(b"--" + boundary):
+        if b"\r\n\r\n" not in part:
+            continue
+        head, content = part.split(b"\r\n\r\n", 1)
+        if content.endswith(b"\r\n"):
+            content = content[:-2]
+        for line in head.decode("utf-8", "replace").split("\r\n"):
+            if line.lower().startswith("content-disposition:") and 'name="' in line:
+                name = line.split('name="', 1)[1].split('"', 1)[0]
+                fields[name] = content
+    return fields
+
+
+class Handler(BaseHTTPRequestHandler):
+    def do_POST(self):
+        body = self.rfile.read(int(self.headers.get("Content-Length", 0)))
+        content_type = self.headers.get("Content-Type", "")
+        boundary = content_type.split("boundary=", 1)[-1].encode()
+        fields = parse_multipart(body, boundary)
+
+        question = fields.get("question", b"").decode("utf-8", "replace")
+        photo = fields.get("file", b"")
+        if photo:
+            with open("sim/last_photo.jpg", "wb") as f:
+                f.write(photo)
+        print(
+            f"device={self.headers.get('Device-Id')} auth={self.headers.get('Authorization')} "
+            f"question={question!r} photo={len(photo)} bytes"
+        )
+
+        if not photo.startswith(b"\xff\xd8"):
+            answer = {"success": False, "message": "file is not a JPEG"}
+        else:
+            answer = {"success": True, "text": f"收到问题“{question}”和一张 {len(photo)} 字节的照片"}
+        data = json.dumps(answer, ensure_ascii=False).encode()
+        self.send_response(200)
+        self.send_header("Content-Type", "application/json")
+        self.send_header("Content-Length", str(len(data)))
+        self.end_headers()
+        self.wfile.write(data)
+
+
+if __name__ == "__main__":
+    port = int(sys.argv[1]) if len(sys.argv) > 1 else 8088
+    print(f"vision stand-in listening on http://127.0.0.1:{port}/vision/explain")
+    HTTPServer(("127.0.0.1", port), Handler).serve_forever()
@@ -3,29 +3,28 @@ use crate::{
     common::converter::i16_slice_to_bytes,
 };
 use anyhow::{Error, Result};
+#[cfg(target_os = "espidf")]
 use esp_idf_hal::{
     delay::BLOCK,
     i2s::{I2sBiDir, I2sDriver},
     task::thread::ThreadSpawnConfiguration,
 };
+#[cfg(target_os = "espidf")]
+use std::sync::MutexGuard;
 use std::{
     collections::VecDeque,
-    ffi::{c_void, CStr},
-    ptr,
     sync::{
+        atomic::{AtomicBool, Ordering},
         mpsc::{self, channel, Receiver, Sender, SyncSender},
-        Arc, Mutex, MutexGuard,
+        Arc, Mutex,
     },
     thread,
-    time::Duration,
+    time::{Duration, Instant},
 };
 
-use esp_idf_sys::{
-    esp_partition_find, esp_partition_get, esp_partition_next,
-    esp_partition_subtype_t_ESP_PARTITION_SUBTYPE_APP_OTA_0,
-    esp_partition_type_t_ESP_PARTITION_TYPE_APP,
-};
-use log::{error, info, warn};
+#[cfg(target_os = "espidf")]
+use esp_idf_svc::http::server::EspHttpServer;
+use log::{error, info, warn, Level};
 
 use crate::{
     audio::{
@@ -34,36 +33,70 @@ use crate::{
             opus::{decoder::OpusAudioDecoder, encoder::OpusAudioEncoder},
             MAX_AUDIO_PACKETS_IN_QUEUE, OPUS_FRAME_DURATION_MS,
         },
-        processor::{
-            afe_audio_processor::AfeAudioProcessor, audio_processor::AudioProcessor,
-            no_audio_processor::NoAudioProcessor,
-        },
+        processor::{audio_processor::AudioProcessor, no_audio_processor::NoAudioProcessor},
     },
-    boards::{board::Board, jianglian_s3cam_board},
+    boards,
+    camera::{
+        add_camera_tools,
+        vision::{VisionClient, VisionEndpoint},
+        Camera,
+    },
+    clock::{self, sntp::SntpService},
     common::{
         application_context::ApplicationContext,
         converter::bytes_to_i16_slice,
+        diagnostics,
         enums::{AbortReason, AecMode, DeviceState, ListeningMode},
         event::AppEvent,
+        lang,
+        restart::schedule_restart,
+    },
+    firmware::ota::{self, OtaProgress, RollbackGuard},
+    led::{pattern::LedSettings, service::LedService},
+    logging::{log_buffer::parse_level, tee_logger},
+    mcp::mcp_server::McpServer,
+    power::{
+        policy::{PowerTransition, WakeSource},
+        power_manager::{self, PowerManager},
     },
-    protocols::{protocol::Protocol, websocket::ws_protocol::WebSocketProtocol},
-    utils::ffi::c_task_trampoline,
-    wifi::wifi_driver::{Esp32WifiDriver, WifiStation},
+    protocols::protocol::Protocol,
+    scheduler::{
+        service::{add_alarm_tools, SchedulerService},
+        Alarm, AlarmKind,
+    },
+    setting::{
+        settings::{Settings, SettingsGroup},
+        storage::NvsStorage,
+    },
+    utils::task::spawn_pinned,
+    wifi::{ssid_manager::SsidMananger, wifi_driver::WifiStation},
 };
 
-use embedded_svc::http::client::Client as HttpClient;
-use esp_idf_svc::{
-    http::{
-        client::{EspHttpConnection, Response},
-        Method,
-    },
-    io,
-    ota::{EspFirmwareInfoLoad, EspOta, EspOtaUpdate, FirmwareInfo},
+#[cfg(target_os = "espidf")]
+use crate::common::httpd_server::{
+    create_server, register_diag_handler, register_log_handler, register_ota_handler,
 };
 
+// host-sim 下不连服务器，按脚本回放服务器的消息
+#[cfg(not(feature = "host-sim"))]
+use crate::protocols::websocket::ws_protocol::WebSocketProtocol as AppProtocol;
+#[cfg(feature = "host-sim")]
+use crate::protocols::scripted_protocol::ScriptedProtocol as AppProtocol;
+// host-sim 下识图请求发给本地的替身服务器
+#[cfg(not(feature = "host-sim"))]
+use crate::camera::vision::EspHttpTransport as VisionTransport;
+#[cfg(feature = "host-sim")]
+use crate::camera::vision::StdHttpTransport as VisionTransport;
+
 // 使用VecDeque作为缓冲区，因为它在头部移除元素时效率很高
 pub type AudioBuffer = VecDeque<u8>;
 
+/// 推送日志时每条消息最多带这么多行，剩下的下一秒再发
+const MAX_LOG_LINES_PER_MESSAGE: usize = 50;
+
+/// 连接服务器出错时状态灯显示红色的时间
+const LED_ERROR_DURATION: Duration = Duration::from_secs(3);
+
 // 共享状态结构体,主要用于音频测试模式保存PCM数据。
 pub struct SharedAudioState {
     pub buffer: Mutex<AudioBuffer>,
@@ -81,132 +114,10 @@ impl SharedAudioState {
     }
 }
 
-const VERSION: &str = env!("CARGO_PKG_VERSION");
-fn check_new_version() -> anyhow::Result<()> {
-    info!("check_new_version, current version is {}", VERSION);
-
-    let mut client = HttpClient::wrap(EspHttpConnection::new(&Default::default())?);
-    check_for_updates(&mut client)?;
-    Ok(())
-}
-
-mod http_status {
-    pub const OK: u16 = 200;
-    pub const NOT_MODIFIED: u16 = 304;
-}
-
-pub fn check_for_updates(client: &mut HttpClient<EspHttpConnection>) -> anyhow::Result<()> {
-    let mut ota = EspOta::new()?;
-
-    let current_version = VERSION;
-    info!("Current version: {current_version}");
-
-    info!("Checking for updates...");
-
-    let headers = [
-        ("Accept", "application/octet-stream"),
-        ("X-Esp32-Version", &current_version),
-    ];
-
-    let ota_firmware_url = "http://192.168.1.145:3000/api/v1/ota/update";
-
-    let request = client.request(Method::Get, ota_firmware_url, &headers)?;
-    let response = request.submit()?;
-
-    if response.status() == http_status::NOT_MODIFIED {
-        info!("OTA: Already up to date");
-    } else if response.status() == http_status::OK {
-        info!("OTA: An update is available, updating...");
-        // let mut update = ota.initiate_update()?;
-
-        info!("print app partition...");
-        unsafe {
-            let mut it = esp_partition_find(
-                esp_partition_type_t_ESP_PARTITION_TYPE_APP,
-                esp_partition_subtype_t_ESP_PARTITION_SUBTYPE_APP_OTA_0,
-                ptr::null(),
-            );
-            while !it.is_null() {
-                let part = esp_partition_get(it);
-                let name = CStr::from_ptr((*part).label.as_ptr()).to_string_lossy();
-                info!(
-                    "Found app partition: {}, offset: 0x{:x}, size: 0x{:x}",
-                    name,
-                    (*part).address,
-                    (*part).size
-                );
-                it = esp_partition_next(it);
-            }
-        }
-
-        info!("initiate update...");
-        match ota.initiate_update() {
-            Ok(mut update) => {
-                info!("initiate updated");
-                match download_update(response, &mut update) {
-                    Ok(_) => {
-                        info!("Update done. Restarting...");
-                        update.complete()?;
-                        esp_idf_svc::hal::reset::restart();
-                    }
-                    Err(err) => {
-                        error!("Update failed: {err}");
-                        update.abort()?;
-                    }
-                };
-            }
-            Err(err) => {
-                error!("initiate update failed: {err}");
-            }
-        }
-    }
-
-    Ok(())
-}
-
-fn download_update(
-    mut response: Response<&mut EspHttpConnection>,
-    update: &mut EspOtaUpdate<'_>,
-) -> anyhow::Result<()> {
-    let mut buffer = [0_u8; 1024];
-
-    // You can optionally read the firmware metadata header.
-    // It contains information like version and signature you can check before continuing the update
-    let update_info = read_firmware_info(&mut buffer, &mut response, update)?;
-    info!("Update version: {}", update_info.version);
-
-    io::utils::copy(response, update, &mut buffer)?;
-
-    Ok(())
-}
-
-fn read_firmware_info(
-    buffer: &mut [u8],
-    response: &mut Response<&mut EspHttpConnection>,
-    update: &mut EspOtaUpdate,
-) -> anyhow::Result<FirmwareInfo> {
-    let update_info_load = EspFirmwareInfoLoad {};
-    let mut update_info = FirmwareInfo {
-        version: Default::default(),
-        released: Default::default(),
-        description: Default::default(),
-        signature: Default::default(),
-        download_id: Default::default(),
-    };
-
-    loop {
-        let n = response.read(buffer)?;
-        update.write(&buffer[0..n])?;
-        if update_info_load.fetch(&buffer[0..n], &mut update_info)? {
-            return Ok(update_info);
-        }
-    }
-}
-
 pub struct Application {
     state: DeviceState,
-    protocol: WebSocketProtocol,
-    board: Box<dyn Board<WifiDriver = Esp32WifiDriver>>,
+    protocol: AppProtocol,
+    board: boards::DynBoard,
 
     //用于处理内部事件的channel
     inner_sender: Sender<AppEvent>,
@@ -218,6 +129,8 @@ pub struct Application {
 
     aec_mode: AecMode,
     listening_mode: ListeningMode,
+    push_to_talk: bool, // 按住说话模式，双击 boot 键切换
+    encoder_input: Option<Sender<Vec<i16>>>, // 发给编码线程的 PCM，空数据表示把剩下的编码完
 
     opus_encoder: Arc<Mutex<OpusAudioEncoder>>,
     opus_decoder: Arc<Mutex<OpusAudioDecoder>>,
@@ -235,7 +148,45 @@ pub struct Application {
     shared_audio_state: Arc<SharedAudioState>,
 
     audio_format: String, // PCM, OPUS，注意要与服务器端的格式一致
+
+    power_off_stage: PowerOffStage, // 长按电源键后等待告别语播放完毕再关机
+    playback_markers_pending: usize, // 放进播放队列、喇叭还没播到的结束标记个数
+
+    power_manager: PowerManager,
+
+    starting: Arc<AtomicBool>, // 启动过程中按键有特殊含义（重置WiFi、恢复出厂设置）
+
+    mcp_server: McpServer,
+    vision_endpoint: Arc<Mutex<VisionEndpoint>>, // 拍照识图的地址，服务器可以在 MCP initialize 里下发
+
+    rollback_guard: RollbackGuard, // 新固件第一次启动时，等进入 Idle 并连上网络后再确认，否则回滚
+    ota_checked: bool,             // 每次启动只检查一次升级
+    #[cfg(target_os = "espidf")]
+    ota_http_server: Option<EspHttpServer<'static>>, // 联网后提供 POST /ota、GET /diag 和 GET /logs，方便在局域网里上传固件、查看状态
+    diag_timer_started: bool,
+    sntp: Option<SntpService>, // 联网后启动，之后 lwip 会定时重新同步
+    clock_timer_started: bool,
+    led: Option<LedService>, // 状态灯，跟随 DeviceState 变化
+    led_settings: LedSettings,
+    display_brightness: u8,
+    scheduler: SchedulerService, // 本地的闹钟、计时器和提醒
+    log_stream_level: Option<Level>, // 服务器要求推送日志时的级别，None 表示不推送
+    log_stream_seq: u64,             // 下一次从这个序号开始推送
+    log_timer_started: bool,
+}
+/// 长按电源键后的关机进度。告别语完整播完才关机，
+/// 被打断的那句话的 tts stop 和它剩下的音频都不能触发关机
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum PowerOffStage {
+    None,
+    /// 已经请求告别语，还没收到它的 tts start
+    WaitingFarewell,
+    /// 正在下发告别语
+    Farewell,
+    /// 告别语已经下发完（或者播的是本地提示音），等喇叭把播放队列播完
+    Draining,
 }
+
 impl Application {
     pub fn new() -> Result<Self> {
         let (inner_sender, inner_receiver): (Sender<AppEvent>, Receiver<AppEvent>) = channel();
@@ -247,20 +198,77 @@ impl Application {
             app_event_sender: inner_sender.clone(),
         };
 
-        let mut board = Box::new(jianglian_s3cam_board::JiangLianS3CamBoard::new(
-            app_context,
-        )?);
+        let mut board = boards::create_board(app_context)?;
+        info!("board: {}", board.name());
+
+        let starting = Arc::new(AtomicBool::new(true));
 
         let sender = inner_sender.clone();
-        board.on_touch_button_clicked(Box::new(move || {
+        let starting_for_click = starting.clone();
+        board.get_buttons().on_touch_button_clicked(Box::new(move || {
             // println!("Touch button clicked");
-            if let Err(e) = sender.send(AppEvent::BootButtonClicked) {
+            // 启动中（还在连接WiFi）按下 boot 键，重新进入配网
+            let event = if starting_for_click.load(Ordering::Relaxed) {
+                AppEvent::ResetWifiConfiguration
+            } else {
+                AppEvent::BootButtonClicked
+            };
+            if let Err(e) = sender.send(event) {
                 log::error!("Failed to send BootButtonClicked event: {:?}", e);
             }
         }));
 
+        let sender2 = inner_sender.clone();
+        let starting_for_long_press = starting.clone();
+        board.get_buttons().on_touch_button_long_pressed(Box::new(move || {
+            // 只有启动过程中长按 boot 键才恢复出厂设置，避免误触
+            if !starting_for_long_press.load(Ordering::Relaxed) {
+                return;
+            }
+            if let Err(e) = sender2.send(AppEvent::FactoryReset) {
+                log::error!("Failed to send FactoryReset event: {:?}", e);
+            }
+        }));
+
+        let sender3 = inner_sender.clone();
+        let starting_for_press = starting.clone();
+        board.get_buttons().on_touch_button_pressed(Box::new(move || {
+            if starting_for_press.load(Ordering::Relaxed) {
+                return;
+            }
+            if let Err(e) = sender3.send(AppEvent::TalkButtonPressed) {
+                log::error!("Failed to send TalkButtonPressed event: {:?}", e);
+            }
+        }));
+
+        let sender4 = inner_sender.clone();
+        let starting_for_release = starting.clone();
+        board.get_buttons().on_touch_button_released(Box::new(move || {
+            if starting_for_release.load(Ordering::Relaxed) {
+                return;
+            }
+            if let Err(e) = sender4.send(AppEvent::TalkButtonReleased) {
+                log::error!("Failed to send TalkButtonReleased event: {:?}", e);
+            }
+        }));
+
+        let sender5 = inner_sender.clone();
+        board.get_buttons().on_touch_button_double_clicked(Box::new(move || {
+            if let Err(e) = sender5.send(AppEvent::SwitchListeningMode) {
+                log::error!("Failed to send SwitchListeningMode event: {:?}", e);
+            }
+        }));
+
+        // 同时长按 boot 键和音量键，任何时候都可以恢复出厂设置
+        let sender6 = inner_sender.clone();
+        board.get_buttons().on_buttons_combo_long_pressed(Box::new(move || {
+            if let Err(e) = sender6.send(AppEvent::FactoryReset) {
+                log::error!("Failed to send FactoryReset event: {:?}", e);
+            }
+        }));
+
         let sender1 = inner_sender.clone();
-        board.on_volume_button_clicked(Box::new(move || {
+        board.get_buttons().on_volume_button_clicked(Box::new(move || {
             // println!("Volume button clicked");
             if let Err(e) = sender1.send(AppEvent::VolumeButtonClicked) {
                 log::error!("Failed to send VolumeButtonClicked event: {:?}", e);
@@ -270,12 +278,9 @@ impl Application {
         board.init()?;
         info!("board init success");
 
-        // info!("check new version ...");
-        // check_new_version()?;
-
         let mac_address = board.get_wifi_driver().get_mac_address()?;
         let sender_for_protocol = inner_sender.clone();
-        let protocol = WebSocketProtocol::new(mac_address.as_str(), sender_for_protocol);
+        let protocol = AppProtocol::new(mac_address.as_str(), sender_for_protocol);
 
         //待发送的音频队列
         let audio_packet_queue = Arc::new(Mutex::new(
@@ -284,6 +289,10 @@ impl Application {
         let audio_decode_queue = Arc::new(Mutex::new(
             VecDeque::<AudioStreamPacket>::with_capacity(MAX_AUDIO_PACKETS_IN_QUEUE),
         ));
+        diagnostics::register_audio_queues(
+            Arc::clone(&audio_packet_queue),
+            Arc::clone(&audio_decode_queue),
+        );
 
         // let (input_channels, input_reference) = {
         //     let codec = board.get_audio_codec().clone();
@@ -329,6 +338,36 @@ impl Application {
         // 使用 sync_channel 创建一个带缓冲的 channel，防止内存无限制增长
         let (pcm_tx, pcm_rx) = std::sync::mpsc::sync_channel::<Vec<u8>>(10);
 
+        let vision_endpoint = Arc::new(Mutex::new(
+            Settings::load()
+                .map(|settings| VisionEndpoint {
+                    url: settings.server.vision_url,
+                    token: settings.security.vision_token,
+                })
+                .unwrap_or_default(),
+        ));
+        let scheduler = SchedulerService::new();
+        let mcp_server = Self::create_mcp_server(
+            inner_sender.clone(),
+            board.get_camera(),
+            VisionClient::new(VisionTransport, vision_endpoint.clone(), &mac_address),
+            &scheduler,
+        );
+
+        let display_settings = Settings::load()
+            .map(|settings| settings.display)
+            .unwrap_or_default();
+        clock::set_timezone(&display_settings.timezone);
+        if let Err(e) = board.set_backlight(display_settings.brightness) {
+            error!("Failed to set backlight: {:?}", e);
+        }
+
+        let led = board.get_led();
+        let led_settings = display_settings.led;
+        if let Some(led) = &led {
+            led.set_brightness(led_settings.brightness);
+        }
+
         let instance = Self {
             state: DeviceState::Idle,
             protocol,
@@ -340,6 +379,8 @@ impl Application {
             decode_task_receiver: Some(decode_task_receiver),
             aec_mode: AecMode::Off,
             listening_mode: ListeningMode::AutoStop,
+            push_to_talk: false,
+            encoder_input: None,
             audio_processor: audio_processor,
             audio_packet_queue,
             audio_decode_queue,
@@ -351,11 +392,32 @@ impl Application {
             inner_pcm_tx: pcm_tx,
             inner_pcm_rx: Some(pcm_rx),
             audio_format: "opus".to_string(),
+            power_off_stage: PowerOffStage::None,
+            playback_markers_pending: 0,
+            power_manager: PowerManager::new(PowerManager::load_config()),
+            starting,
+            mcp_server,
+            vision_endpoint,
+            rollback_guard: RollbackGuard::new(),
+            ota_checked: false,
+            #[cfg(target_os = "espidf")]
+            ota_http_server: None,
+            diag_timer_started: false,
+            sntp: None,
+            clock_timer_started: false,
+            led,
+            led_settings,
+            display_brightness: display_settings.brightness,
+            scheduler,
+            log_stream_level: None,
+            log_stream_seq: 0,
+            log_timer_started: false,
         };
         Ok(instance)
     }
 
     pub fn start(&mut self) -> Result<(), Error> {
+        power_manager::log_wakeup_cause();
         self.set_device_state(DeviceState::Starting);
         let codec_arc = self.board.get_audio_codec();
         // let codec_arc = Arc::new(Mutex::new(codec));
@@ -418,6 +480,7 @@ impl Application {
             .name("encoder_task".into())
             .stack_size(32 * 1024)
             .spawn(move || {
+                let _task = diagnostics::register_current_task("encoder_task");
                 let opus_encoder = Arc::clone(&opus_encoder_arc);
                 for pcm_data in pcm_rx {
                     // 在这里做编码，环境单纯，没有锁竞争
@@ -428,7 +491,7 @@ impl Application {
                     let encoder = Arc::clone(&opus_encoder);
                     let audio_state1 = Arc::clone(&audio_state);
 
-                    if audio_test_mode {
+                    if audio_test_mode && !pcm_data.is_empty() {
                         // sender.send(XzEvent::AudioPacketReceived(packet)).unwrap();
                         // audio_state1
                         //     .pcm_buffer
@@ -446,6 +509,42 @@ impl Application {
 
                     let sender = inner_sender1.clone();
 
+                    // 编码完成的 opus 包放进待发送队列
+                    let mut on_encoded = move |opus_data: Vec<u8>| {
+                        // info!("编码完成，add audio packet to queue");
+                        let packet = AudioStreamPacket {
+                            sample_rate: AUDIO_INPUT_SAMPLE_RATE as i32,
+                            frame_duration: OPUS_FRAME_DURATION_MS as i32,
+                            timestamp: 0,
+                            payload: opus_data,
+                        };
+
+                        if audio_test_mode {
+                            // sender.send(XzEvent::AudioPacketReceived(packet)).unwrap();
+                            audio_state1
+                                .audio_packet_buffer
+                                .lock()
+                                .unwrap()
+                                .push_back(packet);
+                        } else if let Err(e) = sender.send(AppEvent::AddAudioPacketToQueue(packet)) {
+                            error!("Failed to send audio packet: {:?}", e);
+                        }
+                    };
+
+                    // 空数据是松开按住说话按键时发来的，把剩下不满一帧的数据也编码发出去，
+                    // 然后通知主线程可以发送 listen stop 了
+                    if pcm_data.is_empty() {
+                        if audio_format != "pcm" {
+                            if let Err(e) = encoder.lock().unwrap().flush(&mut on_encoded) {
+                                error!("Failed to flush encoder: {:?}", e);
+                            }
+                        }
+                        if let Err(e) = inner_sender1.send(AppEvent::EncoderFlushed) {
+                            error!("Failed to send EncoderFlushed event: {:?}", e);
+                        }
+                        continue;
+                    }
+
                     if audio_format == "pcm" {
                         let pcm_u8 = i16_slice_to_bytes(&pcm_data.as_slice()).unwrap();
 
@@ -455,11 +554,12 @@ impl Application {
                             timestamp: 0,
                             payload: pcm_u8.to_vec(),
                         };
-                        if let Err(e) = sender.send(AppEvent::AddAudioPacketToQueue(packet)) {
+                        if let Err(e) = inner_sender1.send(AppEvent::AddAudioPacketToQueue(packet)) {
                             error!("Failed to send audio packet: {:?}", e);
                             return;
                         }
                     } else {
+                        let started_at = Instant::now();
                         let result = encoder
                             .lock()
                             .map_err(|e| {
@@ -467,31 +567,8 @@ impl Application {
                                 // 可以选择 clear_poison() 或者直接返回
                             })
                             .unwrap()
-                            .encode(pcm_data, &mut move |opus_data: Vec<u8>| {
-                                // info!("编码完成，add audio packet to queue");
-                                let packet = AudioStreamPacket {
-                                    sample_rate: AUDIO_INPUT_SAMPLE_RATE as i32,
-                                    frame_duration: OPUS_FRAME_DURATION_MS as i32,
-                                    timestamp: 0,
-                                    payload: opus_data,
-                                };
-
-                                if audio_test_mode {
-                                    // sender.send(XzEvent::AudioPacketReceived(packet)).unwrap();
-                                    audio_state1
-                                        .audio_packet_buffer
-                                        .lock()
-                                        .unwrap()
-                                        .push_back(packet);
-                                } else {
-                                    if let Err(e) =
-                                        sender.send(AppEvent::AddAudioPacketToQueue(packet))
-                                    {
-                                        error!("Failed to send audio packet: {:?}", e);
-                                        return;
-                                    }
-                                }
-                            });
+                            .encode(pcm_data, &mut on_encoded);
+                        diagnostics::ENCODE_TIMING.record(started_at);
 
                         match result {
                             Ok(_) => {}
@@ -513,6 +590,7 @@ impl Application {
 
         let audio_state = Arc::clone(&self.shared_audio_state);
 
+        self.encoder_input = Some(pcm_tx.clone());
         audio_processor
             .lock()
             .unwrap()
@@ -530,28 +608,8 @@ impl Application {
         let task_closure: Box<dyn FnOnce() + Send> = Box::new(move || {
             audio_loop(codec_clone, audio_processor);
         });
-
-        let closure_box = Box::new(task_closure);
-        let closure_ptr = Box::into_raw(closure_box);
-
-        info!("try to call xTaskCreatePinnedToCore in the unsafe block");
-        unsafe {
-            let res = esp_idf_sys::xTaskCreatePinnedToCore(
-                Some(c_task_trampoline),
-                b"audio_loop\0".as_ptr() as *const u8,
-                // 4096 * 3,
-                16 * 1024,
-                closure_ptr as *mut c_void,
-                8,
-                ptr::null_mut(),
-                1,
-            );
-            info!("create audio loop task - res: {}", res);
-            if res != 1 {
-                // 如果创建失败，记得收回内存，否则会泄漏
-                let _ = Box::from_raw(closure_ptr);
-                error!("Failed to create task");
-            }
+        if let Err(e) = spawn_pinned(c"audio_loop", 16 * 1024, 8, 1, task_closure) {
+            error!("Failed to create audio loop task: {:?}", e);
         }
 
         info!("启动解码线程 start_output_audio ...");
@@ -566,6 +624,7 @@ impl Application {
         //启动音频输出线程
         info!("启动音频输出线程 start pcm_player_thread ...");
         if let Some(pcm_rx) = self.inner_pcm_rx.take() {
+            #[cfg(target_os = "espidf")]
             ThreadSpawnConfiguration {
                 name: Some(c"pcm_player_thread"),
                 stack_size: 8 * 1024,
@@ -580,9 +639,18 @@ impl Application {
             .set()
             .unwrap();
 
+            let playback_sender = self.inner_sender.clone();
             let _ = thread::spawn(move || {
+                let _task = diagnostics::register_current_task("pcm_player_thread");
                 // let mut opus_decoder = ...;
                 for pcm_packet in pcm_rx {
+                    // 空包是 TTS 的结束标记，前面的 PCM 都已经写进喇叭了
+                    if pcm_packet.is_empty() {
+                        if let Err(e) = playback_sender.send(AppEvent::TTSPlaybackFinished) {
+                            error!("Failed to send TTSPlaybackFinished: {:?}", e);
+                        }
+                        continue;
+                    }
                     pcm_player_codec
                         .lock()
                         .unwrap()
@@ -590,6 +658,7 @@ impl Application {
                         .unwrap();
                 }
             });
+            #[cfg(target_os = "espidf")]
             ThreadSpawnConfiguration::default().set().unwrap();
         }
 
@@ -597,6 +666,13 @@ impl Application {
 
         self.audio_alert("success");
 
+        if let Err(e) = self.power_manager.start(self.inner_sender.clone()) {
+            error!("Failed to start power manager: {:?}", e);
+        }
+        if let Err(e) = self.scheduler.start(self.inner_sender.clone()) {
+            error!("Failed to start scheduler: {:?}", e);
+        }
+
         // 处理内部事件
         self.event_loop()?;
         Ok(())
@@ -625,22 +701,40 @@ impl Application {
                     match event {
                         AppEvent::BootButtonClicked => {
                             info!("Boot button clicked! current state: {:?}", self.state);
-                            if self.state == DeviceState::Starting
-                                && !self.board.get_wifi_driver().is_connected().unwrap_or(false)
-                            {
-                                // TODO: 重置WiFi配置
-                                // self.reset_wifi_configuration();
+                            self.power_manager.on_activity();
+                            // 按住说话模式下单击由按下/松开处理
+                            if !self.push_to_talk && !self.dismiss_alarm() {
+                                self.toggle_device_state();
+                            }
+                        }
+                        AppEvent::TalkButtonPressed => {
+                            if self.push_to_talk {
+                                self.power_manager.on_activity();
+                                if !self.dismiss_alarm() {
+                                    self.start_push_to_talk();
+                                }
+                            }
+                        }
+                        AppEvent::TalkButtonReleased => {
+                            if self.push_to_talk {
+                                self.stop_push_to_talk();
                             }
-                            self.toggle_device_state();
+                        }
+                        AppEvent::SwitchListeningMode => {
+                            self.power_manager.on_activity();
+                            self.switch_listening_mode();
                         }
                         AppEvent::VolumeButtonClicked => {
                             info!("Volume button clicked! current state: {:?}", self.state);
+                            self.power_manager.on_activity();
                             // if self.state == DeviceState::Idle {
                             //     self.set_device_state(DeviceState::Listening);
                             // } else if self.state == DeviceState::Listening {
                             //     self.set_device_state(DeviceState::Idle);
                             // }
-                            self.toggle_device_state();
+                            if !self.dismiss_alarm() {
+                                self.toggle_device_state();
+                            }
 
                             if audio_test_mode {
                                 //下面的代码是用于PCM音频本地回放测试用的
@@ -693,7 +787,10 @@ impl Application {
                             //     display->SetChatMessage("system", "");
                             //     SetDeviceState(kDeviceStateIdle);
                             // }); });
-                            self.set_device_state(DeviceState::Idle);
+                            // 进入升级状态时会主动关闭对话，不要因此退出升级状态
+                            if self.state != DeviceState::Upgrading {
+                                self.set_device_state(DeviceState::Idle);
+                            }
                         }
                         AppEvent::WebsocketTextMessageReceived(text) => {
                             info!("Received text message: {}", text);
@@ -791,6 +888,15 @@ impl Application {
 
                                         if message_type == "hello" {
                                             self.protocol.set_connected(true);
+                                            self.rollback_guard.on_connected();
+                                        }
+
+                                        if message_type == "mcp" {
+                                            self.handle_mcp_message(&message["payload"]);
+                                        }
+
+                                        if message_type == "log" {
+                                            self.handle_log_message(&message);
                                         }
 
                                         if message_type == "tts" {
@@ -802,6 +908,12 @@ impl Application {
                                                         "处理文本消息开始: {} 当前状态: {:?}",
                                                         text, self.state
                                                     );
+                                                    if self.power_off_stage
+                                                        == PowerOffStage::WaitingFarewell
+                                                    {
+                                                        self.power_off_stage =
+                                                            PowerOffStage::Farewell;
+                                                    }
                                                     if self.state == DeviceState::Idle
                                                         || self.state == DeviceState::Listening
                                                     {
@@ -815,9 +927,18 @@ impl Application {
                                                         text, self.state
                                                     );
 
+                                                    // 解码线程收到后在播放队列末尾放一个结束标记
                                                     self.decode_task_sender
                                                         .send(AppEvent::TTSStop)
                                                         .unwrap();
+                                                    self.playback_markers_pending += 1;
+
+                                                    if self.power_off_stage
+                                                        == PowerOffStage::Farewell
+                                                    {
+                                                        self.power_off_stage =
+                                                            PowerOffStage::Draining;
+                                                    }
 
                                                     // TODO:: 看一下 background_task_ 在我们这里怎么实现，他的作用应该是等后台任务完成。
                                                     // background_task_->WaitForCompletion();
@@ -850,17 +971,15 @@ impl Application {
                         }
                         AppEvent::SendAudioEvent => {
                             // info!("XzEvent::SendAudioEvent");
-                            let packets = {
-                                let mut queue = audio_packet_send_queue_arc.lock().unwrap();
-                                // std::mem::take 会把 queue 换成默认值（空），并把原来的值返回
-                                // 这完全等同于 C++ 的 std::move
-                                std::mem::take(&mut *queue)
-                            };
-
-                            // 此时锁已经释放了
-                            for packet in packets {
-                                // info!("send audio packet using protocol!");
-                                self.protocol.send_audio(&packet)?;
+                            self.send_queued_audio()?;
+                        }
+                        AppEvent::EncoderFlushed => {
+                            // 最后一个包的 SendAudioEvent 还排在后面，先把队列里的发完再发 listen stop
+                            if let Err(e) = self.send_queued_audio() {
+                                error!("Failed to send queued audio: {:?}", e);
+                            }
+                            if self.listening_mode == ListeningMode::Manual {
+                                self.stop_listening();
                             }
                         }
                         AppEvent::AudioPacketReceived(audio_stream_packet) => {
@@ -897,6 +1016,8 @@ impl Application {
                                     {
                                         Ok(_) => {
                                             // info!("send audio decode event ok");
+                                            diagnostics::DECODE_PENDING
+                                                .fetch_add(1, Ordering::Relaxed);
                                         }
                                         Err(e) => {
                                             error!("send audio decode event error: {:?}", e);
@@ -957,12 +1078,153 @@ impl Application {
                         AppEvent::ProtocolNetworkError(err) => {
                             self.set_device_state(DeviceState::Idle);
                             error!("ProtocolNetworkError: {:?}", err);
+                            self.show_led_error(Some(LED_ERROR_DURATION));
                         }
 
                         AppEvent::PlayAudioAlert(message) => {
                             self.audio_alert(&message);
                         }
 
+                        AppEvent::PowerKeyClicked => {
+                            info!("Power key clicked! current state: {:?}", self.state);
+                            self.power_manager.on_activity();
+                            if !self.dismiss_alarm() {
+                                self.toggle_device_state();
+                            }
+                        }
+
+                        AppEvent::PowerKeyLongPressed => {
+                            info!("Power key long pressed! current state: {:?}", self.state);
+                            self.request_power_off();
+                        }
+
+                        AppEvent::ChargingStateChanged(charging) => {
+                            info!("Charging state changed: {}", charging);
+                            self.power_manager.set_charging(charging);
+                            self.power_manager.on_activity();
+                            self.board.get_display().set_charging(charging);
+                        }
+
+                        AppEvent::BatteryOverheat => {
+                            warn!("Battery overheat!");
+                            self.board.get_display().set_status("Battery overheat");
+                            self.audio_alert("exclamation");
+                        }
+
+                        AppEvent::PowerTick => {
+                            if let Some(transition) = self.power_manager.tick() {
+                                self.apply_power_transition(transition);
+                            }
+                        }
+
+                        AppEvent::TTSPlaybackFinished => {
+                            self.playback_markers_pending =
+                                self.playback_markers_pending.saturating_sub(1);
+                            if self.power_off_stage == PowerOffStage::Draining
+                                && self.playback_markers_pending == 0
+                            {
+                                self.inner_sender.send(AppEvent::PowerOff).unwrap();
+                            }
+                        }
+
+                        AppEvent::PowerOff => {
+                            if let Err(e) = self.board.power_off() {
+                                error!("Failed to power off: {:?}", e);
+                            }
+                        }
+
+                        AppEvent::ResetWifiConfiguration => {
+                            // 按键是在启动过程中按下的，如果最后没有连上WiFi，就清除WiFi配置重新配网
+                            if !self.board.get_wifi_driver().is_connected().unwrap_or(false) {
+                                self.reset_wifi_configuration();
+                            }
+                        }
+
+                        AppEvent::FactoryReset => {
+                            self.factory_reset();
+                        }
+
+                        AppEvent::OtaProgress(progress) => {
+                            self.on_ota_progress(progress);
+                        }
+
+                        AppEvent::DiagnosticsTick => {
+                            self.send_diagnostics();
+                        }
+
+                        AppEvent::LogStreamTick => {
+                            self.send_log_lines();
+                        }
+
+                        AppEvent::ShowCameraPreview(frame) => {
+                            self.board.get_display().show_camera_preview(&frame);
+                        }
+
+                        AppEvent::NetworkConnected(ip) => {
+                            info!("Network connected, ip: {}", ip);
+                            if let Some(led) = &self.led {
+                                led.clear_error();
+                            }
+                            self.board.get_display().set_status("已连接");
+                            self.rollback_guard.on_connected();
+                            self.check_new_version();
+                            #[cfg(target_os = "espidf")]
+                            self.start_ota_http_server();
+                            self.start_diagnostics_report();
+                            self.start_time_sync();
+                        }
+
+                        AppEvent::TimeSynced => {
+                            if let Some(now) = clock::local_time() {
+                                info!("Time synced: {} {}", now.date_text(), now.clock_text());
+                            }
+                            if let Err(e) = SsidMananger::get_instance().touch_pending_ssid() {
+                                warn!("Failed to update last connect time: {:?}", e);
+                            }
+                            self.update_clock();
+                        }
+
+                        AppEvent::ClockTick => {
+                            self.update_clock();
+                        }
+
+                        AppEvent::AlarmFired(alarm) => {
+                            self.on_alarm_fired(alarm);
+                        }
+
+                        AppEvent::AlarmRing => {
+                            self.power_manager.on_activity();
+                            self.audio_alert("vibration");
+                        }
+
+                        AppEvent::AlarmStopped => {
+                            self.update_clock();
+                        }
+
+                        AppEvent::NetworkDisconnected => {
+                            warn!("Network disconnected");
+                            self.show_led_error(None);
+                            if self.protocol.is_audio_channel_opened() {
+                                if let Err(e) = self.protocol.close_audio_channel() {
+                                    error!("Failed to close audio channel: {:?}", e);
+                                }
+                            }
+                            self.set_device_state(DeviceState::Idle);
+                            self.board.get_display().set_status("网络已断开，正在重连");
+                        }
+
+                        AppEvent::NetworkProvisioningRequired => {
+                            warn!("Network reconnect failed, start wifi provisioning");
+                            // 配网页面也用 80 端口，先关掉本地升级服务
+                            #[cfg(target_os = "espidf")]
+                            {
+                                self.ota_http_server = None;
+                            }
+                            if let Err(e) = self.board.start_wifi_ap() {
+                                error!("Failed to start wifi provisioning: {:?}", e);
+                            }
+                        }
+
                         _ => {
                             info!("Received unhandled event: {:?}", event);
                         }
@@ -976,6 +1238,7 @@ impl Application {
         // info!("application start 函数返回！");
     }
 
+    #[cfg(target_os = "espidf")]
     pub fn read_audio(
         &mut self,
         mut i2s_driver: MutexGuard<'_, I2sDriver<'_, I2sBiDir>>,
@@ -994,6 +1257,9 @@ impl Application {
         }
         let previous_state = self.state.clone();
         self.state = state;
+        if let Some(led) = &self.led {
+            led.set_pattern(self.led_settings.pattern_for(&self.state));
+        }
 
         match self.state {
             DeviceState::Idle => {
@@ -1006,6 +1272,8 @@ impl Application {
                 // audio_processor_->Stop();
                 // wake_word_->StartDetection();
                 self.audio_processor.lock().unwrap().stop();
+                self.rollback_guard.on_idle();
+                self.update_clock();
             }
             DeviceState::Activating => {
                 info!(
@@ -1075,14 +1343,561 @@ impl Application {
                     previous_state, self.state
                 )
             }
+            DeviceState::Upgrading => {
+                info!(
+                    "Device state changed from {:?} to {:?}",
+                    previous_state, self.state
+                );
+                // 升级过程中不能对话，先关掉正在进行的对话
+                self.audio_processor.lock().unwrap().stop();
+                if self.protocol.is_audio_channel_opened() {
+                    if let Err(e) = self.protocol.close_audio_channel() {
+                        error!("Failed to close audio channel: {:?}", e);
+                    }
+                }
+                self.board.get_display().set_status(lang::UPGRADING);
+                self.audio_alert("upgrade");
+            }
+            _ => {}
+        }
+
+        self.starting
+            .store(self.state == DeviceState::Starting, Ordering::Relaxed);
+
+        if let Some(transition) = self.power_manager.on_device_state(&self.state) {
+            self.apply_power_transition(transition);
+        }
+    }
+
+    fn create_mcp_server(
+        sender: Sender<AppEvent>,
+        camera: Option<Arc<Mutex<dyn Camera>>>,
+        vision: VisionClient<VisionTransport>,
+        scheduler: &SchedulerService,
+    ) -> McpServer {
+        let mut mcp_server = McpServer::new();
+        if let Some(camera) = camera {
+            add_camera_tools(&mut mcp_server, camera, vision, sender.clone());
+        }
+        add_alarm_tools(&mut mcp_server, scheduler);
+        mcp_server.add_tool(
+            "self.system.factory_reset",
+            "Reset the device to factory settings. All saved Wi-Fi networks, the server address \
+             and the volume are cleared, then the device reboots into Wi-Fi provisioning. \
+             Only call this when the user explicitly asks for a factory reset.",
+            serde_json::json!({}),
+            &[],
+            move |_| {
+                sender
+                    .send(AppEvent::FactoryReset)
+                    .map_err(|e| anyhow::anyhow!("Failed to send FactoryReset event: {:?}", e))?;
+                Ok(serde_json::json!(true))
+            },
+        );
+        mcp_server.add_tool(
+            "self.system.get_diagnostics",
+            "Get runtime diagnostics of the device: free heap and PSRAM, task stack high-water \
+             marks, Wi-Fi RSSI, audio queue depths, opus encode/decode timings and the reset \
+             reason. Use this when the user asks about the device status or reports problems.",
+            serde_json::json!({}),
+            &[],
+            |_| Ok(serde_json::to_value(diagnostics::collect())?),
+        );
+        mcp_server
+    }
+
+    fn handle_mcp_message(&mut self, payload: &serde_json::Value) {
+        // 服务器在 initialize 的 capabilities 里下发识图服务的地址和 token
+        if payload["method"] == "initialize" {
+            let vision = &payload["params"]["capabilities"]["vision"];
+            if let Some(url) = vision["url"].as_str() {
+                info!("Vision url from server: {}", url);
+                let mut endpoint = self.vision_endpoint.lock().unwrap();
+                endpoint.url = Some(url.to_string());
+                if let Some(token) = vision["token"].as_str() {
+                    endpoint.token = Some(token.to_string());
+                }
+            }
+        }
+
+        let Some(response) = self.mcp_server.handle_message(payload) else {
+            return;
+        };
+        if let Err(e) = self.protocol.send_mcp_message(&response) {
+            error!("Failed to send MCP response: {:?}", e);
+        }
+    }
+
+    /// 只清除保存的WiFi，重启后进入配网
+    fn reset_wifi_configuration(&mut self) {
+        warn!("Reset wifi configuration");
+        if let Err(e) = Settings::update(SettingsGroup::Wifi, |settings| {
+            settings.wifi = Default::default();
+        }) {
+            error!("Failed to reset wifi configuration: {:?}", e);
+            return;
+        }
+        self.board.get_display().set_status("WiFi 已重置，即将重启");
+        schedule_restart();
+    }
+
+    /// 恢复出厂设置：清除所有设置后重启
+    fn factory_reset(&mut self) {
+        warn!("Factory reset!");
+        if let Err(e) = Settings::factory_reset(&mut NvsStorage::new()) {
+            error!("Failed to factory reset: {:?}", e);
+            return;
+        }
+        self.board
+            .get_display()
+            .set_status("已恢复出厂设置，即将重启");
+        self.audio_alert("exclamation");
+        schedule_restart();
+    }
+
+    // 连上网络后在后台检查一次升级。新固件还没有确认时不检查，避免在回滚之前又被覆盖
+    fn check_new_version(&mut self) {
+        if self.ota_checked || self.rollback_guard.is_pending() {
+            return;
+        }
+        self.ota_checked = true;
+
+        let manifest_url = Settings::load()
+            .ok()
+            .and_then(|settings| settings.server.ota_url)
+            .unwrap_or_else(|| ota::DEFAULT_MANIFEST_URL.to_string());
+        ota::spawn_update_check(manifest_url, self.inner_sender.clone());
+    }
+
+    // 设置了 diag_interval_s 时定时把诊断信息推送给服务器，只有 websocket 连着的时候才推送
+    fn start_diagnostics_report(&mut self) {
+        if self.diag_timer_started {
+            return;
+        }
+        let interval = match Settings::load() {
+            Ok(settings) => settings.server.diag_interval_s,
+            Err(e) => {
+                error!("Failed to load diagnostics setting: {:?}", e);
+                return;
+            }
+        };
+        if interval == 0 {
+            return;
+        }
+        self.diag_timer_started = true;
+        if let Err(e) = diagnostics::start_report_timer(
+            Duration::from_secs(interval as u64),
+            self.inner_sender.clone(),
+        ) {
+            error!("Failed to start diagnostics timer: {:?}", e);
+        }
+    }
+
+    // 联网后启动 SNTP 和每分钟一次的时钟刷新，重连时不重复启动
+    fn start_time_sync(&mut self) {
+        if self.sntp.is_none() {
+            match SntpService::start(self.inner_sender.clone()) {
+                Ok(sntp) => self.sntp = Some(sntp),
+                Err(e) => error!("Failed to start SNTP: {:?}", e),
+            }
+        }
+        if !self.clock_timer_started {
+            self.clock_timer_started = true;
+            if let Err(e) = clock::start_clock_timer(self.inner_sender.clone()) {
+                error!("Failed to start clock timer: {:?}", e);
+            }
+        }
+    }
+
+    /// 闹钟到点：点亮屏幕、打断对话，显示标题并开始响铃，之后由 `AppEvent::AlarmRing` 重复播放
+    fn on_alarm_fired(&mut self, alarm: Alarm) {
+        self.power_manager.on_activity();
+        if let Err(e) = self.board.set_backlight(self.display_brightness) {
+            error!("Failed to set backlight: {:?}", e);
+        }
+
+        match self.state {
+            DeviceState::Speaking => {
+                if let Err(e) = self.protocol.send_abort_speaking(AbortReason::None) {
+                    error!("Failed to send abort speaking: {:?}", e);
+                }
+            }
+            DeviceState::Listening => {
+                self.audio_processor.lock().unwrap().stop();
+                self.stop_listening();
+            }
             _ => {}
         }
+
+        let title = match alarm.kind {
+            AlarmKind::Timer => lang::TIMER_DONE,
+            AlarmKind::Alarm => lang::ALARM,
+            AlarmKind::Reminder => lang::REMINDER,
+        };
+        self.board.get_display().show_alarm(title, &alarm.label);
+        self.audio_alert("vibration");
+    }
+
+    /// 按键时先关掉正在响的闹钟，返回 true 表示这次按键已经处理了
+    fn dismiss_alarm(&mut self) -> bool {
+        if self.scheduler.dismiss().is_none() {
+            return false;
+        }
+        self.reset_decoder();
+        self.update_clock();
+        true
+    }
+
+    // 待机时显示时钟，时间还没同步或者闹钟正在响时不显示
+    fn update_clock(&mut self) {
+        if self.state != DeviceState::Idle || self.scheduler.is_ringing() {
+            return;
+        }
+        if let Some(now) = clock::local_time() {
+            self.board
+                .get_display()
+                .show_clock(&now.clock_text(), &now.date_text());
+        }
+    }
+
+    fn send_diagnostics(&mut self) {
+        if !self.protocol.is_connected() {
+            return;
+        }
+        let result = serde_json::to_value(diagnostics::collect())
+            .map_err(Error::from)
+            .and_then(|report| self.protocol.send_diagnostics(&report));
+        if let Err(e) = result {
+            error!("Failed to send diagnostics: {:?}", e);
+        }
+    }
+
+    // 服务器发送 {"type":"log","state":"start","level":"info"} 开始推送日志，
+    // {"type":"log","state":"stop"} 停止。只推送开始之后的日志，之前的可以用 GET /logs 下载
+    fn handle_log_message(&mut self, message: &serde_json::Value) {
+        match message["state"].as_str() {
+            Some("start") => {
+                let level = message["level"]
+                    .as_str()
+                    .and_then(parse_level)
+                    .unwrap_or(Level::Info);
+                info!("Start streaming logs, level: {}", level);
+                self.log_stream_level = Some(level);
+                self.log_stream_seq = tee_logger::next_seq();
+                if !self.log_timer_started {
+                    self.log_timer_started = true;
+                    if let Err(e) = tee_logger::start_stream_timer(self.inner_sender.clone()) {
+                        error!("Failed to start log stream timer: {:?}", e);
+                    }
+                }
+            }
+            Some("stop") => {
+                info!("Stop streaming logs");
+                self.log_stream_level = None;
+            }
+            _ => warn!("Unknown log message: {}", message),
+        }
+    }
+
+    fn send_log_lines(&mut self) {
+        let Some(level) = self.log_stream_level else {
+            return;
+        };
+        if !self.protocol.is_connected() {
+            return;
+        }
+        let lines = tee_logger::lines_since(self.log_stream_seq, level, MAX_LOG_LINES_PER_MESSAGE);
+        let Some(last) = lines.last() else {
+            return;
+        };
+        let next_seq = last.seq + 1;
+        let lines: Vec<String> = lines.into_iter().map(|line| line.text).collect();
+        match self.protocol.send_log_lines(&lines) {
+            Ok(()) => self.log_stream_seq = next_seq,
+            Err(e) => error!("Failed to send log lines: {:?}", e),
+        }
+    }
+
+    // host-sim 下不提供本地升级、诊断和日志接口
+    #[cfg(target_os = "espidf")]
+    fn start_ota_http_server(&mut self) {
+        if self.ota_http_server.is_some() {
+            return;
+        }
+        let result = create_server().and_then(|mut http_server| {
+            register_ota_handler(&mut http_server, Some(self.inner_sender.clone()))?;
+            register_diag_handler(&mut http_server)?;
+            register_log_handler(&mut http_server)?;
+            Ok(http_server)
+        });
+        match result {
+            Ok(http_server) => self.ota_http_server = Some(http_server),
+            Err(e) => error!("Failed to start ota http server: {:?}", e),
+        }
+    }
+
+    fn on_ota_progress(&mut self, progress: OtaProgress) {
+        match &progress {
+            OtaProgress::Checking => {
+                info!("OTA: {:?}", progress);
+                if self.state == DeviceState::Idle {
+                    self.board
+                        .get_display()
+                        .set_status(lang::CHECKING_NEW_VERSION);
+                }
+            }
+            OtaProgress::UpToDate => {
+                info!("OTA: {:?}", progress);
+            }
+            OtaProgress::Downloading { version, speed, .. } => {
+                if self.state != DeviceState::Upgrading {
+                    self.set_device_state(DeviceState::Upgrading);
+                }
+                self.board
+                    .get_display()
+                    .show_upgrade_progress(version, progress.percent(), *speed);
+            }
+            OtaProgress::Verifying { version } | OtaProgress::Completed { version } => {
+                self.board
+                    .get_display()
+                    .show_upgrade_progress(version, Some(100), 0);
+            }
+            OtaProgress::RetryIn { seconds, reason } => {
+                // 下载失败后回到 Idle，倒计时期间仍然可以对话
+                if self.state == DeviceState::Upgrading {
+                    self.set_device_state(DeviceState::Idle);
+                }
+                if self.state == DeviceState::Idle {
+                    self.board
+                        .get_display()
+                        .set_status(&lang::check_new_version_failed(*seconds, reason));
+                }
+            }
+            OtaProgress::Failed(reason) => {
+                error!("OTA failed: {}", reason);
+                if self.state == DeviceState::Upgrading {
+                    self.set_device_state(DeviceState::Idle);
+                }
+                self.board.get_display().set_status(lang::UPGRADE_FAILED);
+            }
+        }
+    }
+
+    fn apply_power_transition(&mut self, transition: PowerTransition) {
+        info!("Power transition: {:?}", transition);
+        match transition {
+            PowerTransition::EnterModemSleep => {
+                if let Err(e) = self.board.set_power_save_mode(true) {
+                    error!("Failed to enable power save mode: {:?}", e);
+                }
+            }
+            PowerTransition::ExitModemSleep => {
+                if let Err(e) = self.board.set_power_save_mode(false) {
+                    error!("Failed to disable power save mode: {:?}", e);
+                }
+            }
+            PowerTransition::EnterLightSleep(max_duration) => {
+                if self.protocol.is_audio_channel_opened() {
+                    if let Err(e) = self.protocol.close_audio_channel() {
+                        error!("Failed to close audio channel: {:?}", e);
+                    }
+                }
+
+                let codec = self.board.get_audio_codec();
+                if let Err(e) = codec.lock().unwrap().suspend() {
+                    error!("Failed to suspend audio codec: {:?}", e);
+                }
+
+                // 有闹钟的话按时醒来，最少睡 1 秒
+                let alarm_due_in = self
+                    .scheduler
+                    .next_due_in()
+                    .map(|due_in| due_in.max(Duration::from_secs(1)));
+                let sleep_duration = match (max_duration, alarm_due_in) {
+                    (Some(max_duration), Some(due_in)) => Some(max_duration.min(due_in)),
+                    (max_duration, due_in) => max_duration.or(due_in),
+                };
+                let wake_source = match self.board.enter_light_sleep(sleep_duration) {
+                    Ok(wake_source) => wake_source,
+                    Err(e) => {
+                        error!("Failed to enter light sleep: {:?}", e);
+                        WakeSource::Other
+                    }
+                };
+                self.power_manager.on_wake(wake_source);
+                // 为闹钟醒来的不能接着进 deep sleep，要等调度线程把闹钟响起来
+                if wake_source == WakeSource::Timer && sleep_duration == alarm_due_in {
+                    self.power_manager.on_activity();
+                }
+
+                if let Err(e) = codec.lock().unwrap().resume() {
+                    error!("Failed to resume audio codec: {:?}", e);
+                }
+
+                // light sleep 期间 AP 可能已经把我们踢掉了，由 StationManager 在后台重连。
+                // 不能调用 start_network：它会阻塞事件循环，连不上时还会进入配网，
+                // 和重连线程同时操作同一个 EspWifi
+                if let Err(e) = self.board.resume_network() {
+                    error!("Failed to resume network: {:?}", e);
+                }
+            }
+            PowerTransition::EnterDeepSleep => {
+                if self.protocol.is_audio_channel_opened() {
+                    if let Err(e) = self.protocol.close_audio_channel() {
+                        error!("Failed to close audio channel: {:?}", e);
+                    }
+                }
+                if let Err(e) = self.board.get_audio_codec().lock().unwrap().suspend() {
+                    error!("Failed to suspend audio codec: {:?}", e);
+                }
+                if let Err(e) = self.board.enter_deep_sleep(self.scheduler.next_due_in()) {
+                    error!("Failed to enter deep sleep: {:?}", e);
+                }
+            }
+        }
+    }
+
+    /// 状态灯显示出错，`duration` 为 None 时一直显示到网络恢复
+    fn show_led_error(&self, duration: Option<Duration>) {
+        if let Some(led) = &self.led {
+            led.show_error(self.led_settings.error, duration);
+        }
     }
 
     fn set_listening_mode(&mut self, mode: ListeningMode) {
         self.listening_mode = mode;
         self.set_device_state(DeviceState::Listening);
     }
+
+    /// 双击 boot 键：在按住说话和自动模式之间切换
+    fn switch_listening_mode(&mut self) {
+        if self.state == DeviceState::Listening {
+            self.audio_processor.lock().unwrap().stop();
+            self.stop_listening();
+        }
+        self.push_to_talk = !self.push_to_talk;
+        info!("Push to talk: {}", self.push_to_talk);
+        self.board.get_display().set_status(if self.push_to_talk {
+            "按住说话"
+        } else {
+            "自动对话"
+        });
+        self.audio_alert("popup");
+    }
+
+    /// 按住说话：按下 boot 键开始录音，正在说话的话先打断
+    fn start_push_to_talk(&mut self) {
+        match self.state {
+            DeviceState::Idle => {
+                if !self.protocol.is_audio_channel_opened() {
+                    self.set_device_state(DeviceState::Connecting);
+                    if !self.protocol.open_audio_channel().unwrap_or(false) {
+                        return;
+                    }
+                }
+            }
+            DeviceState::Speaking => {
+                if let Err(e) = self.protocol.send_abort_speaking(AbortReason::None) {
+                    error!("Failed to send abort speaking: {:?}", e);
+                }
+                // 已经收到的 TTS 音频不再播放
+                self.reset_decoder();
+            }
+            _ => {
+                info!("Push to talk ignored, current state: {:?}", self.state);
+                return;
+            }
+        }
+        self.set_listening_mode(ListeningMode::Manual);
+    }
+
+    /// 按住说话：松开 boot 键停止录音。
+    /// 等编码线程把剩下的音频（包括不满一帧的部分）编码发送完，收到 `EncoderFlushed` 后再发 listen stop
+    fn stop_push_to_talk(&mut self) {
+        if self.state != DeviceState::Listening || self.listening_mode != ListeningMode::Manual {
+            return;
+        }
+        self.audio_processor.lock().unwrap().stop();
+        let flushing = match &self.encoder_input {
+            Some(encoder_input) => encoder_input.send(Vec::new()).is_ok(),
+            None => false,
+        };
+        if !flushing {
+            self.stop_listening();
+        }
+    }
+
+    /// 把待发送队列里编码好的音频发给服务器
+    fn send_queued_audio(&mut self) -> Result<()> {
+        let packets = {
+            let mut queue = self.audio_packet_queue.lock().unwrap();
+            // std::mem::take 会把 queue 换成默认值（空），并把原来的值返回
+            // 这完全等同于 C++ 的 std::move
+            std::mem::take(&mut *queue)
+        };
+
+        // 此时锁已经释放了
+        for packet in packets {
+            // info!("send audio packet using protocol!");
+            self.protocol.send_audio(&packet)?;
+        }
+        Ok(())
+    }
+
+    /// 长按电源键关机：在线时先让服务器说一句告别语，离线时播放本地提示音
+    ///
+    /// 告别语的 tts stop 之后，还要等喇叭播到播放队列里最后一个结束标记才关机，这里的定时器只是兜底
+    fn request_power_off(&mut self) {
+        if self.power_off_stage != PowerOffStage::None {
+            return;
+        }
+
+        let mut delay = Duration::from_secs(2);
+        if self.protocol.is_audio_channel_opened() {
+            if self.state == DeviceState::Speaking {
+                if let Err(e) = self.protocol.send_abort_speaking(AbortReason::None) {
+                    error!("Failed to send abort speaking: {:?}", e);
+                }
+            }
+            match self.protocol.send_wake_word_detected("再见") {
+                Ok(_) => {
+                    self.power_off_stage = PowerOffStage::WaitingFarewell;
+                    delay = Duration::from_secs(10);
+                }
+                Err(e) => {
+                    error!("Failed to send farewell: {:?}", e);
+                    self.play_power_off_alert();
+                }
+            }
+        } else {
+            self.play_power_off_alert();
+        }
+
+        let sender = self.inner_sender.clone();
+        if let Err(e) = thread::Builder::new()
+            .name("power_off_timer".into())
+            .stack_size(2 * 1024)
+            .spawn(move || {
+                thread::sleep(delay);
+                if let Err(e) = sender.send(AppEvent::PowerOff) {
+                    error!("Failed to send PowerOff event: {:?}", e);
+                }
+            })
+        {
+            error!("Failed to spawn power off timer: {:?}", e);
+        }
+    }
+
+    /// 离线时用本地提示音代替告别语，播完就关机
+    fn play_power_off_alert(&mut self) {
+        self.audio_alert("exclamation");
+        self.power_off_stage = PowerOffStage::Draining;
+        match self.inner_pcm_tx.send(Vec::new()) {
+            Ok(_) => self.playback_markers_pending += 1,
+            Err(e) => error!("Failed to queue playback marker: {:?}", e),
+        }
+    }
+
     fn toggle_device_state(&mut self) {
         match self.state {
             DeviceState::Activating => {
@@ -1093,6 +1908,10 @@ impl Application {
                 // self.enter_audio_testing_mode();
                 return;
             }
+            DeviceState::Upgrading => {
+                info!("Upgrading, ignore button");
+                return;
+            }
             // DeviceState::AudioTesting => {
             //     // self.exit_audio_testing_mode();
             //     return;
@@ -1250,6 +2069,7 @@ impl Application {
             .unwrap();
     }
 
+    #[cfg(target_os = "espidf")]
     fn play_p3_audio(&mut self, filename: &str) {
         // const P3_DATA: &'static [u8] = include_bytes!("../assets/zh-CN/wificonfig.p3");
         // const P3_DATA: &'static [u8] = include_bytes!(p3_file);
@@ -1262,7 +2082,11 @@ impl Application {
         let p3_data = match filename {
             "wificonfig" => Some(include_bytes!("../assets/zh-CN/wificonfig.p3").to_vec()),
             "welcome" => Some(include_bytes!("../assets/zh-CN/welcome.p3").to_vec()),
+            "upgrade" => Some(include_bytes!("../assets/zh-CN/upgrade.p3").to_vec()),
             "success" => Some(include_bytes!("../assets/common/success.p3").to_vec()),
+            "exclamation" => Some(include_bytes!("../assets/common/exclamation.p3").to_vec()),
+            "popup" => Some(include_bytes!("../assets/common/popup.p3").to_vec()),
+            "vibration" => Some(include_bytes!("../assets/common/vibration.p3").to_vec()),
             _ => None,
         };
 
@@ -1274,6 +2098,13 @@ impl Application {
         }
     }
 
+    /// 提示音是真的 opus 数据，host-sim 的解码器解不了，只打一行日志
+    #[cfg(not(target_os = "espidf"))]
+    fn play_p3_audio(&mut self, filename: &str) {
+        info!("Play alert: {}", filename);
+    }
+
+    #[cfg(target_os = "espidf")]
     fn play_p3_data(&mut self, p3_data: Vec<u8>) {
         const CHUNK_SIZE: usize = 4096;
 
@@ -1383,6 +2214,7 @@ fn audio_loop(
     // codec.set_output_volume(50);
     // let codec_arc = Arc::clone(&audio_codec);
     // let codec_arc1 = Arc::clone(&audio_codec);
+    let _task = diagnostics::register_current_task("audio_loop");
     let audio_processor_arc = Arc::clone(&audio_processor);
 
     let feed_size = audio_processor.lock().unwrap().get_feed_size();
@@ -1694,6 +2526,7 @@ fn decode_opus_audio(
     }
 }
 
+#[cfg(target_os = "espidf")]
 fn play_pcm_audio(mut i2s_driver: MutexGuard<'_, I2sDriver<'_, I2sBiDir>>, audio_data: &[u8]) {
     const CHUNK_SIZE: usize = 4096;
     for chunk in audio_data.chunks(CHUNK_SIZE) {
@@ -1712,6 +2545,7 @@ fn play_pcm_audio(mut i2s_driver: MutexGuard<'_, I2sDriver<'_, I2sBiDir>>, audio
     }
 }
 
+#[cfg(target_os = "espidf")]
 fn play_opus_audio(
     mut opus_decoder: Arc<Mutex<Box<OpusAudioDecoder>>>,
     mut i2s_driver: MutexGuard<'_, I2sDriver<'_, I2sBiDir>>,
@@ -1782,6 +2616,7 @@ fn run_audio_decode_task(
 
     let task_closure: Box<dyn FnOnce() + Send> = Box::new(move || {
         info!("Starting audio decode task!");
+        let _task = diagnostics::register_current_task("audio_decode");
         let mut opus_decoder = OpusAudioDecoder::new(
             sample_rate,
             channels,
@@ -1797,12 +2632,16 @@ fn run_audio_decode_task(
             match xz_event_rx.recv() {
                 Ok(event) => match event {
                     AppEvent::AudioPacketReceived(audio_packet) => {
-                        match decode_opus_audio(
+                        diagnostics::DECODE_PENDING.fetch_sub(1, Ordering::Relaxed);
+                        let started_at = Instant::now();
+                        let decoded = decode_opus_audio(
                             // codec.clone(),
                             &mut opus_decoder,
                             audio_packet.payload,
                             &mut shared_pcm_buffer,
-                        ) {
+                        );
+                        diagnostics::DECODE_TIMING.record(started_at);
+                        match decoded {
                             Ok(mut pcm_data) => {
                                 pcm_buffer.append(&mut pcm_data);
                                 if cached_packet_count < 10 {
@@ -1829,18 +2668,19 @@ fn run_audio_decode_task(
                     }
                     AppEvent::TTSStop => {
                         //把缓存里剩下的的PCM数据发送出去
-                        let cached_pcm = pcm_buffer.clone();
-                        match pcm_sender.send(cached_pcm) {
-                            Ok(_) => {
-                                cached_packet_count = 0;
-                                // tts_start = true;
-                                pcm_buffer.clear();
-                                // info!("Send pcm data success.");
-                            }
-                            Err(e) => {
+                        if !pcm_buffer.is_empty() {
+                            let cached_pcm = pcm_buffer.clone();
+                            if let Err(e) = pcm_sender.send(cached_pcm) {
                                 error!("Send decoded opus data(pcm data) error: {:?}", e);
                             }
                         }
+                        cached_packet_count = 0;
+                        // tts_start = true;
+                        pcm_buffer.clear();
+                        // 再放一个空包作为结束标记，喇叭播到这里时通知 application
+                        if let Err(e) = pcm_sender.send(Vec::new()) {
+                            error!("Send tts end marker error: {:?}", e);
+                        }
                     }
                     _ => {
                         info!("Received unhandled event: {:?}", event);
@@ -1853,30 +2693,12 @@ fn run_audio_decode_task(
         }
     });
 
-    info!("只装箱一次！");
-
-    let closure_box = Box::new(task_closure);
-    let closure_ptr = Box::into_raw(closure_box);
-
-    info!("try to call xTaskCreatePinnedToCore in the unsafe block");
-    unsafe {
-        let res = esp_idf_sys::xTaskCreatePinnedToCore(
-            Some(c_task_trampoline),
-            b"decode_task\0".as_ptr() as *const u8,
-            16 * 1024,
-            closure_ptr as *mut c_void,
-            5,
-            ptr::null_mut(),
-            1,
-        );
-        // if res != esp_idf_sys::pdPass {
-        //     // 如果创建失败，记得收回内存，否则会泄漏
-        //     let _ = Box::from_raw(closure_ptr);
-        //     error!("Failed to create task");
-        // }
+    if let Err(e) = spawn_pinned(c"decode_task", 16 * 1024, 5, 1, task_closure) {
+        error!("Failed to create audio decode task: {:?}", e);
     }
 }
 
+#[cfg(target_os = "espidf")]
 fn play_p3_audio(mut i2s_driver: MutexGuard<'_, I2sDriver<'_, I2sBiDir>>) {
     const P3_DATA: &'static [u8] = include_bytes!("../assets/activation.p3");
 
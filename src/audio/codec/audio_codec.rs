@@ -17,6 +17,10 @@ pub trait AudioCodec: Send {
 
     fn start(&mut self);
 
+    /// 进入睡眠前关闭 codec，唤醒后用 `resume` 恢复
+    fn suspend(&mut self) -> Result<(), Error>;
+    fn resume(&mut self) -> Result<(), Error>;
+
     fn read_audio_data(&mut self, buffer: &mut Vec<u8>) -> Result<usize, Error>;
 
     fn output_data(&mut self, data: &[u8]) -> Result<(), Error>;
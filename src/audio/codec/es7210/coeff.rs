@@ -341,3 +341,10 @@ static COEFF_DIV_TABLE: &[CoeffDiv] = &[
         lrckl: 0xc8,
     },
 ];
+
+/// 找到 MCLK 和采样率对应的分频系数，表里没有就返回 None
+pub fn get_coeff(mclk: u32, lrck: u32) -> Option<&'static CoeffDiv> {
+    COEFF_DIV_TABLE
+        .iter()
+        .find(|coeff| coeff.mclk == mclk && coeff.lrck == lrck)
+}
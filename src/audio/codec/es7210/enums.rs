@@ -1,3 +1,19 @@
+use bitflags::bitflags;
+
+use crate::audio::codec::es7210::reg::{
+    ES7210_INPUT_MIC1, ES7210_INPUT_MIC2, ES7210_INPUT_MIC3, ES7210_INPUT_MIC4,
+};
+
+bitflags! {
+    /// 麦克风输入，可以同时选中几个，bit 0 是 MIC1
+    pub struct Mics : u8 {
+        const MIC1 = ES7210_INPUT_MIC1;
+        const MIC2 = ES7210_INPUT_MIC2;
+        const MIC3 = ES7210_INPUT_MIC3;
+        const MIC4 = ES7210_INPUT_MIC4;
+    }
+}
+
 /// Represents the gain settings for the ES7210 microphone.
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 #[repr(u8)] // 告诉编译器用一个u8来表示这个enum
@@ -26,3 +42,66 @@ impl MicGain {
         *self as u8
     }
 }
+
+/// 串口数据格式（寄存器 0x11 的 SP_PROTOCAL 和 DSP 的 A/B 模式）
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SdpFormat {
+    I2s,
+    LeftJustified,
+    DspA,
+    DspB,
+}
+
+/// 麦克风偏置电压（寄存器 0x41/0x42 的 bit 6:4），VDDM 为 3.3V 时的值
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum MicBias {
+    Bias2_18v = 0,
+    Bias2_26v,
+    Bias2_36v,
+    Bias2_45v,
+    Bias2_55v,
+    Bias2_66v,
+    Bias2_78v,
+    Bias2_87v,
+}
+
+impl MicBias {
+    pub fn value(&self) -> u8 {
+        *self as u8
+    }
+}
+
+/// ES7210 的配置，`open()` 时写进芯片，之后也可以用各个 set 方法单独修改
+#[derive(Debug, Clone, PartialEq)]
+pub struct Es7210Config {
+    /// 选中的麦克风
+    pub mics: Mics,
+    /// 每个麦克风的 PGA 增益（dB），下标 0 是 MIC1，取不超过它的最近一档
+    pub gains_db: [f32; 4],
+    pub format: SdpFormat,
+    /// TDM 时 4 路 ADC 都从 SDOUT1 输出，按 MIC1 ~ MIC4 的顺序各占一个 slot；
+    /// 否则 SDOUT1 输出 MIC1/MIC2，SDOUT2 输出 MIC3/MIC4
+    pub tdm: bool,
+    /// 每个 slot 的位数：16、18、20、24 或 32
+    pub bits: u8,
+    pub sample_rate: u32,
+    /// MCLK 是采样率的多少倍，和采样率一起在 coeff.rs 的表里查分频系数
+    pub mclk_ratio: u32,
+    pub mic_bias: MicBias,
+}
+
+impl Default for Es7210Config {
+    fn default() -> Self {
+        Self {
+            mics: Mics::all(),
+            gains_db: [30.0, 30.0, 0.0, 0.0],
+            format: SdpFormat::I2s,
+            tdm: false,
+            bits: 16,
+            sample_rate: 16000,
+            mclk_ratio: 256,
+            mic_bias: MicBias::Bias2_87v,
+        }
+    }
+}
@@ -1,53 +1,77 @@
-use crate::audio::codec::es7210::enums::MicGain;
+use crate::audio::codec::es7210::coeff::get_coeff;
+use crate::audio::codec::es7210::enums::{Es7210Config, MicBias, MicGain, Mics, SdpFormat};
 use crate::audio::codec::es7210::reg::*;
 use crate::utils::bits::update_bit;
 use anyhow::Result;
 use embedded_hal::blocking::i2c::{Write, WriteRead};
-use esp_idf_hal::i2c::I2cError;
 use log::info;
 // 1. 在文件顶部引入 thiserror::Error
 use thiserror::Error;
 
-// 2. 定义一个新的、更强大的Error枚举
+// 2. 定义一个新的、更强大的Error枚举，`E` 是 I2C 总线的错误类型
 #[derive(Error, Debug)]
-pub enum Error {
-    #[error("Underlying I2C communication error")]
-    I2c(#[from] I2cError), // <-- #[from] 属性会自动实现 From<E> for Error<E>
+pub enum Error<E> {
+    #[error("Underlying I2C communication error: {0:?}")]
+    I2c(E),
 
     #[error("Invalid microphone input mode provided")] // <-- #[error] 属性提供了人类可读的描述
     InvalidMicInputMode,
+
+    #[error("Unsupported sample rate {rate}Hz with MCLK {mclk}Hz")]
+    UnsupportedSampleRate { mclk: u32, rate: u32 },
+
+    #[error("Unsupported bits per sample {0}")]
+    UnsupportedBits(u8),
+}
+
+impl<E> From<E> for Error<E> {
+    fn from(e: E) -> Self {
+        Error::I2c(e)
+    }
 }
 
 const ADDR: u8 = 0x41;
 
 pub struct Es7210<I2C> {
     i2c: I2C,
+    addr: u8,
     is_open: bool,
     enabled: bool,
     clock_off_status: u8,
-    input_mics: u8,
+    config: Es7210Config,
 }
 
-impl<I2C> Es7210<I2C>
+impl<I2C, E> Es7210<I2C>
 where
-    I2C: Write<Error = I2cError> + WriteRead<Error = I2cError>,
+    I2C: Write<Error = E> + WriteRead<Error = E>,
 {
     /// 创建一个新的ES7210驱动实例
     pub fn new(i2c: I2C) -> Self {
+        Self::with_address(i2c, ADDR)
+    }
+
+    /// 使用指定的 I2C 地址（由 AD0/AD1 脚决定，0x40~0x43）
+    pub fn with_address(i2c: I2C, addr: u8) -> Self {
+        Self::with_config(i2c, addr, Es7210Config::default())
+    }
+
+    /// 使用指定的配置，`open()` 时写进芯片
+    pub fn with_config(i2c: I2C, addr: u8, config: Es7210Config) -> Self {
         Self {
             i2c,
+            addr,
             is_open: false,
             enabled: false,
             clock_off_status: 0,
-            // input_mics: ES7210_INPUT_MIC1 | ES7210_INPUT_MIC2,
-            input_mics: ES7210_INPUT_MIC1
-                | ES7210_INPUT_MIC2
-                | ES7210_INPUT_MIC3
-                | ES7210_INPUT_MIC4,
+            config,
         }
     }
 
-    pub fn open(&mut self) -> Result<(), Error> {
+    pub fn config(&self) -> &Es7210Config {
+        &self.config
+    }
+
+    pub fn open(&mut self) -> Result<(), Error<E>> {
         // ret |= es7210_write_reg(codec, ES7210_RESET_REG00, 0xff);//0xff=11111111b. Reset all registers.
         // ret |= es7210_write_reg(codec, ES7210_RESET_REG00, 0x41); //0x41=01000001b. reset master mode LRCK and SCLK,
         self.write_reg(ES7210_RESET_REG_00, 0xff)?; // 复位数字部分
@@ -86,20 +110,19 @@ where
         // ret |= es7210_write_reg(codec, ES7210_MIC34_BIAS_REG42, 0x70); /* Select 2.87v */
         // ret |= es7210_write_reg(codec, ES7210_OSR_REG07, 0x20);
         self.write_reg(ES7210_ANALOG_REG_40, 0x43)?; // 0x43=0b01000011
-        self.write_reg(ES7210_ANALOG_REG_41, 0x70)?; // 0x70=0b01110000. Select 2.87v
-        self.write_reg(ES7210_ANALOG_REG_42, 0x70)?; // 0x70=0b01110000. Select 2.87v
-        self.write_reg(ES7210_OSR_REG_07, 0x20)?;
+        self.set_mic_bias(self.config.mic_bias)?; // 默认 2.87v
+        let config = self.config.clone();
+        self.set_format(config.format, config.tdm, config.bits)?;
 
         /* Set the frequency division coefficient and use dll except clock doubler, and need to set 0xc1 to clear the state */
-        // ret |= es7210_write_reg(codec, ES7210_MAINCLK_REG02, 0xc1);
-        self.write_reg(ES7210_MAINCLK_REG_02, 0xc1)?; // 0xc1=0b11000001.reg 0x02:
-                                                      // 7: 1 – bypass DLL 0 – not bypass DLL
-                                                      // 6: 1 – use clock doubler 0 – not use clock doubler;
-                                                      // 5: Reserved
-                                                      // 4:0 ADC clock divide 0/1 – no divide 2 – divide by 2
-
-        self.mic_select(self.input_mics)?;
-        self.set_channel_gain(self.input_mics, 0x0F, 30.0)?;
+        // 16k、256 倍 MCLK 时 reg 0x02 是 0xc1=0b11000001：
+        // 7: 1 – bypass DLL 0 – not bypass DLL
+        // 6: 1 – use clock doubler 0 – not use clock doubler;
+        // 5: Reserved
+        // 4:0 ADC clock divide 0/1 – no divide 2 – divide by 2
+        self.set_sample_rate(config.sample_rate, config.mclk_ratio)?;
+
+        self.mic_select(config.mics)?;
         self.write_reg(ES7210_ANALOG_REG_40, 0x43)?;
 
         //把clcok off的设置保存起来,在enbale的时候需要用到。
@@ -108,7 +131,7 @@ where
         Ok(())
     }
 
-    pub fn close(&mut self) -> Result<(), Error> {
+    pub fn close(&mut self) -> Result<(), Error<E>> {
         if self.is_open {
             self.disable()?;
             self.is_open = false;
@@ -116,7 +139,7 @@ where
         Ok(())
     }
 
-    pub fn start(&mut self) -> Result<(), Error> {
+    pub fn start(&mut self) -> Result<(), Error<E>> {
         // int ret = 0;
         // ret |= es7210_write_reg(codec, ES7210_CLOCK_OFF_REG01, clock_reg_value);
         // ret |= es7210_write_reg(codec, ES7210_POWER_DOWN_REG06, 0x00);
@@ -138,7 +161,7 @@ where
         self.write_reg(ES7210_MIC3_POWER_REG_49, 0x08)?;
         self.write_reg(ES7210_MIC4_POWER_REG_4A, 0x08)?;
 
-        self.mic_select(self.input_mics)?;
+        self.mic_select(self.config.mics)?;
 
         // self.write_reg(ES7210_ANALOG_REG_40, 0x43)?;
 
@@ -148,7 +171,7 @@ where
         Ok(())
     }
 
-    pub fn stop(&mut self) -> Result<(), Error> {
+    pub fn stop(&mut self) -> Result<(), Error<E>> {
         // ret |= es7210_write_reg(codec, ES7210_MIC1_POWER_REG47, 0xff);
         // ret |= es7210_write_reg(codec, ES7210_MIC2_POWER_REG48, 0xff);
         // ret |= es7210_write_reg(codec, ES7210_MIC3_POWER_REG49, 0xff);
@@ -172,16 +195,15 @@ where
         Ok(())
     }
 
-    pub fn enable(&mut self) -> Result<(), Error> {
+    pub fn enable(&mut self) -> Result<(), Error<E>> {
         self.start()?;
-        self.set_channel_gain(3, 0x0F, 30.0)?;
 
         self.enabled = true;
 
         Ok(())
     }
 
-    pub fn disable(&mut self) -> Result<(), Error> {
+    pub fn disable(&mut self) -> Result<(), Error<E>> {
         self.stop()?;
         self.enabled = false;
         Ok(())
@@ -206,7 +228,7 @@ where
         // }
         // return (mic_num >= ENABLE_TDM_MAX_NUM);
 
-        return false; //我先用std channel来测试。
+        self.config.tdm
 
         // let mut mic_num = 0;
         // for i in 1..=4 {
@@ -220,7 +242,7 @@ where
     /// 设置工作模式为master
     /// 参数：
     /// master_mode: true为master模式，false为slave模式
-    fn set_master_mode(&mut self, master_mode: bool) -> Result<(), Error> {
+    fn set_master_mode(&mut self, master_mode: bool) -> Result<(), Error<E>> {
         // if (codec_cfg->master_mode) {
         //     ESP_LOGI(TAG, "Work in Master mode");
         //     ret |= es7210_update_reg_bit(codec, ES7210_MODE_CONFIG_REG08, 0x01, 0x01);
@@ -252,22 +274,107 @@ where
     }
 
     /// Reads a single byte from a register.
-    pub fn read_reg(&mut self, reg: u8) -> Result<u8, Error> {
+    pub fn read_reg(&mut self, reg: u8) -> Result<u8, Error<E>> {
         let mut byte: [u8; 1] = [0; 1];
 
-        match self.i2c.write_read(ADDR, &[reg], &mut byte) {
+        match self.i2c.write_read(self.addr, &[reg], &mut byte) {
             Ok(_) => Ok(byte[0]),
             Err(e) => Err(Error::I2c(e)),
         }
     }
 
+    /// 设置麦克风的增益（dB），`mics` 可以同时选几个。
+    /// 没有选中的麦克风只记下来，选中时再写进芯片
+    pub fn set_mic_gain(&mut self, mics: Mics, db: f32) -> Result<(), Error<E>> {
+        for i in 0..4 {
+            if mics.bits() & (1 << i) != 0 {
+                self.config.gains_db[i] = db;
+            }
+        }
+        self.set_channel_gain(mics & self.config.mics, 0x0F, db)
+    }
+
+    /// 选择要打开的麦克风，没选中的关掉电源和时钟
+    pub fn select_mics(&mut self, mics: Mics) -> Result<(), Error<E>> {
+        self.config.mics = mics;
+        self.mic_select(mics)
+    }
+
+    /// 设置采样率和 MCLK 的倍数，分频系数从 coeff.rs 的表里查，表里没有就返回错误
+    pub fn set_sample_rate(&mut self, sample_rate: u32, mclk_ratio: u32) -> Result<(), Error<E>> {
+        // 两个参数都可能来自设置，乘出来溢出的话也当作不支持
+        let mclk = sample_rate.checked_mul(mclk_ratio);
+        let Some(coeff) = mclk.and_then(|mclk| get_coeff(mclk, sample_rate)) else {
+            return Err(Error::UnsupportedSampleRate {
+                mclk: mclk.unwrap_or(u32::MAX),
+                rate: sample_rate,
+            });
+        };
+        self.config.sample_rate = sample_rate;
+        self.config.mclk_ratio = mclk_ratio;
+
+        // reg 0x02: bit 7 是 DLL，bit 6 是 clock doubler，bit 4:0 是 ADC 时钟分频
+        let regv = coeff.adc_div | (coeff.doubler << 6) | (coeff.dll << 7);
+        self.write_reg(ES7210_MAINCLK_REG_02, regv)?;
+        self.write_reg(ES7210_OSR_REG_07, coeff.osr)?;
+        self.write_reg(ES7210_LRCK_DIVH_REG_04, coeff.lrckh)?;
+        self.write_reg(ES7210_LRCK_DIVL_REG_05, coeff.lrckl)?;
+        // reg 0x08 bit 1: 0 – single speed，1 – double speed
+        self.update_reg_bit(ES7210_MODE_CONFIG_REG_08, 0x02, coeff.ss_ds << 1)?;
+        Ok(())
+    }
+
+    /// 设置串口的数据格式、是否 TDM 和每个 slot 的位数
+    pub fn set_format(&mut self, format: SdpFormat, tdm: bool, bits: u8) -> Result<(), Error<E>> {
+        // reg 0x11: bit 7:5 是 SP_WL，bit 4 在 DSP 模式下选择 A/B，bit 1:0 是 SP_PROTOCAL
+        let word_length = match bits {
+            16 => 0x60,
+            18 => 0x40,
+            20 => 0x20,
+            24 => 0x00,
+            32 => 0x80,
+            _ => return Err(Error::UnsupportedBits(bits)),
+        };
+        let protocol = match format {
+            SdpFormat::I2s => 0x00,
+            SdpFormat::LeftJustified => 0x01,
+            SdpFormat::DspA => 0x03,
+            SdpFormat::DspB => 0x13,
+        };
+        self.config.format = format;
+        self.config.tdm = tdm;
+        self.config.bits = bits;
+        self.update_reg_bit(ES7210_SDP_INTERFACE1_REG_11, 0xF3, word_length | protocol)?;
+
+        // reg 0x12 bit 1:0: 00 – ADC12 到 SDOUT1、ADC34 到 SDOUT2，01 – TDM DSP/PCM，10 – TDM I2S/左对齐
+        let sdout_mode = match (tdm, format) {
+            (false, _) => 0x00,
+            (true, SdpFormat::DspA | SdpFormat::DspB) => 0x01,
+            (true, _) => 0x02,
+        };
+        self.update_reg_bit(ES7210_SDP_INTERFACE2_REG_12, 0x03, sdout_mode)
+    }
+
+    /// 设置 4 个麦克风共用的偏置电压
+    pub fn set_mic_bias(&mut self, bias: MicBias) -> Result<(), Error<E>> {
+        self.config.mic_bias = bias;
+        self.write_reg(ES7210_ANALOG_REG_41, bias.value() << 4)?;
+        self.write_reg(ES7210_ANALOG_REG_42, bias.value() << 4)?;
+        Ok(())
+    }
+
     //private methods
     /// 向一个寄存器写入一个字节
-    fn write_reg(&mut self, reg: u8, value: u8) -> Result<(), Error> {
-        self.i2c.write(ADDR, &[reg, value]).map_err(Error::I2c)
+    fn write_reg(&mut self, reg: u8, value: u8) -> Result<(), Error<E>> {
+        self.i2c.write(self.addr, &[reg, value]).map_err(Error::I2c)
     }
 
-    fn set_channel_gain(&mut self, input_mics: u8, channel_mask: u8, db: f32) -> Result<(), Error> {
+    fn set_channel_gain(
+        &mut self,
+        input_mics: Mics,
+        channel_mask: u8,
+        db: f32,
+    ) -> Result<(), Error<E>> {
         //     int ret = 0;
         //     es7210_gain_value_t gain = get_db(db);
         //     if ((codec->mic_select & ES7210_INPUT_MIC1) & (channel_mask & ESP_CODEC_DEV_MAKE_CHANNEL_MASK(0)))
@@ -288,22 +395,22 @@ where
         //     }
         //     return ret == 0 ? ESP_CODEC_DEV_OK : ESP_CODEC_DEV_WRITE_FAIL;
         let gain = self.get_db(db);
-        if (input_mics & ES7210_INPUT_MIC1) & (channel_mask & self.make_channel_mask(0)) != 0 {
+        if input_mics.contains(Mics::MIC1) && channel_mask & self.make_channel_mask(0) != 0 {
             info!("set mic1 gain to {:08b}", gain);
             self.update_reg_bit(ES7210_MIC1_GAIN_REG_43, 0x0f, gain)?;
         }
 
-        if (input_mics & ES7210_INPUT_MIC2) & (channel_mask & self.make_channel_mask(1)) != 0 {
+        if input_mics.contains(Mics::MIC2) && channel_mask & self.make_channel_mask(1) != 0 {
             info!("set mic2 gain to {:08b}", gain);
             self.update_reg_bit(ES7210_MIC2_GAIN_REG_44, 0x0f, gain)?;
         }
 
-        if (input_mics & ES7210_INPUT_MIC3) & (channel_mask & self.make_channel_mask(2)) != 0 {
+        if input_mics.contains(Mics::MIC3) && channel_mask & self.make_channel_mask(2) != 0 {
             info!("set mic3 gain to {:08b}", gain);
             self.update_reg_bit(ES7210_MIC3_GAIN_REG_45, 0x0f, gain)?;
         }
 
-        if (input_mics & ES7210_INPUT_MIC4) & (channel_mask & self.make_channel_mask(3)) != 0 {
+        if input_mics.contains(Mics::MIC4) && channel_mask & self.make_channel_mask(3) != 0 {
             info!("set mic4 gain to {:08b}", gain);
             self.update_reg_bit(ES7210_MIC4_GAIN_REG_46, 0x0f, gain)?;
         }
@@ -333,11 +440,8 @@ where
         return MicGain::Gain37_5db.value();
     }
 
-    fn mic_select(&mut self, input_mics: u8) -> Result<(), Error> {
-        if (input_mics
-            & (ES7210_INPUT_MIC1 | ES7210_INPUT_MIC2 | ES7210_INPUT_MIC3 | ES7210_INPUT_MIC4))
-            != 0
-        {
+    fn mic_select(&mut self, input_mics: Mics) -> Result<(), Error<E>> {
+        if !input_mics.is_empty() {
             // es7210_update_reg_bit(codec, ES7210_MIC1_GAIN_REG43 + i, 0x10, 0x00);
             self.update_reg_bit(ES7210_MIC1_GAIN_REG_43, 0x10, 0x00)?; //把第4位置为0，deselect MIC1,下面三行依次deselect MIC2 MIC3 MIC4
             self.update_reg_bit(ES7210_MIC2_GAIN_REG_44, 0x10, 0x00)?;
@@ -353,7 +457,7 @@ where
             //     ret |= es7210_update_reg_bit(codec, ES7210_MIC1_GAIN_REG43, 0x0f, codec->gain);
             // }
 
-            if (input_mics & ES7210_INPUT_MIC1) != 0 {
+            if input_mics.contains(Mics::MIC1) {
                 info!(target: "ES7210", "Enable ES7210_INPUT_MIC1");
                 self.update_reg_bit(ES7210_CLOCK_OFF_REG_01, 0x0b, 0x00)?; //0x0b=0b1011,
                                                                            //turn on master clock
@@ -361,10 +465,11 @@ where
                                                                            //turn on ADC12 master clock
                 self.write_reg(ES7210_MIC12_POWER_DOWN_REG_4B, 0x00)?; //打开mic1,mic2的电源
                 self.update_reg_bit(ES7210_MIC1_GAIN_REG_43, 0x10, 0x10)?;
-                self.update_reg_bit(ES7210_MIC1_GAIN_REG_43, 0x0f, 0)?;
+                let gain = self.get_db(self.config.gains_db[0]);
+                self.update_reg_bit(ES7210_MIC1_GAIN_REG_43, 0x0f, gain)?;
             }
 
-            if (input_mics & ES7210_INPUT_MIC2) != 0 {
+            if input_mics.contains(Mics::MIC2) {
                 info!(target: "ES7210", "Enable ES7210_INPUT_MIC2");
                 self.update_reg_bit(ES7210_CLOCK_OFF_REG_01, 0x0b, 0x00)?; //0x0b=0b1011,
                                                                            //turn on master clock
@@ -372,10 +477,11 @@ where
                                                                            //turn on ADC12 master clock
                 self.write_reg(ES7210_MIC12_POWER_DOWN_REG_4B, 0x00)?; //打开mic1,mic2的电源
                 self.update_reg_bit(ES7210_MIC2_GAIN_REG_44, 0x10, 0x10)?;
-                self.update_reg_bit(ES7210_MIC2_GAIN_REG_44, 0x0f, 0)?;
+                let gain = self.get_db(self.config.gains_db[1]);
+                self.update_reg_bit(ES7210_MIC2_GAIN_REG_44, 0x0f, gain)?;
             }
 
-            if (input_mics & ES7210_INPUT_MIC3) != 0 {
+            if input_mics.contains(Mics::MIC3) {
                 info!(target: "ES7210", "Enable ES7210_INPUT_MIC3");
                 // ret |= es7210_update_reg_bit(codec, ES7210_CLOCK_OFF_REG01, 0x15, 0x00);
                 // ret |= es7210_write_reg(codec, ES7210_MIC34_POWER_REG4C, 0x00);
@@ -385,10 +491,11 @@ where
                                                                            //turn on ADC34 master clock
                 self.write_reg(ES7210_MIC34_POWER_DOWN_REG_4C, 0x00)?; //打开MIC3/4的电源
                 self.update_reg_bit(ES7210_MIC3_GAIN_REG_45, 0x10, 0x10)?;
-                self.update_reg_bit(ES7210_MIC3_GAIN_REG_45, 0x0f, 0)?;
+                let gain = self.get_db(self.config.gains_db[2]);
+                self.update_reg_bit(ES7210_MIC3_GAIN_REG_45, 0x0f, gain)?;
             }
 
-            if (input_mics & ES7210_INPUT_MIC4) != 0 {
+            if input_mics.contains(Mics::MIC4) {
                 info!(target: "ES7210", "Enable ES7210_INPUT_MIC4");
                 self.update_reg_bit(ES7210_CLOCK_OFF_REG_01, 0x15, 0x00)?; //0x15=0b00010101
                                                                            //turn on master clock
@@ -396,7 +503,8 @@ where
                                                                            //turn on ADC34 master clock
                 self.write_reg(ES7210_MIC34_POWER_DOWN_REG_4C, 0x00)?; //打开MIC3/4的电源
                 self.update_reg_bit(ES7210_MIC4_GAIN_REG_46, 0x10, 0x10)?;
-                self.update_reg_bit(ES7210_MIC4_GAIN_REG_46, 0x0f, 0)?;
+                let gain = self.get_db(self.config.gains_db[3]);
+                self.update_reg_bit(ES7210_MIC4_GAIN_REG_46, 0x0f, gain)?;
             }
         } else {
             return Err(Error::InvalidMicInputMode);
@@ -411,15 +519,14 @@ where
 
         if self.is_tdm_mode() {
             info!(target: "ES7210", "Enable TDM mode");
-            self.write_reg(ES7210_SDP_INTERFACE2_REG_12, 0x02)?;
-        } else {
-            self.write_reg(ES7210_SDP_INTERFACE2_REG_12, 0x00)?;
         }
+        let config = self.config.clone();
+        self.set_format(config.format, config.tdm, config.bits)?;
 
         Ok(())
     }
 
-    fn update_reg_bit(&mut self, reg_addr: u8, update_bits: u8, data: u8) -> Result<(), Error> {
+    fn update_reg_bit(&mut self, reg_addr: u8, update_bits: u8, data: u8) -> Result<(), Error<E>> {
         //  int regv = 0;
         // es7210_read_reg(codec, reg_addr, &regv);
         // regv = (regv & (~update_bits)) | (update_bits & data);
@@ -433,3 +540,167 @@ where
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::recording_i2c::{I2cOp, RecordingI2c};
+
+    fn codec(mics: Mics) -> (Es7210<RecordingI2c>, RecordingI2c) {
+        let i2c = RecordingI2c::new();
+        let config = Es7210Config {
+            mics,
+            ..Default::default()
+        };
+        (Es7210::with_config(i2c.clone(), ADDR, config), i2c)
+    }
+
+    #[test]
+    fn format_word_length_and_protocol() {
+        let (mut codec, i2c) = codec(Mics::all());
+        // 0x11 的 bit 3:2 和 0x12 的 bit 7:2 不归 set_format 管
+        i2c.set_register(ADDR, ES7210_SDP_INTERFACE1_REG_11, 0x0C);
+        i2c.set_register(ADDR, ES7210_SDP_INTERFACE2_REG_12, 0xFC);
+
+        codec.set_format(SdpFormat::I2s, false, 16).unwrap();
+        assert_eq!(
+            i2c.writes(ADDR),
+            [
+                (ES7210_SDP_INTERFACE1_REG_11, 0x6C),
+                (ES7210_SDP_INTERFACE2_REG_12, 0xFC),
+            ]
+        );
+
+        // DSP-B 是 SP_PROTOCAL=11 加上 bit 4，TDM 时 SDOUT 选 DSP/PCM
+        i2c.clear();
+        codec.set_format(SdpFormat::DspB, true, 24).unwrap();
+        assert_eq!(
+            i2c.writes(ADDR),
+            [
+                (ES7210_SDP_INTERFACE1_REG_11, 0x1F),
+                (ES7210_SDP_INTERFACE2_REG_12, 0xFD),
+            ]
+        );
+
+        i2c.clear();
+        codec
+            .set_format(SdpFormat::LeftJustified, true, 32)
+            .unwrap();
+        assert_eq!(
+            i2c.writes(ADDR),
+            [
+                (ES7210_SDP_INTERFACE1_REG_11, 0x8D),
+                (ES7210_SDP_INTERFACE2_REG_12, 0xFE),
+            ]
+        );
+        assert!(codec.is_tdm_mode());
+    }
+
+    #[test]
+    fn unsupported_bits_does_not_touch_registers() {
+        let (mut codec, i2c) = codec(Mics::all());
+        let err = codec.set_format(SdpFormat::DspA, true, 17).unwrap_err();
+        assert!(matches!(err, Error::UnsupportedBits(17)));
+        assert!(i2c.ops().is_empty());
+        assert_eq!(codec.config().bits, 16);
+        assert!(!codec.is_tdm_mode());
+    }
+
+    #[test]
+    fn sample_rate_16k_256fs() {
+        let (mut codec, i2c) = codec(Mics::all());
+        codec.set_sample_rate(16000, 256).unwrap();
+        // MCLK 4.096MHz：DLL + clock doubler，ADC 不分频，OSR 0x20，LRCK 256 分频
+        assert_eq!(
+            i2c.writes(ADDR),
+            [
+                (ES7210_MAINCLK_REG_02, 0xC1),
+                (ES7210_OSR_REG_07, 0x20),
+                (ES7210_LRCK_DIVH_REG_04, 0x01),
+                (ES7210_LRCK_DIVL_REG_05, 0x00),
+                (ES7210_MODE_CONFIG_REG_08, 0x00),
+            ]
+        );
+    }
+
+    #[test]
+    fn unsupported_sample_rate() {
+        let (mut codec, i2c) = codec(Mics::all());
+
+        let err = codec.set_sample_rate(16000, 300).unwrap_err();
+        assert!(matches!(
+            err,
+            Error::UnsupportedSampleRate {
+                mclk: 4_800_000,
+                rate: 16000
+            }
+        ));
+
+        // 乘法溢出也是不支持，不能 panic
+        let err = codec.set_sample_rate(u32::MAX, 256).unwrap_err();
+        assert!(matches!(
+            err,
+            Error::UnsupportedSampleRate {
+                mclk: u32::MAX,
+                rate: u32::MAX
+            }
+        ));
+
+        assert!(i2c.ops().is_empty());
+        assert_eq!(codec.config().sample_rate, 16000);
+        assert_eq!(codec.config().mclk_ratio, 256);
+    }
+
+    #[test]
+    fn mic_gain_only_written_for_selected_mics() {
+        let (mut codec, i2c) = codec(Mics::MIC1 | Mics::MIC2);
+        // bit 4 是选中麦克风，不能被改掉
+        i2c.set_register(ADDR, ES7210_MIC1_GAIN_REG_43, 0x10);
+
+        // 12dB 是第 4 档
+        codec.set_mic_gain(Mics::MIC1 | Mics::MIC3, 12.0).unwrap();
+        assert_eq!(
+            i2c.ops(),
+            [
+                I2cOp::Read {
+                    addr: ADDR,
+                    reg: ES7210_MIC1_GAIN_REG_43,
+                    len: 1
+                },
+                I2cOp::Write {
+                    addr: ADDR,
+                    reg: ES7210_MIC1_GAIN_REG_43,
+                    data: vec![0x14]
+                },
+            ]
+        );
+        assert_eq!(codec.config().gains_db, [12.0, 30.0, 12.0, 0.0]);
+
+        // MIC3 的增益记下来了，选中时写进去
+        i2c.clear();
+        codec.select_mics(Mics::MIC3).unwrap();
+        assert_eq!(i2c.register(ADDR, ES7210_MIC1_GAIN_REG_43), 0x04);
+        assert_eq!(i2c.register(ADDR, ES7210_MIC3_GAIN_REG_45), 0x14);
+        let writes = i2c.writes(ADDR);
+        assert!(!writes.contains(&(ES7210_MIC12_POWER_DOWN_REG_4B, 0x00)));
+        assert!(writes.contains(&(ES7210_MIC34_POWER_DOWN_REG_4C, 0x00)));
+    }
+
+    #[test]
+    fn selecting_no_mic_is_an_error() {
+        let (mut codec, _i2c) = codec(Mics::all());
+        let err = codec.select_mics(Mics::empty()).unwrap_err();
+        assert!(matches!(err, Error::InvalidMicInputMode));
+    }
+
+    #[test]
+    fn gain_rounds_to_nearest_step() {
+        let (mut codec, _i2c) = codec(Mics::all());
+        assert_eq!(codec.get_db(0.0), MicGain::Gain0db.value());
+        assert_eq!(codec.get_db(2.4), MicGain::Gain0db.value());
+        assert_eq!(codec.get_db(30.0), MicGain::Gain30db.value());
+        assert_eq!(codec.get_db(34.5), MicGain::Gain34_5db.value());
+        assert_eq!(codec.get_db(36.0), MicGain::Gain36db.value());
+        assert_eq!(codec.get_db(50.0), MicGain::Gain37_5db.value());
+    }
+}
@@ -1,6 +1,8 @@
 pub const ES7210_RESET_REG_00: u8 = 0x00;
 pub const ES7210_CLOCK_OFF_REG_01: u8 = 0x01;
 pub const ES7210_MAINCLK_REG_02: u8 = 0x02;
+pub const ES7210_LRCK_DIVH_REG_04: u8 = 0x04;
+pub const ES7210_LRCK_DIVL_REG_05: u8 = 0x05;
 pub const ES7210_POWER_DOWN_REG_06: u8 = 0x06;
 pub const ES7210_OSR_REG_07: u8 = 0x07;
 pub const ES7210_MODE_CONFIG_REG_08: u8 = 0x08;
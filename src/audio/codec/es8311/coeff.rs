@@ -0,0 +1,317 @@
+/// 一组时钟分频系数，对应手册里的 0x02~0x08 寄存器。
+/// 内部时钟 dig_mclk = mclk * pre_multi / pre_div，这张表里都配成 256fs，
+/// ADC/DAC 的 OSR 取 0x10（256 倍），LRCK 和 BCLK 都从 dig_mclk 分频得到
+#[derive(Debug, Clone, Copy)]
+pub struct CoeffDiv {
+    pub mclk: u32,
+    pub rate: u32,
+    /// MCLK 预分频，1~8
+    pub pre_div: u8,
+    /// MCLK 倍频，寄存器值：0 = x1，1 = x2，2 = x4，3 = x8
+    pub pre_multi: u8,
+    /// ADC 时钟分频，1~16
+    pub adc_div: u8,
+    /// DAC 时钟分频，1~16
+    pub dac_div: u8,
+    /// 0 = single speed，1 = double speed
+    pub fs_mode: u8,
+    /// LRCK 分频，dig_mclk / lrck_div = 采样率
+    pub lrck_div: u16,
+    /// BCLK 分频，dig_mclk / bclk_div = BCLK（从模式下不用）
+    pub bclk_div: u8,
+    pub adc_osr: u8,
+    pub dac_osr: u8,
+}
+
+/// A static lookup table containing pre-calculated clock divider settings
+/// for various MCLK and sample rate combinations.
+static COEFF_DIV_TABLE: &[CoeffDiv] = &[
+    /* 8k */
+    CoeffDiv {
+        mclk: 2048000,
+        rate: 8000,
+        pre_div: 1,
+        pre_multi: 0,
+        adc_div: 1,
+        dac_div: 1,
+        fs_mode: 0,
+        lrck_div: 256,
+        bclk_div: 4,
+        adc_osr: 0x10,
+        dac_osr: 0x10,
+    },
+    CoeffDiv {
+        mclk: 3072000,
+        rate: 8000,
+        pre_div: 3,
+        pre_multi: 1,
+        adc_div: 1,
+        dac_div: 1,
+        fs_mode: 0,
+        lrck_div: 256,
+        bclk_div: 4,
+        adc_osr: 0x10,
+        dac_osr: 0x10,
+    },
+    CoeffDiv {
+        mclk: 12288000,
+        rate: 8000,
+        pre_div: 6,
+        pre_multi: 0,
+        adc_div: 1,
+        dac_div: 1,
+        fs_mode: 0,
+        lrck_div: 256,
+        bclk_div: 4,
+        adc_osr: 0x10,
+        dac_osr: 0x10,
+    },
+    /* 11.025k */
+    CoeffDiv {
+        mclk: 2822400,
+        rate: 11025,
+        pre_div: 1,
+        pre_multi: 0,
+        adc_div: 1,
+        dac_div: 1,
+        fs_mode: 0,
+        lrck_div: 256,
+        bclk_div: 4,
+        adc_osr: 0x10,
+        dac_osr: 0x10,
+    },
+    CoeffDiv {
+        mclk: 4233600,
+        rate: 11025,
+        pre_div: 3,
+        pre_multi: 1,
+        adc_div: 1,
+        dac_div: 1,
+        fs_mode: 0,
+        lrck_div: 256,
+        bclk_div: 4,
+        adc_osr: 0x10,
+        dac_osr: 0x10,
+    },
+    CoeffDiv {
+        mclk: 11289600,
+        rate: 11025,
+        pre_div: 4,
+        pre_multi: 0,
+        adc_div: 1,
+        dac_div: 1,
+        fs_mode: 0,
+        lrck_div: 256,
+        bclk_div: 4,
+        adc_osr: 0x10,
+        dac_osr: 0x10,
+    },
+    /* 16k */
+    CoeffDiv {
+        mclk: 4096000,
+        rate: 16000,
+        pre_div: 1,
+        pre_multi: 0,
+        adc_div: 1,
+        dac_div: 1,
+        fs_mode: 0,
+        lrck_div: 256,
+        bclk_div: 4,
+        adc_osr: 0x10,
+        dac_osr: 0x10,
+    },
+    CoeffDiv {
+        mclk: 6144000,
+        rate: 16000,
+        pre_div: 3,
+        pre_multi: 1,
+        adc_div: 1,
+        dac_div: 1,
+        fs_mode: 0,
+        lrck_div: 256,
+        bclk_div: 4,
+        adc_osr: 0x10,
+        dac_osr: 0x10,
+    },
+    CoeffDiv {
+        mclk: 12288000,
+        rate: 16000,
+        pre_div: 3,
+        pre_multi: 0,
+        adc_div: 1,
+        dac_div: 1,
+        fs_mode: 0,
+        lrck_div: 256,
+        bclk_div: 4,
+        adc_osr: 0x10,
+        dac_osr: 0x10,
+    },
+    /* 22.05k */
+    CoeffDiv {
+        mclk: 5644800,
+        rate: 22050,
+        pre_div: 1,
+        pre_multi: 0,
+        adc_div: 1,
+        dac_div: 1,
+        fs_mode: 0,
+        lrck_div: 256,
+        bclk_div: 4,
+        adc_osr: 0x10,
+        dac_osr: 0x10,
+    },
+    CoeffDiv {
+        mclk: 8467200,
+        rate: 22050,
+        pre_div: 3,
+        pre_multi: 1,
+        adc_div: 1,
+        dac_div: 1,
+        fs_mode: 0,
+        lrck_div: 256,
+        bclk_div: 4,
+        adc_osr: 0x10,
+        dac_osr: 0x10,
+    },
+    CoeffDiv {
+        mclk: 11289600,
+        rate: 22050,
+        pre_div: 2,
+        pre_multi: 0,
+        adc_div: 1,
+        dac_div: 1,
+        fs_mode: 0,
+        lrck_div: 256,
+        bclk_div: 4,
+        adc_osr: 0x10,
+        dac_osr: 0x10,
+    },
+    /* 24k */
+    CoeffDiv {
+        mclk: 6144000,
+        rate: 24000,
+        pre_div: 1,
+        pre_multi: 0,
+        adc_div: 1,
+        dac_div: 1,
+        fs_mode: 0,
+        lrck_div: 256,
+        bclk_div: 4,
+        adc_osr: 0x10,
+        dac_osr: 0x10,
+    },
+    CoeffDiv {
+        mclk: 9216000,
+        rate: 24000,
+        pre_div: 3,
+        pre_multi: 1,
+        adc_div: 1,
+        dac_div: 1,
+        fs_mode: 0,
+        lrck_div: 256,
+        bclk_div: 4,
+        adc_osr: 0x10,
+        dac_osr: 0x10,
+    },
+    CoeffDiv {
+        mclk: 12288000,
+        rate: 24000,
+        pre_div: 2,
+        pre_multi: 0,
+        adc_div: 1,
+        dac_div: 1,
+        fs_mode: 0,
+        lrck_div: 256,
+        bclk_div: 4,
+        adc_osr: 0x10,
+        dac_osr: 0x10,
+    },
+    /* 32k */
+    CoeffDiv {
+        mclk: 8192000,
+        rate: 32000,
+        pre_div: 1,
+        pre_multi: 0,
+        adc_div: 1,
+        dac_div: 1,
+        fs_mode: 0,
+        lrck_div: 256,
+        bclk_div: 4,
+        adc_osr: 0x10,
+        dac_osr: 0x10,
+    },
+    CoeffDiv {
+        mclk: 12288000,
+        rate: 32000,
+        pre_div: 3,
+        pre_multi: 1,
+        adc_div: 1,
+        dac_div: 1,
+        fs_mode: 0,
+        lrck_div: 256,
+        bclk_div: 4,
+        adc_osr: 0x10,
+        dac_osr: 0x10,
+    },
+    /* 44.1k */
+    CoeffDiv {
+        mclk: 11289600,
+        rate: 44100,
+        pre_div: 1,
+        pre_multi: 0,
+        adc_div: 1,
+        dac_div: 1,
+        fs_mode: 0,
+        lrck_div: 256,
+        bclk_div: 4,
+        adc_osr: 0x10,
+        dac_osr: 0x10,
+    },
+    CoeffDiv {
+        mclk: 16934400,
+        rate: 44100,
+        pre_div: 3,
+        pre_multi: 1,
+        adc_div: 1,
+        dac_div: 1,
+        fs_mode: 0,
+        lrck_div: 256,
+        bclk_div: 4,
+        adc_osr: 0x10,
+        dac_osr: 0x10,
+    },
+    /* 48k */
+    CoeffDiv {
+        mclk: 12288000,
+        rate: 48000,
+        pre_div: 1,
+        pre_multi: 0,
+        adc_div: 1,
+        dac_div: 1,
+        fs_mode: 0,
+        lrck_div: 256,
+        bclk_div: 4,
+        adc_osr: 0x10,
+        dac_osr: 0x10,
+    },
+    CoeffDiv {
+        mclk: 18432000,
+        rate: 48000,
+        pre_div: 3,
+        pre_multi: 1,
+        adc_div: 1,
+        dac_div: 1,
+        fs_mode: 0,
+        lrck_div: 256,
+        bclk_div: 4,
+        adc_osr: 0x10,
+        dac_osr: 0x10,
+    },
+];
+
+/// 找到 MCLK 和采样率对应的分频系数，表里没有就返回 None
+pub fn get_coeff(mclk: u32, rate: u32) -> Option<&'static CoeffDiv> {
+    COEFF_DIV_TABLE
+        .iter()
+        .find(|coeff| coeff.mclk == mclk && coeff.rate == rate)
+}
@@ -0,0 +1,121 @@
+/// 模拟麦克风 PGA 的增益，0~30dB，每档 3dB（寄存器 0x14 的 bit 3:0）
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum MicGain {
+    Gain0db = 0,
+    Gain3db,
+    Gain6db,
+    Gain9db,
+    Gain12db,
+    Gain15db,
+    Gain18db,
+    Gain21db,
+    Gain24db,
+    Gain27db,
+    Gain30db,
+}
+
+impl MicGain {
+    /// 获取该增益设置对应的、需要写入寄存器的整数值。
+    pub fn value(&self) -> u8 {
+        *self as u8
+    }
+}
+
+/// 麦克风输入选择（寄存器 0x14 的 DMIC_ON 和 LINSEL）
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MicInput {
+    /// Mic1p-Mic1n 差分输入
+    Mic1,
+    /// Mic2p-Mic2n 差分输入
+    Mic2,
+    /// Mic1 和 Mic2 一起
+    Mic1AndMic2,
+    /// MIC1P 上接 PDM 数字麦克风，时钟用 BCLK
+    Digital,
+}
+
+/// ADC 数字增益，OSR 比默认值小时用来补偿信号幅度（寄存器 0x16 的 bit 2:0）
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum AdcScale {
+    Scale0db = 0,
+    Scale6db,
+    Scale12db,
+    Scale18db,
+    Scale24db,
+    Scale30db,
+    Scale36db,
+    Scale42db,
+}
+
+impl AdcScale {
+    pub fn value(&self) -> u8 {
+        *self as u8
+    }
+}
+
+/// ALC（麦克风）和 DRC（喇叭）的目标电平表，下标就是寄存器里的值
+const LEVEL_DB: [f32; 16] = [
+    -30.1, -24.1, -20.6, -18.1, -16.1, -14.5, -13.2, -12.0, -11.0, -10.1, -9.3, -8.5, -7.8, -7.2,
+    -6.6, -6.0,
+];
+
+/// 找到表里最接近 `db` 的一档
+pub fn level_value(db: f32) -> u8 {
+    let mut best = 0;
+    for (i, level) in LEVEL_DB.iter().enumerate() {
+        if (level - db).abs() < (LEVEL_DB[best] - db).abs() {
+            best = i;
+        }
+    }
+    best as u8
+}
+
+/// ADC 和 DAC 的数字音量，-95.5dB 到 +32dB，每档 0.5dB，0xBF 是 0dB
+pub fn volume_value(db: f32) -> u8 {
+    let db = db.clamp(-95.5, 32.0);
+    (0xBF as f32 + db * 2.0).round() as u8
+}
+
+/// 自动电平控制的参数。麦克风一侧叫 ALC，喇叭一侧叫 DRC，寄存器的格式一样
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct AlcConfig {
+    /// 目标电平上限，-30.1 到 -6.0dB，取表里最接近的一档
+    pub max_level_db: f32,
+    /// 目标电平下限，同上
+    pub min_level_db: f32,
+    /// 最多放大多少。打开后音量寄存器就变成了最大增益
+    pub max_gain_db: f32,
+    /// 检测窗口 0~15，每 2^(n+1) 个 LRCK 调整 0.25dB
+    pub window: u8,
+}
+
+impl Default for AlcConfig {
+    fn default() -> Self {
+        Self {
+            max_level_db: -6.0,
+            min_level_db: -12.0,
+            max_gain_db: 0.0,
+            window: 7,
+        }
+    }
+}
+
+/// ADC 的二阶 EQ 系数，每个都是 30 位，用 Everest 的工具算出来
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AdcEq {
+    pub b0: u32,
+    pub b1: u32,
+    pub b2: u32,
+    pub a1: u32,
+    pub a2: u32,
+}
+
+/// DAC 的一阶 EQ 系数，可以做成低通或高通，每个都是 30 位
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DacEq {
+    pub b0: u32,
+    pub b1: u32,
+    pub a1: u32,
+}
@@ -1,17 +1,56 @@
 // src/es8311.rs
+use crate::audio::codec::es8311::coeff::get_coeff;
+use crate::audio::codec::es8311::enums::{
+    level_value, volume_value, AdcEq, AdcScale, AlcConfig, DacEq, MicGain, MicInput,
+};
 use crate::audio::codec::es8311::reg::*;
+use crate::utils::bits::update_bit;
 use embedded_hal::blocking::delay::DelayUs;
 use embedded_hal::blocking::i2c::{Write, WriteRead};
-// 定义错误类型，这里我们直接用I2C的错误类型
+use embedded_hal::digital::v2::OutputPin;
+use log::info;
+use thiserror::Error;
+
 pub type Result<T, E> = core::result::Result<T, E>;
 
+/// 驱动的错误，`E` 是 I2C 总线的错误类型
+#[derive(Error, Debug)]
+pub enum Error<E> {
+    #[error("Underlying I2C communication error: {0:?}")]
+    I2c(E),
+
+    #[error("Unsupported sample rate {rate}Hz with MCLK {mclk}Hz")]
+    UnsupportedSampleRate { mclk: u32, rate: u32 },
+
+    #[error("Failed to drive the PA enable GPIO")]
+    PaGpio,
+}
+
+impl<E> From<E> for Error<E> {
+    fn from(e: E) -> Self {
+        Error::I2c(e)
+    }
+}
+
+/// 功放使能脚，参数是要不要打开功放
+type PaSwitch = Box<dyn FnMut(bool) -> Result<(), ()> + Send>;
+
 // ES8311 默认的I2C从机地址
 const ADDR: u8 = 0x18;
 
 /// 代表ES8311音频编解码器驱动
 pub struct Es8311<I2C> {
     i2c: I2C,
+    addr: u8,
     is_open: bool,
+    pa: Option<PaSwitch>,
+    // 下面这些 enable() 会先写成默认值，再把设置过的重新写一遍
+    mic_input: MicInput,
+    mic_gain: MicGain,
+    alc: Option<AlcConfig>,
+    drc: Option<AlcConfig>,
+    adc_eq: Option<AdcEq>,
+    dac_eq: Option<DacEq>,
 }
 
 impl<I2C, E> Es8311<I2C>
@@ -20,15 +59,28 @@ where
 {
     /// 创建一个新的ES8311驱动实例
     pub fn new(i2c: I2C) -> Self {
+        Self::with_address(i2c, ADDR)
+    }
+
+    /// 使用指定的 I2C 地址（CE 脚接高电平时是 0x19）
+    pub fn with_address(i2c: I2C, addr: u8) -> Self {
         Self {
             i2c,
+            addr,
             is_open: false,
+            pa: None,
+            mic_input: MicInput::Mic1,
+            mic_gain: MicGain::Gain30db,
+            alc: None,
+            drc: None,
+            adc_eq: None,
+            dac_eq: None,
         }
     }
 
     /// 初始化CODEC芯片
     /// 这是最关键的函数，它按照datasheet的推荐序列来配置芯片
-    pub fn open<D: DelayUs<u32>>(&mut self, delay: &mut D) -> Result<(), E> {
+    pub fn open<D: DelayUs<u32>>(&mut self, delay: &mut D) -> Result<(), Error<E>> {
         println!("开始初始化ES8311");
 
         // 1. 复位芯片
@@ -89,7 +141,7 @@ where
         Ok(())
     }
 
-    pub fn enable(&mut self) -> Result<(), E> {
+    pub fn enable(&mut self) -> Result<(), Error<E>> {
         // int ret = ESP_CODEC_DEV_OK;
         // int adc_iface = 0, dac_iface = 0;
         // int regv = 0x80;
@@ -223,7 +275,7 @@ where
         self.write_reg(ES8311_ADC_REG17, 0xBF)?;
         self.write_reg(ES8311_SYSTEM_REG_0E, 0x02)?;
         self.write_reg(ES8311_SYSTEM_REG_12, 0x00)?;
-        self.write_reg(ES8311_SYSTEM_REG_14, 0x1A)?;
+        self.write_reg(ES8311_SYSTEM_REG_14, 0x10 | self.mic_gain.value())?;
 
         // // pdm dmic enable or disable
         // regv = 0;
@@ -235,9 +287,7 @@ where
         // }
         // ret |= es8311_write_reg(codec, ES8311_SYSTEM_REG14, regv);
 
-        let mut regv = self.read_u8(ES8311_SYSTEM_REG_14)?; //这个值不是刚赋的吗？
-        regv &= !(0x40);
-        self.write_reg(ES8311_SYSTEM_REG_14, regv)?;
+        self.set_mic_input(self.mic_input)?;
 
         // ret |= es8311_write_reg(codec, ES8311_SYSTEM_REG0D, 0x01);
         // ret |= es8311_write_reg(codec, ES8311_ADC_REG15, 0x40);
@@ -249,12 +299,24 @@ where
         self.write_reg(ES8311_DAC_REG_37, 0x08)?;
         self.write_reg(ES8311_GP_REG_45, 0x00)?;
 
+        // 上面把 ALC、DRC 和 EQ 都写回了默认值，把设置过的恢复
+        if let Some(alc) = self.alc {
+            self.set_alc(Some(alc))?;
+        }
+        if let Some(drc) = self.drc {
+            self.set_drc(Some(drc))?;
+        }
+        if self.adc_eq.is_some() || self.dac_eq.is_some() {
+            self.set_eq(self.adc_eq, self.dac_eq)?;
+        }
+
+        self.pa_power(true)?;
         self.is_open = true;
 
         Ok(())
     }
 
-    pub fn suspend(&mut self) -> Result<(), E> {
+    pub fn suspend(&mut self) -> Result<(), Error<E>> {
         // int ret = es8311_write_reg(codec, ES8311_DAC_REG32, 0x00);
         // ret |= es8311_write_reg(codec, ES8311_ADC_REG17, 0x00);
         // ret |= es8311_write_reg(codec, ES8311_SYSTEM_REG0E, 0xFF);
@@ -292,11 +354,11 @@ where
         Ok(())
     }
 
-    pub fn disable(&mut self) -> Result<(), E> {
+    pub fn disable(&mut self) -> Result<(), Error<E>> {
         self.close()
     }
 
-    pub fn close(&mut self) -> Result<(), E> {
+    pub fn close(&mut self) -> Result<(), Error<E>> {
         // audio_codec_es8311_t *codec = (audio_codec_es8311_t *) h;
         // if (codec == NULL) {
         //     return ESP_CODEC_DEV_INVALID_ARG;
@@ -318,7 +380,7 @@ where
 
     /// 设置播放音量
     /// `volume`: 100 (最大) -  0(静音)
-    pub fn set_voice_volume(&mut self, volume: u8) -> Result<(), E> {
+    pub fn set_voice_volume(&mut self, volume: u8) -> Result<(), Error<E>> {
         let mut percent = volume.min(100); // 确保值在范围内
         percent = volume.max(0);
 
@@ -330,7 +392,7 @@ where
     }
 
     /// 设置静音
-    pub fn set_mute(&mut self, mute: bool) -> Result<(), E> {
+    pub fn set_mute(&mut self, mute: bool) -> Result<(), Error<E>> {
         if mute {
             self.set_voice_volume(0) // 设置为最大衰减即静音
         } else {
@@ -339,26 +401,176 @@ where
         }
     }
 
+    /// 设置 MCLK 和采样率对应的时钟分频，`mclk` 是 I2S 输出的 MCLK 频率（Hz）。
+    /// 分频系数见 coeff.rs，表里没有的组合返回 `Error::UnsupportedSampleRate`
+    pub fn set_sample_rate(&mut self, mclk: u32, rate: u32) -> Result<(), Error<E>> {
+        let coeff = get_coeff(mclk, rate).ok_or(Error::UnsupportedSampleRate { mclk, rate })?;
+        info!("es8311 sample rate {}Hz, MCLK {}Hz", rate, mclk);
+
+        // 0x02 bit 7:5 是预分频减一，bit 4:3 是倍频
+        let regv = ((coeff.pre_div - 1) << 5) | (coeff.pre_multi << 3);
+        self.update_reg_bit(ES8311_CLK_MANAGER_REG_02, 0xF8, regv)?;
+        // 0x05 bit 7:4 是 ADC 分频减一，bit 3:0 是 DAC 分频减一
+        let regv = ((coeff.adc_div - 1) << 4) | (coeff.dac_div - 1);
+        self.write_reg(ES8311_CLOCK_MANAGER_REG_05, regv)?;
+        // 0x03 bit 6 是 single/double speed，bit 5:0 是 ADC OSR
+        let regv = (coeff.fs_mode << 6) | coeff.adc_osr;
+        self.update_reg_bit(ES8311_CLK_MANAGER_REG_03, 0x7F, regv)?;
+        self.update_reg_bit(ES8311_CLOCK_MANAGER_REG_04, 0x7F, coeff.dac_osr)?;
+        // LRCK 分频减一，高 4 位在 0x07，低 8 位在 0x08
+        let lrck = coeff.lrck_div - 1;
+        self.update_reg_bit(ES8311_CLK_MANAGER_REG_07, 0x0F, (lrck >> 8) as u8)?;
+        self.write_reg(ES8311_CLK_MANAGER_REG_08, lrck as u8)?;
+        // 0x06 bit 4:0 是 BCLK 分频减一，bit 5 是 SCLK 反相，不动它
+        self.update_reg_bit(ES8311_CLOCK_MANAGER_REG_06, 0x1F, coeff.bclk_div - 1)?;
+        Ok(())
+    }
+
+    /// 选择麦克风输入，默认是 Mic1 差分输入
+    pub fn set_mic_input(&mut self, input: MicInput) -> Result<(), Error<E>> {
+        self.mic_input = input;
+        // 0x14 bit 6 是 DMIC_ON，bit 5:4 是 LINSEL
+        let regv = match input {
+            MicInput::Mic1 => 0x10,
+            MicInput::Mic2 => 0x20,
+            MicInput::Mic1AndMic2 => 0x30,
+            MicInput::Digital => 0x40,
+        };
+        self.update_reg_bit(ES8311_SYSTEM_REG_14, 0x70, regv)
+    }
+
+    /// 设置模拟麦克风 PGA 的增益，默认 30dB
+    pub fn set_mic_gain(&mut self, gain: MicGain) -> Result<(), Error<E>> {
+        self.mic_gain = gain;
+        self.update_reg_bit(ES8311_SYSTEM_REG_14, 0x0F, gain.value())
+    }
+
+    /// 设置 ADC 的数字增益，open() 里配置成了 24dB
+    pub fn set_adc_scale(&mut self, scale: AdcScale) -> Result<(), Error<E>> {
+        self.update_reg_bit(ES8311_ADC_REG_16, 0x07, scale.value())
+    }
+
+    /// 麦克风的自动电平控制（ALC），None 表示关掉。
+    /// 打开时 ADC 音量寄存器就是 ALC 的最大增益，关掉时恢复成 0dB
+    pub fn set_alc(&mut self, alc: Option<AlcConfig>) -> Result<(), Error<E>> {
+        self.alc = alc;
+        match alc {
+            Some(alc) => {
+                let levels = (level_value(alc.max_level_db) << 4) | level_value(alc.min_level_db);
+                self.write_reg(ES8311_ADC_REG_19, levels)?;
+                self.write_reg(ES8311_ADC_REG_17, volume_value(alc.max_gain_db))?;
+                // 0x18 bit 7 是 ALC_EN，bit 3:0 是窗口，bit 6 的 automute 不动
+                self.update_reg_bit(ES8311_ADC_REG_18, 0x8F, 0x80 | alc.window.min(15))
+            }
+            None => {
+                self.update_reg_bit(ES8311_ADC_REG_18, 0x80, 0x00)?;
+                self.write_reg(ES8311_ADC_REG_17, 0xBF)
+            }
+        }
+    }
+
+    /// 喇叭的动态范围控制（DRC），None 表示关掉。
+    /// 打开时 DAC 音量寄存器就是 DRC 的最大增益，所以 `set_voice_volume` 会改掉它
+    pub fn set_drc(&mut self, drc: Option<AlcConfig>) -> Result<(), Error<E>> {
+        self.drc = drc;
+        match drc {
+            Some(drc) => {
+                let levels = (level_value(drc.max_level_db) << 4) | level_value(drc.min_level_db);
+                self.write_reg(ES8311_DAC_REG_35, levels)?;
+                self.write_reg(ES8311_DAC_VOLUME_REG_32, volume_value(drc.max_gain_db))?;
+                // 0x34 bit 7 是 DRC_EN，bit 3:0 是窗口
+                self.update_reg_bit(ES8311_DAC_REG_34, 0x8F, 0x80 | drc.window.min(15))
+            }
+            None => self.update_reg_bit(ES8311_DAC_REG_34, 0x80, 0x00),
+        }
+    }
+
+    /// 设置 ADC 和 DAC 的 EQ，None 表示旁路（默认）。系数用 Everest 提供的工具计算。
+    /// 先写系数，再取消旁路
+    pub fn set_eq(&mut self, adc: Option<AdcEq>, dac: Option<DacEq>) -> Result<(), Error<E>> {
+        self.adc_eq = adc;
+        self.dac_eq = dac;
+
+        // 0x1C bit 6 是 ADC_EQBYPASS
+        match adc {
+            Some(eq) => {
+                let coeffs = [eq.b0, eq.a1, eq.a2, eq.b1, eq.b2];
+                for (i, coeff) in coeffs.into_iter().enumerate() {
+                    self.write_coeff(ES8311_ADCEQ_REG_1D + 4 * i as u8, coeff)?;
+                }
+                self.update_reg_bit(ES8311_ADC_REG_1C, 0x40, 0x00)?;
+            }
+            None => self.update_reg_bit(ES8311_ADC_REG_1C, 0x40, 0x40)?,
+        }
+
+        // 0x37 bit 3 是 DAC_EQBYPASS
+        match dac {
+            Some(eq) => {
+                let coeffs = [eq.b0, eq.b1, eq.a1];
+                for (i, coeff) in coeffs.into_iter().enumerate() {
+                    self.write_coeff(ES8311_DACEQ_REG_38 + 4 * i as u8, coeff)?;
+                }
+                self.update_reg_bit(ES8311_DAC_REG_37, 0x08, 0x00)
+            }
+            None => self.update_reg_bit(ES8311_DAC_REG_37, 0x08, 0x08),
+        }
+    }
+
+    /// ADC 的高通滤波器，用来去掉麦克风的直流偏置，open() 里是打开的
+    pub fn set_high_pass(&mut self, enable: bool) -> Result<(), Error<E>> {
+        self.update_reg_bit(ES8311_ADC_REG_1C, 0x20, if enable { 0x20 } else { 0x00 })
+    }
+
+    /// 设置功放的使能脚，`active_high` 为 false 时低电平打开功放。
+    /// 设置后马上按当前状态驱动一次，之后 enable() 打开功放，close() 关掉
+    pub fn set_pa_gpio<P>(&mut self, mut pin: P, active_high: bool) -> Result<(), Error<E>>
+    where
+        P: OutputPin + Send + 'static,
+    {
+        self.pa = Some(Box::new(move |enable| {
+            let result = if enable == active_high {
+                pin.set_high()
+            } else {
+                pin.set_low()
+            };
+            result.map_err(|_| ())
+        }));
+        self.pa_power(self.is_open)
+    }
+
     /// Reads a single byte from a register.
-    pub fn read_u8(&mut self, reg: u8) -> Result<u8, E> {
+    pub fn read_u8(&mut self, reg: u8) -> Result<u8, Error<E>> {
         let mut byte: [u8; 1] = [0; 1];
 
-        match self.i2c.write_read(ADDR, &[reg], &mut byte) {
+        match self.i2c.write_read(self.addr, &[reg], &mut byte) {
             Ok(_) => Ok(byte[0]),
-            Err(e) => Err(e),
+            Err(e) => Err(Error::I2c(e)),
         }
     }
 
     //private methods
     /// 向一个寄存器写入一个字节
-    fn write_reg(&mut self, reg: u8, value: u8) -> Result<(), E> {
-        self.i2c.write(ADDR, &[reg, value])
+    fn write_reg(&mut self, reg: u8, value: u8) -> Result<(), Error<E>> {
+        self.i2c.write(self.addr, &[reg, value]).map_err(Error::I2c)
+    }
+
+    fn update_reg_bit(&mut self, reg: u8, update_bits: u8, data: u8) -> Result<(), Error<E>> {
+        let regv = self.read_u8(reg)?;
+        self.write_reg(reg, update_bit(regv, update_bits, data))
+    }
+
+    /// 30 位的 EQ 系数，从 `reg` 开始高位在前写 4 个字节
+    fn write_coeff(&mut self, reg: u8, coeff: u32) -> Result<(), Error<E>> {
+        for (i, byte) in (coeff & 0x3FFF_FFFF).to_be_bytes().iter().enumerate() {
+            self.write_reg(reg + i as u8, *byte)?;
+        }
+        Ok(())
     }
 
     /// 设置工作模式为master
     /// 参数：
     /// master_mode: true为master模式，false为slave模式
-    fn set_master_mode(&mut self, master_mode: bool) -> Result<(), E> {
+    fn set_master_mode(&mut self, master_mode: bool) -> Result<(), Error<E>> {
         // ret = es8311_read_reg(codec, ES8311_RESET_REG00, &regv);
         // if (codec_cfg->master_mode) {
         //     ESP_LOGI(TAG, "Work in Master mode");
@@ -382,7 +594,7 @@ where
         Ok(())
     }
 
-    fn set_invert_mclk(&mut self, invert: bool) -> Result<(), E> {
+    fn set_invert_mclk(&mut self, invert: bool) -> Result<(), Error<E>> {
         let mut regv = self.read_u8(ES8311_CLK_MANAGER_REG_01)?;
         if invert {
             regv |= 0x40;
@@ -394,7 +606,7 @@ where
         Ok(())
     }
 
-    fn set_invert_sclk(&mut self, invert: bool) -> Result<(), E> {
+    fn set_invert_sclk(&mut self, invert: bool) -> Result<(), Error<E>> {
         // SCLK inverted or not
         // ret |= es8311_read_reg(codec, ES8311_CLK_MANAGER_REG06, &regv);
         // if (codec_cfg->invert_sclk) {
@@ -419,7 +631,7 @@ where
         Ok(())
     }
 
-    fn configure_mclk_source(&mut self, use_mclk: bool) -> Result<(), E> {
+    fn configure_mclk_source(&mut self, use_mclk: bool) -> Result<(), Error<E>> {
         let mut regv = 0x3F;
         if use_mclk {
             regv &= 0x7F;
@@ -430,10 +642,290 @@ where
         Ok(())
     }
 
-    /// 因为es8311的由axp 173来控制的，所以先不实现它。
-    /// PA 通常指的是 Power Amplifier（功率放大器）
-    fn pa_power(&self, enable: bool) -> Result<(), E> {
-        let _ = enable;
-        Ok(())
+    /// PA 通常指的是 Power Amplifier（功率放大器）。
+    /// 没有调用 `set_pa_gpio` 时什么都不做，比如江联的板子功放是由 axp173 来控制的。
+    pub fn pa_power(&mut self, enable: bool) -> Result<(), Error<E>> {
+        match self.pa.as_mut() {
+            Some(pa) => pa(enable).map_err(|_| Error::PaGpio),
+            None => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        convert::Infallible,
+        sync::{Arc, Mutex},
+    };
+
+    use super::*;
+    use crate::test_support::recording_i2c::{I2cOp, RecordingI2c};
+
+    fn codec() -> (Es8311<RecordingI2c>, RecordingI2c) {
+        let i2c = RecordingI2c::new();
+        (Es8311::new(i2c.clone()), i2c)
+    }
+
+    /// 记录每次驱动的电平，true 是高电平
+    #[derive(Clone, Default)]
+    struct FakePin {
+        levels: Arc<Mutex<Vec<bool>>>,
+        broken: bool,
+    }
+
+    impl FakePin {
+        fn levels(&self) -> Vec<bool> {
+            self.levels.lock().unwrap().clone()
+        }
+
+        fn set(&mut self, level: bool) -> Result<(), ()> {
+            if self.broken {
+                return Err(());
+            }
+            self.levels.lock().unwrap().push(level);
+            Ok(())
+        }
+    }
+
+    impl OutputPin for FakePin {
+        type Error = ();
+
+        fn set_low(&mut self) -> Result<(), ()> {
+            self.set(false)
+        }
+
+        fn set_high(&mut self) -> Result<(), ()> {
+            self.set(true)
+        }
+    }
+
+    #[test]
+    fn sample_rate_16k_from_12m288() {
+        let (mut codec, i2c) = codec();
+        // 不属于时钟分频的位要保留
+        i2c.set_register(ADDR, ES8311_CLK_MANAGER_REG_02, 0x07);
+        i2c.set_register(ADDR, ES8311_CLK_MANAGER_REG_03, 0x80);
+        i2c.set_register(ADDR, ES8311_CLOCK_MANAGER_REG_04, 0x80);
+        i2c.set_register(ADDR, ES8311_CLK_MANAGER_REG_07, 0xF0);
+        i2c.set_register(ADDR, ES8311_CLOCK_MANAGER_REG_06, 0x20);
+
+        codec.set_sample_rate(12_288_000, 16000).unwrap();
+
+        // 手册的 16k 表：pre_div=3 x1，ADC/DAC 不分频，OSR 0x10，LRCK 256 分频，BCLK 4 分频
+        assert_eq!(
+            i2c.writes(ADDR),
+            [
+                (ES8311_CLK_MANAGER_REG_02, 0x47),
+                (ES8311_CLOCK_MANAGER_REG_05, 0x00),
+                (ES8311_CLK_MANAGER_REG_03, 0x90),
+                (ES8311_CLOCK_MANAGER_REG_04, 0x90),
+                (ES8311_CLK_MANAGER_REG_07, 0xF0),
+                (ES8311_CLK_MANAGER_REG_08, 0xFF),
+                (ES8311_CLOCK_MANAGER_REG_06, 0x23),
+            ]
+        );
+    }
+
+    #[test]
+    fn sample_rate_8k_uses_larger_pre_div() {
+        let (mut codec, i2c) = codec();
+        codec.set_sample_rate(12_288_000, 8000).unwrap();
+        // pre_div=6，写进去是 5
+        assert_eq!(i2c.register(ADDR, ES8311_CLK_MANAGER_REG_02), 0xA0);
+        assert_eq!(i2c.register(ADDR, ES8311_CLK_MANAGER_REG_08), 0xFF);
+    }
+
+    #[test]
+    fn unsupported_sample_rate_does_not_touch_registers() {
+        let (mut codec, i2c) = codec();
+        let err = codec.set_sample_rate(12_288_000, 12345).unwrap_err();
+        assert!(matches!(
+            err,
+            Error::UnsupportedSampleRate {
+                mclk: 12_288_000,
+                rate: 12345
+            }
+        ));
+        assert!(i2c.ops().is_empty());
+    }
+
+    #[test]
+    fn mic_gain_and_input_share_reg_14() {
+        let (mut codec, i2c) = codec();
+        // open() 之后是 Mic1 + 30dB
+        i2c.set_register(ADDR, ES8311_SYSTEM_REG_14, 0x1A);
+
+        codec.set_mic_gain(MicGain::Gain12db).unwrap();
+        assert_eq!(
+            i2c.ops(),
+            [
+                I2cOp::Read {
+                    addr: ADDR,
+                    reg: ES8311_SYSTEM_REG_14,
+                    len: 1
+                },
+                I2cOp::Write {
+                    addr: ADDR,
+                    reg: ES8311_SYSTEM_REG_14,
+                    data: vec![0x14]
+                },
+            ]
+        );
+
+        // 换成数字麦克风，PGA 增益不变
+        codec.set_mic_input(MicInput::Digital).unwrap();
+        assert_eq!(i2c.register(ADDR, ES8311_SYSTEM_REG_14), 0x44);
+    }
+
+    #[test]
+    fn alc_on_and_off() {
+        let (mut codec, i2c) = codec();
+        // bit 6 是 automute，不能被改掉
+        i2c.set_register(ADDR, ES8311_ADC_REG_18, 0x40);
+
+        let alc = AlcConfig {
+            max_gain_db: 12.0,
+            ..Default::default()
+        };
+        codec.set_alc(Some(alc)).unwrap();
+        // 目标电平 -6.0dB 是第 15 档，-12.0dB 是第 7 档；+12dB 是 0xBF + 24
+        assert_eq!(
+            i2c.writes(ADDR),
+            [
+                (ES8311_ADC_REG_19, 0xF7),
+                (ES8311_ADC_REG_17, 0xD7),
+                (ES8311_ADC_REG_18, 0xC7),
+            ]
+        );
+
+        i2c.clear();
+        codec.set_alc(None).unwrap();
+        assert_eq!(
+            i2c.writes(ADDR),
+            [(ES8311_ADC_REG_18, 0x47), (ES8311_ADC_REG_17, 0xBF)]
+        );
+    }
+
+    #[test]
+    fn drc_on_and_off() {
+        let (mut codec, i2c) = codec();
+        let drc = AlcConfig {
+            max_level_db: -30.1,
+            min_level_db: -30.1,
+            max_gain_db: -95.5,
+            // 窗口最多 15
+            window: 20,
+        };
+        codec.set_drc(Some(drc)).unwrap();
+        assert_eq!(
+            i2c.writes(ADDR),
+            [
+                (ES8311_DAC_REG_35, 0x00),
+                (ES8311_DAC_VOLUME_REG_32, 0x00),
+                (ES8311_DAC_REG_34, 0x8F),
+            ]
+        );
+
+        i2c.clear();
+        codec.set_drc(None).unwrap();
+        assert_eq!(i2c.writes(ADDR), [(ES8311_DAC_REG_34, 0x0F)]);
+    }
+
+    #[test]
+    fn eq_writes_coefficients_then_clears_bypass() {
+        let (mut codec, i2c) = codec();
+        // open() 之后 0x1C 是 0x6A（EQ 旁路 + 高通），0x37 是 0x08（EQ 旁路）
+        i2c.set_register(ADDR, ES8311_ADC_REG_1C, 0x6A);
+        i2c.set_register(ADDR, ES8311_DAC_REG_37, 0x08);
+
+        let adc = AdcEq {
+            b0: 0x0102_0304,
+            a1: 0x1112_1314,
+            a2: 0x2122_2324,
+            b1: 0x3132_3334,
+            // 只有低 30 位有效
+            b2: 0xFFFF_FFFF,
+        };
+        let dac = DacEq {
+            b0: 0x0A0B_0C0D,
+            b1: 0x1A1B_1C1D,
+            a1: 0x2A2B_2C2D,
+        };
+        codec.set_eq(Some(adc), Some(dac)).unwrap();
+
+        let mut expected = Vec::new();
+        let adc_coeffs = [
+            0x01, 0x02, 0x03, 0x04, 0x11, 0x12, 0x13, 0x14, 0x21, 0x22, 0x23, 0x24, 0x31, 0x32,
+            0x33, 0x34, 0x3F, 0xFF, 0xFF, 0xFF,
+        ];
+        for (i, value) in adc_coeffs.into_iter().enumerate() {
+            expected.push((ES8311_ADCEQ_REG_1D + i as u8, value));
+        }
+        expected.push((ES8311_ADC_REG_1C, 0x2A));
+        let dac_coeffs = [
+            0x0A, 0x0B, 0x0C, 0x0D, 0x1A, 0x1B, 0x1C, 0x1D, 0x2A, 0x2B, 0x2C, 0x2D,
+        ];
+        for (i, value) in dac_coeffs.into_iter().enumerate() {
+            expected.push((ES8311_DACEQ_REG_38 + i as u8, value));
+        }
+        expected.push((ES8311_DAC_REG_37, 0x00));
+        assert_eq!(i2c.writes(ADDR), expected);
+
+        i2c.clear();
+        codec.set_eq(None, None).unwrap();
+        assert_eq!(
+            i2c.writes(ADDR),
+            [(ES8311_ADC_REG_1C, 0x6A), (ES8311_DAC_REG_37, 0x08)]
+        );
+    }
+
+    #[test]
+    fn pa_gpio_follows_enable_and_close() {
+        let (mut codec, _i2c) = codec();
+        let pin = FakePin::default();
+
+        // 还没打开，马上把功放关掉
+        codec.set_pa_gpio(pin.clone(), true).unwrap();
+        assert_eq!(pin.levels(), [false]);
+
+        codec.enable().unwrap();
+        assert_eq!(pin.levels(), [false, true]);
+
+        codec.close().unwrap();
+        assert_eq!(pin.levels(), [false, true, false]);
+        // 已经关了，不会再动
+        codec.close().unwrap();
+        assert_eq!(pin.levels(), [false, true, false]);
+    }
+
+    #[test]
+    fn pa_gpio_active_low() {
+        let (mut codec, _i2c) = codec();
+        let pin = FakePin::default();
+
+        codec.set_pa_gpio(pin.clone(), false).unwrap();
+        codec.pa_power(true).unwrap();
+        codec.pa_power(false).unwrap();
+        assert_eq!(pin.levels(), [true, false, true]);
+    }
+
+    #[test]
+    fn pa_gpio_error() {
+        let (mut codec, _i2c) = codec();
+        let pin = FakePin {
+            broken: true,
+            ..Default::default()
+        };
+
+        let err = codec.set_pa_gpio(pin, true).unwrap_err();
+        assert!(matches!(err, Error::<Infallible>::PaGpio));
+    }
+
+    #[test]
+    fn without_pa_gpio_pa_power_does_nothing() {
+        let (mut codec, i2c) = codec();
+        codec.pa_power(true).unwrap();
+        assert!(i2c.ops().is_empty());
     }
 }
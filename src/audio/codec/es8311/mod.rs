@@ -1,4 +1,6 @@
+pub mod coeff;
+pub mod enums;
 pub mod es8311;
 pub mod reg;
 
-pub use es8311::Es8311;
+pub use es8311::{Error, Es8311};
@@ -9,6 +9,8 @@ pub const ES8311_CLOCK_MANAGER_REG_05: u8 = 0x05;
 pub const ES8311_CLOCK_MANAGER_REG_06: u8 = 0x06;
 pub const ES8311_GPIO_REG: u8 = 0x07;
 pub const ES8311_MASTER_MODE_REG: u8 = 0x08;
+pub const ES8311_CLK_MANAGER_REG_07: u8 = 0x07; // LRCK 分频的高 4 位
+pub const ES8311_CLK_MANAGER_REG_08: u8 = 0x08; // LRCK 分频的低 8 位
 
 pub const ES8311_SDPIN_REG_09: u8 = 0x09;
 pub const ES8311_SDPOUT_REG_0A: u8 = 0x0A;
@@ -25,13 +27,19 @@ pub const ES8311_SYSTEM_REG_14: u8 = 0x14;
 pub const ES8311_ADC_REG_15: u8 = 0x15;
 pub const ES8311_ADC_REG_16: u8 = 0x16;
 pub const ES8311_ADC_REG_17: u8 = 0x17;
+pub const ES8311_ADC_REG_18: u8 = 0x18; // ALC 开关和窗口
+pub const ES8311_ADC_REG_19: u8 = 0x19; // ALC 目标电平
 
 pub const ES8311_ADC_REG_1B: u8 = 0x1B;
 pub const ES8311_ADC_REG_1C: u8 = 0x1C;
+pub const ES8311_ADCEQ_REG_1D: u8 = 0x1D; // ADC EQ 系数 B0 A1 A2 B1 B2，到 0x30
 
 pub const ES8311_ADC_REG17: u8 = 0x17;
 
+pub const ES8311_DAC_REG_34: u8 = 0x34; // DRC 开关和窗口
+pub const ES8311_DAC_REG_35: u8 = 0x35; // DRC 目标电平
 pub const ES8311_DAC_REG_37: u8 = 0x37;
+pub const ES8311_DACEQ_REG_38: u8 = 0x38; // DAC EQ 系数 B0 B1 A1，到 0x43
 pub const ES8311_GPIO_REG_44: u8 = 0x44;
 pub const ES8311_GP_REG_45: u8 = 0x45;
 
@@ -0,0 +1,185 @@
+use std::{
+    path::Path,
+    sync::{Arc, Mutex},
+    thread,
+    time::{Duration, Instant},
+};
+
+use anyhow::{anyhow, Error, Result};
+use log::{error, info};
+
+use crate::audio::{
+    codec::{
+        audio_codec::AudioCodec, opus::decoder::OpusAudioDecoder, AUDIO_INPUT_SAMPLE_RATE,
+        AUDIO_OUTPUT_SAMPLE_RATE,
+    },
+    wav::{read_wav, WavWriter},
+};
+
+const DEFAULT_OUTPUT_VOLUME: u8 = 70;
+
+/// host-sim 用的假 codec：麦克风从 WAV 文件读，喇叭写到 WAV 文件里。
+///
+/// 和真 codec 一样按实时的速度读麦克风数据，读完之后一直返回静音；
+/// 输入输出都是双声道 16 bit，和应用里的音频流水线一致
+pub struct MockAudioCodec {
+    mic: Vec<i16>,
+    mic_pos: usize,
+    speaker: WavWriter,
+    input_enabled: bool,
+    output_enabled: bool,
+    output_volume: u8,
+    /// 按这个时间点之后才能读下一段麦克风数据，模拟 I2S 的阻塞读
+    next_read: Option<Instant>,
+}
+
+impl MockAudioCodec {
+    /// `mic_wav` 必须是 16kHz、16 bit 的单声道或双声道 WAV；不存在时麦克风只有静音
+    pub fn new(mic_wav: impl AsRef<Path>, speaker_wav: impl AsRef<Path>) -> Result<Self> {
+        let mic = if mic_wav.as_ref().exists() {
+            let wav = read_wav(mic_wav.as_ref())?;
+            if wav.sample_rate != AUDIO_INPUT_SAMPLE_RATE {
+                return Err(anyhow!(
+                    "mic WAV must be {} Hz, got {} Hz",
+                    AUDIO_INPUT_SAMPLE_RATE,
+                    wav.sample_rate
+                ));
+            }
+            match wav.channels {
+                1 => wav.samples.iter().flat_map(|&s| [s, s]).collect(),
+                2 => wav.samples,
+                n => {
+                    return Err(anyhow!(
+                        "mic WAV must be mono or stereo, got {} channels",
+                        n
+                    ))
+                }
+            }
+        } else {
+            info!(
+                "{} not found, mic input is silence",
+                mic_wav.as_ref().display()
+            );
+            Vec::new()
+        };
+        info!(
+            "Mock audio codec: {:.1}s mic input, speaker output to {}",
+            mic.len() as f32 / 2.0 / AUDIO_INPUT_SAMPLE_RATE as f32,
+            speaker_wav.as_ref().display()
+        );
+
+        Ok(Self {
+            mic,
+            mic_pos: 0,
+            speaker: WavWriter::create(speaker_wav, AUDIO_OUTPUT_SAMPLE_RATE, 2)?,
+            input_enabled: false,
+            output_enabled: false,
+            output_volume: DEFAULT_OUTPUT_VOLUME,
+            next_read: None,
+        })
+    }
+}
+
+impl AudioCodec for MockAudioCodec {
+    fn set_output_volume(&mut self, volume: u8) -> Result<(), Error> {
+        self.output_volume = volume.min(100);
+        Ok(())
+    }
+
+    fn enable_input(&mut self, enable: bool) -> Result<(), Error> {
+        self.input_enabled = enable;
+        self.next_read = None;
+        Ok(())
+    }
+
+    fn enable_output(&mut self, enable: bool) -> Result<(), Error> {
+        self.output_enabled = enable;
+        Ok(())
+    }
+
+    fn input_enabled(&self) -> bool {
+        self.input_enabled
+    }
+
+    fn output_enabled(&self) -> bool {
+        self.output_enabled
+    }
+
+    fn input_reference(&self) -> bool {
+        false
+    }
+
+    fn input_channels(&self) -> i32 {
+        2
+    }
+
+    fn start(&mut self) {
+        if let Err(e) = self.enable_input(true) {
+            error!("Failed to enable input: {:?}", e);
+        }
+        if let Err(e) = self.enable_output(true) {
+            error!("Failed to enable output: {:?}", e);
+        }
+        info!("Mock audio codec started");
+    }
+
+    fn suspend(&mut self) -> Result<(), Error> {
+        self.enable_output(false)?;
+        self.enable_input(false)
+    }
+
+    fn resume(&mut self) -> Result<(), Error> {
+        self.enable_input(true)?;
+        self.enable_output(true)
+    }
+
+    fn read_audio_data(&mut self, buffer: &mut Vec<u8>) -> Result<usize, Error> {
+        // 每帧两个声道 4 字节，按采样率算出这段数据应该花多长时间
+        let frames = buffer.len() / 4;
+        let duration =
+            Duration::from_micros(frames as u64 * 1_000_000 / AUDIO_INPUT_SAMPLE_RATE as u64);
+        let now = Instant::now();
+        let next_read = self.next_read.unwrap_or(now);
+        if next_read > now {
+            thread::sleep(next_read - now);
+        }
+        self.next_read = Some(next_read + duration);
+
+        for (i, sample) in buffer[..frames * 4].chunks_exact_mut(2).enumerate() {
+            let value = self.mic.get(self.mic_pos + i).copied().unwrap_or(0);
+            sample.copy_from_slice(&value.to_le_bytes());
+        }
+        self.mic_pos = (self.mic_pos + frames * 2).min(self.mic.len());
+        Ok(frames * 4)
+    }
+
+    fn output_data(&mut self, data: &[u8]) -> Result<(), Error> {
+        if !self.output_enabled {
+            return Ok(());
+        }
+        let volume = self.output_volume as i32;
+        let scaled: Vec<u8> = data
+            .chunks_exact(2)
+            .flat_map(|sample| {
+                let sample = i16::from_le_bytes([sample[0], sample[1]]) as i32 * volume / 100;
+                (sample as i16).to_le_bytes()
+            })
+            .collect();
+        self.speaker.write(&scaled)
+    }
+
+    fn test_play_pcm(&mut self, data: &[u8]) -> Result<(), Error> {
+        self.output_data(data)
+    }
+
+    fn play_opus(
+        &mut self,
+        opus_decoder: Arc<Mutex<OpusAudioDecoder>>,
+        data: &[u8],
+        _pcm_buffer: &mut Vec<i16>,
+    ) -> Result<(), Error> {
+        let pcm_data = opus_decoder.lock().unwrap().decode(data)?;
+        let pcm_bytes: Vec<u8> = pcm_data.iter().flat_map(|s| s.to_le_bytes()).collect();
+        self.output_data(&pcm_bytes)
+    }
+}
@@ -1,8 +1,13 @@
 pub mod audio_codec;
 pub mod es7210;
 pub mod es8311;
+#[cfg(feature = "host-sim")]
+pub mod mock_audio_codec;
 pub mod opus;
+#[cfg(target_os = "espidf")]
+pub mod simplex_audio_codec;
 pub mod types;
+#[cfg(target_os = "espidf")]
 pub mod xiaozhi_audio_codec;
 
 pub const AUDIO_INPUT_SAMPLE_RATE: u32 = 16000;
@@ -160,6 +160,21 @@ impl OpusAudioEncoder {
         Ok(())
     }
 
+    /// 把缓冲区里不满一帧的数据补零后编码，按住说话松开按键时调用，避免丢掉最后一个字
+    pub fn flush<F>(&mut self, handler: &mut F) -> Result<()>
+    where
+        F: FnMut(Vec<u8>),
+    {
+        if self.in_buffer.is_empty() {
+            return Ok(());
+        }
+        let padding = self.frame_size - self.in_buffer.len() % self.frame_size;
+        if padding < self.frame_size {
+            self.in_buffer.resize(self.in_buffer.len() + padding, 0);
+        }
+        self.encode(Vec::new(), handler)
+    }
+
     pub fn set_complexity(&mut self, complexity: i32) {
         if !self.encoder.is_null() {
             unsafe {
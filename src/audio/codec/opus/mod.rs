@@ -1,2 +1,12 @@
+#[cfg(target_os = "espidf")]
 pub mod decoder;
+#[cfg(target_os = "espidf")]
+pub mod encoder;
+
+// host-sim 下没有 libopus，换成接口一样、不压缩的 PCM 编解码
+#[cfg(feature = "host-sim")]
+#[path = "sim_decoder.rs"]
+pub mod decoder;
+#[cfg(feature = "host-sim")]
+#[path = "sim_encoder.rs"]
 pub mod encoder;
@@ -0,0 +1,36 @@
+//! host-sim 用的“解码器”，还原 `sim_encoder` 编出来的 PCM 包。
+
+use anyhow::{anyhow, Result};
+
+pub struct OpusAudioDecoder {
+    max_frame_size: usize,
+}
+
+impl OpusAudioDecoder {
+    pub fn new(sample_rate: i32, channels: i32, duration_ms: i32) -> Result<Self> {
+        // 和板子上的解码器一样，最多接受两帧的数据
+        let max_frame_size = (2 * duration_ms * sample_rate / 1000) as usize * channels as usize;
+        Ok(Self { max_frame_size })
+    }
+
+    pub fn decode(&mut self, opus_packet_data: &[u8]) -> Result<Vec<i16>> {
+        if opus_packet_data.len() % 2 != 0 {
+            return Err(anyhow!(
+                "PCM packet has odd length: {}",
+                opus_packet_data.len()
+            ));
+        }
+        if opus_packet_data.len() / 2 > self.max_frame_size {
+            return Err(anyhow!(
+                "PCM packet too large: {} bytes",
+                opus_packet_data.len()
+            ));
+        }
+        Ok(opus_packet_data
+            .chunks_exact(2)
+            .map(|b| i16::from_le_bytes([b[0], b[1]]))
+            .collect())
+    }
+
+    pub fn reset_state(&mut self) {}
+}
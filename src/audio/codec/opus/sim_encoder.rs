@@ -0,0 +1,55 @@
+//! host-sim 用的“编码器”：不压缩，每一帧 PCM 按小端字节原样作为一个包，
+//! 由 `sim_decoder` 还原。ScriptedProtocol 下发的音频和设备上传的音频都走这里。
+
+use anyhow::Result;
+
+pub struct OpusAudioEncoder {
+    frame_size: usize,
+    in_buffer: Vec<i16>,
+}
+
+impl OpusAudioEncoder {
+    pub fn new(sample_rate: i32, channels: i32, duration_ms: i32) -> Result<Self> {
+        let frame_size = sample_rate / 1000 * channels * duration_ms;
+        Ok(Self {
+            frame_size: frame_size.try_into()?,
+            in_buffer: Vec::new(),
+        })
+    }
+
+    pub fn encode<F>(&mut self, mut pcm: Vec<i16>, handler: &mut F) -> Result<()>
+    where
+        F: FnMut(Vec<u8>),
+    {
+        self.in_buffer.append(&mut pcm);
+        while self.in_buffer.len() >= self.frame_size {
+            let packet = self
+                .in_buffer
+                .drain(..self.frame_size)
+                .flat_map(i16::to_le_bytes)
+                .collect();
+            handler(packet);
+        }
+        Ok(())
+    }
+
+    /// 把缓冲区里不满一帧的数据补零后编码
+    pub fn flush<F>(&mut self, handler: &mut F) -> Result<()>
+    where
+        F: FnMut(Vec<u8>),
+    {
+        if self.in_buffer.is_empty() {
+            return Ok(());
+        }
+        self.in_buffer.resize(self.frame_size, 0);
+        self.encode(Vec::new(), handler)
+    }
+
+    pub fn set_complexity(&mut self, _complexity: i32) {}
+
+    pub fn set_dtx(&mut self, _enable: bool) {}
+
+    pub fn reset_state(&mut self) {
+        self.in_buffer.clear();
+    }
+}
@@ -0,0 +1,188 @@
+use std::sync::{Arc, Mutex};
+
+use anyhow::{Error, Result};
+use esp_idf_hal::{
+    delay::BLOCK,
+    i2s::{I2sDriver, I2sRx, I2sTx},
+};
+use log::{error, info};
+
+use crate::{
+    audio::codec::{audio_codec::AudioCodec, opus::decoder::OpusAudioDecoder},
+    setting::settings::Settings,
+};
+
+const DEFAULT_OUTPUT_VOLUME: u8 = 30;
+/// INMP441 输出的是 32 bit 里的高 24 bit，右移 12 位得到 16 bit，顺便放大了 16 倍（和原版 C++ 一样）
+const INPUT_SHIFT: u32 = 12;
+
+/// 没有 codec 芯片的板子：I2S 数字麦克风（INMP441）和 I2S 功放（MAX98357）各用一个 I2S 口。
+///
+/// 应用里的音频流水线是双声道 16 bit 的，所以单声道麦克风的数据复制到两个声道；
+/// 没有硬件音量，输出时用软件缩放
+pub struct SimplexAudioCodec {
+    tx_driver: I2sDriver<'static, I2sTx>,
+    rx_driver: I2sDriver<'static, I2sRx>,
+    input_enabled: bool,
+    output_enabled: bool,
+    output_volume: u8,
+    raw_input: Vec<u8>,
+    scaled_output: Vec<u8>,
+}
+
+impl SimplexAudioCodec {
+    /// `tx_driver` 需要配置成 16 bit 双声道，`rx_driver` 配置成 32 bit 单声道
+    pub fn new(tx_driver: I2sDriver<'static, I2sTx>, rx_driver: I2sDriver<'static, I2sRx>) -> Self {
+        Self {
+            tx_driver,
+            rx_driver,
+            input_enabled: false,
+            output_enabled: false,
+            output_volume: DEFAULT_OUTPUT_VOLUME,
+            raw_input: Vec::new(),
+            scaled_output: Vec::new(),
+        }
+    }
+
+    fn write_all(&mut self, data: &[u8]) -> Result<()> {
+        const CHUNK_SIZE: usize = 4096;
+        for chunk in data.chunks(CHUNK_SIZE) {
+            self.tx_driver.write_all(chunk, BLOCK)?;
+        }
+        Ok(())
+    }
+}
+
+impl AudioCodec for SimplexAudioCodec {
+    fn set_output_volume(&mut self, volume: u8) -> Result<(), Error> {
+        self.output_volume = volume.min(100);
+        Ok(())
+    }
+
+    fn enable_input(&mut self, enable: bool) -> Result<(), Error> {
+        if enable == self.input_enabled {
+            return Ok(());
+        }
+        if enable {
+            self.rx_driver.rx_enable()?;
+        } else {
+            self.rx_driver.rx_disable()?;
+        }
+        self.input_enabled = enable;
+        Ok(())
+    }
+
+    fn enable_output(&mut self, enable: bool) -> Result<(), Error> {
+        if enable == self.output_enabled {
+            return Ok(());
+        }
+        if enable {
+            self.tx_driver.tx_enable()?;
+        } else {
+            self.tx_driver.tx_disable()?;
+        }
+        self.output_enabled = enable;
+        Ok(())
+    }
+
+    fn input_enabled(&self) -> bool {
+        self.input_enabled
+    }
+
+    fn output_enabled(&self) -> bool {
+        self.output_enabled
+    }
+
+    fn input_reference(&self) -> bool {
+        false
+    }
+
+    fn input_channels(&self) -> i32 {
+        2
+    }
+
+    fn start(&mut self) {
+        self.output_volume = match Settings::load() {
+            Ok(settings) if settings.audio.output_volume > 0 => settings.audio.output_volume,
+            Ok(_) => DEFAULT_OUTPUT_VOLUME,
+            Err(e) => {
+                error!("Failed to get audio setting: {:?}", e);
+                DEFAULT_OUTPUT_VOLUME
+            }
+        };
+        if let Err(e) = self.enable_input(true) {
+            error!("Failed to enable input: {:?}", e);
+        }
+        if let Err(e) = self.enable_output(true) {
+            error!("Failed to enable output: {:?}", e);
+        }
+        info!("Simplex audio codec started");
+    }
+
+    fn suspend(&mut self) -> Result<(), Error> {
+        self.enable_output(false)?;
+        self.enable_input(false)?;
+        info!("Audio codec suspended");
+        Ok(())
+    }
+
+    fn resume(&mut self) -> Result<(), Error> {
+        self.enable_input(true)?;
+        self.enable_output(true)?;
+        info!("Audio codec resumed");
+        Ok(())
+    }
+
+    fn read_audio_data(&mut self, buffer: &mut Vec<u8>) -> Result<usize, Error> {
+        // buffer 里每帧是两个 i16（4 字节），麦克风每帧是一个 i32（也是 4 字节）
+        let frames = buffer.len() / 4;
+        self.raw_input.resize(frames * 4, 0);
+        let bytes_read = self.rx_driver.read(&mut self.raw_input, 1000)?;
+
+        let frames_read = bytes_read / 4;
+        for i in 0..frames_read {
+            let raw = i32::from_le_bytes(self.raw_input[i * 4..i * 4 + 4].try_into()?);
+            let sample = (raw >> INPUT_SHIFT).clamp(i16::MIN as i32, i16::MAX as i32) as i16;
+            let bytes = sample.to_le_bytes();
+            buffer[i * 4..i * 4 + 2].copy_from_slice(&bytes);
+            buffer[i * 4 + 2..i * 4 + 4].copy_from_slice(&bytes);
+        }
+        Ok(frames_read * 4)
+    }
+
+    fn output_data(&mut self, data: &[u8]) -> Result<(), Error> {
+        let mut scaled = std::mem::take(&mut self.scaled_output);
+        scaled.clear();
+        let volume = self.output_volume as i32;
+        for sample in data.chunks_exact(2) {
+            let sample = i16::from_le_bytes([sample[0], sample[1]]) as i32 * volume / 100;
+            scaled.extend_from_slice(&(sample as i16).to_le_bytes());
+        }
+        let result = self.write_all(&scaled);
+        self.scaled_output = scaled;
+        if let Err(e) = &result {
+            info!("I2S write error: {:?}", e);
+        }
+        result
+    }
+
+    fn test_play_pcm(&mut self, data: &[u8]) -> Result<(), Error> {
+        self.output_data(data)
+    }
+
+    fn play_opus(
+        &mut self,
+        opus_decoder: Arc<Mutex<OpusAudioDecoder>>,
+        data: &[u8],
+        _pcm_buffer: &mut Vec<i16>,
+    ) -> Result<(), Error> {
+        let pcm_data = opus_decoder.lock().unwrap().decode(data)?;
+        let pcm_bytes: &[u8] = unsafe {
+            core::slice::from_raw_parts(
+                pcm_data.as_ptr() as *const u8,
+                pcm_data.len() * std::mem::size_of::<i16>(),
+            )
+        };
+        self.output_data(pcm_bytes)
+    }
+}
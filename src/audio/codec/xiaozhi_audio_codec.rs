@@ -2,11 +2,18 @@ use std::sync::{Arc, Mutex};
 
 use crate::{
     audio::codec::{
-        audio_codec::AudioCodec, es7210::es7210::Es7210, es8311::Es8311, make_channel_mask,
-        opus::decoder::OpusAudioDecoder, types::CodecSampleInfo,
+        audio_codec::AudioCodec,
+        es7210::{
+            enums::{Es7210Config, Mics},
+            es7210::Es7210,
+        },
+        es8311::Es8311,
+        make_channel_mask,
+        opus::decoder::OpusAudioDecoder,
+        types::CodecSampleInfo,
     },
     i2s::mixed_i2s::MixedI2sDriver,
-    setting::nvs_setting::NvsSetting,
+    setting::settings::Settings,
 };
 use anyhow::{Error, Result};
 use esp_idf_hal::{
@@ -18,6 +25,32 @@ use log::{error, info};
 
 type I2cProxy = shared_bus::I2cProxy<'static, Mutex<I2cDriver<'static>>>;
 const DEFAULT_OUTPUT_VOLUME: u8 = 30;
+const MIC_GAIN_DB: f32 = 30.0;
+/// 喇叭回采信号的增益，和原来一样是 30dB，喇叭声音大时回采削顶的话调小
+const REFERENCE_GAIN_DB: f32 = 30.0;
+/// ES7210 的 TDM 一帧固定 4 个 slot，按 MIC1 ~ MIC4 的顺序
+const TDM_SLOTS: usize = 4;
+
+/// AEC 用的 ES7210 配置：两个麦克风加一路喇叭的回采信号。
+/// TDM 时 MIC1、MIC2 是麦克风，MIC3 接回采信号，4 个 slot 里只用前 3 个；
+/// 标准 I2S 一帧只有两个 slot，只能用 MIC1 做麦克风，MIC2 接回采信号
+fn aec_input_config(tdm: bool) -> Es7210Config {
+    if tdm {
+        Es7210Config {
+            mics: Mics::MIC1 | Mics::MIC2 | Mics::MIC3,
+            gains_db: [MIC_GAIN_DB, MIC_GAIN_DB, REFERENCE_GAIN_DB, 0.0],
+            tdm: true,
+            ..Default::default()
+        }
+    } else {
+        Es7210Config {
+            mics: Mics::MIC1 | Mics::MIC2,
+            gains_db: [MIC_GAIN_DB, REFERENCE_GAIN_DB, 0.0, 0.0],
+            ..Default::default()
+        }
+    }
+}
+
 pub struct XiaozhiAudioCodec {
     input_codec: Es7210<I2cProxy>,
     output_codec: Es8311<I2cProxy>,
@@ -28,16 +61,22 @@ pub struct XiaozhiAudioCodec {
     // i2s_driver: Arc<Mutex<MixedI2sDriver>>,
     input_reference: bool,
     input_channels: i32,
+    /// TDM 时从 I2S 读到的原始数据，去掉没用的 slot 后再交给调用者
+    raw_input: Vec<u8>,
 }
 
 impl XiaozhiAudioCodec {
     pub fn new(
         es8311_i2c_proxy: I2cProxy,
         es7210_i2c_proxy: I2cProxy,
+        es8311_addr: u8,
+        es7210_addr: u8,
         i2s_driver: I2sDriver<'static, I2sBiDir>,
         // i2s_driver: MixedI2sDriver,
+        // 录音的 I2S 是不是 4 个 slot 的 TDM
+        tdm_input: bool,
     ) -> Self {
-        let mut es8311 = Es8311::new(es8311_i2c_proxy);
+        let mut es8311 = Es8311::with_address(es8311_i2c_proxy, es8311_addr);
         let mut delay = Delay::new_default();
         match es8311.open(&mut delay) {
             Ok(_) => {
@@ -49,7 +88,10 @@ impl XiaozhiAudioCodec {
             }
         }
 
-        let mut es7210 = Es7210::new(es7210_i2c_proxy);
+        let input_reference = true;
+
+        let mut es7210 =
+            Es7210::with_config(es7210_i2c_proxy, es7210_addr, aec_input_config(tdm_input));
         info!("初始化ES7210...");
 
         match es7210.open() {
@@ -62,10 +104,13 @@ impl XiaozhiAudioCodec {
             }
         }
 
-        let input_reference = true;
-
-        //一共就两个channels,只有input_reference时才会使用两个channels，而且要是全双工的才行。
-        let input_channels = if input_reference { 2 } else { 1 };
+        //标准 I2S 一共就两个channels,只有input_reference时才会使用两个channels，而且要是全双工的才行。
+        //TDM 时是两个麦克风加一路回采
+        let input_channels = match (input_reference, tdm_input) {
+            (true, true) => 3,
+            (true, false) => 2,
+            (false, _) => 1,
+        };
 
         Self {
             input_codec: es7210,
@@ -76,6 +121,7 @@ impl XiaozhiAudioCodec {
             i2s_driver: Arc::new(Mutex::new(i2s_driver)),
             input_reference: input_reference,
             input_channels,
+            raw_input: Vec::new(),
         }
     }
 }
@@ -132,14 +178,13 @@ impl AudioCodec for XiaozhiAudioCodec {
     }
 
     fn start(&mut self) {
-        match NvsSetting::new("audio") {
-            Ok(setting) => {
-                if let Some(volume) = setting.get_u8("output_volume") {
-                    if volume <= 0 {
-                        self.output_volume = DEFAULT_OUTPUT_VOLUME;
-                    } else {
-                        self.output_volume = volume;
-                    }
+        match Settings::load() {
+            Ok(settings) => {
+                let volume = settings.audio.output_volume;
+                if volume <= 0 {
+                    self.output_volume = DEFAULT_OUTPUT_VOLUME;
+                } else {
+                    self.output_volume = volume;
                 }
             }
             Err(_) => {
@@ -158,11 +203,44 @@ impl AudioCodec for XiaozhiAudioCodec {
         info!("Audio codec started");
     }
 
+    fn suspend(&mut self) -> Result<(), Error> {
+        self.output_codec.suspend()?;
+        self.input_codec.stop()?;
+        info!("Audio codec suspended");
+        Ok(())
+    }
+
+    fn resume(&mut self) -> Result<(), Error> {
+        // suspend 时复位过 ES8311 的寄存器，这里需要重新 open 一次
+        let mut delay = Delay::new_default();
+        self.output_codec.open(&mut delay)?;
+        self.output_codec.enable()?;
+        self.output_codec.set_voice_volume(self.output_volume)?;
+        self.input_codec.enable()?;
+        info!("Audio codec resumed");
+        Ok(())
+    }
+
     fn read_audio_data(&mut self, mut buffer: &mut Vec<u8>) -> Result<usize, Error> {
         let i2s_driver_arc = self.i2s_driver.clone();
         let mut i2s_driver = i2s_driver_arc.lock().unwrap();
-        let bytes_read = i2s_driver.read(&mut buffer, 1000)?;
-        return Ok(bytes_read);
+        if !self.input_codec.is_tdm_mode() {
+            let bytes_read = i2s_driver.read(&mut buffer, 1000)?;
+            return Ok(bytes_read);
+        }
+
+        // TDM 每帧 4 个 i16 slot，只留前 input_channels 个（MIC1、MIC2、回采）
+        let frame_size = self.input_channels as usize * 2;
+        let frames = buffer.len() / frame_size;
+        self.raw_input.resize(frames * TDM_SLOTS * 2, 0);
+        let bytes_read = i2s_driver.read(&mut self.raw_input, 1000)?;
+
+        let frames_read = bytes_read / (TDM_SLOTS * 2);
+        for i in 0..frames_read {
+            let slots = &self.raw_input[i * TDM_SLOTS * 2..i * TDM_SLOTS * 2 + frame_size];
+            buffer[i * frame_size..(i + 1) * frame_size].copy_from_slice(slots);
+        }
+        Ok(frames_read * frame_size)
     }
 
     fn output_data(&mut self, audio_data: &[u8]) -> Result<(), Error> {
@@ -273,3 +351,57 @@ impl AudioCodec for XiaozhiAudioCodec {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{audio::codec::es7210::reg::*, test_support::recording_i2c::RecordingI2c};
+
+    const ADDR: u8 = 0x40;
+
+    fn open(tdm: bool) -> RecordingI2c {
+        let i2c = RecordingI2c::new();
+        let mut es7210 = Es7210::with_config(i2c.clone(), ADDR, aec_input_config(tdm));
+        es7210.open().unwrap();
+        assert!(es7210.is_open());
+        i2c
+    }
+
+    #[test]
+    fn tdm_uses_mic1_to_mic3() {
+        let i2c = open(true);
+
+        // MIC1、MIC2 和回采的 MIC3 选中，增益都是 30dB（第 10 档），MIC4 不选
+        assert_eq!(i2c.register(ADDR, ES7210_MIC1_GAIN_REG_43), 0x1A);
+        assert_eq!(i2c.register(ADDR, ES7210_MIC2_GAIN_REG_44), 0x1A);
+        assert_eq!(i2c.register(ADDR, ES7210_MIC3_GAIN_REG_45), 0x1A);
+        assert_eq!(i2c.register(ADDR, ES7210_MIC4_GAIN_REG_46), 0x00);
+        // ADC12 和 ADC34 的时钟都打开
+        assert_eq!(i2c.register(ADDR, ES7210_CLOCK_OFF_REG_01), 0x20);
+        assert!(i2c
+            .writes(ADDR)
+            .contains(&(ES7210_MIC34_POWER_DOWN_REG_4C, 0x00)));
+        // 16 位 I2S，TDM 时 4 路都从 SDOUT1 输出
+        assert_eq!(i2c.register(ADDR, ES7210_SDP_INTERFACE1_REG_11), 0x60);
+        assert_eq!(i2c.register(ADDR, ES7210_SDP_INTERFACE2_REG_12), 0x02);
+        assert_eq!(i2c.register(ADDR, ES7210_MAINCLK_REG_02), 0xC1);
+    }
+
+    #[test]
+    fn standard_i2s_uses_mic1_and_mic2() {
+        let i2c = open(false);
+
+        assert_eq!(i2c.register(ADDR, ES7210_MIC1_GAIN_REG_43), 0x1A);
+        assert_eq!(i2c.register(ADDR, ES7210_MIC2_GAIN_REG_44), 0x1A);
+        assert_eq!(i2c.register(ADDR, ES7210_MIC3_GAIN_REG_45), 0x00);
+        assert_eq!(i2c.register(ADDR, ES7210_MIC4_GAIN_REG_46), 0x00);
+        // 只打开 ADC12 的时钟和 MIC1/MIC2 的电源
+        assert_eq!(i2c.register(ADDR, ES7210_CLOCK_OFF_REG_01), 0x34);
+        assert!(!i2c
+            .writes(ADDR)
+            .iter()
+            .any(|(reg, _)| *reg == ES7210_MIC34_POWER_DOWN_REG_4C));
+        assert_eq!(i2c.register(ADDR, ES7210_SDP_INTERFACE1_REG_11), 0x60);
+        assert_eq!(i2c.register(ADDR, ES7210_SDP_INTERFACE2_REG_12), 0x00);
+    }
+}
@@ -1,2 +1,4 @@
 pub mod codec;
 pub mod processor;
+#[cfg(feature = "host-sim")]
+pub mod wav;
@@ -1,3 +1,4 @@
+#[cfg(target_os = "espidf")]
 pub mod afe_audio_processor;
 // pub mod afe_audio_processor_new;
 pub mod audio_processor;
@@ -0,0 +1,101 @@
+//! host-sim 用的 WAV 读写，只支持 16 bit PCM。
+
+use std::{
+    fs::File,
+    io::{Seek, SeekFrom, Write},
+    path::Path,
+};
+
+use anyhow::{anyhow, Result};
+
+pub struct WavData {
+    pub sample_rate: u32,
+    pub channels: u16,
+    /// 交错存放的采样
+    pub samples: Vec<i16>,
+}
+
+pub fn read_wav(path: impl AsRef<Path>) -> Result<WavData> {
+    let bytes = std::fs::read(path.as_ref())?;
+    if bytes.len() < 12 || &bytes[0..4] != b"RIFF" || &bytes[8..12] != b"WAVE" {
+        return Err(anyhow!("{} is not a WAV file", path.as_ref().display()));
+    }
+
+    let mut format: Option<(u16, u16, u32, u16)> = None;
+    let mut pos = 12;
+    while pos + 8 <= bytes.len() {
+        let id = &bytes[pos..pos + 4];
+        let size = u32::from_le_bytes(bytes[pos + 4..pos + 8].try_into()?) as usize;
+        let body = &bytes[pos + 8..(pos + 8 + size).min(bytes.len())];
+        if id == b"fmt " && body.len() >= 16 {
+            format = Some((
+                u16::from_le_bytes([body[0], body[1]]),
+                u16::from_le_bytes([body[2], body[3]]),
+                u32::from_le_bytes(body[4..8].try_into()?),
+                u16::from_le_bytes([body[14], body[15]]),
+            ));
+        } else if id == b"data" {
+            let (audio_format, channels, sample_rate, bits) =
+                format.ok_or_else(|| anyhow!("WAV data chunk before fmt chunk"))?;
+            if audio_format != 1 || bits != 16 {
+                return Err(anyhow!(
+                    "only 16 bit PCM WAV is supported, got format {} with {} bits",
+                    audio_format,
+                    bits
+                ));
+            }
+            let samples = body
+                .chunks_exact(2)
+                .map(|sample| i16::from_le_bytes([sample[0], sample[1]]))
+                .collect();
+            return Ok(WavData {
+                sample_rate,
+                channels,
+                samples,
+            });
+        }
+        // chunk 按偶数字节对齐
+        pos += 8 + size + (size & 1);
+    }
+    Err(anyhow!("WAV file has no data chunk"))
+}
+
+/// 边写边更新文件头，进程被直接杀掉时文件也是完整的
+pub struct WavWriter {
+    file: File,
+    data_len: u32,
+}
+
+impl WavWriter {
+    pub fn create(path: impl AsRef<Path>, sample_rate: u32, channels: u16) -> Result<Self> {
+        let mut file = File::create(path)?;
+        let block_align = channels * 2;
+        let mut header = Vec::with_capacity(44);
+        header.extend_from_slice(b"RIFF");
+        header.extend_from_slice(&36u32.to_le_bytes());
+        header.extend_from_slice(b"WAVEfmt ");
+        header.extend_from_slice(&16u32.to_le_bytes());
+        header.extend_from_slice(&1u16.to_le_bytes());
+        header.extend_from_slice(&channels.to_le_bytes());
+        header.extend_from_slice(&sample_rate.to_le_bytes());
+        header.extend_from_slice(&(sample_rate * block_align as u32).to_le_bytes());
+        header.extend_from_slice(&block_align.to_le_bytes());
+        header.extend_from_slice(&16u16.to_le_bytes());
+        header.extend_from_slice(b"data");
+        header.extend_from_slice(&0u32.to_le_bytes());
+        file.write_all(&header)?;
+        Ok(Self { file, data_len: 0 })
+    }
+
+    /// `data` 是小端 16 bit 的 PCM 字节
+    pub fn write(&mut self, data: &[u8]) -> Result<()> {
+        self.file.write_all(data)?;
+        self.data_len += data.len() as u32;
+        self.file.seek(SeekFrom::Start(4))?;
+        self.file.write_all(&(36 + self.data_len).to_le_bytes())?;
+        self.file.seek(SeekFrom::Start(40))?;
+        self.file.write_all(&self.data_len.to_le_bytes())?;
+        self.file.seek(SeekFrom::End(0))?;
+        Ok(())
+    }
+}
@@ -7,6 +7,12 @@ use crate::axp173::{Axp173, Axp173Result, Error, OperationResult};
 
 // use crate::{axp173, Axp173Result, Error, OperationResult};
 
+/// First IRQ status register, the other three follow it consecutively.
+const IRQ_STATUS_FIRST_REG: u8 = 0x44;
+
+/// First IRQ enable register, laid out like the status registers.
+const IRQ_ENABLE_FIRST_REG: u8 = 0x40;
+
 /// An AXP173 interrupt.
 #[derive(Debug, Copy, Clone)]
 #[allow(missing_docs)] // TODO: document IRQs
@@ -93,6 +99,23 @@ impl Irq {
     }
 }
 
+/// Snapshot of the IRQ status registers 0x44..=0x47.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub struct IrqStatus(pub [u8; 4]);
+
+impl IrqStatus {
+    /// Returns `true` if selected IRQ is pending in this snapshot.
+    pub fn is_set(&self, irq: Irq) -> bool {
+        let (status_reg, bit) = irq.to_reg_and_bit();
+        self.0[(status_reg - IRQ_STATUS_FIRST_REG) as usize].get_bit(bit)
+    }
+
+    /// Returns `true` if no IRQ is pending.
+    pub fn is_empty(&self) -> bool {
+        self.0.iter().all(|reg| *reg == 0)
+    }
+}
+
 impl<I, E> Axp173<I>
 where
     I: WriteRead<Error = E> + Write<Error = E>,
@@ -109,6 +132,22 @@ where
         Ok(())
     }
 
+    /// Enables exactly the IRQs in `irqs` and masks all others.
+    /// Writes all four enable registers, so power-on defaults don't matter.
+    pub fn set_enabled_irqs(&mut self, irqs: &[Irq]) -> OperationResult<E> {
+        let mut regs = [0u8; 4];
+        for irq in irqs {
+            let (status_reg, bit) = irq.to_reg_and_bit();
+            regs[(status_reg - IRQ_STATUS_FIRST_REG) as usize].set_bit(bit, true);
+        }
+        for (i, bits) in regs.iter().enumerate() {
+            self.write_u8(IRQ_ENABLE_FIRST_REG + i as u8, *bits)
+                .map_err(Error::I2c)?;
+        }
+
+        Ok(())
+    }
+
     /// Clears previously fired selected IRQ.
     pub fn clear_irq(&mut self, irq: Irq) -> OperationResult<E> {
         let (status_reg, bit) = irq.to_reg_and_bit();
@@ -137,4 +176,27 @@ where
         let reg_val = self.read_u8(status_reg).map_err(Error::I2c)?;
         Ok(reg_val.get_bit(bit))
     }
+
+    /// Reads all four IRQ status registers at once.
+    pub fn read_irq_status(&mut self) -> Axp173Result<IrqStatus, E> {
+        let mut buf = [0u8; 4];
+        self.read_bytes(IRQ_STATUS_FIRST_REG, &mut buf)
+            .map_err(Error::I2c)?;
+
+        Ok(IrqStatus(buf))
+    }
+
+    /// Clears exactly the IRQs that are pending in `status`.
+    /// IRQs fired after `status` was read stay pending.
+    pub fn clear_irq_status(&mut self, status: &IrqStatus) -> OperationResult<E> {
+        for (i, bits) in status.0.iter().enumerate() {
+            if *bits != 0 {
+                // Writing '1' clears the bit, '0' leaves it untouched
+                self.write_u8(IRQ_STATUS_FIRST_REG + i as u8, *bits)
+                    .map_err(Error::I2c)?;
+            }
+        }
+
+        Ok(())
+    }
 }
@@ -18,7 +18,7 @@ mod regs;
 mod units;
 
 pub use adc::AdcSettings;
-pub use irq::Irq;
+pub use irq::{Irq, IrqStatus};
 pub use ldo::{Ldo, LdoKind};
 use log::info;
 pub use pek::{BootTime, LongPressTime, ShutdownLongPressTime};
@@ -1,22 +1,35 @@
-use std::sync::{Arc, Mutex};
+use std::{
+    sync::{Arc, Mutex},
+    time::Duration,
+};
 
 use anyhow::{Error, Result};
 
-use crate::{audio::codec::audio_codec::AudioCodec, wifi::wifi_driver::WifiStation};
+use crate::{
+    audio::codec::audio_codec::AudioCodec, boards::board_buttons::BoardButtons, camera::Camera,
+    display::Display, led::service::LedService, power::policy::WakeSource,
+    wifi::wifi_driver::WifiStation,
+};
 
-// 定义主板的抽象
+// 定义主板的抽象。应用只通过这个 trait 访问硬件，引脚分配等都留在各个板子的文件里
 pub trait Board {
     // 关联类型：具体的 WiFi 驱动类型，只要它实现了 WifiStation
     type WifiDriver: WifiStation;
 
+    // 板子的名字，和 cargo feature `board-xxx` 对应
+    fn name(&self) -> &'static str;
+
+    // 应用设置好按键回调之后调用，初始化按键、PMIC 等
+    fn init(&mut self) -> Result<()>;
+
     // 获取该主板的 WiFi 驱动
     // fn get_wifi(&self) -> Self::WifiDriver;
     fn init_wifi(&mut self) -> Result<(), Error>;
 
     fn get_wifi_driver(&self) -> &Self::WifiDriver;
 
-    fn on_touch_button_clicked(&mut self, on_clicked: Box<dyn FnMut() + Send + 'static>);
-    fn on_volume_button_clicked(&mut self, on_clicked: Box<dyn FnMut() + Send + 'static>);
+    // 按键，需要在 init 之前设置回调
+    fn get_buttons(&mut self) -> &mut BoardButtons;
 
     fn get_audio_codec(&mut self) -> Arc<Mutex<dyn AudioCodec>>;
 
@@ -26,7 +39,30 @@ pub trait Board {
 
     fn start_network(&mut self) -> Result<()>;
 
-    // 你还可以加其他的，比如 Display
-    // type DisplayDriver: DrawTarget;
-    // fn get_display(&self) -> Self::DisplayDriver;
+    // light sleep 醒来后检查网络，掉线的话交给后台重连，不能阻塞也不能进入配网
+    fn resume_network(&mut self) -> Result<()>;
+
+    fn get_display(&mut self) -> &mut dyn Display;
+
+    // 屏幕背光亮度 0-100，没有背光的板子直接返回 Ok
+    fn set_backlight(&mut self, brightness: u8) -> Result<()>;
+
+    // 状态灯，没有灯的板子返回 None
+    fn get_led(&self) -> Option<LedService>;
+
+    // 摄像头，没有摄像头或者初始化失败的板子返回 None
+    fn get_camera(&self) -> Option<Arc<Mutex<dyn Camera>>>;
+
+    // 通过 PMIC 切断整机供电，没有 PMIC 的板子返回错误
+    fn power_off(&mut self) -> Result<()>;
+
+    // 对应 C++ 的 SetPowerSaveMode
+    fn set_power_save_mode(&mut self, enabled: bool) -> Result<()>;
+
+    // 进入 light sleep，阻塞到被按键、PMIC IRQ 或定时器唤醒为止
+    fn enter_light_sleep(&mut self, max_duration: Option<Duration>) -> Result<WakeSource>;
+
+    // 进入 deep sleep，唤醒后芯片会重新启动，所以不会返回。
+    // wakeup_after 是定时唤醒的时间，有闹钟时用来按时醒来
+    fn enter_deep_sleep(&mut self, wakeup_after: Option<Duration>) -> Result<()>;
 }
@@ -0,0 +1,165 @@
+use std::time::Duration;
+
+use anyhow::Result;
+use log::info;
+
+use crate::common::gpio_button::{ButtonCombo, DEFAULT_LONG_PRESS};
+// host-sim 下换成软件按键，由模拟器触发
+#[cfg(not(feature = "host-sim"))]
+use crate::common::gpio_button::Button;
+#[cfg(feature = "host-sim")]
+use crate::common::sim_button::Button;
+
+// 组合键里的按键编号
+const TOUCH_BUTTON_ID: u8 = 0;
+const VOLUME_BUTTON_ID: u8 = 1;
+/// 同时按住 boot 键和音量键这么久算组合键长按
+const COMBO_LONG_PRESS: Duration = Duration::from_secs(5);
+
+type Callback = Box<dyn FnMut() + Send + 'static>;
+
+/// 板子上的两个按键：boot（touch）键和音量键。
+///
+/// 应用先通过 `on_xxx` 设置回调，板子在 `Board::init` 里调用 `init` 注册到按键驱动上。
+/// 按键由这个结构体持有，回调的内存在 Button drop 时释放
+pub struct BoardButtons {
+    touch_button: Button,
+    volume_button: Button,
+    on_touch_button_clicked: Option<Callback>,
+    on_volume_button_clicked: Option<Callback>,
+    on_touch_button_long_pressed: Option<Callback>,
+    on_touch_button_double_clicked: Option<Callback>,
+    on_touch_button_pressed: Option<Callback>,
+    on_touch_button_released: Option<Callback>,
+    on_buttons_combo_long_pressed: Option<Callback>,
+}
+
+impl BoardButtons {
+    /// `active_level` 是按键按下时的电平，两个按键接法相同
+    pub fn new(touch_button_gpio: i32, volume_button_gpio: i32, active_level: u8) -> Result<Self> {
+        Ok(Self {
+            touch_button: Button::with_active_level(touch_button_gpio, active_level)?,
+            volume_button: Button::with_active_level(volume_button_gpio, active_level)?,
+            on_touch_button_clicked: None,
+            on_volume_button_clicked: None,
+            on_touch_button_long_pressed: None,
+            on_touch_button_double_clicked: None,
+            on_touch_button_pressed: None,
+            on_touch_button_released: None,
+            on_buttons_combo_long_pressed: None,
+        })
+    }
+
+    pub fn on_touch_button_clicked(&mut self, on_clicked: Callback) {
+        self.on_touch_button_clicked = Some(on_clicked);
+    }
+
+    pub fn on_volume_button_clicked(&mut self, on_clicked: Callback) {
+        self.on_volume_button_clicked = Some(on_clicked);
+    }
+
+    pub fn on_touch_button_long_pressed(&mut self, on_long_pressed: Callback) {
+        self.on_touch_button_long_pressed = Some(on_long_pressed);
+    }
+
+    pub fn on_touch_button_double_clicked(&mut self, on_double_clicked: Callback) {
+        self.on_touch_button_double_clicked = Some(on_double_clicked);
+    }
+
+    /// 按下立即触发，用于按住说话
+    pub fn on_touch_button_pressed(&mut self, on_pressed: Callback) {
+        self.on_touch_button_pressed = Some(on_pressed);
+    }
+
+    pub fn on_touch_button_released(&mut self, on_released: Callback) {
+        self.on_touch_button_released = Some(on_released);
+    }
+
+    /// 同时长按 boot 键和音量键
+    pub fn on_buttons_combo_long_pressed(&mut self, on_long_pressed: Callback) {
+        self.on_buttons_combo_long_pressed = Some(on_long_pressed);
+    }
+
+    /// 把已经设置的回调注册到按键驱动上
+    pub fn init(&mut self) -> Result<()> {
+        info!("Init buttons");
+        let combo = ButtonCombo::new(&[TOUCH_BUTTON_ID, VOLUME_BUTTON_ID]);
+
+        // 两个按键的按下/松开都要更新组合键的状态
+        let touch_combo = combo.clone();
+        let mut on_pressed = self.on_touch_button_pressed.take();
+        self.touch_button.on_press_down(move || {
+            touch_combo.press(TOUCH_BUTTON_ID);
+            if let Some(on_pressed) = on_pressed.as_mut() {
+                on_pressed();
+            }
+        })?;
+        let touch_combo = combo.clone();
+        let mut on_released = self.on_touch_button_released.take();
+        self.touch_button.on_press_up(move || {
+            touch_combo.release(TOUCH_BUTTON_ID);
+            if let Some(on_released) = on_released.as_mut() {
+                on_released();
+            }
+        })?;
+        let volume_combo = combo.clone();
+        self.volume_button.on_press_down(move || {
+            volume_combo.press(VOLUME_BUTTON_ID);
+        })?;
+        let volume_combo = combo.clone();
+        self.volume_button.on_press_up(move || {
+            volume_combo.release(VOLUME_BUTTON_ID);
+        })?;
+
+        if let Some(on_clicked) = self.on_touch_button_clicked.take() {
+            self.touch_button
+                .on_click(unless_combo(&combo, TOUCH_BUTTON_ID, on_clicked))?;
+        }
+
+        if let Some(on_double_clicked) = self.on_touch_button_double_clicked.take() {
+            self.touch_button.on_double_click(unless_combo(
+                &combo,
+                TOUCH_BUTTON_ID,
+                on_double_clicked,
+            ))?;
+        }
+
+        if let Some(on_long_pressed) = self.on_touch_button_long_pressed.take() {
+            self.touch_button.on_long_press(
+                DEFAULT_LONG_PRESS,
+                unless_combo(&combo, TOUCH_BUTTON_ID, on_long_pressed),
+            )?;
+        }
+
+        if let Some(on_clicked) = self.on_volume_button_clicked.take() {
+            self.volume_button
+                .on_click(unless_combo(&combo, VOLUME_BUTTON_ID, on_clicked))?;
+        }
+
+        // 长按时间从 boot 键按下开始算，到时两个键都还按着才触发
+        if let Some(mut on_combo_long_pressed) = self.on_buttons_combo_long_pressed.take() {
+            let touch_combo = combo.clone();
+            self.touch_button.on_long_press(COMBO_LONG_PRESS, move || {
+                if touch_combo.is_active() {
+                    on_combo_long_pressed();
+                }
+            })?;
+        }
+
+        Ok(())
+    }
+}
+
+// 组合键按下之后，组合里的按键的单击、长按等不再触发
+fn unless_combo(
+    combo: &ButtonCombo,
+    id: u8,
+    mut callback: Callback,
+) -> impl FnMut() + Send + 'static {
+    let combo = combo.clone();
+    move || {
+        if !combo.is_suppressed(id) {
+            callback();
+        }
+    }
+}
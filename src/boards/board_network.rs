@@ -0,0 +1,153 @@
+use std::sync::mpsc::Sender;
+
+use anyhow::Result;
+#[cfg(feature = "provisioning-ble")]
+use esp_idf_hal::modem::BluetoothModem;
+use esp_idf_hal::modem::Modem;
+use esp_idf_svc::eventloop::EspSystemEventLoop;
+use log::{error, info};
+
+#[cfg(feature = "provisioning-ble")]
+use crate::wifi::ble_provisioning::BleProvisioning;
+use crate::{
+    common::{event::AppEvent, httpd_server::ensure_ota_token},
+    display::Display,
+    wifi::{
+        station_manager::StationManager,
+        wifi_driver::{Esp32WifiDriver, WifiAP, WifiStation},
+    },
+};
+
+/// 所有板子共用的 WiFi 部分：连接已保存的 WiFi，连不上时进入配网
+pub struct BoardNetwork {
+    wifi_driver: Esp32WifiDriver,
+    station_manager: StationManager,
+    /// BLE 配网用的蓝牙 modem，启动配网时取走
+    #[cfg(feature = "provisioning-ble")]
+    bt_modem: Option<BluetoothModem<'static>>,
+    #[cfg(feature = "provisioning-ble")]
+    ble_provisioning: Option<BleProvisioning>,
+    wifi_config_mode: bool,
+    app_event_sender: Sender<AppEvent>,
+}
+
+impl BoardNetwork {
+    pub fn new(modem: Modem<'static>, app_event_sender: Sender<AppEvent>) -> Result<Self> {
+        let sysloop = EspSystemEventLoop::take()?;
+        // 打开 BLE 配网时 wifi 和蓝牙共用 modem，需要拆分
+        #[cfg(feature = "provisioning-ble")]
+        let (wifi_modem, bt_modem) = modem.split();
+        #[cfg(not(feature = "provisioning-ble"))]
+        let wifi_modem = modem;
+        let wifi_driver = Esp32WifiDriver::new(wifi_modem, sysloop.clone())?;
+        let station_manager =
+            StationManager::new(wifi_driver.esp_wifi(), sysloop, app_event_sender.clone());
+
+        Ok(Self {
+            wifi_driver,
+            station_manager,
+            #[cfg(feature = "provisioning-ble")]
+            bt_modem: Some(bt_modem),
+            #[cfg(feature = "provisioning-ble")]
+            ble_provisioning: None,
+            wifi_config_mode: false,
+            app_event_sender,
+        })
+    }
+
+    pub fn wifi_driver(&self) -> &Esp32WifiDriver {
+        &self.wifi_driver
+    }
+
+    pub fn wifi_driver_mut(&mut self) -> &mut Esp32WifiDriver {
+        &mut self.wifi_driver
+    }
+
+    pub fn is_wifi_config_mode(&self) -> bool {
+        self.wifi_config_mode
+    }
+
+    pub fn start_wifi_station(&mut self) -> Result<bool> {
+        // 按信号强度和最近连接时间排序，依次尝试已保存的 WiFi
+        let connected = self.station_manager.connect_best()?;
+        if connected {
+            self.station_manager.start_monitor()?;
+        }
+        Ok(connected)
+    }
+
+    /// light sleep 醒来后检查 WiFi，重连交给 StationManager
+    pub fn resume_network(&self) {
+        self.station_manager.check_connection();
+    }
+
+    /// 进入配网，SoftAP 配网的二维码显示在 `display` 上
+    pub fn start_wifi_ap(&mut self, display: &mut dyn Display) -> Result<bool> {
+        // 配网时会切换 WiFi 模式并测试连接，这些断开不能触发重连
+        self.station_manager.set_auto_reconnect(false);
+        self.wifi_config_mode = true;
+
+        // 配网需要拿着设备操作，忘记了本地上传固件用的 token 时可以在这里看到
+        match ensure_ota_token() {
+            Ok(token) => display.show_local_token(&token),
+            Err(e) => error!("读取 OTA token 出错：{:?}", e),
+        }
+
+        #[cfg(feature = "provisioning-softap")]
+        match self.wifi_driver.start_ap("xiaozhi_ap", "") {
+            Ok(ip_info) => match self.wifi_driver.start_http_server() {
+                Ok(_) => {
+                    info!(
+                        "成功启动http server,请访问 http://{}",
+                        ip_info.ip.to_string()
+                    );
+
+                    let url = format!("http://{}", ip_info.ip.to_string());
+
+                    display.show_qrcode(&url);
+                    self.app_event_sender
+                        .send(AppEvent::PlayAudioAlert("wificonfig".to_string()))?;
+                }
+                Err(e) => {
+                    error!("启动http server 出错：{:?}", e);
+                    return Err(e.into());
+                }
+            },
+            Err(err) => {
+                error!("启动http server 出错：{:?}", err);
+            }
+        }
+
+        #[cfg(feature = "provisioning-ble")]
+        if let Some(bt_modem) = self.bt_modem.take() {
+            match BleProvisioning::start(
+                bt_modem,
+                self.wifi_driver.esp_wifi(),
+                self.wifi_driver.sysloop(),
+            ) {
+                Ok(ble_provisioning) => {
+                    self.ble_provisioning = Some(ble_provisioning);
+                    // 只有 BLE 配网时没有二维码，需要提示用户用 App 配网
+                    #[cfg(not(feature = "provisioning-softap"))]
+                    self.app_event_sender
+                        .send(AppEvent::PlayAudioAlert("wificonfig".to_string()))?;
+                }
+                Err(e) => {
+                    error!("启动 BLE 配网出错：{:?}", e);
+                }
+            }
+        }
+
+        Ok(true)
+    }
+
+    pub fn start_network(&mut self, display: &mut dyn Display) -> Result<()> {
+        info!("Start network");
+        let wifi_connected = self.start_wifi_station()?;
+        if !wifi_connected {
+            self.start_wifi_ap(display)?;
+        }
+
+        Ok(())
+    }
+}
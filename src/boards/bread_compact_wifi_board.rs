@@ -0,0 +1,237 @@
+//! 面包板版本（bread-compact-wifi）：ESP32-S3 + INMP441 麦克风 + MAX98357 功放，
+//! 引脚和原版 xiaozhi-esp32 的 `bread-compact-wifi` 一样，定义在 `boards/bread-compact-wifi.toml`。
+//!
+//! 板子上的 SSD1306 OLED 还没有驱动，状态只打到日志里；没有 PMIC，不能软关机。
+
+use std::{
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use anyhow::{anyhow, Result};
+use esp_idf_hal::{
+    gpio::AnyIOPin,
+    i2s::{
+        config::{Config, SlotMode, StdClkConfig, StdConfig, StdGpioConfig, StdSlotConfig},
+        I2sDriver,
+    },
+    peripherals::Peripherals,
+};
+use log::{error, info};
+
+use crate::{
+    audio::codec::{
+        audio_codec::AudioCodec, simplex_audio_codec::SimplexAudioCodec, AUDIO_INPUT_SAMPLE_RATE,
+    },
+    boards::{
+        board::Board,
+        board_buttons::BoardButtons,
+        board_network::BoardNetwork,
+        config::{self, data_bit_width, input_pin, io_pin, output_pin},
+    },
+    camera::Camera,
+    common::application_context::ApplicationContext,
+    display::{no_display::NoDisplay, Display},
+    led::{service::LedService, WS2812RMT},
+    power::policy::WakeSource,
+    wifi::wifi_driver::{Esp32WifiDriver, WifiStation},
+};
+
+const BOOT_BUTTON_GPIO: i32 = config::buttons::BOOT as i32;
+/// 音量+ 键当作音量键用
+const VOLUME_BUTTON_GPIO: i32 = config::buttons::VOLUME as i32;
+
+pub struct BreadCompactWifiBoard {
+    network: BoardNetwork,
+    display: NoDisplay,
+    audio_codec: Arc<Mutex<dyn AudioCodec + 'static>>,
+    buttons: BoardButtons,
+    led: Option<LedService>,
+}
+
+impl BreadCompactWifiBoard {
+    pub fn new(app_context: ApplicationContext) -> Result<Self> {
+        let peripherals: Peripherals = Peripherals::take()?;
+
+        let network = BoardNetwork::new(peripherals.modem, app_context.app_event_sender.clone())?;
+
+        // 功放：16 bit 双声道，MAX98357 会把左右声道混成一个
+        let tx_config = config::i2s::FORMAT.std_config(config::i2s::SAMPLE_RATE, config::i2s::BITS);
+        let tx_driver = I2sDriver::new_std_tx(
+            peripherals.i2s0,
+            &tx_config,
+            io_pin(config::i2s::BCLK),
+            output_pin(config::i2s::DOUT),
+            Option::<AnyIOPin<'static>>::None,
+            io_pin(config::i2s::WS),
+        )?;
+
+        // 麦克风：INMP441 只在 32 bit 的时钟下工作，L/R 脚接地输出在左声道
+        let rx_config = StdConfig::new(
+            Config::default(),
+            StdClkConfig::from_sample_rate_hz(AUDIO_INPUT_SAMPLE_RATE),
+            StdSlotConfig::philips_slot_default(
+                data_bit_width(config::i2s_mic::BITS),
+                SlotMode::Mono,
+            ),
+            StdGpioConfig::default(),
+        );
+        let rx_driver = I2sDriver::new_std_rx(
+            peripherals.i2s1,
+            &rx_config,
+            io_pin(config::i2s_mic::BCLK),
+            input_pin(config::i2s_mic::DIN),
+            Option::<AnyIOPin<'static>>::None,
+            io_pin(config::i2s_mic::WS),
+        )?;
+
+        let audio_codec = SimplexAudioCodec::new(tx_driver, rx_driver);
+
+        let buttons = BoardButtons::new(
+            BOOT_BUTTON_GPIO,
+            VOLUME_BUTTON_GPIO,
+            config::buttons::ACTIVE_LEVEL,
+        )?;
+
+        // 状态灯启动失败不影响其他功能
+        let led = WS2812RMT::new(output_pin(config::led::DATA), peripherals.rmt.channel0)
+            .and_then(|driver| LedService::start(driver, config::led::PIXELS))
+            .map_err(|e| error!("Failed to start status led: {:?}", e))
+            .ok();
+
+        Ok(Self {
+            network,
+            display: NoDisplay,
+            audio_codec: Arc::new(Mutex::new(audio_codec)),
+            buttons,
+            led,
+        })
+    }
+}
+
+impl Board for BreadCompactWifiBoard {
+    type WifiDriver = Esp32WifiDriver;
+
+    fn name(&self) -> &'static str {
+        config::NAME
+    }
+
+    fn init(&mut self) -> Result<()> {
+        self.buttons.init()
+    }
+
+    fn init_wifi(&mut self) -> Result<()> {
+        self.start_network()
+    }
+
+    fn get_wifi_driver(&self) -> &Self::WifiDriver {
+        self.network.wifi_driver()
+    }
+
+    fn get_buttons(&mut self) -> &mut BoardButtons {
+        &mut self.buttons
+    }
+
+    fn get_audio_codec(&mut self) -> Arc<Mutex<dyn AudioCodec>> {
+        Arc::clone(&self.audio_codec)
+    }
+
+    fn start_wifi_station(&mut self) -> Result<bool> {
+        self.network.start_wifi_station()
+    }
+
+    fn start_wifi_ap(&mut self) -> Result<bool> {
+        self.network.start_wifi_ap(&mut self.display)
+    }
+
+    fn start_network(&mut self) -> Result<()> {
+        self.network.start_network(&mut self.display)
+    }
+
+    fn resume_network(&mut self) -> Result<()> {
+        self.network.resume_network();
+        Ok(())
+    }
+
+    fn get_display(&mut self) -> &mut dyn Display {
+        &mut self.display
+    }
+
+    fn set_backlight(&mut self, _brightness: u8) -> Result<()> {
+        // OLED 没有背光
+        Ok(())
+    }
+
+    fn get_led(&self) -> Option<LedService> {
+        self.led.clone()
+    }
+
+    fn get_camera(&self) -> Option<Arc<Mutex<dyn Camera>>> {
+        None
+    }
+
+    fn power_off(&mut self) -> Result<()> {
+        Err(anyhow!("bread-compact-wifi has no PMIC, cannot power off"))
+    }
+
+    fn set_power_save_mode(&mut self, enabled: bool) -> Result<()> {
+        self.network.wifi_driver_mut().set_power_save(enabled)
+    }
+
+    fn enter_light_sleep(&mut self, max_duration: Option<Duration>) -> Result<WakeSource> {
+        use esp_idf_sys::*;
+
+        info!("Enter light sleep, max duration: {:?}", max_duration);
+        unsafe {
+            for gpio in [BOOT_BUTTON_GPIO, VOLUME_BUTTON_GPIO] {
+                esp!(gpio_wakeup_enable(
+                    gpio,
+                    gpio_int_type_t_GPIO_INTR_LOW_LEVEL
+                ))?;
+            }
+            esp!(esp_sleep_enable_gpio_wakeup())?;
+            if let Some(max_duration) = max_duration {
+                esp!(esp_sleep_enable_timer_wakeup(
+                    max_duration.as_micros() as u64
+                ))?;
+            }
+
+            esp!(esp_light_sleep_start())?;
+
+            let cause = esp_sleep_get_wakeup_cause();
+            esp!(esp_sleep_disable_wakeup_source(
+                esp_sleep_source_t_ESP_SLEEP_WAKEUP_ALL
+            ))?;
+            for gpio in [BOOT_BUTTON_GPIO, VOLUME_BUTTON_GPIO] {
+                esp!(gpio_wakeup_disable(gpio))?;
+            }
+
+            Ok(match cause {
+                esp_sleep_source_t_ESP_SLEEP_WAKEUP_TIMER => WakeSource::Timer,
+                esp_sleep_source_t_ESP_SLEEP_WAKEUP_GPIO => WakeSource::Gpio,
+                _ => WakeSource::Other,
+            })
+        }
+    }
+
+    fn enter_deep_sleep(&mut self, wakeup_after: Option<Duration>) -> Result<()> {
+        use esp_idf_sys::*;
+
+        info!("Enter deep sleep, wakeup after: {:?}", wakeup_after);
+        // GPIO40 不是 RTC GPIO，只能用 BOOT 键唤醒
+        unsafe {
+            esp!(rtc_gpio_pullup_en(BOOT_BUTTON_GPIO))?;
+            esp!(rtc_gpio_pulldown_dis(BOOT_BUTTON_GPIO))?;
+            esp!(esp_sleep_enable_ext1_wakeup(
+                1 << BOOT_BUTTON_GPIO,
+                esp_sleep_ext1_wakeup_mode_t_ESP_EXT1_WAKEUP_ANY_LOW
+            ))?;
+            if let Some(wakeup_after) = wakeup_after {
+                esp!(esp_sleep_enable_timer_wakeup(
+                    wakeup_after.as_micros() as u64
+                ))?;
+            }
+            esp_deep_sleep_start();
+        }
+    }
+}
@@ -1,4 +0,0 @@
-pub trait BoardNetwork {
-    fn new() -> Self;
-    fn init(&self);
-}
@@ -0,0 +1,51 @@
+//! 当前板子的引脚和外设配置。
+//!
+//! 常量由 build.rs 根据 `boards/<板子>.toml` 生成，生成前已经检查过引脚不重复、不是 flash 引脚，
+//! 所以下面按编号取引脚是安全的：同一个引脚不会被两个驱动拿到。
+
+use esp_idf_hal::{
+    gpio::{AnyIOPin, AnyInputPin, AnyOutputPin},
+    i2s::config::{DataBitWidth, StdConfig},
+};
+
+include!(concat!(env!("OUT_DIR"), "/board_config.rs"));
+
+/// I2S 的数据格式
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum I2sFormat {
+    Philips,
+    Msb,
+    Pcm,
+}
+
+impl I2sFormat {
+    pub fn std_config(self, sample_rate: u32, bits: u8) -> StdConfig {
+        let bits = data_bit_width(bits);
+        match self {
+            I2sFormat::Philips => StdConfig::philips(sample_rate, bits),
+            I2sFormat::Msb => StdConfig::msb(sample_rate, bits),
+            I2sFormat::Pcm => StdConfig::pcm(sample_rate, bits),
+        }
+    }
+}
+
+/// build.rs 只允许 16、24、32 位
+pub fn data_bit_width(bits: u8) -> DataBitWidth {
+    match bits {
+        24 => DataBitWidth::Bits24,
+        32 => DataBitWidth::Bits32,
+        _ => DataBitWidth::Bits16,
+    }
+}
+
+pub fn io_pin(gpio: u8) -> AnyIOPin<'static> {
+    unsafe { AnyIOPin::steal(gpio) }
+}
+
+pub fn input_pin(gpio: u8) -> AnyInputPin<'static> {
+    unsafe { AnyInputPin::steal(gpio) }
+}
+
+pub fn output_pin(gpio: u8) -> AnyOutputPin<'static> {
+    unsafe { AnyOutputPin::steal(gpio) }
+}
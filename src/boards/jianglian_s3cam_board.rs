@@ -1,66 +1,82 @@
 use std::{
-    collections::{HashSet, VecDeque},
+    collections::VecDeque,
     sync::{mpsc::Sender, Arc, Mutex, MutexGuard},
+    time::Duration,
 };
 
 use anyhow::{Error, Ok, Result};
 use esp_idf_hal::{
     gpio::{AnyInputPin, Pin},
     i2c::{I2cConfig, I2cDriver},
-    i2s::{
-        config::{DataBitWidth, StdConfig, TdmConfig},
-        I2sBiDir, I2sDriver,
-    },
+    i2s::{config::TdmConfig, I2sBiDir, I2sDriver},
     ledc::{config::TimerConfig, LedcDriver, LedcTimerDriver},
     peripherals::Peripherals,
     spi::SpiDriver,
     units::*,
 };
-use esp_idf_svc::eventloop::EspSystemEventLoop;
 use log::{error, info};
+use mipidsi::ModelOptions;
 
 use crate::{
     audio::codec::{audio_codec::AudioCodec, xiaozhi_audio_codec::XiaozhiAudioCodec},
     axp173::{Axp173, Ldo},
-    boards::board::Board,
-    common::{application_context::ApplicationContext, gpio_button::Button},
-    display::{lcd::st7789::LcdSt7789, Display},
-    i2s::mixed_i2s::MixedI2sDriver,
-    wifi::{
-        ssid_manager::SsidMananger,
-        wifi_driver::{Esp32WifiDriver, WifiAP, WifiStation},
+    boards::{
+        board::Board,
+        board_buttons::BoardButtons,
+        board_network::BoardNetwork,
+        config::{self, input_pin, io_pin, output_pin},
+    },
+    camera::{
+        esp_camera::{CameraPins, EspCamera},
+        Camera,
     },
+    common::application_context::ApplicationContext,
+    display::{
+        backlight::PwmBacklight,
+        lcd::st7789::{LcdConfig, LcdSt7789},
+        Display,
+    },
+    i2s::mixed_i2s::MixedI2sDriver,
+    led::{service::LedService, WS2812RMT},
+    power::{pmic_irq::PmicIrqService, policy::WakeSource},
+    wifi::wifi_driver::{Esp32WifiDriver, WifiStation},
 };
 use shared_bus::{BusManager, BusManagerStd};
 
+// 引脚定义在 boards/jianglian-s3cam.toml
+const BOOT_BUTTON_GPIO: i32 = config::buttons::BOOT as i32;
+const VOLUME_BUTTON_GPIO: i32 = config::buttons::VOLUME as i32;
+const PMIC_IRQ_GPIO: i32 = config::pmic::IRQ as i32;
+
+fn lcd_window_offset(_: &ModelOptions) -> (u16, u16) {
+    (config::display::OFFSET_X, config::display::OFFSET_Y)
+}
+
 pub struct JiangLianS3CamBoard {
-    wifi_driver: Esp32WifiDriver,
-    display: Box<dyn Display>,
+    network: BoardNetwork,
+    display: LcdSt7789,
+    backlight: PwmBacklight,
     audio_codec: Arc<Mutex<dyn AudioCodec + 'static>>,
     bus_manager: &'static BusManager<Mutex<I2cDriver<'static>>>,
-    touch_button: &'static mut Button,
-    volume_button: &'static mut Button,
-
-    on_touch_button_clicked: Option<Box<dyn FnMut() + Send + 'static>>,
-    on_volume_button_clicked: Option<Box<dyn FnMut() + Send + 'static>>,
-    wifi_config_mode: bool,
+    buttons: BoardButtons,
+    pmic_irq_pin: Option<AnyInputPin<'static>>,
+    led: Option<LedService>,
+    camera: Option<Arc<Mutex<dyn Camera>>>,
     app_context: ApplicationContext,
 }
 
 impl JiangLianS3CamBoard {
     pub fn new(app_context: ApplicationContext) -> Result<Self, Error> {
         let peripherals: Peripherals = Peripherals::take().unwrap();
-        let pins = peripherals.pins;
 
-        let sysloop = EspSystemEventLoop::take()?;
-        let wifi_driver = Esp32WifiDriver::new(peripherals.modem, sysloop.clone())?;
+        let network = BoardNetwork::new(peripherals.modem, app_context.app_event_sender.clone())?;
 
-        let dc = pins.gpio7;
+        let dc = output_pin(config::display::DC);
         // SPI 总线引脚 (使用硬件 SPI2)
-        let sck = pins.gpio5;
-        let sdi = pins.gpio4; // MOSI 在驱动中通常被称为 SDI (Serial Data In)
+        let sck = output_pin(config::display::SCLK);
+        let sdi = output_pin(config::display::MOSI); // MOSI 在驱动中通常被称为 SDI (Serial Data In)
         let sdo = Option::<AnyInputPin>::None; // MISO
-        let cs = pins.gpio6; // 直接使用引脚，而不是PinDriver
+        let cs = output_pin(config::display::CS); // 直接使用引脚，而不是PinDriver
 
         // 3. 初始化 SPI 驱动
         // 创建 SPI 驱动程序实例
@@ -79,18 +95,29 @@ impl JiangLianS3CamBoard {
             &TimerConfig::new().frequency(25000.Hz().into()),
         )
         .unwrap();
-        let backlight_pin = pins.gpio8;
+        let backlight_pin = output_pin(config::display::BACKLIGHT);
 
         // 2. 配置LEDC通道，并绑定到背光引脚
         let channel_led: LedcDriver<'_> =
             LedcDriver::new(peripherals.ledc.channel0, timer_driver, backlight_pin).unwrap();
 
-        let display = LcdSt7789::new(driver, dc.into(), cs.into(), channel_led)?;
+        // 背光是低电平点亮的
+        let mut backlight = PwmBacklight::new(channel_led, config::display::BACKLIGHT_INVERTED);
+        backlight.set_brightness(100)?;
+
+        let lcd_config = LcdConfig {
+            width: config::display::WIDTH,
+            height: config::display::HEIGHT,
+            window_offset: lcd_window_offset,
+            rotation: config::display::ROTATION,
+            mirror: config::display::MIRROR,
+        };
+        let display = LcdSt7789::new(driver, dc, cs, &lcd_config)?;
         // display.init()?;
 
         // 初始化 I2C 驱动和总线管理器
-        let sda = pins.gpio1;
-        let scl = pins.gpio2;
+        let sda = io_pin(config::i2c::SDA);
+        let scl = io_pin(config::i2c::SCL);
         let i2c: esp_idf_hal::i2c::I2C1 = peripherals.i2c1;
         let config = I2cConfig::new();
 
@@ -109,10 +136,20 @@ impl JiangLianS3CamBoard {
         //    这块内存将永远不会被释放（直到断电），从而满足了生命周期要求。
         let bus_manager = Box::leak(manager_box);
 
-        let touch_button_box = Box::new(Button::new(0)?);
-        let volume_button_box = Box::new(Button::new(47)?);
-        let touch_button = Box::leak(touch_button_box);
-        let volume_button = Box::leak(volume_button_box);
+        let buttons = BoardButtons::new(
+            BOOT_BUTTON_GPIO,
+            VOLUME_BUTTON_GPIO,
+            config::buttons::ACTIVE_LEVEL,
+        )?;
+
+        // AXP173 的 IRQ 输出是开漏，板上已上拉，低电平表示有中断
+        let pmic_irq_pin = input_pin(config::pmic::IRQ);
+
+        // 状态灯启动失败不影响其他功能
+        let led = WS2812RMT::new(output_pin(config::led::DATA), peripherals.rmt.channel0)
+            .and_then(|driver| LedService::start(driver, config::led::PIXELS))
+            .map_err(|e| error!("Failed to start status led: {:?}", e))
+            .ok();
 
         // 现在从 bus_manager 获取 I2C 代理来创建 audio_codec
         let es8311_i2c_proxy = bus_manager.acquire_i2c();
@@ -120,13 +157,14 @@ impl JiangLianS3CamBoard {
 
         // 初始化I2S
         // let tdm_config = TdmConfig::default();
-        let std_config = StdConfig::philips(16000, DataBitWidth::Bits16);
+        let std_config =
+            config::i2s::FORMAT.std_config(config::i2s::SAMPLE_RATE, config::i2s::BITS);
 
-        let bclk = pins.gpio42;
-        let din = pins.gpio45;
-        let dout = pins.gpio39;
-        let mclk = pins.gpio41;
-        let ws = pins.gpio40;
+        let bclk = io_pin(config::i2s::BCLK);
+        let din = input_pin(config::i2s::DIN);
+        let dout = output_pin(config::i2s::DOUT);
+        let mclk = output_pin(config::i2s::MCLK);
+        let ws = io_pin(config::i2s::WS);
 
         // i2s_config
         let mut i2s_driver = I2sDriver::<I2sBiDir>::new_std_bidir(
@@ -154,29 +192,55 @@ impl JiangLianS3CamBoard {
         // i2s_driver.tx_enable().unwrap();
         // i2s_driver.rx_enable().unwrap();
 
-        let audio_codec = XiaozhiAudioCodec::new(es8311_i2c_proxy, es7210_i2c_proxy, i2s_driver);
+        let audio_codec = XiaozhiAudioCodec::new(
+            es8311_i2c_proxy,
+            es7210_i2c_proxy,
+            config::i2c::ES8311_ADDR,
+            config::i2c::ES7210_ADDR,
+            i2s_driver,
+            // 板子上是标准 I2S，录音一帧只有两个 slot
+            false,
+        );
 
         Ok(Self {
-            wifi_driver,
-            display: Box::new(display),
+            network,
+            display,
+            backlight,
             audio_codec: Arc::new(Mutex::new(audio_codec)),
             bus_manager,
-            touch_button,
-            volume_button,
-            on_touch_button_clicked: None,
-            on_volume_button_clicked: None,
-            wifi_config_mode: false,
+            buttons,
+            pmic_irq_pin: Some(pmic_irq_pin),
+            led,
+            camera: None,
             app_context,
         })
     }
 
-    pub fn init(&mut self) -> Result<()> {
-        // self.init_wifi()?;
-        info!("Init power management");
-        self.init_power_management()?;
-        info!("Init buttons");
-        self.init_buttons()?;
-        Ok(())
+    /// 摄像头要等 PMIC 上电之后再初始化，失败了不影响其他功能
+    fn init_camera(&mut self) {
+        let optional = |pin: Option<u8>| pin.map_or(-1, i32::from);
+        let pins = CameraPins {
+            xclk: config::camera::XCLK as i32,
+            pclk: config::camera::PCLK as i32,
+            vsync: config::camera::VSYNC as i32,
+            href: config::camera::HREF as i32,
+            data: config::camera::DATA.map(i32::from),
+            sccb_sda: optional(config::camera::SCCB_SDA),
+            sccb_scl: optional(config::camera::SCCB_SCL),
+            // 没有单独的 SCCB 引脚时和音频芯片共用 I2C1
+            sccb_i2c_port: if config::camera::SCCB_SDA.is_some() {
+                0
+            } else {
+                1
+            },
+            pwdn: optional(config::camera::PWDN),
+            reset: optional(config::camera::RESET),
+            xclk_freq_hz: config::camera::XCLK_FREQ_HZ as i32,
+        };
+        self.camera = EspCamera::new(&pins)
+            .map(|camera| Arc::new(Mutex::new(camera)) as Arc<Mutex<dyn Camera>>)
+            .map_err(|e| error!("Failed to init camera: {:?}", e))
+            .ok();
     }
 
     fn init_power_management(&mut self) -> Result<()> {
@@ -216,31 +280,14 @@ impl JiangLianS3CamBoard {
             .set_exten(true)
             .map_err(|e| anyhow::anyhow!("Failed to set EXTEN: {:?}", e))?;
 
-        info!("Init power management done");
-        Ok(())
-    }
-
-    fn init_buttons(&mut self) -> Result<()> {
-        println!("Init buttons");
-        if let Some(on_clicked) = self.on_touch_button_clicked.take() {
-            self.touch_button.on_click(on_clicked)?;
-        }
-
-        if let Some(on_clicked) = self.on_volume_button_clicked.take() {
-            self.volume_button.on_click(on_clicked)?;
+        // PEK 按键、USB 插拔等中断交给单独的线程处理
+        if let Some(irq_pin) = self.pmic_irq_pin.take() {
+            let irq_axp173 = Axp173::new(self.bus_manager.acquire_i2c());
+            PmicIrqService::new(irq_axp173, self.app_context.app_event_sender.clone())
+                .spawn(irq_pin)?;
         }
 
-        Ok(())
-    }
-
-    fn wifi_scan(&mut self) -> Result<()> {
-        let ssid = "CU_liu81802";
-        let password = "china-ops";
-
-        // let ssid = "1802";
-        // let password = "20250101";
-
-        self.wifi_driver.connect(ssid, password)?;
+        info!("Init power management done");
         Ok(())
     }
 
@@ -257,26 +304,29 @@ impl JiangLianS3CamBoard {
 impl Board for JiangLianS3CamBoard {
     type WifiDriver = Esp32WifiDriver;
 
-    fn on_touch_button_clicked(&mut self, on_clicked: Box<dyn FnMut() + Send + 'static>) {
-        self.on_touch_button_clicked = Some(on_clicked);
+    fn name(&self) -> &'static str {
+        config::NAME
     }
 
-    fn on_volume_button_clicked(&mut self, on_clicked: Box<dyn FnMut() + Send + 'static>) {
-        self.on_volume_button_clicked = Some(on_clicked);
+    fn init(&mut self) -> Result<()> {
+        // self.init_wifi()?;
+        info!("Init power management");
+        self.init_power_management()?;
+        self.init_camera();
+        self.buttons.init()?;
+        Ok(())
     }
 
     fn init_wifi(&mut self) -> std::result::Result<(), Error> {
-        self.wifi_scan()?;
-        // let wifi_connected = self.start_wifi_station()?;
-        // if !wifi_connected {
-        //     self.start_wifi_ap()?;
-        // }
-
-        Ok(())
+        self.start_network()
     }
 
     fn get_wifi_driver(&self) -> &Self::WifiDriver {
-        &self.wifi_driver
+        self.network.wifi_driver()
+    }
+
+    fn get_buttons(&mut self) -> &mut BoardButtons {
+        &mut self.buttons
     }
 
     fn get_audio_codec(&mut self) -> Arc<Mutex<dyn AudioCodec>> {
@@ -284,89 +334,110 @@ impl Board for JiangLianS3CamBoard {
     }
 
     fn start_wifi_station(&mut self) -> std::result::Result<bool, Error> {
-        let ssid_manager = SsidMananger::get_instance();
-
-        // scanning available access points
-        let available_ap_names = self.wifi_driver.get_available_access_points()?;
-        info!("Available AP names: {:?}", available_ap_names);
-
-        // get saved ssid list
-        let ssid_list = ssid_manager.get_ssid_list()?;
-        info!("Saved SSID list: {:?}", ssid_list);
-
-        let saved_ap_names = ssid_list
-            .iter()
-            .map(|ssid| ssid.ssid.clone())
-            .collect::<Vec<_>>();
-
-        let set1: HashSet<_> = available_ap_names.iter().collect();
-        let set2: HashSet<_> = saved_ap_names.iter().collect();
-
-        // 2. 使用 intersection 找交集
-        // 返回的是一个迭代器，里面依然是引用 (&&i32)
-        let intersection: Vec<&String> = set1
-            .intersection(&set2)
-            .copied() // 把 &&i32 解引用成 i32 (也就是克隆一层引用指向的值)
-            .collect();
-
-        info!("Intersection: {:?}", intersection);
-
-        for ssid_item in ssid_list {
-            if intersection.contains(&&ssid_item.ssid) {
-                let connet_result = self
-                    .wifi_driver
-                    .connect(ssid_item.ssid.as_str(), ssid_item.password.as_str());
-
-                if let Err(_) = connet_result {
-                    continue;
-                } else {
-                    return Ok(true);
-                }
-            }
-        }
-
-        Ok(false)
+        self.network.start_wifi_station()
     }
 
     fn start_wifi_ap(&mut self) -> std::result::Result<bool, Error> {
-        match self.wifi_driver.start_ap("xiaozhi_ap", "") {
-            std::result::Result::Ok(ip_info) => match self.wifi_driver.start_http_server() {
-                std::result::Result::Ok(_) => {
-                    info!(
-                        "成功启动http server,请访问 http://{}",
-                        ip_info.ip.to_string()
-                    );
-
-                    let url = format!("http://{}", ip_info.ip.to_string());
-
-                    self.display.show_qrcode(&url);
-                    self.app_context.app_event_sender.send(
-                        crate::common::event::AppEvent::PlayAudioAlert("wificonfig".to_string()),
-                    )?;
-                }
-                Err(e) => {
-                    error!("启动http server 出错：{:?}", e);
-                    return Err(e.into());
-                }
-            },
-            Err(err) => {
-                error!("启动http server 出错：{:?}", err);
-            }
-        }
-
-        Ok(true)
+        self.network.start_wifi_ap(&mut self.display)
     }
 
     fn start_network(&mut self) -> Result<()> {
-        self.wifi_scan()?;
+        self.network.start_network(&mut self.display)
+    }
 
-        // info!("Start network");
-        // let wifi_connected = self.start_wifi_station()?;
-        // if !wifi_connected {
-        //     self.wifi_config_mode = true;
-        //     self.start_wifi_ap()?;
-        // }
+    fn resume_network(&mut self) -> Result<()> {
+        self.network.resume_network();
+        Ok(())
+    }
 
+    fn get_display(&mut self) -> &mut dyn Display {
+        &mut self.display
+    }
+
+    fn set_backlight(&mut self, brightness: u8) -> Result<()> {
+        self.backlight.set_brightness(brightness)
+    }
+
+    fn get_led(&self) -> Option<LedService> {
+        self.led.clone()
+    }
+
+    fn get_camera(&self) -> Option<Arc<Mutex<dyn Camera>>> {
+        self.camera.clone()
+    }
+
+    fn power_off(&mut self) -> Result<()> {
+        info!("Power off");
+        let mut axp173 = Axp173::new(self.bus_manager.acquire_i2c());
+        axp173.shutdown();
         Ok(())
     }
+
+    fn set_power_save_mode(&mut self, enabled: bool) -> Result<()> {
+        self.network.wifi_driver_mut().set_power_save(enabled)
+    }
+
+    fn enter_light_sleep(&mut self, max_duration: Option<Duration>) -> Result<WakeSource> {
+        use esp_idf_sys::*;
+
+        info!("Enter light sleep, max duration: {:?}", max_duration);
+        // 两个按键和 AXP173 IRQ 都是低电平有效
+        unsafe {
+            for gpio in [BOOT_BUTTON_GPIO, VOLUME_BUTTON_GPIO, PMIC_IRQ_GPIO] {
+                esp!(gpio_wakeup_enable(gpio, gpio_int_type_t_GPIO_INTR_LOW_LEVEL))?;
+            }
+            esp!(esp_sleep_enable_gpio_wakeup())?;
+            if let Some(max_duration) = max_duration {
+                esp!(esp_sleep_enable_timer_wakeup(
+                    max_duration.as_micros() as u64
+                ))?;
+            }
+
+            esp!(esp_light_sleep_start())?;
+
+            let cause = esp_sleep_get_wakeup_cause();
+            esp!(esp_sleep_disable_wakeup_source(
+                esp_sleep_source_t_ESP_SLEEP_WAKEUP_ALL
+            ))?;
+            for gpio in [BOOT_BUTTON_GPIO, VOLUME_BUTTON_GPIO, PMIC_IRQ_GPIO] {
+                esp!(gpio_wakeup_disable(gpio))?;
+            }
+
+            Ok(match cause {
+                esp_sleep_source_t_ESP_SLEEP_WAKEUP_TIMER => WakeSource::Timer,
+                esp_sleep_source_t_ESP_SLEEP_WAKEUP_GPIO => WakeSource::Gpio,
+                _ => WakeSource::Other,
+            })
+        }
+    }
+
+    fn enter_deep_sleep(&mut self, wakeup_after: Option<Duration>) -> Result<()> {
+        use esp_idf_sys::*;
+
+        info!("Enter deep sleep, wakeup after: {:?}", wakeup_after);
+        // 关掉功放电源
+        let mut axp173 = Axp173::new(self.bus_manager.acquire_i2c());
+        if let Err(e) = axp173.set_exten(false) {
+            error!("Failed to disable EXTEN: {:?}", e);
+        }
+
+        // deep sleep 只能用 RTC GPIO 唤醒，GPIO47 不是 RTC GPIO，所以只用 BOOT 键和 AXP173 IRQ
+        let wakeup_mask: u64 = (1 << BOOT_BUTTON_GPIO) | (1 << PMIC_IRQ_GPIO);
+        unsafe {
+            for gpio in [BOOT_BUTTON_GPIO, PMIC_IRQ_GPIO] {
+                esp!(rtc_gpio_pullup_en(gpio))?;
+                esp!(rtc_gpio_pulldown_dis(gpio))?;
+            }
+            esp!(esp_sleep_enable_ext1_wakeup(
+                wakeup_mask,
+                esp_sleep_ext1_wakeup_mode_t_ESP_EXT1_WAKEUP_ANY_LOW
+            ))?;
+            if let Some(wakeup_after) = wakeup_after {
+                esp!(esp_sleep_enable_timer_wakeup(
+                    wakeup_after.as_micros() as u64
+                ))?;
+            }
+            esp_deep_sleep_start();
+        }
+    }
 }
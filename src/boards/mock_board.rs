@@ -0,0 +1,194 @@
+//! host-sim 用的假板子，不接任何外设：WiFi、按键、屏幕都在内存里，音频读写 WAV 文件。
+//!
+//! 文件路径用环境变量指定：
+//! - `XIAOXIN_SIM_MIC`：麦克风输入，默认 `sim/mic.wav`
+//! - `XIAOXIN_SIM_SPEAKER`：喇叭输出，默认 `sim/speaker.wav`
+//! - `XIAOXIN_SIM_PHOTO`：拍照时返回的 JPEG，不设置时合成一张彩条图
+//!
+//! 按键从标准输入模拟，每行一个命令：`click`、`double`、`press`、`release`、`long`、`volume`、`combo`。
+//!
+//! 板子交给 Application 之后就拿不到了，测试用 `display_history()` 查看屏幕上显示过什么。
+
+use std::{
+    io::BufRead,
+    sync::{mpsc::Sender, Arc, Mutex},
+    thread,
+    time::Duration,
+};
+
+use anyhow::{anyhow, Result};
+use log::{error, info, warn};
+
+use crate::{
+    audio::codec::{audio_codec::AudioCodec, mock_audio_codec::MockAudioCodec},
+    boards::{board::Board, board_buttons::BoardButtons},
+    camera::{sim_camera::SimCamera, Camera},
+    common::{
+        application_context::ApplicationContext, event::AppEvent, gpio_button::DEFAULT_LONG_PRESS,
+        sim_button,
+    },
+    display::{memory_display::MemoryDisplay, Display},
+    led::service::LedService,
+    power::policy::WakeSource,
+    wifi::{mock_wifi::MockWifi, wifi_driver::WifiStation},
+};
+
+const BOOT_BUTTON_GPIO: i32 = 0;
+const VOLUME_BUTTON_GPIO: i32 = 1;
+/// 和 BoardButtons 里组合键长按的时间一样
+const COMBO_HOLD: Duration = Duration::from_secs(5);
+
+/// 最近创建的 MockBoard 的屏幕记录
+static DISPLAY_HISTORY: Mutex<Option<Arc<Mutex<Vec<String>>>>> = Mutex::new(None);
+
+/// 最近创建的 MockBoard 在屏幕上显示过的内容，还没有创建时返回 `None`
+pub fn display_history() -> Option<Arc<Mutex<Vec<String>>>> {
+    DISPLAY_HISTORY.lock().unwrap().clone()
+}
+
+pub struct MockBoard {
+    wifi: MockWifi,
+    display: MemoryDisplay,
+    audio_codec: Arc<Mutex<dyn AudioCodec + 'static>>,
+    buttons: BoardButtons,
+    camera: Arc<Mutex<SimCamera>>,
+    app_event_sender: Sender<AppEvent>,
+}
+
+impl MockBoard {
+    pub fn new(app_context: ApplicationContext) -> Result<Self> {
+        let mic = std::env::var("XIAOXIN_SIM_MIC").unwrap_or_else(|_| "sim/mic.wav".to_string());
+        let speaker =
+            std::env::var("XIAOXIN_SIM_SPEAKER").unwrap_or_else(|_| "sim/speaker.wav".to_string());
+        let audio_codec = MockAudioCodec::new(mic, speaker)?;
+        let display = MemoryDisplay::default();
+        *DISPLAY_HISTORY.lock().unwrap() = Some(display.history());
+
+        Ok(Self {
+            wifi: MockWifi::new(),
+            display,
+            audio_codec: Arc::new(Mutex::new(audio_codec)),
+            buttons: BoardButtons::new(BOOT_BUTTON_GPIO, VOLUME_BUTTON_GPIO, 0)?,
+            camera: Arc::new(Mutex::new(SimCamera::new())),
+            app_event_sender: app_context.app_event_sender,
+        })
+    }
+}
+
+/// 从标准输入读按键命令
+fn spawn_stdin_buttons() -> Result<()> {
+    thread::Builder::new()
+        .name("sim_buttons".to_string())
+        .spawn(|| {
+            for line in std::io::stdin().lock().lines() {
+                let Ok(line) = line else {
+                    break;
+                };
+                match line.trim() {
+                    "click" => sim_button::click(BOOT_BUTTON_GPIO),
+                    "double" => sim_button::double_click(BOOT_BUTTON_GPIO),
+                    "press" => sim_button::press(BOOT_BUTTON_GPIO),
+                    "release" => sim_button::release(BOOT_BUTTON_GPIO),
+                    "long" => sim_button::long_press(BOOT_BUTTON_GPIO, DEFAULT_LONG_PRESS),
+                    "volume" => sim_button::click(VOLUME_BUTTON_GPIO),
+                    "combo" => sim_button::hold_together(
+                        &[BOOT_BUTTON_GPIO, VOLUME_BUTTON_GPIO],
+                        COMBO_HOLD,
+                    ),
+                    "" => {}
+                    other => warn!("Unknown button command: {}", other),
+                }
+            }
+        })?;
+    Ok(())
+}
+
+impl Board for MockBoard {
+    type WifiDriver = MockWifi;
+
+    fn name(&self) -> &'static str {
+        "host-sim"
+    }
+
+    fn init(&mut self) -> Result<()> {
+        self.buttons.init()?;
+        spawn_stdin_buttons()
+    }
+
+    fn init_wifi(&mut self) -> Result<()> {
+        self.start_network()
+    }
+
+    fn get_wifi_driver(&self) -> &Self::WifiDriver {
+        &self.wifi
+    }
+
+    fn get_buttons(&mut self) -> &mut BoardButtons {
+        &mut self.buttons
+    }
+
+    fn get_audio_codec(&mut self) -> Arc<Mutex<dyn AudioCodec>> {
+        Arc::clone(&self.audio_codec)
+    }
+
+    fn start_wifi_station(&mut self) -> Result<bool> {
+        self.wifi.connect("host-sim", "")?;
+        let ip = self.wifi.get_ip_address()?;
+        if let Err(e) = self.app_event_sender.send(AppEvent::NetworkConnected(ip)) {
+            error!("Failed to send NetworkConnected: {:?}", e);
+        }
+        Ok(true)
+    }
+
+    fn start_wifi_ap(&mut self) -> Result<bool> {
+        info!("host-sim has no wifi AP");
+        Ok(false)
+    }
+
+    fn start_network(&mut self) -> Result<()> {
+        self.start_wifi_station()?;
+        Ok(())
+    }
+
+    // 模拟的 WiFi 不会掉线
+    fn resume_network(&mut self) -> Result<()> {
+        Ok(())
+    }
+
+    fn get_display(&mut self) -> &mut dyn Display {
+        &mut self.display
+    }
+
+    fn set_backlight(&mut self, brightness: u8) -> Result<()> {
+        info!("Backlight: {}", brightness);
+        Ok(())
+    }
+
+    fn get_led(&self) -> Option<LedService> {
+        None
+    }
+
+    fn get_camera(&self) -> Option<Arc<Mutex<dyn Camera>>> {
+        Some(self.camera.clone())
+    }
+
+    fn power_off(&mut self) -> Result<()> {
+        Err(anyhow!("host-sim cannot power off"))
+    }
+
+    fn set_power_save_mode(&mut self, enabled: bool) -> Result<()> {
+        self.wifi.set_power_save(enabled)
+    }
+
+    // 没有真的睡眠，等到超时当作定时器唤醒
+    fn enter_light_sleep(&mut self, max_duration: Option<Duration>) -> Result<WakeSource> {
+        info!("Enter light sleep, max duration: {:?}", max_duration);
+        thread::sleep(max_duration.unwrap_or(Duration::from_secs(1)));
+        Ok(WakeSource::Timer)
+    }
+
+    fn enter_deep_sleep(&mut self, wakeup_after: Option<Duration>) -> Result<()> {
+        info!("Enter deep sleep, exiting, wakeup after: {:?}", wakeup_after);
+        std::process::exit(0);
+    }
+}
@@ -1,2 +1,60 @@
+//! 板子的注册表。用 cargo feature 选择一块板子，例如：
+//!
+//! ```text
+//! cargo build --release                                   # 默认 board-jianglian-s3cam
+//! cargo build --release --no-default-features --features board-bread-compact-wifi,provisioning-softap
+//! ```
+//!
+//! 添加新板子：在这个目录下加一个实现了 `Board` 的文件，在 Cargo.toml 里加上 feature，
+//! 在仓库根目录的 `boards/` 下加一个同名的 TOML 写引脚，再在下面的 `register_boards!` 里加一行。
+//! 只能打开一块板子，由 build.rs 检查。
+//!
+//! `host-sim` feature 也算一块板子（MockBoard），不接任何外设。
+
+use anyhow::Result;
+
+use crate::{boards::board::Board, common::application_context::ApplicationContext};
+
 pub mod board;
-pub mod jianglian_s3cam_board;
+pub mod board_buttons;
+#[cfg(target_os = "espidf")]
+pub mod board_network;
+#[cfg(target_os = "espidf")]
+pub mod config;
+
+/// 每块板子一行：`"feature 名" => 模块::板子类型`，打开的那块作为 `SelectedBoard`
+macro_rules! register_boards {
+    ($($feature:literal => $module:ident::$board:ident,)*) => {
+        $(
+            #[cfg(feature = $feature)]
+            pub mod $module;
+            #[cfg(feature = $feature)]
+            use $module::$board as SelectedBoard;
+        )*
+    };
+}
+
+register_boards! {
+    "board-jianglian-s3cam" => jianglian_s3cam_board::JiangLianS3CamBoard,
+    "board-bread-compact-wifi" => bread_compact_wifi_board::BreadCompactWifiBoard,
+    "host-sim" => mock_board::MockBoard,
+}
+
+#[cfg(not(any(
+    feature = "provisioning-softap",
+    feature = "provisioning-ble",
+    feature = "host-sim"
+)))]
+compile_error!("没有配网方式，请打开 provisioning-softap 或 provisioning-ble");
+
+#[cfg(not(feature = "host-sim"))]
+pub type BoardWifiDriver = crate::wifi::wifi_driver::Esp32WifiDriver;
+#[cfg(feature = "host-sim")]
+pub type BoardWifiDriver = crate::wifi::mock_wifi::MockWifi;
+
+pub type DynBoard = Box<dyn Board<WifiDriver = BoardWifiDriver>>;
+
+/// 创建编译时选择的板子
+pub fn create_board(app_context: ApplicationContext) -> Result<DynBoard> {
+    Ok(Box::new(SelectedBoard::new(app_context)?))
+}
@@ -0,0 +1,122 @@
+use std::{ptr, slice};
+
+use anyhow::{anyhow, Result};
+use esp_idf_sys::{
+    es32_component_camera::{
+        camera_config_t, camera_config_t__bindgen_ty_1, camera_config_t__bindgen_ty_2,
+        camera_fb_location_t_CAMERA_FB_IN_PSRAM, camera_grab_mode_t_CAMERA_GRAB_LATEST,
+        esp_camera_deinit, esp_camera_fb_get, esp_camera_fb_return, esp_camera_init, frame2jpg,
+        framesize_t_FRAMESIZE_QVGA, pixformat_t_PIXFORMAT_RGB565,
+    },
+    esp, free, ledc_channel_t_LEDC_CHANNEL_1, ledc_timer_t_LEDC_TIMER_1,
+};
+use log::info;
+
+use crate::camera::{Camera, CameraFrame, CapturedPhoto};
+
+/// JPEG 质量，0-100，越大越清楚，上传也越慢
+const JPEG_QUALITY: u8 = 80;
+
+/// DVP 摄像头的引脚，-1 表示没有接
+pub struct CameraPins {
+    pub xclk: i32,
+    pub pclk: i32,
+    pub vsync: i32,
+    pub href: i32,
+    /// D0 ~ D7
+    pub data: [i32; 8],
+    /// SCCB 的 SDA/SCL 都是 -1 时和别的设备共用 `sccb_i2c_port`，这个 I2C 驱动要先装好
+    pub sccb_sda: i32,
+    pub sccb_scl: i32,
+    pub sccb_i2c_port: i32,
+    pub pwdn: i32,
+    pub reset: i32,
+    pub xclk_freq_hz: i32,
+}
+
+/// esp32-camera 驱动的摄像头。
+///
+/// 输出 QVGA 的 RGB565，刚好是横屏 ST7789 的大小，可以直接预览；上传前再用 `frame2jpg` 转成 JPEG。
+/// XCLK 用 LEDC 的 timer1/channel1，背光占了 timer0/channel0
+pub struct EspCamera {
+    _private: (),
+}
+
+impl EspCamera {
+    pub fn new(pins: &CameraPins) -> Result<Self> {
+        let config = camera_config_t {
+            pin_pwdn: pins.pwdn,
+            pin_reset: pins.reset,
+            pin_xclk: pins.xclk,
+            __bindgen_anon_1: camera_config_t__bindgen_ty_1 {
+                pin_sccb_sda: pins.sccb_sda,
+            },
+            __bindgen_anon_2: camera_config_t__bindgen_ty_2 {
+                pin_sccb_scl: pins.sccb_scl,
+            },
+            pin_d0: pins.data[0],
+            pin_d1: pins.data[1],
+            pin_d2: pins.data[2],
+            pin_d3: pins.data[3],
+            pin_d4: pins.data[4],
+            pin_d5: pins.data[5],
+            pin_d6: pins.data[6],
+            pin_d7: pins.data[7],
+            pin_vsync: pins.vsync,
+            pin_href: pins.href,
+            pin_pclk: pins.pclk,
+            xclk_freq_hz: pins.xclk_freq_hz,
+            ledc_timer: ledc_timer_t_LEDC_TIMER_1,
+            ledc_channel: ledc_channel_t_LEDC_CHANNEL_1,
+            pixel_format: pixformat_t_PIXFORMAT_RGB565,
+            frame_size: framesize_t_FRAMESIZE_QVGA,
+            fb_count: 1,
+            fb_location: camera_fb_location_t_CAMERA_FB_IN_PSRAM,
+            grab_mode: camera_grab_mode_t_CAMERA_GRAB_LATEST,
+            sccb_i2c_port: pins.sccb_i2c_port,
+            ..Default::default()
+        };
+        esp!(unsafe { esp_camera_init(&config) })?;
+        info!("Camera initialized");
+        Ok(Self { _private: () })
+    }
+}
+
+impl Camera for EspCamera {
+    fn capture(&mut self) -> Result<CapturedPhoto> {
+        let fb = unsafe { esp_camera_fb_get() };
+        if fb.is_null() {
+            return Err(anyhow!("Failed to get camera frame"));
+        }
+
+        let frame = unsafe { &*fb };
+        let preview = CameraFrame {
+            width: frame.width as u16,
+            height: frame.height as u16,
+            rgb565: unsafe { slice::from_raw_parts(frame.buf, frame.len) }.to_vec(),
+        };
+
+        let mut jpeg_buf: *mut u8 = ptr::null_mut();
+        let mut jpeg_len: usize = 0;
+        let converted = unsafe { frame2jpg(fb, JPEG_QUALITY, &mut jpeg_buf, &mut jpeg_len) };
+        unsafe { esp_camera_fb_return(fb) };
+        if !converted || jpeg_buf.is_null() {
+            return Err(anyhow!("Failed to convert camera frame to JPEG"));
+        }
+
+        // frame2jpg 用 malloc 分配的内存，复制出来后释放
+        let jpeg = unsafe { slice::from_raw_parts(jpeg_buf, jpeg_len) }.to_vec();
+        unsafe { free(jpeg_buf as *mut _) };
+
+        Ok(CapturedPhoto {
+            jpeg,
+            preview: Some(preview),
+        })
+    }
+}
+
+impl Drop for EspCamera {
+    fn drop(&mut self) {
+        unsafe { esp_camera_deinit() };
+    }
+}
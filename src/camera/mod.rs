@@ -0,0 +1,94 @@
+//! 摄像头：拍一张 JPEG 照片，连同用户的问题上传到识图服务，把回答交给大模型。
+//!
+//! - `Camera` 是拍照的抽象，板子上用 esp32-camera（`esp_camera`），host-sim 用合成的画面（`sim_camera`）
+//! - `vision` 负责 multipart 上传和解析回答，HTTP 请求通过 `HttpTransport` 发出，方便在电脑上换成本地的替身
+//! - `add_camera_tools` 注册 MCP 工具 `self.camera.take_photo`
+
+use std::{
+    fmt,
+    sync::{mpsc::Sender, Arc, Mutex},
+};
+
+use anyhow::{anyhow, Result};
+use log::{error, info};
+use serde_json::json;
+
+use crate::{
+    camera::vision::{HttpTransport, VisionClient},
+    common::event::AppEvent,
+    mcp::mcp_server::McpServer,
+};
+
+#[cfg(not(feature = "host-sim"))]
+pub mod esp_camera;
+#[cfg(feature = "host-sim")]
+pub mod sim_camera;
+pub mod vision;
+
+/// 给屏幕预览用的一帧画面
+#[derive(Clone)]
+pub struct CameraFrame {
+    pub width: u16,
+    pub height: u16,
+    /// 大端的 RGB565，和 esp32-camera 输出的字节序一样
+    pub rgb565: Vec<u8>,
+}
+
+// 画面有上百 KB，打日志时只打尺寸
+impl fmt::Debug for CameraFrame {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "CameraFrame({}x{})", self.width, self.height)
+    }
+}
+
+pub struct CapturedPhoto {
+    pub jpeg: Vec<u8>,
+    /// 摄像头输出 JPEG 时没有预览
+    pub preview: Option<CameraFrame>,
+}
+
+pub trait Camera: Send {
+    fn capture(&mut self) -> Result<CapturedPhoto>;
+}
+
+/// 注册 `self.camera.take_photo`。拍照后先把预览发给应用显示，再上传识图，回答作为工具的结果返回给大模型。
+///
+/// MCP 工具是在主循环里同步调用的，上传期间主循环会等着。这时候大模型在等工具的结果，
+/// 服务器不会发语音过来，所以问题不大
+pub fn add_camera_tools<T>(
+    mcp_server: &mut McpServer,
+    camera: Arc<Mutex<dyn Camera>>,
+    vision: VisionClient<T>,
+    sender: Sender<AppEvent>,
+) where
+    T: HttpTransport + 'static,
+{
+    let vision = Mutex::new(vision);
+    mcp_server.add_tool(
+        "self.camera.take_photo",
+        "Take a photo with the camera and ask a question about it. Use this tool when the user \
+         asks you to look at something, e.g. \"what is this\" or \"what do you see\". \
+         Args: `question`: the question about the photo, in the user's language. \
+         Returns the answer from the vision model.",
+        json!({
+            "question": { "type": "string", "description": "The question about the photo" },
+        }),
+        &["question"],
+        move |arguments| {
+            let question = arguments["question"]
+                .as_str()
+                .ok_or_else(|| anyhow!("question must be a string"))?;
+
+            let photo = camera.lock().unwrap().capture()?;
+            info!("Captured photo: {} bytes", photo.jpeg.len());
+            if let Some(preview) = photo.preview {
+                if let Err(e) = sender.send(AppEvent::ShowCameraPreview(preview)) {
+                    error!("Failed to send ShowCameraPreview event: {:?}", e);
+                }
+            }
+
+            let answer = vision.lock().unwrap().explain(question, &photo.jpeg)?;
+            Ok(json!(answer))
+        },
+    );
+}
@@ -0,0 +1,92 @@
+use anyhow::Result;
+use jpeg_encoder::{ColorType, Encoder};
+
+use crate::camera::{Camera, CameraFrame, CapturedPhoto};
+
+const WIDTH: u16 = 320;
+const HEIGHT: u16 = 240;
+
+/// 彩条，从左到右：白、黄、青、绿、品红、红、蓝、黑
+const BARS: [(u8, u8, u8); 8] = [
+    (255, 255, 255),
+    (255, 255, 0),
+    (0, 255, 255),
+    (0, 255, 0),
+    (255, 0, 255),
+    (255, 0, 0),
+    (0, 0, 255),
+    (0, 0, 0),
+];
+
+/// host-sim 用的假摄像头。
+///
+/// 设置了环境变量 `XIAOXIN_SIM_PHOTO` 时直接返回这个 JPEG 文件（没有预览）；
+/// 否则合成一张 QVGA 的彩条图，上面有一个每拍一张就往右移的方块，方便分辨是哪一张
+#[derive(Default)]
+pub struct SimCamera {
+    shots: u32,
+}
+
+impl SimCamera {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn synthetic_rgb(&self) -> Vec<u8> {
+        let square = 40;
+        let square_x = (self.shots * 48) % (WIDTH as u32 - square);
+        let square_y = (HEIGHT as u32 - square) / 2;
+
+        let mut rgb = Vec::with_capacity(WIDTH as usize * HEIGHT as usize * 3);
+        for y in 0..HEIGHT as u32 {
+            for x in 0..WIDTH as u32 {
+                let in_square = (square_x..square_x + square).contains(&x)
+                    && (square_y..square_y + square).contains(&y);
+                let (r, g, b) = if in_square {
+                    (128, 128, 128)
+                } else {
+                    BARS[(x * BARS.len() as u32 / WIDTH as u32) as usize]
+                };
+                rgb.extend_from_slice(&[r, g, b]);
+            }
+        }
+        rgb
+    }
+}
+
+/// RGB888 转成大端 RGB565，和 esp32-camera 的输出一样
+fn rgb888_to_rgb565_be(rgb: &[u8]) -> Vec<u8> {
+    rgb.chunks_exact(3)
+        .flat_map(|pixel| {
+            let value = ((pixel[0] as u16 & 0xf8) << 8)
+                | ((pixel[1] as u16 & 0xfc) << 3)
+                | (pixel[2] as u16 >> 3);
+            value.to_be_bytes()
+        })
+        .collect()
+}
+
+impl Camera for SimCamera {
+    fn capture(&mut self) -> Result<CapturedPhoto> {
+        if let Ok(path) = std::env::var("XIAOXIN_SIM_PHOTO") {
+            return Ok(CapturedPhoto {
+                jpeg: std::fs::read(path)?,
+                preview: None,
+            });
+        }
+
+        let rgb = self.synthetic_rgb();
+        self.shots += 1;
+
+        let mut jpeg = Vec::new();
+        Encoder::new(&mut jpeg, 80).encode(&rgb, WIDTH, HEIGHT, ColorType::Rgb)?;
+        Ok(CapturedPhoto {
+            jpeg,
+            preview: Some(CameraFrame {
+                width: WIDTH,
+                height: HEIGHT,
+                rgb565: rgb888_to_rgb565_be(&rgb),
+            }),
+        })
+    }
+}
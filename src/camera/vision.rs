@@ -0,0 +1,474 @@
+//! 识图服务的客户端：把照片和问题用 multipart/form-data 上传，返回识别结果。
+//!
+//! 请求和 xiaozhi 的服务器一致：
+//! - 表单字段 `question` 是问题，`file` 是照片（`camera.jpg`，`image/jpeg`）
+//! - 请求头带 `Device-Id`、`Client-Id`，配置了 token 时带 `Authorization: Bearer <token>`
+//! - 返回 `{"success": true, "text": "..."}`，失败时是 `{"success": false, "message": "..."}`
+
+use std::sync::{Arc, Mutex};
+
+use anyhow::{anyhow, bail, Result};
+use serde::Deserialize;
+
+const BOUNDARY: &str = "----XIAOXIN_CAMERA_BOUNDARY";
+
+/// 发 HTTP POST 的抽象，返回状态码和响应内容。
+/// 板子上用 `EspHttpTransport`，host-sim 用 `StdHttpTransport` 连本地的替身服务器
+pub trait HttpTransport: Send {
+    fn post(&mut self, url: &str, headers: &[(&str, &str)], body: &[u8]) -> Result<(u16, Vec<u8>)>;
+}
+
+/// 识图服务的地址和 token。设置里可以配置，服务器也可以在 MCP initialize 里下发
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct VisionEndpoint {
+    pub url: Option<String>,
+    pub token: Option<String>,
+}
+
+pub struct VisionClient<T> {
+    transport: T,
+    endpoint: Arc<Mutex<VisionEndpoint>>,
+    device_id: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct VisionResponse {
+    #[serde(default)]
+    success: bool,
+    text: Option<String>,
+    message: Option<String>,
+}
+
+impl<T: HttpTransport> VisionClient<T> {
+    pub fn new(transport: T, endpoint: Arc<Mutex<VisionEndpoint>>, device_id: &str) -> Self {
+        Self {
+            transport,
+            endpoint,
+            device_id: device_id.to_string(),
+        }
+    }
+
+    /// 上传照片和问题，返回识图服务的回答
+    pub fn explain(&mut self, question: &str, jpeg: &[u8]) -> Result<String> {
+        let endpoint = self.endpoint.lock().unwrap().clone();
+        let url = endpoint
+            .url
+            .ok_or_else(|| anyhow!("vision url is not configured"))?;
+
+        let content_type = format!("multipart/form-data; boundary={}", BOUNDARY);
+        let authorization = endpoint.token.map(|token| format!("Bearer {}", token));
+        let mut headers = vec![
+            ("Content-Type", content_type.as_str()),
+            ("Device-Id", self.device_id.as_str()),
+            ("Client-Id", self.device_id.as_str()),
+        ];
+        if let Some(authorization) = &authorization {
+            headers.push(("Authorization", authorization.as_str()));
+        }
+
+        let body = build_multipart(BOUNDARY, question, jpeg);
+        let (status, response) = self.transport.post(&url, &headers, &body)?;
+        parse_response(status, &response)
+    }
+}
+
+/// 生成 multipart/form-data 的请求体
+pub fn build_multipart(boundary: &str, question: &str, jpeg: &[u8]) -> Vec<u8> {
+    let mut body = Vec::with_capacity(jpeg.len() + 512);
+    body.extend_from_slice(
+        format!(
+            "--{}\r\nContent-Disposition: form-data; name=\"question\"\r\n\r\n{}\r\n",
+            boundary, question
+        )
+        .as_bytes(),
+    );
+    body.extend_from_slice(
+        format!(
+            "--{}\r\nContent-Disposition: form-data; name=\"file\"; filename=\"camera.jpg\"\r\n\
+             Content-Type: image/jpeg\r\n\r\n",
+            boundary
+        )
+        .as_bytes(),
+    );
+    body.extend_from_slice(jpeg);
+    body.extend_from_slice(format!("\r\n--{}--\r\n", boundary).as_bytes());
+    body
+}
+
+fn parse_response(status: u16, body: &[u8]) -> Result<String> {
+    if status != 200 {
+        bail!(
+            "vision server returned {}: {}",
+            status,
+            String::from_utf8_lossy(body)
+        );
+    }
+    let response: VisionResponse = serde_json::from_slice(body)?;
+    match response {
+        VisionResponse {
+            success: true,
+            text: Some(text),
+            ..
+        } => Ok(text),
+        VisionResponse { message, .. } => Err(anyhow!(
+            "vision failed: {}",
+            message.unwrap_or_else(|| "no answer".to_string())
+        )),
+    }
+}
+
+#[cfg(not(feature = "host-sim"))]
+pub use esp_transport::EspHttpTransport;
+
+#[cfg(not(feature = "host-sim"))]
+mod esp_transport {
+    use std::time::Duration;
+
+    use anyhow::Result;
+    use embedded_svc::http::client::Client as HttpClient;
+    use esp_idf_hal::io::{Read, Write};
+    use esp_idf_svc::http::{
+        client::{Configuration, EspHttpConnection},
+        Method,
+    };
+    use esp_idf_sys::esp_crt_bundle_attach;
+
+    use super::HttpTransport;
+
+    /// 用 esp_http_client 发请求，每次请求新建一个连接
+    pub struct EspHttpTransport;
+
+    impl HttpTransport for EspHttpTransport {
+        fn post(
+            &mut self,
+            url: &str,
+            headers: &[(&str, &str)],
+            body: &[u8],
+        ) -> Result<(u16, Vec<u8>)> {
+            let connection = EspHttpConnection::new(&Configuration {
+                buffer_size: Some(4096),
+                timeout: Some(Duration::from_secs(30)),
+                crt_bundle_attach: Some(esp_crt_bundle_attach),
+                ..Default::default()
+            })?;
+            let mut client = HttpClient::wrap(connection);
+
+            let content_length = body.len().to_string();
+            let mut headers = headers.to_vec();
+            headers.push(("Content-Length", content_length.as_str()));
+
+            let mut request = client.request(Method::Post, url, &headers)?;
+            request.write_all(body)?;
+            request.flush()?;
+            let mut response = request.submit()?;
+            let status = response.status();
+
+            let mut response_body = Vec::new();
+            let mut buffer = [0u8; 512];
+            loop {
+                let n = response.read(&mut buffer)?;
+                if n == 0 {
+                    break;
+                }
+                response_body.extend_from_slice(&buffer[..n]);
+            }
+            Ok((status, response_body))
+        }
+    }
+}
+
+#[cfg(feature = "host-sim")]
+pub use std_transport::StdHttpTransport;
+
+#[cfg(feature = "host-sim")]
+mod std_transport {
+    use std::{
+        io::{Read, Write},
+        net::TcpStream,
+        time::Duration,
+    };
+
+    use anyhow::{anyhow, Result};
+
+    use super::HttpTransport;
+
+    /// host-sim 用的极简 HTTP/1.1 客户端，只支持 `http://`，用来连本地的识图替身
+    pub struct StdHttpTransport;
+
+    impl HttpTransport for StdHttpTransport {
+        fn post(
+            &mut self,
+            url: &str,
+            headers: &[(&str, &str)],
+            body: &[u8],
+        ) -> Result<(u16, Vec<u8>)> {
+            let rest = url
+                .strip_prefix("http://")
+                .ok_or_else(|| anyhow!("only http:// is supported in host-sim: {}", url))?;
+            let (host, path) = match rest.find('/') {
+                Some(i) => (&rest[..i], &rest[i..]),
+                None => (rest, "/"),
+            };
+            let address = if host.contains(':') {
+                host.to_string()
+            } else {
+                format!("{}:80", host)
+            };
+
+            let mut stream = TcpStream::connect(&address)?;
+            stream.set_read_timeout(Some(Duration::from_secs(30)))?;
+            let mut request = format!("POST {} HTTP/1.1\r\nHost: {}\r\n", path, host);
+            for (name, value) in headers {
+                request.push_str(&format!("{}: {}\r\n", name, value));
+            }
+            request.push_str(&format!(
+                "Content-Length: {}\r\nConnection: close\r\n\r\n",
+                body.len()
+            ));
+            stream.write_all(request.as_bytes())?;
+            stream.write_all(body)?;
+
+            let mut response = Vec::new();
+            stream.read_to_end(&mut response)?;
+            parse_http_response(&response)
+        }
+    }
+
+    fn parse_http_response(response: &[u8]) -> Result<(u16, Vec<u8>)> {
+        let header_end = response
+            .windows(4)
+            .position(|w| w == b"\r\n\r\n")
+            .ok_or_else(|| anyhow!("incomplete HTTP response"))?;
+        let head = String::from_utf8_lossy(&response[..header_end]);
+        let status = head
+            .split_whitespace()
+            .nth(1)
+            .and_then(|status| status.parse::<u16>().ok())
+            .ok_or_else(|| anyhow!("bad HTTP status line: {}", head))?;
+        let body = &response[header_end + 4..];
+
+        let chunked = head.lines().any(|line| {
+            let line = line.to_ascii_lowercase();
+            line.starts_with("transfer-encoding:") && line.contains("chunked")
+        });
+        if !chunked {
+            return Ok((status, body.to_vec()));
+        }
+
+        // chunk 的格式：十六进制长度\r\n内容\r\n，长度为 0 时结束
+        let mut decoded = Vec::new();
+        let mut pos = 0;
+        while let Some(line_end) = body[pos..].windows(2).position(|w| w == b"\r\n") {
+            let size_line = String::from_utf8_lossy(&body[pos..pos + line_end]);
+            let size = usize::from_str_radix(size_line.split(';').next().unwrap().trim(), 16)?;
+            pos += line_end + 2;
+            if size == 0 || pos + size > body.len() {
+                break;
+            }
+            decoded.extend_from_slice(&body[pos..pos + size]);
+            pos += size + 2;
+        }
+        Ok((status, decoded))
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        #[test]
+        fn plain_response() {
+            let response = b"HTTP/1.1 200 OK\r\nContent-Length: 2\r\n\r\n{}";
+            assert_eq!(
+                parse_http_response(response).unwrap(),
+                (200, b"{}".to_vec())
+            );
+        }
+
+        #[test]
+        fn chunked_response() {
+            let response = b"HTTP/1.1 401 Unauthorized\r\nTransfer-Encoding: chunked\r\n\r\n\
+                             4\r\nbad \r\n5;ext=1\r\ntoken\r\n0\r\n\r\n";
+            assert_eq!(
+                parse_http_response(response).unwrap(),
+                (401, b"bad token".to_vec())
+            );
+        }
+
+        #[test]
+        fn incomplete_response() {
+            assert!(parse_http_response(b"HTTP/1.1 200 OK\r\n").is_err());
+            assert!(parse_http_response(b"garbage\r\n\r\n").is_err());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    struct Request {
+        url: String,
+        headers: Vec<(String, String)>,
+        body: Vec<u8>,
+    }
+
+    /// 记下请求，回一个固定的响应
+    #[derive(Clone)]
+    struct FakeTransport {
+        requests: Arc<Mutex<Vec<Request>>>,
+        status: u16,
+        response: &'static str,
+    }
+
+    impl FakeTransport {
+        fn new(status: u16, response: &'static str) -> Self {
+            Self {
+                requests: Arc::default(),
+                status,
+                response,
+            }
+        }
+
+        fn requests(&self) -> Vec<Request> {
+            self.requests.lock().unwrap().clone()
+        }
+    }
+
+    impl HttpTransport for FakeTransport {
+        fn post(
+            &mut self,
+            url: &str,
+            headers: &[(&str, &str)],
+            body: &[u8],
+        ) -> Result<(u16, Vec<u8>)> {
+            self.requests.lock().unwrap().push(Request {
+                url: url.to_string(),
+                headers: headers
+                    .iter()
+                    .map(|(name, value)| (name.to_string(), value.to_string()))
+                    .collect(),
+                body: body.to_vec(),
+            });
+            Ok((self.status, self.response.as_bytes().to_vec()))
+        }
+    }
+
+    fn client(
+        transport: &FakeTransport,
+        url: Option<&str>,
+        token: Option<&str>,
+    ) -> VisionClient<FakeTransport> {
+        let endpoint = VisionEndpoint {
+            url: url.map(str::to_string),
+            token: token.map(str::to_string),
+        };
+        VisionClient::new(
+            transport.clone(),
+            Arc::new(Mutex::new(endpoint)),
+            "aa:bb:cc:dd:ee:ff",
+        )
+    }
+
+    fn explain(status: u16, response: &'static str) -> Result<String> {
+        let transport = FakeTransport::new(status, response);
+        client(&transport, Some("http://vision/explain"), None).explain("这是什么", b"jpeg")
+    }
+
+    #[test]
+    fn multipart_body_layout() {
+        let body = build_multipart("XYZ", "这是什么？", &[0xFF, 0xD8, 0xFF, 0xD9]);
+
+        let mut expected = Vec::new();
+        expected.extend_from_slice(
+            "--XYZ\r\n\
+             Content-Disposition: form-data; name=\"question\"\r\n\
+             \r\n\
+             这是什么？\r\n\
+             --XYZ\r\n\
+             Content-Disposition: form-data; name=\"file\"; filename=\"camera.jpg\"\r\n\
+             Content-Type: image/jpeg\r\n\
+             \r\n"
+                .as_bytes(),
+        );
+        expected.extend_from_slice(&[0xFF, 0xD8, 0xFF, 0xD9]);
+        expected.extend_from_slice(b"\r\n--XYZ--\r\n");
+        assert_eq!(body, expected);
+    }
+
+    #[test]
+    fn explain_uploads_photo_with_token() {
+        let transport = FakeTransport::new(200, r#"{"success":true,"text":"一只猫"}"#);
+        let mut client = client(&transport, Some("https://vision/explain"), Some("secret"));
+
+        let answer = client.explain("这是什么", b"jpeg").unwrap();
+        assert_eq!(answer, "一只猫");
+
+        let requests = transport.requests();
+        assert_eq!(requests.len(), 1);
+        assert_eq!(requests[0].url, "https://vision/explain");
+        let header = |name: &str| {
+            requests[0]
+                .headers
+                .iter()
+                .find(|(n, _)| n == name)
+                .map(|(_, value)| value.clone())
+        };
+        assert_eq!(
+            header("Content-Type").unwrap(),
+            format!("multipart/form-data; boundary={}", BOUNDARY)
+        );
+        assert_eq!(header("Device-Id").unwrap(), "aa:bb:cc:dd:ee:ff");
+        assert_eq!(header("Client-Id").unwrap(), "aa:bb:cc:dd:ee:ff");
+        assert_eq!(header("Authorization").unwrap(), "Bearer secret");
+        assert_eq!(
+            requests[0].body,
+            build_multipart(BOUNDARY, "这是什么", b"jpeg")
+        );
+    }
+
+    #[test]
+    fn explain_without_token_has_no_authorization() {
+        let transport = FakeTransport::new(200, r#"{"success":true,"text":"ok"}"#);
+        client(&transport, Some("http://vision/explain"), None)
+            .explain("?", b"jpeg")
+            .unwrap();
+
+        let requests = transport.requests();
+        assert!(requests[0]
+            .headers
+            .iter()
+            .all(|(name, _)| name != "Authorization"));
+    }
+
+    #[test]
+    fn explain_without_url_does_not_upload() {
+        let transport = FakeTransport::new(200, r#"{"success":true,"text":"ok"}"#);
+        let err = client(&transport, None, Some("secret"))
+            .explain("?", b"jpeg")
+            .unwrap_err();
+        assert!(err.to_string().contains("not configured"));
+        assert!(transport.requests().is_empty());
+    }
+
+    #[test]
+    fn server_errors_are_reported() {
+        let err = explain(200, r#"{"success":false,"message":"quota exceeded"}"#).unwrap_err();
+        assert_eq!(err.to_string(), "vision failed: quota exceeded");
+
+        // success 但是没有 text 也算失败
+        let err = explain(200, r#"{"success":true}"#).unwrap_err();
+        assert_eq!(err.to_string(), "vision failed: no answer");
+
+        let err = explain(401, "invalid token").unwrap_err();
+        assert_eq!(err.to_string(), "vision server returned 401: invalid token");
+
+        assert!(explain(200, "<html>").is_err());
+    }
+
+    #[test]
+    fn extra_fields_in_response_are_ignored() {
+        let answer = explain(200, r#"{"success":true,"text":"红色","model":"qwen-vl"}"#);
+        assert_eq!(answer.unwrap(), "红色");
+    }
+}
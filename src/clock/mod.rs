@@ -0,0 +1,184 @@
+//! 系统时间。
+//!
+//! 联网后用 SNTP 同步（`sntp`），SNTP 还没同步上时用 OTA 检查时服务器返回的时间兜底。
+//! 没同步之前系统时间是 1970 年，依赖时间的地方（时钟、WiFi 的最近连接时间）先用 `time_synced()` 判断一下。
+
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc::Sender,
+    },
+    thread,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::Result;
+use chrono::{Datelike, NaiveDateTime, Timelike};
+use log::info;
+
+use crate::common::{event::AppEvent, lang};
+
+pub mod sntp;
+
+/// 没有配置时区时用北京时间
+pub const DEFAULT_TIMEZONE: &str = "CST-8";
+
+static TIME_SYNCED: AtomicBool = AtomicBool::new(false);
+
+/// 系统时间是否已经同步过（SNTP 或服务器时间）
+pub fn time_synced() -> bool {
+    TIME_SYNCED.load(Ordering::Relaxed)
+}
+
+pub(crate) fn mark_synced() {
+    TIME_SYNCED.store(true, Ordering::Relaxed);
+}
+
+/// 用服务器返回的时间设置系统时间，SNTP 已经同步过时不改
+#[cfg(target_os = "espidf")]
+pub fn set_fallback_time(since_epoch: Duration) -> Result<()> {
+    use esp_idf_sys::{esp, settimeofday, timeval};
+
+    if time_synced() {
+        return Ok(());
+    }
+    let tv = timeval {
+        tv_sec: since_epoch.as_secs() as _,
+        tv_usec: since_epoch.subsec_micros() as _,
+    };
+    esp!(unsafe { settimeofday(&tv, std::ptr::null()) })?;
+    mark_synced();
+    info!("System time set from server: {}s", since_epoch.as_secs());
+    Ok(())
+}
+
+/// 电脑的系统时间本来就是准的，不去改它
+#[cfg(not(target_os = "espidf"))]
+pub fn set_fallback_time(_since_epoch: Duration) -> Result<()> {
+    mark_synced();
+    Ok(())
+}
+
+/// 设置时区，格式是 POSIX 的 TZ，例如 `CST-8`、`JST-9`、`EST5EDT,M3.2.0,M11.1.0`
+pub fn set_timezone(timezone: &str) {
+    let timezone = if timezone.is_empty() {
+        DEFAULT_TIMEZONE
+    } else {
+        timezone
+    };
+    std::env::set_var("TZ", timezone);
+    // 电脑上 chrono 每次都会重新读 TZ
+    #[cfg(target_os = "espidf")]
+    unsafe {
+        esp_idf_sys::tzset()
+    };
+    info!("Timezone: {}", timezone);
+}
+
+/// 本地时间，按 `set_timezone` 设置的时区换算
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LocalTime {
+    pub year: i32,
+    /// 1 ~ 12
+    pub month: u8,
+    pub day: u8,
+    pub hour: u8,
+    pub minute: u8,
+    pub second: u8,
+    /// 0 是星期日
+    pub weekday: u8,
+}
+
+impl LocalTime {
+    /// 例如 `09:05`
+    pub fn clock_text(&self) -> String {
+        format!("{:02}:{:02}", self.hour, self.minute)
+    }
+
+    /// 例如 `2024-05-01 星期三`
+    pub fn date_text(&self) -> String {
+        format!(
+            "{}-{:02}-{:02} {}",
+            self.year,
+            self.month,
+            self.day,
+            lang::WEEKDAYS[self.weekday as usize % 7]
+        )
+    }
+}
+
+/// 当前的 Unix 时间（秒），还没同步过时返回 None
+pub fn now_secs() -> Option<i64> {
+    if !time_synced() {
+        return None;
+    }
+    Some(SystemTime::now().duration_since(UNIX_EPOCH).ok()?.as_secs() as i64)
+}
+
+#[cfg(target_os = "espidf")]
+fn localtime(secs: i64) -> Option<NaiveDateTime> {
+    use esp_idf_sys::{localtime_r, time_t, tm};
+
+    let secs = secs as time_t;
+    let mut local = tm::default();
+    if unsafe { localtime_r(&secs, &mut local) }.is_null() {
+        return None;
+    }
+    chrono::NaiveDate::from_ymd_opt(
+        local.tm_year + 1900,
+        (local.tm_mon + 1) as u32,
+        local.tm_mday as u32,
+    )?
+    .and_hms_opt(
+        local.tm_hour as u32,
+        local.tm_min as u32,
+        // 闰秒时 tm_sec 是 60
+        local.tm_sec.min(59) as u32,
+    )
+}
+
+#[cfg(not(target_os = "espidf"))]
+fn localtime(secs: i64) -> Option<NaiveDateTime> {
+    use chrono::TimeZone;
+
+    Some(chrono::Local.timestamp_opt(secs, 0).single()?.naive_local())
+}
+
+/// 本地时间比 UTC 快多少秒，北京时间是 28800。还没同步过时返回 None
+pub fn utc_offset() -> Option<i64> {
+    let now = now_secs()?;
+    let local_secs = localtime(now)?.and_utc().timestamp();
+    Some(local_secs - now)
+}
+
+/// 当前的本地时间，还没同步过时返回 None
+pub fn local_time() -> Option<LocalTime> {
+    let local = localtime(now_secs()?)?;
+    Some(LocalTime {
+        year: local.year(),
+        month: local.month() as u8,
+        day: local.day() as u8,
+        hour: local.hour() as u8,
+        minute: local.minute() as u8,
+        second: local.second() as u8,
+        weekday: local.weekday().num_days_from_sunday() as u8,
+    })
+}
+
+/// 每到整分钟发一次 `AppEvent::ClockTick`
+pub fn start_clock_timer(sender: Sender<AppEvent>) -> Result<()> {
+    thread::Builder::new()
+        .name("clock_tick".into())
+        .stack_size(2 * 1024)
+        .spawn(move || loop {
+            let seconds = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|now| now.as_secs() % 60)
+                .unwrap_or(0);
+            thread::sleep(Duration::from_secs(60 - seconds));
+            if sender.send(AppEvent::ClockTick).is_err() {
+                break;
+            }
+        })?;
+    Ok(())
+}
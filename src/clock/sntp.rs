@@ -0,0 +1,51 @@
+use std::sync::mpsc::Sender;
+
+use anyhow::Result;
+#[cfg(target_os = "espidf")]
+use esp_idf_svc::sntp::{EspSntp, SntpConf};
+use log::error;
+#[cfg(target_os = "espidf")]
+use log::info;
+
+use crate::{clock, common::event::AppEvent};
+
+/// 国内访问 pool.ntp.org 比较慢，第一个服务器换成阿里云的
+#[cfg(target_os = "espidf")]
+const PRIMARY_SERVER: &str = "ntp.aliyun.com";
+
+/// SNTP 客户端，drop 之后停止同步。同步成功后发 `AppEvent::TimeSynced`，之后 lwip 会定时重新同步
+pub struct SntpService {
+    #[cfg(target_os = "espidf")]
+    _sntp: EspSntp<'static>,
+}
+
+impl SntpService {
+    #[cfg(target_os = "espidf")]
+    pub fn start(sender: Sender<AppEvent>) -> Result<Self> {
+        let mut conf = SntpConf::default();
+        conf.servers[0] = PRIMARY_SERVER;
+
+        let sntp = EspSntp::new_with_callback(&conf, move |since_epoch| {
+            let first_sync = !clock::time_synced();
+            clock::mark_synced();
+            info!("SNTP synced: {}s", since_epoch.as_secs());
+            if first_sync {
+                if let Err(e) = sender.send(AppEvent::TimeSynced) {
+                    error!("Failed to send TimeSynced event: {:?}", e);
+                }
+            }
+        })?;
+        info!("SNTP started");
+        Ok(Self { _sntp: sntp })
+    }
+
+    /// host-sim 下电脑的系统时间已经同步过，直接当作同步成功
+    #[cfg(not(target_os = "espidf"))]
+    pub fn start(sender: Sender<AppEvent>) -> Result<Self> {
+        clock::mark_synced();
+        if let Err(e) = sender.send(AppEvent::TimeSynced) {
+            error!("Failed to send TimeSynced event: {:?}", e);
+        }
+        Ok(Self {})
+    }
+}
@@ -0,0 +1,305 @@
+//! 运行时诊断信息：堆内存、任务栈水位、WiFi 信号、音频队列、编解码耗时和复位原因。
+//!
+//! 以前 AFE 处理器栈溢出时完全看不到现场，现在可以通过 `GET /diag`、MCP 工具
+//! `self.system.get_diagnostics` 查看，也可以定时推送给服务器。
+
+use std::{
+    collections::VecDeque,
+    sync::{
+        atomic::{AtomicU32, Ordering},
+        mpsc::Sender,
+        Arc, Mutex, OnceLock,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+use anyhow::Result;
+use serde::Serialize;
+
+use crate::{audio::codec::types::AudioStreamPacket, common::event::AppEvent};
+
+type AudioQueue = Arc<Mutex<VecDeque<AudioStreamPacket>>>;
+
+/// 编码/解码耗时统计，单位微秒。各个任务里直接更新，不需要加锁
+pub struct TimingStats {
+    last_us: AtomicU32,
+    max_us: AtomicU32,
+    total_us: AtomicU32,
+    count: AtomicU32,
+}
+
+impl TimingStats {
+    pub const fn new() -> Self {
+        Self {
+            last_us: AtomicU32::new(0),
+            max_us: AtomicU32::new(0),
+            total_us: AtomicU32::new(0),
+            count: AtomicU32::new(0),
+        }
+    }
+
+    pub fn record(&self, started_at: Instant) {
+        let us = started_at.elapsed().as_micros().min(u32::MAX as u128) as u32;
+        self.last_us.store(us, Ordering::Relaxed);
+        self.max_us.fetch_max(us, Ordering::Relaxed);
+        // 累计值溢出时重新开始统计，平均值只是个参考
+        if self
+            .total_us
+            .fetch_add(us, Ordering::Relaxed)
+            .checked_add(us)
+            .is_none()
+        {
+            self.total_us.store(us, Ordering::Relaxed);
+            self.count.store(1, Ordering::Relaxed);
+        } else {
+            self.count.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    pub fn snapshot(&self) -> TimingReport {
+        let count = self.count.load(Ordering::Relaxed);
+        let total = self.total_us.load(Ordering::Relaxed);
+        TimingReport {
+            count,
+            last_us: self.last_us.load(Ordering::Relaxed),
+            max_us: self.max_us.load(Ordering::Relaxed),
+            avg_us: if count == 0 { 0 } else { total / count },
+        }
+    }
+}
+
+/// 一帧 opus 编码的耗时（encoder_task）
+pub static ENCODE_TIMING: TimingStats = TimingStats::new();
+/// 一帧 opus 解码的耗时（audio decode task）
+pub static DECODE_TIMING: TimingStats = TimingStats::new();
+
+/// 已经发给解码任务、还没有处理的音频包数量。解码任务用的是 channel，拿不到长度，只能自己计数
+pub static DECODE_PENDING: AtomicU32 = AtomicU32::new(0);
+
+/// 需要监控栈水位的任务，保存的是 FreeRTOS 的 TaskHandle
+static TASKS: Mutex<Vec<(&'static str, usize)>> = Mutex::new(Vec::new());
+
+static QUEUES: OnceLock<(AudioQueue, AudioQueue)> = OnceLock::new();
+
+/// host-sim 下的启动时间，从 Application 创建时算起
+#[cfg(not(target_os = "espidf"))]
+static STARTED_AT: OnceLock<Instant> = OnceLock::new();
+
+/// 在任务里调用，登记当前任务。
+/// std 的线程名不会设置到 FreeRTOS 任务上，所以不能用 `xTaskGetHandle` 按名字查，
+/// 只能让任务自己登记。返回的 guard 被 drop 时取消登记，避免任务退出后用到失效的 handle
+pub fn register_current_task(name: &'static str) -> TaskRegistration {
+    let handle = current_task_handle();
+    let mut tasks = TASKS.lock().unwrap();
+    tasks.retain(|(n, _)| *n != name);
+    tasks.push((name, handle));
+    TaskRegistration { name }
+}
+
+pub struct TaskRegistration {
+    name: &'static str,
+}
+
+impl Drop for TaskRegistration {
+    fn drop(&mut self) {
+        TASKS.lock().unwrap().retain(|(n, _)| *n != self.name);
+    }
+}
+
+/// 登记音频队列，只需要在 Application 创建时调用一次
+pub fn register_audio_queues(audio_packet_queue: AudioQueue, audio_decode_queue: AudioQueue) {
+    let _ = QUEUES.set((audio_packet_queue, audio_decode_queue));
+    #[cfg(not(target_os = "espidf"))]
+    STARTED_AT.get_or_init(Instant::now);
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct TimingReport {
+    pub count: u32,
+    pub last_us: u32,
+    pub max_us: u32,
+    pub avg_us: u32,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct HeapReport {
+    pub free: usize,
+    pub min_free: usize,
+    pub largest_free_block: usize,
+    pub total: usize,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct TaskReport {
+    pub name: &'static str,
+    /// 栈剩余的最小值（字节），越接近 0 越危险
+    pub stack_high_water_mark: u32,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct QueueReport {
+    pub audio_packet_queue: usize,
+    pub audio_decode_queue: usize,
+    pub decode_pending: u32,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct DiagnosticsReport {
+    pub version: &'static str,
+    pub uptime_s: u64,
+    pub reset_reason: &'static str,
+    pub heap: HeapReport,
+    /// 没有 PSRAM 时为 None
+    pub psram: Option<HeapReport>,
+    pub tasks: Vec<TaskReport>,
+    /// 没有连上 WiFi 时为 None
+    pub wifi_rssi: Option<i8>,
+    pub queues: QueueReport,
+    pub encode: TimingReport,
+    pub decode: TimingReport,
+}
+
+/// 启动定时器线程，每隔 `interval` 发送一次 `AppEvent::DiagnosticsTick`
+pub fn start_report_timer(interval: Duration, sender: Sender<AppEvent>) -> Result<()> {
+    thread::Builder::new()
+        .name("diag_tick".into())
+        .stack_size(2 * 1024)
+        .spawn(move || loop {
+            thread::sleep(interval);
+            if sender.send(AppEvent::DiagnosticsTick).is_err() {
+                break;
+            }
+        })?;
+    Ok(())
+}
+
+/// 采集一次诊断信息，可以在任意线程里调用
+#[cfg(target_os = "espidf")]
+pub fn collect() -> DiagnosticsReport {
+    let psram = heap_report(esp_idf_sys::MALLOC_CAP_SPIRAM);
+    DiagnosticsReport {
+        version: env!("CARGO_PKG_VERSION"),
+        uptime_s: (unsafe { esp_idf_sys::esp_timer_get_time() } / 1_000_000) as u64,
+        reset_reason: reset_reason(),
+        heap: heap_report(esp_idf_sys::MALLOC_CAP_INTERNAL),
+        psram: (psram.total > 0).then_some(psram),
+        tasks: task_reports(),
+        wifi_rssi: wifi_rssi(),
+        queues: queue_report(),
+        encode: ENCODE_TIMING.snapshot(),
+        decode: DECODE_TIMING.snapshot(),
+    }
+}
+
+/// host-sim 下拿不到堆和任务栈的信息，只有队列和编解码耗时是真的
+#[cfg(not(target_os = "espidf"))]
+pub fn collect() -> DiagnosticsReport {
+    DiagnosticsReport {
+        version: env!("CARGO_PKG_VERSION"),
+        uptime_s: STARTED_AT
+            .get()
+            .map_or(0, |started_at| started_at.elapsed().as_secs()),
+        reset_reason: reset_reason(),
+        heap: HeapReport {
+            free: 0,
+            min_free: 0,
+            largest_free_block: 0,
+            total: 0,
+        },
+        psram: None,
+        tasks: Vec::new(),
+        wifi_rssi: None,
+        queues: queue_report(),
+        encode: ENCODE_TIMING.snapshot(),
+        decode: DECODE_TIMING.snapshot(),
+    }
+}
+
+#[cfg(target_os = "espidf")]
+fn current_task_handle() -> usize {
+    unsafe { esp_idf_sys::xTaskGetCurrentTaskHandle() as usize }
+}
+
+#[cfg(not(target_os = "espidf"))]
+fn current_task_handle() -> usize {
+    0
+}
+
+#[cfg(target_os = "espidf")]
+fn heap_report(caps: u32) -> HeapReport {
+    unsafe {
+        HeapReport {
+            free: esp_idf_sys::heap_caps_get_free_size(caps) as usize,
+            min_free: esp_idf_sys::heap_caps_get_minimum_free_size(caps) as usize,
+            largest_free_block: esp_idf_sys::heap_caps_get_largest_free_block(caps) as usize,
+            total: esp_idf_sys::heap_caps_get_total_size(caps) as usize,
+        }
+    }
+}
+
+#[cfg(target_os = "espidf")]
+fn task_reports() -> Vec<TaskReport> {
+    TASKS
+        .lock()
+        .unwrap()
+        .iter()
+        .map(|(name, handle)| TaskReport {
+            name: *name,
+            // ESP-IDF 的 StackType_t 是 uint8_t，所以返回的就是字节数
+            stack_high_water_mark: unsafe {
+                esp_idf_sys::uxTaskGetStackHighWaterMark(*handle as esp_idf_sys::TaskHandle_t)
+            },
+        })
+        .collect()
+}
+
+#[cfg(target_os = "espidf")]
+fn wifi_rssi() -> Option<i8> {
+    let mut ap_info: esp_idf_sys::wifi_ap_record_t = Default::default();
+    let ret = unsafe { esp_idf_sys::esp_wifi_sta_get_ap_info(&mut ap_info) };
+    (ret == esp_idf_sys::ESP_OK).then_some(ap_info.rssi)
+}
+
+fn queue_report() -> QueueReport {
+    let (audio_packet_queue, audio_decode_queue) = match QUEUES.get() {
+        Some((packet_queue, decode_queue)) => (
+            packet_queue.lock().map(|q| q.len()).unwrap_or(0),
+            decode_queue.lock().map(|q| q.len()).unwrap_or(0),
+        ),
+        None => (0, 0),
+    };
+    QueueReport {
+        audio_packet_queue,
+        audio_decode_queue,
+        decode_pending: DECODE_PENDING.load(Ordering::Relaxed),
+    }
+}
+
+#[cfg(target_os = "espidf")]
+pub fn reset_reason() -> &'static str {
+    match unsafe { esp_idf_sys::esp_reset_reason() } {
+        esp_idf_sys::esp_reset_reason_t_ESP_RST_POWERON => "power_on",
+        esp_idf_sys::esp_reset_reason_t_ESP_RST_EXT => "external_pin",
+        esp_idf_sys::esp_reset_reason_t_ESP_RST_SW => "software",
+        esp_idf_sys::esp_reset_reason_t_ESP_RST_PANIC => "panic",
+        esp_idf_sys::esp_reset_reason_t_ESP_RST_INT_WDT => "interrupt_watchdog",
+        esp_idf_sys::esp_reset_reason_t_ESP_RST_TASK_WDT => "task_watchdog",
+        esp_idf_sys::esp_reset_reason_t_ESP_RST_WDT => "other_watchdog",
+        esp_idf_sys::esp_reset_reason_t_ESP_RST_DEEPSLEEP => "deep_sleep",
+        esp_idf_sys::esp_reset_reason_t_ESP_RST_BROWNOUT => "brownout",
+        esp_idf_sys::esp_reset_reason_t_ESP_RST_SDIO => "sdio",
+        esp_idf_sys::esp_reset_reason_t_ESP_RST_USB => "usb",
+        esp_idf_sys::esp_reset_reason_t_ESP_RST_JTAG => "jtag",
+        esp_idf_sys::esp_reset_reason_t_ESP_RST_EFUSE => "efuse",
+        esp_idf_sys::esp_reset_reason_t_ESP_RST_PWR_GLITCH => "power_glitch",
+        esp_idf_sys::esp_reset_reason_t_ESP_RST_CPU_LOCKUP => "cpu_lockup",
+        _ => "unknown",
+    }
+}
+
+/// host-sim 每次都是重新启动
+#[cfg(not(target_os = "espidf"))]
+pub fn reset_reason() -> &'static str {
+    "power_on"
+}
@@ -27,6 +27,7 @@ pub enum DeviceState {
     Speaking,
     Listening,
     Starting,
+    Upgrading, // 正在下载安装新固件，不响应对话
 }
 
 #[derive(PartialEq, Debug, Clone)]
@@ -1,19 +1,27 @@
+#[cfg(target_os = "espidf")]
 use std::ffi::CStr;
 
+#[cfg(target_os = "espidf")]
 use esp_idf_svc::eventloop::{
     EspEvent, EspEventDeserializer, EspEventPostData, EspEventSerializer, EspEventSource,
 };
 
-use crate::audio::codec::types::AudioStreamPacket;
+use crate::{
+    audio::codec::types::AudioStreamPacket, camera::CameraFrame, firmware::ota::OtaProgress,
+    scheduler::Alarm,
+};
 
 pub const WEBSOCKET_PROTOCOL_SERVER_HELLO_EVENT: u32 = 1;
 
+/// 通过 ESP-IDF 的事件循环发送的 websocket 事件
+#[cfg(target_os = "espidf")]
 #[derive(Copy, Clone, Debug)]
 pub enum WsEvent {
     WebSocketConnected,
     ServerHelloMessageReceived, // 收到服务器返回的hello消息
     SendAudioEvent,             // 发送音频数据事件
 }
+#[cfg(target_os = "espidf")]
 unsafe impl EspEventSource for WsEvent {
     #[allow(clippy::manual_c_str_literals)]
     fn source() -> Option<&'static CStr> {
@@ -22,6 +30,7 @@ unsafe impl EspEventSource for WsEvent {
     }
 }
 
+#[cfg(target_os = "espidf")]
 impl EspEventSerializer for WsEvent {
     type Data<'a> = WsEvent;
 
@@ -34,6 +43,7 @@ impl EspEventSerializer for WsEvent {
     }
 }
 
+#[cfg(target_os = "espidf")]
 impl EspEventDeserializer for WsEvent {
     type Data<'a> = WsEvent;
 
@@ -61,5 +71,30 @@ pub enum AppEvent {
     AudioTestEvent(Vec<i16>),
     TTSStop,
     TTSStart,
-    PlayAudioAlert(String), //播放内置的提示音频
+    TTSPlaybackFinished,            // 喇叭播完了一段 TTS（或提示音）的最后一个包
+    PlayAudioAlert(String),         //播放内置的提示音频
+    PowerKeyClicked,                // AXP173 PEK 短按
+    PowerKeyLongPressed,            // AXP173 PEK 长按
+    ChargingStateChanged(bool),     // USB 插入/拔出
+    BatteryOverheat,                // 电池过温
+    PowerOff,                       // 告别语播放完毕（或超时）后关机
+    PowerTick,                      // PowerManager 的定时检查
+    NetworkConnected(String),       // WiFi 连上并拿到 IP
+    NetworkDisconnected,            // WiFi 掉线，正在重连
+    NetworkProvisioningRequired,    // 重连多次失败，需要重新配网
+    ResetWifiConfiguration,         // 启动过程中按下 boot 键，清除WiFi配置
+    FactoryReset,                   // 恢复出厂设置后重启
+    OtaProgress(OtaProgress),       // OTA 检查/下载/校验的进度
+    DiagnosticsTick,                // 定时推送诊断信息
+    LogStreamTick,                  // 定时推送日志
+    TalkButtonPressed,              // 按住说话：按下 boot 键
+    TalkButtonReleased,             // 按住说话：松开 boot 键
+    SwitchListeningMode,            // 双击 boot 键，切换按住说话/自动模式
+    EncoderFlushed,                 // 编码线程已经把剩下的音频编码完
+    ShowCameraPreview(CameraFrame), // 拍照后在屏幕上显示拍到的画面
+    TimeSynced,                     // 系统时间第一次同步（SNTP 或服务器时间）
+    ClockTick,                      // 每分钟刷新一次待机时钟
+    AlarmFired(Alarm),              // 闹钟、计时器或提醒到点了
+    AlarmRing,                      // 响铃期间定时再播一次铃声
+    AlarmStopped,                   // 没人关掉，响铃超时停止
 }
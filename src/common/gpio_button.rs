@@ -1,37 +1,61 @@
+#[cfg(target_os = "espidf")]
 use anyhow::Result;
+#[cfg(target_os = "espidf")]
 use esp_idf_sys::es32_component_button::{
-    button_config_t, button_event_t_BUTTON_SINGLE_CLICK, button_gpio_config_t, button_handle_t,
-    iot_button_delete, iot_button_new_gpio_device, iot_button_register_cb,
+    button_config_t, button_event_args_t, button_event_t, button_event_t_BUTTON_DOUBLE_CLICK,
+    button_event_t_BUTTON_LONG_PRESS_START, button_event_t_BUTTON_PRESS_DOWN,
+    button_event_t_BUTTON_PRESS_UP, button_event_t_BUTTON_SINGLE_CLICK, button_gpio_config_t,
+    button_handle_t, iot_button_delete, iot_button_new_gpio_device, iot_button_register_cb,
     iot_button_unregister_cb,
 };
+#[cfg(target_os = "espidf")]
 use std::ffi::c_void;
+#[cfg(target_os = "espidf")]
 use std::ptr;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+// 板子上的按键用 espressif/button 组件，host-sim 下换成 `sim_button::Button`，组合键检测两边共用
+
+/// 默认的长按时间
+pub const DEFAULT_LONG_PRESS: Duration = Duration::from_millis(1000);
+/// 松开后等待这么久没有再按下才算单击，同时也是双击的最大间隔
+#[cfg(target_os = "espidf")]
+const SHORT_PRESS_TIME_MS: u16 = 200;
 
 // 定义一个类型别名，方便阅读：这是一个装箱的、线程安全的、可变的闭包
+#[cfg(target_os = "espidf")]
 type BoxedCallback = Box<dyn FnMut() + Send + 'static>;
 
+#[cfg(target_os = "espidf")]
 pub struct Button {
     button_handle: button_handle_t,
     // 我们需要保存回调的指针，原因有两个：
     // 1. 保证闭包在 C 回调期间活着
     // 2. 在 Button Drop 时，我们需要手动释放这块内存，否则会内存泄漏
-    // 这里保存的是指向 Box<BoxedCallback> 的裸指针
-    callback_ptr: Option<*mut BoxedCallback>,
+    // 这里保存的是事件类型、长按时间（其它事件为 0）和指向 Box<BoxedCallback> 的裸指针
+    callbacks: Vec<(button_event_t, u16, *mut BoxedCallback)>,
 }
 
+#[cfg(target_os = "espidf")]
 impl Button {
-    /// 创建并配置一个新的按钮实例
+    /// 创建并配置一个新的按钮实例，低电平表示按下
     pub fn new(gpio_num: i32) -> Result<Self> {
+        Self::with_active_level(gpio_num, 0)
+    }
+
+    /// 创建按钮，`active_level` 是按下时的电平（0 或 1）
+    pub fn with_active_level(gpio_num: i32, active_level: u8) -> Result<Self> {
         // 使用 Default 或 zeroed 初始化 C 结构体通常更安全，防止未来字段变动
         let button_config = button_config_t {
-            long_press_time: 1000,
-            short_press_time: 50,
+            long_press_time: DEFAULT_LONG_PRESS.as_millis() as u16,
+            short_press_time: SHORT_PRESS_TIME_MS,
             ..Default::default()
         };
 
         let button_gpio_config = button_gpio_config_t {
             gpio_num,
-            active_level: 0,
+            active_level,
             enable_power_save: false,
             disable_pull: false,
         };
@@ -47,17 +71,58 @@ impl Button {
 
         Ok(Self {
             button_handle,
-            callback_ptr: None,
+            callbacks: Vec::new(),
         })
     }
 
-    /// 注册单击事件
+    /// 注册单击事件。松开后 200ms 内没有再次按下才触发，和双击互斥
     pub fn on_click<F>(&mut self, callback: F) -> Result<()>
     where
         F: FnMut() + Send + 'static,
     {
-        // 1. 清理旧的回调（如果有）
-        self.free_callback();
+        self.register(button_event_t_BUTTON_SINGLE_CLICK, 0, callback)
+    }
+
+    /// 注册双击事件
+    pub fn on_double_click<F>(&mut self, callback: F) -> Result<()>
+    where
+        F: FnMut() + Send + 'static,
+    {
+        self.register(button_event_t_BUTTON_DOUBLE_CLICK, 0, callback)
+    }
+
+    /// 注册按下事件，去抖之后立即触发
+    pub fn on_press_down<F>(&mut self, callback: F) -> Result<()>
+    where
+        F: FnMut() + Send + 'static,
+    {
+        self.register(button_event_t_BUTTON_PRESS_DOWN, 0, callback)
+    }
+
+    /// 注册松开事件，包括长按之后的松开
+    pub fn on_press_up<F>(&mut self, callback: F) -> Result<()>
+    where
+        F: FnMut() + Send + 'static,
+    {
+        self.register(button_event_t_BUTTON_PRESS_UP, 0, callback)
+    }
+
+    /// 注册长按事件，按住超过 `duration` 时触发一次。
+    /// 可以注册多个不同时长的长按，同一个时长重复注册会替换掉旧的回调
+    pub fn on_long_press<F>(&mut self, duration: Duration, callback: F) -> Result<()>
+    where
+        F: FnMut() + Send + 'static,
+    {
+        let press_time = duration.as_millis().min(u16::MAX as u128) as u16;
+        self.register(button_event_t_BUTTON_LONG_PRESS_START, press_time, callback)
+    }
+
+    fn register<F>(&mut self, event: button_event_t, press_time: u16, callback: F) -> Result<()>
+    where
+        F: FnMut() + Send + 'static,
+    {
+        // 1. 清理这个事件旧的回调（如果有）
+        self.free_callback(event, press_time);
 
         // 2. 处理闭包的指针转换
         // 第一步：把闭包 Box 起来，变成 Trait Object (这是一个胖指针)
@@ -70,12 +135,15 @@ impl Button {
         // 第三步：转为裸指针，准备传给 C
         let usr_data = Box::into_raw(cb_wrapper);
 
-        // 3. 注册回调
+        // 3. 注册回调，长按需要带上时长
+        let mut event_args = event_args(event, press_time);
         let ret = unsafe {
             iot_button_register_cb(
                 self.button_handle,
-                button_event_t_BUTTON_SINGLE_CLICK,
-                ptr::null_mut(),
+                event,
+                event_args
+                    .as_mut()
+                    .map_or(ptr::null_mut(), |args| args as *mut _),
                 Some(trampoline),        // 使用下面的蹦床函数
                 usr_data as *mut c_void, // 传入我们的闭包指针
             )
@@ -90,20 +158,29 @@ impl Button {
         }
 
         // 4. 保存指针以便后续释放
-        self.callback_ptr = Some(usr_data);
+        self.callbacks.push((event, press_time, usr_data));
 
         Ok(())
     }
 
-    // 辅助函数：释放回调占用的内存
-    fn free_callback(&mut self) {
-        if let Some(ptr) = self.callback_ptr.take() {
+    // 辅助函数：释放某个事件的回调占用的内存
+    fn free_callback(&mut self, event: button_event_t, press_time: u16) {
+        if let Some(index) = self
+            .callbacks
+            .iter()
+            .position(|(e, t, _)| *e == event && *t == press_time)
+        {
+            let (event, press_time, ptr) = self.callbacks.remove(index);
+            let mut event_args = event_args(event, press_time);
             unsafe {
                 // 先取消注册 (虽然 iot_button_delete 会处理，但显式处理是个好习惯)
+                // 长按要带上时长，否则会把所有时长的长按都取消掉
                 iot_button_unregister_cb(
                     self.button_handle,
-                    button_event_t_BUTTON_SINGLE_CLICK,
-                    ptr::null_mut(),
+                    event,
+                    event_args
+                        .as_mut()
+                        .map_or(ptr::null_mut(), |args| args as *mut _),
                 );
                 // 将裸指针转回 Box，让它离开作用域自动 Drop
                 let _ = Box::from_raw(ptr);
@@ -112,9 +189,21 @@ impl Button {
     }
 }
 
+// 长按事件需要 event_args 指定时长，其它事件传 NULL
+#[cfg(target_os = "espidf")]
+fn event_args(event: button_event_t, press_time: u16) -> Option<button_event_args_t> {
+    if event != button_event_t_BUTTON_LONG_PRESS_START {
+        return None;
+    }
+    let mut args = button_event_args_t::default();
+    args.long_press.press_time = press_time;
+    Some(args)
+}
+
 // --- 蹦床函数 (Trampoline) ---
 // 这是一个符合 C ABI 的静态函数。
 // 它的作用是接收 C 的调用，把 void* 转换回 Rust 的闭包，然后执行。
+#[cfg(target_os = "espidf")]
 unsafe extern "C" fn trampoline(_arg: *mut c_void, usr_data: *mut c_void) {
     if usr_data.is_null() {
         return;
@@ -131,10 +220,13 @@ unsafe extern "C" fn trampoline(_arg: *mut c_void, usr_data: *mut c_void) {
     (callback)();
 }
 
+#[cfg(target_os = "espidf")]
 impl Drop for Button {
     fn drop(&mut self) {
         // 1. 先释放回调的内存
-        self.free_callback();
+        while let Some((event, press_time, _)) = self.callbacks.first().copied() {
+            self.free_callback(event, press_time);
+        }
 
         // 2. 再删除按钮句柄
         if !self.button_handle.is_null() {
@@ -144,3 +236,61 @@ impl Drop for Button {
         }
     }
 }
+
+/// 组合键检测：组合里的按键同时按住时算组合键。
+///
+/// 按键的回调在 button 组件的定时器任务里执行，所以状态放在 Mutex 里，可以 clone 给各个回调共用。
+/// 组合键触发后，组合里的按键的单击、双击、长按都被忽略，直到这个按键下一次单独按下，
+/// 避免松开组合键时又触发了单个按键的功能。按下/松开事件不会被忽略。
+#[derive(Clone)]
+pub struct ButtonCombo {
+    state: Arc<Mutex<ComboState>>,
+    mask: u32,
+}
+
+#[derive(Default)]
+struct ComboState {
+    pressed: u32,
+    suppressed: u32,
+}
+
+impl ButtonCombo {
+    /// `ids` 是组合里的按键编号（0-31），由调用者自己分配
+    pub fn new(ids: &[u8]) -> Self {
+        Self {
+            state: Arc::new(Mutex::new(ComboState::default())),
+            mask: ids.iter().fold(0, |mask, id| mask | (1 << id)),
+        }
+    }
+
+    /// 按键按下时调用，返回组合键是否刚好凑齐
+    pub fn press(&self, id: u8) -> bool {
+        let bit = 1 << id;
+        let mut state = self.state.lock().unwrap();
+        state.pressed |= bit;
+        if state.pressed & self.mask == self.mask {
+            state.suppressed |= self.mask;
+            return true;
+        }
+        // 组合里没有其它按键按住，说明是单独按下
+        if state.pressed & self.mask == bit {
+            state.suppressed &= !bit;
+        }
+        false
+    }
+
+    /// 按键松开时调用
+    pub fn release(&self, id: u8) {
+        self.state.lock().unwrap().pressed &= !(1 << id);
+    }
+
+    /// 组合里的按键是否都按住了
+    pub fn is_active(&self) -> bool {
+        self.state.lock().unwrap().pressed & self.mask == self.mask
+    }
+
+    /// 这个按键的单击、双击、长按是否应该忽略
+    pub fn is_suppressed(&self, id: u8) -> bool {
+        self.state.lock().unwrap().suppressed & (1 << id) != 0
+    }
+}
@@ -1,17 +1,43 @@
-use embedded_svc::http::Headers;
+use std::{
+    net::Ipv4Addr,
+    sync::{mpsc::Sender, Arc, Mutex},
+};
+
+use embedded_svc::http::{Headers, Query};
 use esp_idf_hal::io::{Read, Write};
-use esp_idf_svc::http::{server::EspHttpServer, Method};
-use esp_idf_sys::esp_restart;
-use log::info;
-use serde::Deserialize;
+use esp_idf_svc::{
+    eventloop::EspSystemEventLoop,
+    http::{
+        server::{EspHttpConnection, EspHttpServer, Request},
+        Method,
+    },
+    wifi::EspWifi,
+};
+use esp_idf_sys::esp_random;
+use log::{error, info, warn, Level};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
 
-use crate::wifi::ssid_manager::SsidMananger;
+use crate::{
+    common::{diagnostics, event::AppEvent, restart::schedule_restart},
+    firmware::ota::{flash_firmware, parse_sha256, report_progress, OtaProgress},
+    logging::{log_buffer::parse_level, tee_logger},
+    setting::{
+        settings::{Settings, SettingsGroup},
+        storage::NvsStorage,
+    },
+    wifi::{
+        ssid_manager::{SsidItem, SsidMananger, WifiAuth},
+        wifi_driver::{scan_access_points, try_connect},
+    },
+};
 
 pub fn create_server() -> anyhow::Result<EspHttpServer<'static>> {
     const STACK_SIZE: usize = 10240;
     let server_configuration = esp_idf_svc::http::server::Configuration {
         stack_size: STACK_SIZE,
         max_resp_headers: 4096,
+        // 用于把系统的联网检测和其它未知路径都重定向到配网页面
+        uri_match_wildcard: true,
         ..Default::default()
     };
 
@@ -20,13 +46,243 @@ pub fn create_server() -> anyhow::Result<EspHttpServer<'static>> {
 
 static INDEX_HTML: &str = include_str!("../../assets/html/config_wifi.html");
 
+const MAX_LEN: usize = 2048;
+/// 导入的配置里可能有十几个WiFi
+const MAX_IMPORT_LEN: usize = 8192;
+
 #[derive(Deserialize)]
 struct FormData<'a> {
     wifi_ssid: &'a str,
     wifi_password: &'a str,
 }
 
-pub fn start_http_server(http_server: &mut EspHttpServer<'static>) -> anyhow::Result<()> {
+#[derive(Deserialize)]
+struct DeleteNetworkRequest {
+    ssid: String,
+}
+
+/// `GET /networks` 不返回密码
+#[derive(Serialize)]
+struct SavedNetwork {
+    ssid: String,
+    last_connect_time: String,
+    auth: WifiAuth,
+    hidden: bool,
+    static_ip: bool,
+}
+
+#[derive(Serialize)]
+struct ConnectResponse {
+    success: bool,
+    message: String,
+}
+
+/// 读取 JSON 格式的请求体，太大或格式不对时直接返回错误响应，并返回 None
+fn read_json<T: DeserializeOwned>(
+    req: &mut Request<&mut EspHttpConnection<'_>>,
+) -> anyhow::Result<Option<T>> {
+    let len = req.content_len().unwrap_or(0) as usize;
+    if len > MAX_LEN {
+        return Ok(None);
+    }
+
+    let mut buf = vec![0; len];
+    req.read_exact(&mut buf)?;
+    Ok(serde_json::from_slice::<T>(&buf).ok())
+}
+
+fn write_json<T: Serialize>(
+    req: Request<&mut EspHttpConnection<'_>>,
+    status: u16,
+    value: &T,
+) -> anyhow::Result<()> {
+    let body = serde_json::to_vec(value)?;
+    req.into_response(status, None, &[("Content-Type", "application/json")])?
+        .write_all(&body)?;
+    Ok(())
+}
+
+/// token 的随机字节数，转成十六进制是 32 个字符
+const OTA_TOKEN_BYTES: usize = 16;
+
+/// 读取本地上传固件用的 token，没有的话随机生成一个保存起来。
+///
+/// 新生成的 token 用 `println!` 在串口上打印一次，不能用 log：
+/// 日志会存进内存和 flash，会推送给服务器，崩溃时还会附在 hello 里。
+/// 之后每次进入配网都会显示在屏幕上
+pub fn ensure_ota_token() -> anyhow::Result<String> {
+    let settings = Settings::load()?;
+    if let Some(token) = settings.security.ota_token {
+        // 旧版本生成的 token 只有 32 位，重新生成
+        if token.len() >= OTA_TOKEN_BYTES * 2 {
+            return Ok(token);
+        }
+    }
+
+    let token = (0..OTA_TOKEN_BYTES / 4)
+        .map(|_| format!("{:08x}", unsafe { esp_random() }))
+        .collect::<String>();
+    Settings::update(SettingsGroup::Security, |settings| {
+        settings.security.ota_token = Some(token.clone());
+    })?;
+    println!("Local OTA token: {}", token);
+    Ok(token)
+}
+
+/// 检查 `Authorization: Bearer <token>`，逐字节比较所有字符，避免按时间猜出 token
+fn is_authorized(header: Option<&str>, token: &str) -> bool {
+    let Some(value) = header.and_then(|header| header.strip_prefix("Bearer ")) else {
+        return false;
+    };
+    let value = value.trim().as_bytes();
+    let token = token.as_bytes();
+    if value.len() != token.len() {
+        return false;
+    }
+    value
+        .iter()
+        .zip(token)
+        .fold(0u8, |diff, (a, b)| diff | (a ^ b))
+        == 0
+}
+
+/// 注册 `POST /ota`，把请求体直接写入 OTA 分区，成功后重启。
+///
+/// 配网页面和联网后的本地服务器都会注册。`app_event_sender` 不为 None 时把进度发给 application，
+/// 可以带上 `X-Firmware-Sha256` 头校验固件
+pub fn register_ota_handler(
+    http_server: &mut EspHttpServer<'static>,
+    app_event_sender: Option<Sender<AppEvent>>,
+) -> anyhow::Result<()> {
+    let token = ensure_ota_token()?;
+    info!("Local OTA enabled: POST /ota");
+
+    http_server.fn_handler::<anyhow::Error, _>("/ota", Method::Post, move |mut req| {
+        if !is_authorized(req.header("Authorization"), &token) {
+            warn!("Unauthorized ota upload");
+            return write_json(req, 401, &ConnectResponse {
+                success: false,
+                message: "invalid token".to_string(),
+            });
+        }
+        let Some(len) = req.content_len().map(|len| len as usize) else {
+            return write_json(req, 411, &ConnectResponse {
+                success: false,
+                message: "Content-Length required".to_string(),
+            });
+        };
+        let expected_sha256 = match req.header("X-Firmware-Sha256").map(parse_sha256) {
+            Some(Ok(sha256)) => Some(sha256),
+            Some(Err(e)) => {
+                return write_json(req, 400, &ConnectResponse {
+                    success: false,
+                    message: e.to_string(),
+                })
+            }
+            None => None,
+        };
+
+        info!("Receiving firmware: {} bytes", len);
+        let report = |progress: OtaProgress| {
+            if let OtaProgress::Downloading { received, .. } = &progress {
+                info!("ota upload: {}/{}", received, len);
+            }
+            if let Some(sender) = &app_event_sender {
+                report_progress(sender, progress);
+            }
+        };
+
+        match flash_firmware(&mut req, Some(len), None, expected_sha256.as_ref(), &report) {
+            Ok(version) => {
+                info!("Firmware {} uploaded, restarting", version);
+                report(OtaProgress::Completed {
+                    version: version.clone(),
+                });
+                write_json(req, 200, &ConnectResponse {
+                    success: true,
+                    message: version,
+                })?;
+                schedule_restart();
+                Ok(())
+            }
+            Err(e) => {
+                error!("Firmware upload failed: {:?}", e);
+                report(OtaProgress::Failed(e.to_string()));
+                write_json(req, 400, &ConnectResponse {
+                    success: false,
+                    message: e.to_string(),
+                })
+            }
+        }
+    })?;
+    Ok(())
+}
+
+/// `GET /diag` 返回运行时诊断信息，只读，不需要 token
+pub fn register_diag_handler(http_server: &mut EspHttpServer<'static>) -> anyhow::Result<()> {
+    http_server.fn_handler::<anyhow::Error, _>("/diag", Method::Get, |req| {
+        write_json(req, 200, &diagnostics::collect())
+    })?;
+    Ok(())
+}
+
+/// 注册 `GET /logs`，下载内存里的日志，`?source=flash` 下载 flash 里保存的日志，
+/// `?level=warn` 只看 warn 以上的。日志里有 token 之类的信息，所以和 `POST /ota` 用同一个 token
+pub fn register_log_handler(http_server: &mut EspHttpServer<'static>) -> anyhow::Result<()> {
+    let token = ensure_ota_token()?;
+
+    http_server.fn_handler::<anyhow::Error, _>("/logs", Method::Get, move |req| {
+        if !is_authorized(req.header("Authorization"), &token) {
+            warn!("Unauthorized log download");
+            return write_json(req, 401, &ConnectResponse {
+                success: false,
+                message: "invalid token".to_string(),
+            });
+        }
+        let uri = req.uri().to_string();
+        let level = query_param(&uri, "level")
+            .and_then(parse_level)
+            .unwrap_or(Level::Trace);
+
+        let mut resp =
+            req.into_response(200, None, &[("Content-Type", "text/plain; charset=utf-8")])?;
+        if query_param(&uri, "source") == Some("flash") {
+            let found = tee_logger::for_each_flash_line(|line| {
+                // flash 里只有文本，按行首的级别标记过滤
+                let line_level = line.get(..1).and_then(parse_level).unwrap_or(Level::Info);
+                if line_level <= level {
+                    resp.write_all(line.as_bytes())?;
+                    resp.write_all(b"\n")?;
+                }
+                Ok(())
+            })?;
+            if !found {
+                resp.write_all("No log partition\n".as_bytes())?;
+            }
+        } else {
+            resp.write_all(tee_logger::dump_ram(level).as_bytes())?;
+        }
+        Ok(())
+    })?;
+    Ok(())
+}
+
+/// 取出 uri 里 `?a=1&b=2` 形式的参数，不做 url 解码
+fn query_param<'a>(uri: &'a str, key: &str) -> Option<&'a str> {
+    let (_, query) = uri.split_once('?')?;
+    query
+        .split('&')
+        .filter_map(|pair| pair.split_once('='))
+        .find(|(k, _)| *k == key)
+        .map(|(_, v)| v)
+}
+
+pub fn start_http_server(
+    http_server: &mut EspHttpServer<'static>,
+    wifi: Arc<Mutex<EspWifi<'static>>>,
+    sysloop: EspSystemEventLoop,
+    portal_ip: Ipv4Addr,
+) -> anyhow::Result<()> {
     // let mut http_server = create_server()?;
 
     http_server.fn_handler("/", Method::Get, |req| {
@@ -49,7 +305,172 @@ pub fn start_http_server(http_server: &mut EspHttpServer<'static>) -> anyhow::Re
     //     Ok(())
     // })?;
 
-    const MAX_LEN: usize = 2048;
+    let scan_wifi = wifi.clone();
+    let scan_sysloop = sysloop.clone();
+    http_server.fn_handler::<anyhow::Error, _>("/scan", Method::Get, move |req| {
+        let result = {
+            let mut esp_wifi = scan_wifi.lock().unwrap();
+            scan_access_points(&mut esp_wifi, &scan_sysloop)
+        };
+        match result {
+            Ok(records) => write_json(req, 200, &records),
+            Err(e) => {
+                error!("扫描wifi失败: {:?}", e);
+                write_json(req, 500, &ConnectResponse {
+                    success: false,
+                    message: e.to_string(),
+                })
+            }
+        }
+    })?;
+
+    http_server.fn_handler::<anyhow::Error, _>("/networks", Method::Get, |req| {
+        let networks = SsidMananger::get_instance()
+            .get_ssid_list()?
+            .into_iter()
+            .map(|item| SavedNetwork {
+                ssid: item.ssid,
+                last_connect_time: item.last_connect_time,
+                auth: item.auth,
+                hidden: item.hidden,
+                static_ip: item.static_ip.is_some(),
+            })
+            .collect::<Vec<_>>();
+        write_json(req, 200, &networks)
+    })?;
+
+    http_server.fn_handler::<anyhow::Error, _>("/networks", Method::Delete, |mut req| {
+        let Some(body) = read_json::<DeleteNetworkRequest>(&mut req)? else {
+            req.into_status_response(400)?
+                .write_all("Invalid request".as_bytes())?;
+            return Ok(());
+        };
+
+        let removed = SsidMananger::get_instance().remove_ssid(&body.ssid)?;
+        info!("删除wifi: {} removed={}", body.ssid, removed);
+        write_json(req, if removed { 200 } else { 404 }, &ConnectResponse {
+            success: removed,
+            message: body.ssid,
+        })
+    })?;
+
+    let connect_wifi = wifi.clone();
+    let connect_sysloop = sysloop.clone();
+    http_server.fn_handler::<anyhow::Error, _>("/connect", Method::Post, move |mut req| {
+        // 请求体就是 SsidItem 的格式，除了 ssid 和 password，其它字段都是可选的
+        let Some(body) = read_json::<SsidItem>(&mut req)? else {
+            req.into_status_response(400)?
+                .write_all("Invalid request".as_bytes())?;
+            return Ok(());
+        };
+        if let Err(e) = body.validate() {
+            return write_json(req, 400, &ConnectResponse {
+                success: false,
+                message: e.to_string(),
+            });
+        }
+
+        info!("测试连接wifi: {}", body.ssid);
+        let result = {
+            let mut esp_wifi = connect_wifi.lock().unwrap();
+            try_connect(&mut esp_wifi, &connect_sysloop, &body)
+        };
+
+        match result {
+            Ok(ip_info) => {
+                if let Err(e) = SsidMananger::get_instance().add_network(body) {
+                    error!("添加新wifi失败: {:?}", e);
+                    return write_json(req, 500, &ConnectResponse {
+                        success: false,
+                        message: e.to_string(),
+                    });
+                }
+                write_json(req, 200, &ConnectResponse {
+                    success: true,
+                    message: ip_info.ip.to_string(),
+                })?;
+                schedule_restart();
+                Ok(())
+            }
+            Err(e) => {
+                error!("连接wifi失败: {:?}", e);
+                write_json(req, 200, &ConnectResponse {
+                    success: false,
+                    message: e.to_string(),
+                })
+            }
+        }
+    })?;
+
+    register_ota_handler(http_server, None)?;
+    register_diag_handler(http_server)?;
+    register_log_handler(http_server)?;
+
+    // 导入和恢复出厂会覆盖设备上的配置，和 `POST /ota` 用同一个 token
+    let token = ensure_ota_token()?;
+
+    // 导出不包含密码的配置，可以导入到其它设备
+    http_server.fn_handler::<anyhow::Error, _>("/settings", Method::Get, |req| {
+        let settings = Settings::load()?;
+        write_json(req, 200, &settings.export())
+    })?;
+
+    let import_token = token.clone();
+    http_server.fn_handler::<anyhow::Error, _>("/settings", Method::Post, move |mut req| {
+        if !is_authorized(req.header("Authorization"), &import_token) {
+            warn!("Unauthorized settings import");
+            return write_json(req, 401, &ConnectResponse {
+                success: false,
+                message: "invalid token".to_string(),
+            });
+        }
+        let len = req.content_len().unwrap_or(0) as usize;
+        if len > MAX_IMPORT_LEN {
+            req.into_status_response(413)?
+                .write_all("Request too big".as_bytes())?;
+            return Ok(());
+        }
+        let mut buf = vec![0; len];
+        req.read_exact(&mut buf)?;
+
+        match Settings::import_json(&buf) {
+            Ok(report) => {
+                info!(
+                    "导入配置: imported={:?} skipped={:?}",
+                    report.imported_networks, report.skipped_networks
+                );
+                write_json(req, 200, &report)?;
+                schedule_restart();
+                Ok(())
+            }
+            Err(e) => {
+                error!("导入配置失败: {:?}", e);
+                write_json(req, 400, &ConnectResponse {
+                    success: false,
+                    message: e.to_string(),
+                })
+            }
+        }
+    })?;
+
+    http_server.fn_handler::<anyhow::Error, _>("/factory_reset", Method::Post, move |req| {
+        if !is_authorized(req.header("Authorization"), &token) {
+            warn!("Unauthorized factory reset");
+            return write_json(req, 401, &ConnectResponse {
+                success: false,
+                message: "invalid token".to_string(),
+            });
+        }
+        Settings::factory_reset(&mut NvsStorage::new())?;
+        write_json(req, 200, &ConnectResponse {
+            success: true,
+            message: "factory reset".to_string(),
+        })?;
+        schedule_restart();
+        Ok(())
+    })?;
+
+    // 旧版页面使用的接口，只保存不测试
     http_server.fn_handler::<anyhow::Error, _>("/config_wifi", Method::Post, |mut req| {
         let len = req.content_len().unwrap_or(0) as usize;
 
@@ -67,10 +488,7 @@ pub fn start_http_server(http_server: &mut EspHttpServer<'static>) -> anyhow::Re
             let wifi_ssid = form.wifi_ssid;
             let wifi_password = form.wifi_password;
 
-            info!(
-                "WiFi config: SSID={}, Password={}",
-                wifi_ssid, wifi_password
-            );
+            info!("WiFi config: SSID={}", wifi_ssid);
 
             let mut ssid_manager = SsidMananger::get_instance();
 
@@ -80,17 +498,33 @@ pub fn start_http_server(http_server: &mut EspHttpServer<'static>) -> anyhow::Re
 
             resp.write_all("OK!".as_bytes()).map(|_| ())?;
 
-            // write!(resp, "SSID: {}  Password: {}!", wifi_ssid, wifi_password)?;
-
-            // TODO:: 临时方案：在配置完wifi后，重启。以后应该是使用消息系统，使application进入wifi连接状态，如果连接不成功再进入这个配置页面。
-            unsafe {
-                esp_restart();
-            }
+            schedule_restart();
         } else {
             resp.write_all("Invalid form data".as_bytes())?;
         }
 
         Ok(())
     })?;
+    // 各系统的 captive portal 检测地址：Android 请求 /generate_204，Apple 请求 /hotspot-detect.html，
+    // Windows 请求 /connecttest.txt。返回的不是预期内容，系统就会弹出登录页，这里统一重定向到配网页面。
+    // 其它未知路径也一样，所以 "/*" 必须最后注册。
+    let portal_url = format!("http://{}/", portal_ip);
+    for uri in [
+        "/generate_204",
+        "/gen_204",
+        "/hotspot-detect.html",
+        "/library/test/success.html",
+        "/connecttest.txt",
+        "/ncsi.txt",
+        "/*",
+    ] {
+        let location = portal_url.clone();
+        http_server.fn_handler::<anyhow::Error, _>(uri, Method::Get, move |req| {
+            req.into_response(302, Some("Found"), &[("Location", location.as_str())])?
+                .write_all("Redirect to wifi config page".as_bytes())?;
+            Ok(())
+        })?;
+    }
+
     Ok(())
 }
@@ -0,0 +1,29 @@
+//! 界面上用到的字符串，和 `assets/zh-CN/language.json` 保持一致（对应 C++ 版本生成的 lang_config.h）
+
+pub const CHECKING_NEW_VERSION: &str = "检查新版本...";
+/// 参数依次是重试的秒数和失败原因
+pub const CHECK_NEW_VERSION_FAILED: &str = "检查新版本失败，将在 %d 秒后重试：%s";
+pub const NEW_VERSION: &str = "新版本 ";
+pub const UPGRADING: &str = "正在升级系统...";
+pub const UPGRADE_FAILED: &str = "升级失败";
+/// 待机时钟下面的日期，language.json 里没有，下标 0 是星期日
+pub const WEEKDAYS: [&str; 7] = [
+    "星期日",
+    "星期一",
+    "星期二",
+    "星期三",
+    "星期四",
+    "星期五",
+    "星期六",
+];
+/// 闹钟、计时器、提醒响的时候屏幕上的标题，language.json 里没有
+pub const ALARM: &str = "闹钟";
+pub const TIMER_DONE: &str = "计时结束";
+pub const REMINDER: &str = "提醒";
+
+/// 按 `CHECK_NEW_VERSION_FAILED` 的格式生成倒计时提示
+pub fn check_new_version_failed(seconds: u32, reason: &str) -> String {
+    CHECK_NEW_VERSION_FAILED
+        .replacen("%d", &seconds.to_string(), 1)
+        .replacen("%s", reason, 1)
+}
@@ -1,7 +1,13 @@
 pub mod application_context;
 pub mod converter;
+pub mod diagnostics;
 pub mod enums;
 pub mod event;
 pub mod gpio_button;
+#[cfg(target_os = "espidf")]
 pub mod httpd_server;
+pub mod lang;
 pub mod qrcode;
+pub mod restart;
+#[cfg(feature = "host-sim")]
+pub mod sim_button;
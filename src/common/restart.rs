@@ -0,0 +1,31 @@
+use std::{thread, time::Duration};
+
+use log::error;
+
+/// 配置完成后延迟重启，让浏览器先收到响应
+pub fn schedule_restart() {
+    // TODO:: 临时方案：在配置完wifi后，重启。以后应该是使用消息系统，使application进入wifi连接状态，如果连接不成功再进入这个配置页面。
+    let result = thread::Builder::new()
+        .name("portal_restart".into())
+        .stack_size(2 * 1024)
+        .spawn(|| {
+            thread::sleep(Duration::from_secs(3));
+            restart();
+        });
+    if let Err(e) = result {
+        error!("Failed to schedule restart: {:?}", e);
+    }
+}
+
+#[cfg(target_os = "espidf")]
+fn restart() {
+    unsafe {
+        esp_idf_sys::esp_restart();
+    }
+}
+
+/// host-sim 下没有重启，直接退出
+#[cfg(not(target_os = "espidf"))]
+fn restart() {
+    std::process::exit(0);
+}
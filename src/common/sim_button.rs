@@ -0,0 +1,174 @@
+//! host-sim 下代替 `gpio_button::Button` 的软件按键，接口和它一样。
+//!
+//! 按键按 GPIO 编号登记在一个全局表里，模拟器（比如 MockBoard 的 stdin 线程）调用
+//! `click`、`press`、`long_press` 等函数触发回调。
+
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex, OnceLock, Weak},
+    time::Duration,
+};
+
+use anyhow::Result;
+
+type BoxedCallback = Box<dyn FnMut() + Send + 'static>;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum SimButtonEvent {
+    PressDown,
+    PressUp,
+    Click,
+    DoubleClick,
+    LongPress(Duration),
+}
+
+type Callbacks = Mutex<Vec<(SimButtonEvent, BoxedCallback)>>;
+
+fn registry() -> &'static Mutex<HashMap<i32, Weak<Callbacks>>> {
+    static REGISTRY: OnceLock<Mutex<HashMap<i32, Weak<Callbacks>>>> = OnceLock::new();
+    REGISTRY.get_or_init(|| Mutex::new(HashMap::new()))
+}
+
+pub struct Button {
+    callbacks: Arc<Callbacks>,
+}
+
+impl Button {
+    pub fn new(gpio_num: i32) -> Result<Self> {
+        Self::with_active_level(gpio_num, 0)
+    }
+
+    /// 软件按键没有电平，`active_level` 只是为了和真按键的接口一致
+    pub fn with_active_level(gpio_num: i32, _active_level: u8) -> Result<Self> {
+        let callbacks = Arc::new(Mutex::new(Vec::new()));
+        registry()
+            .lock()
+            .unwrap()
+            .insert(gpio_num, Arc::downgrade(&callbacks));
+        Ok(Self { callbacks })
+    }
+
+    pub fn on_click<F>(&mut self, callback: F) -> Result<()>
+    where
+        F: FnMut() + Send + 'static,
+    {
+        self.register(SimButtonEvent::Click, callback)
+    }
+
+    pub fn on_double_click<F>(&mut self, callback: F) -> Result<()>
+    where
+        F: FnMut() + Send + 'static,
+    {
+        self.register(SimButtonEvent::DoubleClick, callback)
+    }
+
+    pub fn on_press_down<F>(&mut self, callback: F) -> Result<()>
+    where
+        F: FnMut() + Send + 'static,
+    {
+        self.register(SimButtonEvent::PressDown, callback)
+    }
+
+    pub fn on_press_up<F>(&mut self, callback: F) -> Result<()>
+    where
+        F: FnMut() + Send + 'static,
+    {
+        self.register(SimButtonEvent::PressUp, callback)
+    }
+
+    pub fn on_long_press<F>(&mut self, duration: Duration, callback: F) -> Result<()>
+    where
+        F: FnMut() + Send + 'static,
+    {
+        self.register(SimButtonEvent::LongPress(duration), callback)
+    }
+
+    fn register<F>(&mut self, event: SimButtonEvent, callback: F) -> Result<()>
+    where
+        F: FnMut() + Send + 'static,
+    {
+        self.callbacks
+            .lock()
+            .unwrap()
+            .push((event, Box::new(callback)));
+        Ok(())
+    }
+}
+
+fn fire(gpio_num: i32, event: SimButtonEvent) {
+    let callbacks = registry()
+        .lock()
+        .unwrap()
+        .get(&gpio_num)
+        .and_then(Weak::upgrade);
+    let Some(callbacks) = callbacks else {
+        log::warn!("No simulated button on GPIO{}", gpio_num);
+        return;
+    };
+    for (registered, callback) in callbacks.lock().unwrap().iter_mut() {
+        if *registered == event {
+            callback();
+        }
+    }
+}
+
+pub fn press(gpio_num: i32) {
+    fire(gpio_num, SimButtonEvent::PressDown);
+}
+
+pub fn release(gpio_num: i32) {
+    fire(gpio_num, SimButtonEvent::PressUp);
+}
+
+pub fn click(gpio_num: i32) {
+    press(gpio_num);
+    release(gpio_num);
+    fire(gpio_num, SimButtonEvent::Click);
+}
+
+pub fn double_click(gpio_num: i32) {
+    press(gpio_num);
+    release(gpio_num);
+    press(gpio_num);
+    release(gpio_num);
+    fire(gpio_num, SimButtonEvent::DoubleClick);
+}
+
+/// 按住 `held` 这么久再松开，期间按时间顺序触发注册时长不超过 `held` 的长按回调
+pub fn long_press(gpio_num: i32, held: Duration) {
+    press(gpio_num);
+    let mut durations: Vec<Duration> = registry()
+        .lock()
+        .unwrap()
+        .get(&gpio_num)
+        .and_then(Weak::upgrade)
+        .map(|callbacks| {
+            callbacks
+                .lock()
+                .unwrap()
+                .iter()
+                .filter_map(|(event, _)| match event {
+                    SimButtonEvent::LongPress(duration) if *duration <= held => Some(*duration),
+                    _ => None,
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+    durations.sort();
+    durations.dedup();
+    for duration in durations {
+        fire(gpio_num, SimButtonEvent::LongPress(duration));
+    }
+    release(gpio_num);
+}
+
+/// 同时按住几个键 `held` 这么久，用来模拟组合键
+pub fn hold_together(gpio_nums: &[i32], held: Duration) {
+    for gpio_num in &gpio_nums[1..] {
+        press(*gpio_num);
+    }
+    long_press(gpio_nums[0], held);
+    for gpio_num in &gpio_nums[1..] {
+        release(*gpio_num);
+    }
+}
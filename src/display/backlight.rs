@@ -0,0 +1,27 @@
+use anyhow::Result;
+use esp_idf_hal::ledc::LedcDriver;
+
+/// 用 LEDC PWM 控制的屏幕背光
+pub struct PwmBacklight {
+    ledc_driver: LedcDriver<'static>,
+    /// 低电平点亮的背光（比如 JiangLian S3Cam），占空比要反过来
+    inverted: bool,
+}
+
+impl PwmBacklight {
+    pub fn new(ledc_driver: LedcDriver<'static>, inverted: bool) -> Self {
+        Self {
+            ledc_driver,
+            inverted,
+        }
+    }
+
+    /// 亮度 0-100，0 表示关闭背光
+    pub fn set_brightness(&mut self, brightness: u8) -> Result<()> {
+        let max_duty = self.ledc_driver.get_max_duty();
+        let duty = max_duty * brightness.min(100) as u32 / 100;
+        let duty = if self.inverted { max_duty - duty } else { duty };
+        self.ledc_driver.set_duty(duty)?;
+        Ok(())
+    }
+}
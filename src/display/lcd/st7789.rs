@@ -1,21 +1,26 @@
-use crate::{common::qrcode::draw_qrcode, display::Display};
+use crate::{
+    camera::CameraFrame,
+    common::{lang, qrcode::draw_qrcode},
+    display::Display,
+};
 use anyhow::{Ok, Result};
 use display_interface_spi::SPIInterfaceNoCS;
 use embedded_graphics::{
+    image::{Image, ImageRawBE},
     mono_font::{ascii::FONT_8X13, MonoTextStyle},
     pixelcolor::Rgb565,
     prelude::*,
-    text::Text,
+    primitives::{PrimitiveStyle, Rectangle},
+    text::{Alignment, Text},
 };
 use esp_idf_hal::gpio::*;
 use esp_idf_hal::{
     delay::Delay,
     gpio::{self, PinDriver},
-    ledc::LedcDriver,
     spi::{SpiConfig, SpiDeviceDriver, SpiDriver},
     units::*,
 };
-use mipidsi::{Builder, ColorOrder, Orientation};
+use mipidsi::{Builder, ColorOrder, ModelOptions, Orientation};
 use u8g2_fonts::U8g2TextStyle;
 // 1. 定义具体的硬件类型别名，方便阅读
 type ConcreteSpiDriver = SpiDeviceDriver<'static, SpiDriver<'static>>;
@@ -30,9 +35,31 @@ type DisplayInterface<'a> = SPIInterfaceNoCS<ConcreteSpiDriver, ConcreteDcPin<'a
 pub type St7789Display =
     mipidsi::Display<DisplayInterface<'static>, mipidsi::models::ST7789, ConcreteRstPin<'static>>;
 
+/// 屏幕的尺寸、偏移和方向，由 board 根据板子配置传进来
+pub struct LcdConfig {
+    /// 竖屏时的宽和高
+    pub width: u16,
+    pub height: u16,
+    /// 显存窗口的偏移，mipidsi 要的是函数指针，所以 board 自己写一个返回常量的函数
+    pub window_offset: fn(&ModelOptions) -> (u16, u16),
+    /// 旋转角度：0、90、180、270
+    pub rotation: u16,
+    pub mirror: bool,
+}
+
+impl LcdConfig {
+    fn orientation(&self) -> Orientation {
+        match self.rotation {
+            90 => Orientation::Landscape(self.mirror),
+            180 => Orientation::PortraitInverted(self.mirror),
+            270 => Orientation::LandscapeInverted(self.mirror),
+            _ => Orientation::Portrait(self.mirror),
+        }
+    }
+}
+
 pub struct LcdSt7789 {
     display: St7789Display,
-    ledc_driver: LedcDriver<'static>,
 }
 
 impl LcdSt7789 {
@@ -40,7 +67,7 @@ impl LcdSt7789 {
         driver: SpiDriver<'static>, // 注意这里改为 'static
         dc_pin: gpio::AnyOutputPin<'static>,
         chip_select_pin: gpio::AnyOutputPin<'static>,
-        mut ledc_driver: LedcDriver<'static>,
+        config: &LcdConfig,
     ) -> Result<Self> {
         // --- SPI 配置 ---
         const RATE: u32 = 80 * 1000 * 1000;
@@ -59,19 +86,18 @@ impl LcdSt7789 {
         // 定义 Reset 引脚 (虽然是 None，但类型要对齐)
         let reset_pin: Option<ConcreteRstPin> = None;
 
-        // 3. 设置亮度 (通过设置占空比)
-        let max_duty = ledc_driver.get_max_duty();
-        ledc_driver.set_duty(max_duty * 0 / 4).unwrap(); // 设置为50%的亮度
-                                                         // ledc_driver.set_duty(max_duty).unwrap();
+        // 背光由 board 通过 PwmBacklight 控制
 
         let mut display = Builder::st7789(di)
             .with_color_order(ColorOrder::Rgb)
+            .with_display_size(config.width, config.height)
+            .with_window_offset_handler(config.window_offset)
             .init(&mut delay, reset_pin)
             .map_err(|e| anyhow::anyhow!("Display init failed: {:?}", e))?;
 
         // --- 屏幕设置 ---
         display
-            .set_orientation(Orientation::LandscapeInverted(true))
+            .set_orientation(config.orientation())
             .map_err(|e| anyhow::anyhow!("Orientation failed: {:?}", e))?;
 
         display
@@ -97,10 +123,7 @@ impl LcdSt7789 {
         println!("'Hello, Rust!' 已经显示在 LCD 上。");
 
         // 返回结构体
-        Ok(Self {
-            display,
-            ledc_driver,
-        })
+        Ok(Self { display })
     }
 
     pub fn init(
@@ -191,19 +214,21 @@ impl LcdSt7789 {
 
 impl Display for LcdSt7789 {
     fn set_status(&mut self, status: &str) {
-        // 创建一个文本样式
-        let style = MonoTextStyle::new(&FONT_8X13, Rgb565::WHITE);
+        // 先清掉上一次的状态，否则倒计时之类的文字会叠在一起
+        let _ = Rectangle::new(Point::new(0, 18), Size::new(286, 18))
+            .into_styled(PrimitiveStyle::with_fill(Rgb565::BLACK))
+            .draw(&mut self.display);
+
+        // 状态大多是中文，用 gb2312 字体
+        let style = U8g2TextStyle::new(u8g2_fonts::fonts::u8g2_font_wqy12_t_gb2312, Rgb565::WHITE);
 
         // 创建文本对象
-        Text::new(
+        let _ = Text::new(
             status,
             Point::new(30, 30), // 文本左上角在屏幕上的位置
             style,
         )
-        .draw(&mut self.display) // 绘制文本
-        .unwrap();
-
-        println!("'Hello, Rust!' 已经显示在 LCD 上。");
+        .draw(&mut self.display); // 绘制文本
     }
 
     fn show_qrcode(&mut self, content: &str) {
@@ -216,4 +241,136 @@ impl Display for LcdSt7789 {
             Rgb565::WHITE,
         );
     }
+
+    fn show_local_token(&mut self, token: &str) {
+        // 画在二维码右边，32 个字符一行放不下，分成两行
+        let (first, second) = token.split_at(token.len().min(16));
+        for (text, y) in [("OTA token:", 50), (first, 70), (second, 90)] {
+            let style =
+                U8g2TextStyle::new(u8g2_fonts::fonts::u8g2_font_wqy12_t_gb2312, Rgb565::WHITE);
+            let _ = Text::new(text, Point::new(190, y), style).draw(&mut self.display);
+        }
+    }
+
+    fn set_charging(&mut self, charging: bool) {
+        // 右上角画一个小电池图标，充电时填充为绿色
+        let origin = Point::new(290, 4);
+        let fill = if charging {
+            Rgb565::GREEN
+        } else {
+            Rgb565::BLACK
+        };
+
+        let _ = Rectangle::new(origin, Size::new(22, 12))
+            .into_styled(PrimitiveStyle::with_stroke(Rgb565::WHITE, 1))
+            .draw(&mut self.display);
+        let _ = Rectangle::new(origin + Point::new(22, 3), Size::new(2, 6))
+            .into_styled(PrimitiveStyle::with_fill(Rgb565::WHITE))
+            .draw(&mut self.display);
+        let _ = Rectangle::new(origin + Point::new(2, 2), Size::new(18, 8))
+            .into_styled(PrimitiveStyle::with_fill(fill))
+            .draw(&mut self.display);
+    }
+
+    fn show_upgrade_progress(&mut self, version: &str, percent: Option<u8>, speed: usize) {
+        // 进度条区域在状态栏下面：标题、进度条、百分比和速度
+        let origin = Point::new(30, 60);
+        let bar_size = Size::new(260, 14);
+        let style = U8g2TextStyle::new(u8g2_fonts::fonts::u8g2_font_wqy12_t_gb2312, Rgb565::WHITE);
+
+        let _ = Rectangle::new(Point::new(0, origin.y - 14), Size::new(320, 70))
+            .into_styled(PrimitiveStyle::with_fill(Rgb565::BLACK))
+            .draw(&mut self.display);
+
+        let title = format!("{}{}", lang::NEW_VERSION, version);
+        let _ = Text::new(&title, origin, style).draw(&mut self.display);
+
+        let bar_origin = origin + Point::new(0, 10);
+        let _ = Rectangle::new(bar_origin, bar_size)
+            .into_styled(PrimitiveStyle::with_stroke(Rgb565::WHITE, 1))
+            .draw(&mut self.display);
+        if let Some(percent) = percent {
+            let width = (bar_size.width - 4) * percent.min(100) as u32 / 100;
+            let _ = Rectangle::new(
+                bar_origin + Point::new(2, 2),
+                Size::new(width, bar_size.height - 4),
+            )
+            .into_styled(PrimitiveStyle::with_fill(Rgb565::GREEN))
+            .draw(&mut self.display);
+        }
+
+        let detail = match percent {
+            Some(percent) => format!("{}%  {} KB/s", percent, speed / 1024),
+            None => format!("{} KB/s", speed / 1024),
+        };
+        let style = U8g2TextStyle::new(u8g2_fonts::fonts::u8g2_font_wqy12_t_gb2312, Rgb565::WHITE);
+        let _ = Text::new(&detail, bar_origin + Point::new(0, 32), style).draw(&mut self.display);
+    }
+
+    fn show_camera_preview(&mut self, frame: &CameraFrame) {
+        // 摄像头输出的 RGB565 是大端的
+        let raw = ImageRawBE::<Rgb565>::new(&frame.rgb565, frame.width as u32);
+        let screen = self.display.bounding_box().size;
+        let origin = Point::new(
+            (screen.width as i32 - frame.width as i32).max(0) / 2,
+            (screen.height as i32 - frame.height as i32).max(0) / 2,
+        );
+        let _ = Image::new(&raw, origin).draw(&mut self.display);
+    }
+
+    fn show_clock(&mut self, time: &str, date: &str) {
+        // 状态栏下面居中显示，先清掉上一次的时间
+        let center_x = self.display.bounding_box().size.width as i32 / 2;
+        let _ = Rectangle::new(Point::new(0, 60), Size::new(320, 120))
+            .into_styled(PrimitiveStyle::with_fill(Rgb565::BLACK))
+            .draw(&mut self.display);
+
+        let time_style =
+            U8g2TextStyle::new(u8g2_fonts::fonts::u8g2_font_logisoso42_tn, Rgb565::WHITE);
+        let _ = Text::with_alignment(
+            time,
+            Point::new(center_x, 130),
+            time_style,
+            Alignment::Center,
+        )
+        .draw(&mut self.display);
+
+        let date_style =
+            U8g2TextStyle::new(u8g2_fonts::fonts::u8g2_font_wqy12_t_gb2312, Rgb565::WHITE);
+        let _ = Text::with_alignment(
+            date,
+            Point::new(center_x, 160),
+            date_style,
+            Alignment::Center,
+        )
+        .draw(&mut self.display);
+    }
+
+    fn show_alarm(&mut self, title: &str, label: &str) {
+        // 和时钟用同一块区域，黄色标题比较醒目
+        let center_x = self.display.bounding_box().size.width as i32 / 2;
+        let _ = Rectangle::new(Point::new(0, 60), Size::new(320, 120))
+            .into_styled(PrimitiveStyle::with_fill(Rgb565::BLACK))
+            .draw(&mut self.display);
+
+        let title_style =
+            U8g2TextStyle::new(u8g2_fonts::fonts::u8g2_font_wqy16_t_gb2312, Rgb565::YELLOW);
+        let _ = Text::with_alignment(
+            title,
+            Point::new(center_x, 110),
+            title_style,
+            Alignment::Center,
+        )
+        .draw(&mut self.display);
+
+        let label_style =
+            U8g2TextStyle::new(u8g2_fonts::fonts::u8g2_font_wqy12_t_gb2312, Rgb565::WHITE);
+        let _ = Text::with_alignment(
+            label,
+            Point::new(center_x, 140),
+            label_style,
+            Alignment::Center,
+        )
+        .draw(&mut self.display);
+    }
 }
@@ -0,0 +1,64 @@
+use std::sync::{Arc, Mutex};
+
+use log::info;
+
+use crate::{camera::CameraFrame, display::Display};
+
+/// host-sim 用的假屏幕，把显示过的状态按顺序记下来，同时打到日志里。
+///
+/// `history()` 返回的句柄可以在别的线程里查看，方便检查状态切换是否正确
+#[derive(Default)]
+pub struct MemoryDisplay {
+    history: Arc<Mutex<Vec<String>>>,
+    charging: bool,
+}
+
+impl MemoryDisplay {
+    pub fn history(&self) -> Arc<Mutex<Vec<String>>> {
+        self.history.clone()
+    }
+
+    pub fn charging(&self) -> bool {
+        self.charging
+    }
+
+    fn push(&mut self, entry: String) {
+        info!("Display: {}", entry);
+        self.history.lock().unwrap().push(entry);
+    }
+}
+
+impl Display for MemoryDisplay {
+    fn set_status(&mut self, status: &str) {
+        self.push(format!("status: {}", status));
+    }
+
+    fn show_qrcode(&mut self, content: &str) {
+        self.push(format!("qrcode: {}", content));
+    }
+
+    fn show_local_token(&mut self, token: &str) {
+        self.push(format!("local token: {}", token));
+    }
+
+    fn set_charging(&mut self, charging: bool) {
+        self.charging = charging;
+        self.push(format!("charging: {}", charging));
+    }
+
+    fn show_upgrade_progress(&mut self, version: &str, percent: Option<u8>, speed: usize) {
+        self.push(format!("upgrade: {} {:?}% {} B/s", version, percent, speed));
+    }
+
+    fn show_camera_preview(&mut self, frame: &CameraFrame) {
+        self.push(format!("camera: {}x{}", frame.width, frame.height));
+    }
+
+    fn show_clock(&mut self, time: &str, date: &str) {
+        self.push(format!("clock: {} {}", time, date));
+    }
+
+    fn show_alarm(&mut self, title: &str, label: &str) {
+        self.push(format!("alarm: {} {}", title, label));
+    }
+}
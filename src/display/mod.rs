@@ -1,6 +1,25 @@
+use crate::camera::CameraFrame;
+
+#[cfg(target_os = "espidf")]
+pub mod backlight;
+#[cfg(target_os = "espidf")]
 pub mod lcd;
+#[cfg(feature = "host-sim")]
+pub mod memory_display;
+pub mod no_display;
 
 pub trait Display {
     fn set_status(&mut self, status: &str);
     fn show_qrcode(&mut self, content: &str);
+    /// 配网时显示本地接口（`POST /ota` 等）用的 token，只有拿着设备的人能看到
+    fn show_local_token(&mut self, token: &str);
+    fn set_charging(&mut self, charging: bool);
+    /// OTA 升级进度。percent 为 None 时表示不知道固件大小，speed 单位是字节/秒
+    fn show_upgrade_progress(&mut self, version: &str, percent: Option<u8>, speed: usize);
+    /// 显示摄像头拍到的画面，画面比屏幕小时居中
+    fn show_camera_preview(&mut self, frame: &CameraFrame);
+    /// 待机时显示的时钟，`time` 例如 `09:05`，`date` 例如 `2024-05-01 星期三`
+    fn show_clock(&mut self, time: &str, date: &str);
+    /// 闹钟响的时候显示，`title` 例如 `闹钟`，`label` 是设置时的说明，可能是空的
+    fn show_alarm(&mut self, title: &str, label: &str);
 }
@@ -0,0 +1,42 @@
+use log::{debug, info};
+
+use crate::{camera::CameraFrame, display::Display};
+
+/// 没有屏幕（或者屏幕还没有驱动）的板子用这个，状态只打到日志里
+pub struct NoDisplay;
+
+impl Display for NoDisplay {
+    fn set_status(&mut self, status: &str) {
+        info!("Status: {}", status);
+    }
+
+    fn show_qrcode(&mut self, content: &str) {
+        info!("QR code: {}", content);
+    }
+
+    // 日志会存进 flash 并推送给服务器，token 只能打印在串口上
+    fn show_local_token(&mut self, token: &str) {
+        println!("Local OTA token: {}", token);
+    }
+
+    fn set_charging(&mut self, charging: bool) {
+        info!("Charging: {}", charging);
+    }
+
+    fn show_upgrade_progress(&mut self, version: &str, percent: Option<u8>, speed: usize) {
+        info!("Upgrading to {}: {:?}% {} B/s", version, percent, speed);
+    }
+
+    fn show_camera_preview(&mut self, frame: &CameraFrame) {
+        info!("Camera preview: {}x{}", frame.width, frame.height);
+    }
+
+    // 每分钟都会调用，用 debug 免得刷屏
+    fn show_clock(&mut self, time: &str, date: &str) {
+        debug!("Clock: {} {}", time, date);
+    }
+
+    fn show_alarm(&mut self, title: &str, label: &str) {
+        info!("Alarm: {} {}", title, label);
+    }
+}
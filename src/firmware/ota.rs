@@ -0,0 +1,810 @@
+#[cfg(target_os = "espidf")]
+use std::time::Instant;
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc::Sender,
+        Arc,
+    },
+    thread,
+    time::Duration,
+};
+
+use anyhow::{anyhow, bail, Result};
+#[cfg(any(target_os = "espidf", test))]
+use chrono::DateTime;
+#[cfg(target_os = "espidf")]
+use embedded_svc::http::{client::Client as HttpClient, Headers};
+#[cfg(target_os = "espidf")]
+use esp_idf_hal::io::Read;
+#[cfg(target_os = "espidf")]
+use esp_idf_svc::{
+    http::{
+        client::{Configuration, EspHttpConnection},
+        Method,
+    },
+    ota::{EspFirmwareInfoLoad, EspOta, EspOtaUpdate, FirmwareInfo, SlotState},
+};
+#[cfg(target_os = "espidf")]
+use esp_idf_sys::{
+    esp_crt_bundle_attach, CONFIG_IDF_FIRMWARE_CHIP_ID, ESP_APP_DESC_MAGIC_WORD,
+    ESP_IMAGE_HEADER_MAGIC,
+};
+use log::{error, info, warn};
+use semver::Version;
+use serde::Deserialize;
+
+use crate::common::event::AppEvent;
+#[cfg(any(target_os = "espidf", test))]
+use crate::utils::sha256::to_hex;
+#[cfg(target_os = "espidf")]
+use crate::{clock, utils::sha256::Sha256};
+
+pub const VERSION: &str = env!("CARGO_PKG_VERSION");
+
+/// 没有在设置里配置 OTA 地址时使用
+pub const DEFAULT_MANIFEST_URL: &str = "http://192.168.1.145:3000/api/v1/ota/manifest";
+
+/// 新固件启动后多久还没有确认可用，就回滚到旧固件
+const ROLLBACK_TIMEOUT: Duration = Duration::from_secs(5 * 60);
+
+/// 每下载多少字节报告一次进度
+#[cfg(target_os = "espidf")]
+const PROGRESS_STEP: usize = 64 * 1024;
+
+/// 检查或下载失败后的重试，和 C++ 版本一样每次等待时间翻倍
+#[cfg(target_os = "espidf")]
+const MAX_RETRIES: u32 = 10;
+#[cfg(target_os = "espidf")]
+const INITIAL_RETRY_DELAY_S: u32 = 10;
+#[cfg(target_os = "espidf")]
+const MAX_RETRY_DELAY_S: u32 = 300;
+
+#[cfg(target_os = "espidf")]
+mod http_status {
+    pub const OK: u16 = 200;
+    pub const NOT_MODIFIED: u16 = 304;
+}
+
+/// 服务器返回的升级信息
+///
+/// ```json
+/// {"version": "0.2.0", "url": "http://.../xiaoxin_esp32-0.2.0.bin", "sha256": "9f86d0...", "size": 1572864,
+///  "server_time": {"timestamp": 1714521600000}}
+/// ```
+#[derive(Debug, Clone, Deserialize)]
+pub struct OtaManifest {
+    pub version: String,
+    pub url: String,
+    pub sha256: String,
+    #[serde(default)]
+    pub size: Option<usize>,
+    #[serde(default)]
+    pub server_time: Option<ServerTime>,
+}
+
+/// SNTP 还没同步时用服务器时间设置系统时间
+#[derive(Debug, Clone, Deserialize)]
+pub struct ServerTime {
+    /// 毫秒
+    pub timestamp: u64,
+}
+
+/// 通过 `AppEvent::OtaProgress` 报告给 application 的升级进度
+#[derive(Debug, Clone, PartialEq)]
+pub enum OtaProgress {
+    Checking,
+    UpToDate,
+    Downloading {
+        version: String,
+        received: usize,
+        total: Option<usize>,
+        speed: usize, // 字节/秒
+    },
+    Verifying {
+        version: String,
+    },
+    Completed {
+        version: String,
+    }, // 写入成功，马上重启
+    RetryIn {
+        seconds: u32,
+        reason: String,
+    }, // 失败后的倒计时，每秒报告一次
+    Failed(String), // 重试多次后放弃
+}
+
+impl OtaProgress {
+    /// 下载进度百分比，不知道固件大小时返回 None
+    pub fn percent(&self) -> Option<u8> {
+        match self {
+            OtaProgress::Downloading {
+                received,
+                total: Some(total),
+                ..
+            } if *total > 0 => Some(((*received).min(*total) * 100 / *total) as u8),
+            OtaProgress::Verifying { .. } | OtaProgress::Completed { .. } => Some(100),
+            _ => None,
+        }
+    }
+}
+
+/// 用 semver 比较版本，允许带 `v` 前缀
+pub fn is_newer_version(current: &str, candidate: &str) -> Result<bool> {
+    let current = parse_version(current)?;
+    let candidate = parse_version(candidate)?;
+    Ok(candidate > current)
+}
+
+fn parse_version(version: &str) -> Result<Version> {
+    let version = version.trim();
+    let version = version.strip_prefix('v').unwrap_or(version);
+    Version::parse(version).map_err(|e| anyhow!("Invalid version {:?}: {}", version, e))
+}
+
+/// 把 manifest 里的十六进制 sha256 转成字节
+pub fn parse_sha256(hex: &str) -> Result<[u8; 32]> {
+    let hex = hex.trim();
+    if hex.len() != 64 || !hex.is_ascii() {
+        bail!("Invalid sha256: {:?}", hex);
+    }
+    let mut output = [0u8; 32];
+    for (i, byte) in output.iter_mut().enumerate() {
+        *byte = u8::from_str_radix(&hex[i * 2..i * 2 + 2], 16)
+            .map_err(|e| anyhow!("Invalid sha256 {:?}: {}", hex, e))?;
+    }
+    Ok(output)
+}
+
+/// 没有提供期望值（本地上传不带 sha256）时不检查
+#[cfg(any(target_os = "espidf", test))]
+fn verify_sha256(expected: Option<&[u8; 32]>, actual: &[u8; 32]) -> Result<()> {
+    match expected {
+        Some(expected) if expected != actual => bail!(
+            "Firmware sha256 mismatch, expected {} got {}",
+            to_hex(expected),
+            to_hex(actual)
+        ),
+        _ => Ok(()),
+    }
+}
+
+/// 检查固件头：magic、芯片型号和 app 描述。
+///
+/// 数据还不够长时返回 Ok(None)，校验通过返回固件里的版本号
+#[cfg(target_os = "espidf")]
+pub fn validate_image_header(data: &[u8]) -> Result<Option<String>> {
+    let info_load = EspFirmwareInfoLoad {};
+    let Some(native) = info_load.fetch_native(data) else {
+        return Ok(None);
+    };
+
+    if native.image_header.magic as u32 != ESP_IMAGE_HEADER_MAGIC {
+        bail!("Invalid image magic: 0x{:02x}", native.image_header.magic);
+    }
+    if native.image_header.chip_id as u32 != CONFIG_IDF_FIRMWARE_CHIP_ID {
+        bail!(
+            "Image is built for chip {}, this is chip {}",
+            native.image_header.chip_id,
+            CONFIG_IDF_FIRMWARE_CHIP_ID
+        );
+    }
+    if native.app_desc.magic_word != ESP_APP_DESC_MAGIC_WORD {
+        bail!(
+            "Invalid app description magic: 0x{:08x}",
+            native.app_desc.magic_word
+        );
+    }
+
+    let mut info = FirmwareInfo {
+        version: Default::default(),
+        released: Default::default(),
+        description: Default::default(),
+        signature: Default::default(),
+        download_id: Default::default(),
+    };
+    EspFirmwareInfoLoad::load_firmware_info(&mut info, native.app_desc)?;
+    Ok(Some(info.version.to_string()))
+}
+
+/// 把固件写入 OTA 分区，同时计算 sha256 并检查固件头。
+///
+/// 网络升级和本地上传（`POST /ota`）都用它
+#[cfg(target_os = "espidf")]
+pub struct FirmwareWriter<'a> {
+    update: EspOtaUpdate<'a>,
+    sha256: Sha256,
+    header: Vec<u8>,
+    image_version: Option<String>,
+    written: usize,
+}
+
+#[cfg(target_os = "espidf")]
+impl<'a> FirmwareWriter<'a> {
+    pub fn new(update: EspOtaUpdate<'a>) -> Result<Self> {
+        Ok(Self {
+            update,
+            sha256: Sha256::new()?,
+            header: Vec::new(),
+            image_version: None,
+            written: 0,
+        })
+    }
+
+    pub fn write(&mut self, data: &[u8]) -> Result<()> {
+        self.sha256.update(data)?;
+        self.written += data.len();
+
+        if self.image_version.is_some() {
+            self.update.write(data)?;
+            return Ok(());
+        }
+
+        // 固件头还没检查之前先缓存起来，头不对就不写 flash 了，检查通过后一次写入缓存的数据
+        self.header.extend_from_slice(data);
+        let Some(version) = validate_image_header(&self.header)? else {
+            return Ok(());
+        };
+        info!("Firmware image version: {}", version);
+        self.image_version = Some(version);
+        let header = std::mem::take(&mut self.header);
+        self.update.write(&header)?;
+        Ok(())
+    }
+
+    pub fn written(&self) -> usize {
+        self.written
+    }
+
+    pub fn image_version(&self) -> Option<&str> {
+        self.image_version.as_deref()
+    }
+
+    /// 写完后校验 sha256（如果提供了）并设置为下次启动的分区，失败时放弃这次升级
+    pub fn finish(self, expected_sha256: Option<&[u8; 32]>) -> Result<()> {
+        if self.image_version.is_none() {
+            self.abort();
+            bail!("Firmware image too small: {} bytes", self.written);
+        }
+
+        let actual = self.sha256.finish()?;
+        if let Err(e) = verify_sha256(expected_sha256, &actual) {
+            if let Err(e) = self.update.abort() {
+                error!("Failed to abort ota update: {:?}", e);
+            }
+            return Err(e);
+        }
+        info!("Firmware sha256: {}", to_hex(&actual));
+
+        self.update.complete()?;
+        Ok(())
+    }
+
+    pub fn abort(self) {
+        if let Err(e) = self.update.abort() {
+            error!("Failed to abort ota update: {:?}", e);
+        }
+    }
+}
+
+#[cfg(target_os = "espidf")]
+fn new_http_client() -> Result<HttpClient<EspHttpConnection>> {
+    let connection = EspHttpConnection::new(&Configuration {
+        buffer_size: Some(4096),
+        timeout: Some(Duration::from_secs(30)),
+        crt_bundle_attach: Some(esp_crt_bundle_attach),
+        ..Default::default()
+    })?;
+    Ok(HttpClient::wrap(connection))
+}
+
+/// 请求 manifest，304 表示没有新版本
+#[cfg(target_os = "espidf")]
+pub fn fetch_manifest(manifest_url: &str) -> Result<Option<OtaManifest>> {
+    let mut client = new_http_client()?;
+    let headers = [("Accept", "application/json"), ("X-Esp32-Version", VERSION)];
+    let request = client.request(Method::Get, manifest_url, &headers)?;
+    let mut response = request.submit()?;
+    // 304 的时候没有 body，只能用 Date 头
+    let date = response.header("Date").and_then(parse_http_date);
+
+    let manifest = match response.status() {
+        http_status::NOT_MODIFIED => None,
+        http_status::OK => {
+            let mut body = Vec::new();
+            let mut buffer = [0u8; 512];
+            loop {
+                let n = response.read(&mut buffer)?;
+                if n == 0 {
+                    break;
+                }
+                body.extend_from_slice(&buffer[..n]);
+            }
+            Some(serde_json::from_slice::<OtaManifest>(&body)?)
+        }
+        status => bail!("Unexpected manifest response status: {}", status),
+    };
+
+    let server_time = manifest
+        .as_ref()
+        .and_then(|manifest| manifest.server_time.as_ref())
+        .map(|time| Duration::from_millis(time.timestamp))
+        .or(date);
+    if let Some(server_time) = server_time {
+        if let Err(e) = clock::set_fallback_time(server_time) {
+            warn!("Failed to set time from server: {:?}", e);
+        }
+    }
+    Ok(manifest)
+}
+
+/// 解析 HTTP 的 Date 头，例如 `Wed, 01 May 2024 00:00:00 GMT`
+#[cfg(any(target_os = "espidf", test))]
+fn parse_http_date(date: &str) -> Option<Duration> {
+    let date = DateTime::parse_from_rfc2822(date).ok()?;
+    u64::try_from(date.timestamp()).ok().map(Duration::from_secs)
+}
+
+/// 检查并安装新固件，成功后重启，所以只有在没有升级或者失败时才会返回
+#[cfg(target_os = "espidf")]
+pub fn check_and_update(manifest_url: &str, app_event_sender: &Sender<AppEvent>) -> Result<()> {
+    let report = |progress: OtaProgress| report_progress(app_event_sender, progress);
+
+    info!("Checking for updates, current version: {}", VERSION);
+    report(OtaProgress::Checking);
+
+    let time_synced = clock::time_synced();
+    let manifest = fetch_manifest(manifest_url)?;
+    if !time_synced && clock::time_synced() {
+        if let Err(e) = app_event_sender.send(AppEvent::TimeSynced) {
+            error!("Failed to send TimeSynced event: {:?}", e);
+        }
+    }
+
+    let manifest = match manifest {
+        Some(manifest) if is_newer_version(VERSION, &manifest.version)? => manifest,
+        Some(manifest) => {
+            info!("OTA: server version {} is not newer", manifest.version);
+            report(OtaProgress::UpToDate);
+            return Ok(());
+        }
+        None => {
+            info!("OTA: Already up to date");
+            report(OtaProgress::UpToDate);
+            return Ok(());
+        }
+    };
+
+    info!(
+        "OTA: updating to {} from {}",
+        manifest.version, manifest.url
+    );
+    download_and_flash(&manifest, &report)?;
+
+    report(OtaProgress::Completed {
+        version: manifest.version.clone(),
+    });
+    info!("Update done. Restarting...");
+    // 给 application 一点时间显示升级完成
+    thread::sleep(Duration::from_secs(2));
+    esp_idf_svc::hal::reset::restart();
+}
+
+#[cfg(target_os = "espidf")]
+fn download_and_flash(manifest: &OtaManifest, report: &dyn Fn(OtaProgress)) -> Result<()> {
+    let expected_sha256 = parse_sha256(&manifest.sha256)?;
+
+    let mut client = new_http_client()?;
+    let headers = [("Accept", "application/octet-stream")];
+    let request = client.request(Method::Get, &manifest.url, &headers)?;
+    let mut response = request.submit()?;
+    if response.status() != http_status::OK {
+        bail!("Unexpected firmware response status: {}", response.status());
+    }
+
+    let total = manifest
+        .size
+        .or_else(|| response.content_len().map(|len| len as usize));
+
+    flash_firmware(
+        &mut response,
+        total,
+        Some(&manifest.version),
+        Some(&expected_sha256),
+        report,
+    )?;
+    Ok(())
+}
+
+/// 从 reader 读取固件写入 OTA 分区，网络升级和本地上传（`POST /ota`）共用。
+///
+/// `version` 是期望的版本号，只用于显示进度和检查；成功后返回固件里的版本号
+#[cfg(target_os = "espidf")]
+pub fn flash_firmware<R: Read>(
+    reader: &mut R,
+    total: Option<usize>,
+    version: Option<&str>,
+    expected_sha256: Option<&[u8; 32]>,
+    report: &dyn Fn(OtaProgress),
+) -> Result<String> {
+    let mut ota = EspOta::new()?;
+    let update = match total {
+        Some(size) => ota.initiate_update_with_known_size(size)?,
+        None => ota.initiate_update()?,
+    };
+    let mut writer = FirmwareWriter::new(update)?;
+
+    let mut buffer = vec![0u8; 4096];
+    let mut next_report = 0;
+    let started_at = Instant::now();
+    loop {
+        let n = match reader.read(&mut buffer) {
+            Ok(n) => n,
+            Err(e) => {
+                writer.abort();
+                return Err(anyhow!("Failed to read firmware: {:?}", e));
+            }
+        };
+        if n == 0 {
+            break;
+        }
+        if let Err(e) = writer.write(&buffer[..n]) {
+            writer.abort();
+            return Err(e);
+        }
+
+        if writer.written() >= next_report {
+            next_report = writer.written() + PROGRESS_STEP;
+            let elapsed_ms = started_at.elapsed().as_millis().max(1) as usize;
+            report(OtaProgress::Downloading {
+                version: version
+                    .or(writer.image_version())
+                    .unwrap_or_default()
+                    .to_string(),
+                received: writer.written(),
+                total,
+                speed: writer.written() * 1000 / elapsed_ms,
+            });
+        }
+    }
+
+    if let Some(total) = total {
+        if writer.written() != total {
+            let written = writer.written();
+            writer.abort();
+            bail!("Firmware size mismatch, expected {} got {}", total, written);
+        }
+    }
+
+    let image_version = writer.image_version().unwrap_or_default().to_string();
+    if let Some(version) = version {
+        if image_version != version {
+            // 固件里的版本号来自 CONFIG_APP_PROJECT_VER，可能和 Cargo.toml 不一致，只打印警告
+            warn!(
+                "Image version {} differs from expected version {}",
+                image_version, version
+            );
+        }
+    }
+
+    report(OtaProgress::Verifying {
+        version: version.unwrap_or(&image_version).to_string(),
+    });
+    writer.finish(expected_sha256)?;
+    Ok(image_version)
+}
+
+/// 报告进度给 application
+pub fn report_progress(app_event_sender: &Sender<AppEvent>, progress: OtaProgress) {
+    if let Err(e) = app_event_sender.send(AppEvent::OtaProgress(progress)) {
+        error!("Failed to send OtaProgress event: {:?}", e);
+    }
+}
+
+/// 在后台线程里检查升级，失败时倒计时后重试
+#[cfg(target_os = "espidf")]
+pub fn spawn_update_check(manifest_url: String, app_event_sender: Sender<AppEvent>) {
+    let result = thread::Builder::new()
+        .name("ota_task".into())
+        .stack_size(12 * 1024)
+        .spawn(move || {
+            let mut retry_delay = INITIAL_RETRY_DELAY_S;
+            for attempt in 1..=MAX_RETRIES {
+                let Err(e) = check_and_update(&manifest_url, &app_event_sender) else {
+                    return;
+                };
+                error!("OTA failed ({}/{}): {:?}", attempt, MAX_RETRIES, e);
+                if attempt == MAX_RETRIES {
+                    report_progress(&app_event_sender, OtaProgress::Failed(e.to_string()));
+                    return;
+                }
+
+                for seconds in (1..=retry_delay).rev() {
+                    report_progress(
+                        &app_event_sender,
+                        OtaProgress::RetryIn {
+                            seconds,
+                            reason: e.to_string(),
+                        },
+                    );
+                    thread::sleep(Duration::from_secs(1));
+                }
+                retry_delay = (retry_delay * 2).min(MAX_RETRY_DELAY_S);
+            }
+        });
+    if let Err(e) = result {
+        error!("Failed to spawn ota task: {:?}", e);
+    }
+}
+
+/// host-sim 下没有 OTA 分区，不检查升级
+#[cfg(not(target_os = "espidf"))]
+pub fn spawn_update_check(manifest_url: String, _app_event_sender: Sender<AppEvent>) {
+    info!("OTA is not available in host-sim, skip {}", manifest_url);
+}
+
+/// 新固件的确认。
+///
+/// 开启 `CONFIG_BOOTLOADER_APP_ROLLBACK_ENABLE` 后，新固件第一次启动处于 pending verify 状态，
+/// 如果在确认之前重启，bootloader 会回到旧固件。
+/// 这里要求 application 进入 Idle 并且成功连上网络一次才确认，
+/// 超过 `ROLLBACK_TIMEOUT` 还没有确认就主动回滚。
+pub struct RollbackGuard {
+    pending: bool,
+    idle_reached: bool,
+    connected: bool,
+    confirmed: Arc<AtomicBool>,
+}
+
+impl RollbackGuard {
+    pub fn new() -> Self {
+        let pending = running_slot_unverified();
+        let guard = Self {
+            pending,
+            idle_reached: false,
+            connected: false,
+            confirmed: Arc::new(AtomicBool::new(!pending)),
+        };
+        if pending {
+            warn!(
+                "New firmware is pending verification, rollback in {:?} if not confirmed",
+                ROLLBACK_TIMEOUT
+            );
+            guard.start_rollback_timer();
+        }
+        guard
+    }
+
+    /// 新固件还没有确认
+    pub fn is_pending(&self) -> bool {
+        self.pending
+    }
+
+    pub fn on_idle(&mut self) {
+        self.idle_reached = true;
+        self.try_confirm();
+    }
+
+    /// 连上网络（拿到 IP）或者收到服务器的 hello 都算连接成功
+    pub fn on_connected(&mut self) {
+        self.connected = true;
+        self.try_confirm();
+    }
+
+    fn try_confirm(&mut self) {
+        if !self.pending || !self.idle_reached || !self.connected {
+            return;
+        }
+        match mark_running_slot_valid() {
+            Ok(_) => {
+                info!("New firmware confirmed, rollback cancelled");
+                self.pending = false;
+                self.confirmed.store(true, Ordering::Relaxed);
+            }
+            Err(e) => error!("Failed to mark running slot valid: {:?}", e),
+        }
+    }
+
+    fn start_rollback_timer(&self) {
+        let confirmed = self.confirmed.clone();
+        let result = thread::Builder::new()
+            .name("ota_rollback".into())
+            .stack_size(4 * 1024)
+            .spawn(move || {
+                thread::sleep(ROLLBACK_TIMEOUT);
+                if confirmed.load(Ordering::Relaxed) {
+                    return;
+                }
+                error!("New firmware not confirmed in time, rolling back");
+                rollback_and_reboot();
+            });
+        if let Err(e) = result {
+            error!("Failed to spawn ota rollback timer: {:?}", e);
+        }
+    }
+}
+
+impl Default for RollbackGuard {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(target_os = "espidf")]
+fn running_slot_unverified() -> bool {
+    match EspOta::new().and_then(|ota| ota.get_running_slot()) {
+        Ok(slot) => {
+            info!(
+                "Running firmware: {} {:?} {:?}",
+                slot.label, slot.state, slot.firmware
+            );
+            slot.state == SlotState::Unverified
+        }
+        Err(e) => {
+            error!("Failed to get running slot: {:?}", e);
+            false
+        }
+    }
+}
+
+#[cfg(target_os = "espidf")]
+fn mark_running_slot_valid() -> Result<()> {
+    EspOta::new().and_then(|mut ota| ota.mark_running_slot_valid())?;
+    Ok(())
+}
+
+#[cfg(target_os = "espidf")]
+fn rollback_and_reboot() {
+    match EspOta::new() {
+        Ok(mut ota) => {
+            let e = ota.mark_running_slot_invalid_and_reboot();
+            error!("Rollback failed: {:?}", e);
+        }
+        Err(e) => error!("Rollback failed: {:?}", e),
+    }
+}
+
+// host-sim 下没有 OTA 分区，运行的固件总是已经确认过的
+#[cfg(not(target_os = "espidf"))]
+fn running_slot_unverified() -> bool {
+    false
+}
+
+#[cfg(not(target_os = "espidf"))]
+fn mark_running_slot_valid() -> Result<()> {
+    Ok(())
+}
+
+#[cfg(not(target_os = "espidf"))]
+fn rollback_and_reboot() {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// sha256("abc")
+    const ABC_SHA256: &str = "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad";
+
+    #[test]
+    fn newer_version_uses_semver_order() {
+        assert!(is_newer_version("0.1.2", "0.2.0").unwrap());
+        // 按数字比较，不是按字符串
+        assert!(is_newer_version("0.1.9", "0.1.10").unwrap());
+        assert!(!is_newer_version("0.1.2", "0.1.2").unwrap());
+        assert!(!is_newer_version("0.2.0", "0.1.9").unwrap());
+        assert!(!is_newer_version(VERSION, VERSION).unwrap());
+    }
+
+    #[test]
+    fn prerelease_is_older_than_release() {
+        assert!(is_newer_version("0.2.0-beta.1", "0.2.0").unwrap());
+        assert!(!is_newer_version("0.2.0", "0.2.0-beta.1").unwrap());
+        assert!(is_newer_version("0.2.0-beta.1", "0.2.0-beta.2").unwrap());
+    }
+
+    #[test]
+    fn version_may_have_v_prefix_and_spaces() {
+        assert!(is_newer_version("v0.1.2", " v0.1.3\n").unwrap());
+        assert_eq!(parse_version("v1.2.3").unwrap(), Version::new(1, 2, 3));
+    }
+
+    #[test]
+    fn invalid_version_is_an_error() {
+        // 服务器下发的版本号不对时不升级，而不是当成新版本
+        assert!(is_newer_version("0.1.2", "latest").is_err());
+        assert!(is_newer_version("0.1.2", "1.2").is_err());
+        assert!(is_newer_version("0.1.2", "").is_err());
+        assert!(is_newer_version("dev", "0.1.2").is_err());
+    }
+
+    #[test]
+    fn parses_sha256_hex() {
+        let hash = parse_sha256(ABC_SHA256).unwrap();
+        assert_eq!(hash[0], 0xba);
+        assert_eq!(hash[31], 0xad);
+        assert_eq!(to_hex(&hash), ABC_SHA256);
+        // 大写和首尾空白也可以
+        let upper = format!(" {}\n", ABC_SHA256.to_uppercase());
+        assert_eq!(parse_sha256(&upper).unwrap(), hash);
+    }
+
+    #[test]
+    fn rejects_malformed_sha256() {
+        assert!(parse_sha256("").is_err());
+        assert!(parse_sha256(&ABC_SHA256[..62]).is_err());
+        assert!(parse_sha256(&format!("{}00", ABC_SHA256)).is_err());
+        assert!(parse_sha256(&format!("zz{}", &ABC_SHA256[2..])).is_err());
+        // 64 个字节但不是 ASCII，不能按字节切开
+        assert!(parse_sha256(&"é".repeat(32)).is_err());
+    }
+
+    #[test]
+    fn verify_sha256_compares_when_expected() {
+        let hash = parse_sha256(ABC_SHA256).unwrap();
+        let mut other = hash;
+        other[31] ^= 1;
+
+        assert!(verify_sha256(Some(&hash), &hash).is_ok());
+        assert!(verify_sha256(None, &other).is_ok());
+        let e = verify_sha256(Some(&hash), &other).unwrap_err();
+        assert_eq!(
+            e.to_string(),
+            format!(
+                "Firmware sha256 mismatch, expected {} got {}",
+                ABC_SHA256,
+                to_hex(&other)
+            )
+        );
+    }
+
+    #[test]
+    fn manifest_optional_fields() {
+        let manifest: OtaManifest = serde_json::from_str(&format!(
+            r#"{{"version": "0.2.0", "url": "http://ota/fw.bin", "sha256": "{}"}}"#,
+            ABC_SHA256
+        ))
+        .unwrap();
+        assert_eq!(manifest.size, None);
+        assert!(manifest.server_time.is_none());
+
+        let manifest: OtaManifest = serde_json::from_str(&format!(
+            r#"{{"version": "0.2.0", "url": "http://ota/fw.bin", "sha256": "{}",
+                "size": 1572864, "server_time": {{"timestamp": 1714521600000}}}}"#,
+            ABC_SHA256
+        ))
+        .unwrap();
+        assert_eq!(manifest.size, Some(1572864));
+        assert_eq!(manifest.server_time.unwrap().timestamp, 1714521600000);
+
+        // 缺少 sha256 的 manifest 不接受
+        assert!(serde_json::from_str::<OtaManifest>(
+            r#"{"version": "0.2.0", "url": "http://ota/fw.bin"}"#
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn parses_http_date() {
+        assert_eq!(
+            parse_http_date("Wed, 01 May 2024 00:00:00 GMT"),
+            Some(Duration::from_secs(1714521600))
+        );
+        assert_eq!(parse_http_date("yesterday"), None);
+    }
+
+    #[test]
+    fn progress_percent() {
+        let downloading = |received, total| OtaProgress::Downloading {
+            version: "0.2.0".to_string(),
+            received,
+            total,
+            speed: 0,
+        };
+        assert_eq!(downloading(512, Some(1024)).percent(), Some(50));
+        // 收到的比声明的多时不超过 100
+        assert_eq!(downloading(2048, Some(1024)).percent(), Some(100));
+        assert_eq!(downloading(512, Some(0)).percent(), None);
+        assert_eq!(downloading(512, None).percent(), None);
+        assert_eq!(OtaProgress::UpToDate.percent(), None);
+    }
+}
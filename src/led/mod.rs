@@ -1,17 +1,29 @@
+pub mod pattern;
+pub mod service;
+
+#[cfg(target_os = "espidf")]
 use anyhow::Result;
+#[cfg(target_os = "espidf")]
 use core::time::Duration;
+#[cfg(target_os = "espidf")]
 use esp_idf_hal::{
     gpio::OutputPin,
     peripherals::Peripherals,
-    rmt::{config::TransmitConfig, FixedLengthSignal, PinState, Pulse, RmtChannel, TxRmtDriver},
+    rmt::{
+        config::TransmitConfig, FixedLengthSignal, PinState, Pulse, RmtChannel, TxRmtDriver,
+        VariableLengthSignal,
+    },
 };
 
 pub use rgb::RGB8;
 
+/// WS2812 灯带的 RMT 驱动，只有板子上有
+#[cfg(target_os = "espidf")]
 pub struct WS2812RMT<'a> {
     tx_rtm_driver: TxRmtDriver<'a>,
 }
 
+#[cfg(target_os = "espidf")]
 impl<'d> WS2812RMT<'d> {
     // Rust ESP Board gpio2,  ESP32-C3-DevKitC-02 gpio8
     pub fn new(led: impl OutputPin + 'd, channel: impl RmtChannel + 'd) -> Result<Self> {
@@ -38,8 +50,30 @@ impl<'d> WS2812RMT<'d> {
 
         Ok(())
     }
+
+    /// 一次写整条灯带，按顺序每个像素 24 bit（GRB）
+    pub fn set_pixels(&mut self, pixels: &[RGB8]) -> Result<()> {
+        let ticks_hz = self.tx_rtm_driver.counter_clock()?;
+        let t0h = Pulse::new_with_duration(ticks_hz, PinState::High, &ns(350))?;
+        let t0l = Pulse::new_with_duration(ticks_hz, PinState::Low, &ns(800))?;
+        let t1h = Pulse::new_with_duration(ticks_hz, PinState::High, &ns(700))?;
+        let t1l = Pulse::new_with_duration(ticks_hz, PinState::Low, &ns(600))?;
+        let mut signal = VariableLengthSignal::new();
+        for rgb in pixels {
+            let color: u32 = ((rgb.g as u32) << 16) | ((rgb.r as u32) << 8) | rgb.b as u32;
+            for i in (0..24).rev() {
+                let bit = (1 << i) & color != 0;
+                let (high_pulse, low_pulse) = if bit { (&t1h, &t1l) } else { (&t0h, &t0l) };
+                signal.push([high_pulse, low_pulse])?;
+            }
+        }
+        self.tx_rtm_driver.start_blocking(&signal)?;
+
+        Ok(())
+    }
 }
 
+#[cfg(target_os = "espidf")]
 fn ns(nanos: u64) -> Duration {
     Duration::from_nanos(nanos)
 }
@@ -0,0 +1,302 @@
+//! 状态灯的图案，只做计算，不依赖硬件。
+//!
+//! `LedPattern::render` 根据时间算出每个像素的颜色，再用 `correct` 做亮度和 gamma 校正，
+//! 最后由 `LedService` 写到灯带上。
+
+use rgb::RGB8;
+use serde::{Deserialize, Serialize};
+
+use crate::common::enums::DeviceState;
+
+/// WS2812 的亮度和人眼感觉不是线性的，不校正的话呼吸灯大部分时间看起来都是最亮
+const GAMMA: f32 = 2.2;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "pattern", rename_all = "snake_case")]
+pub enum LedPattern {
+    Off,
+    Solid {
+        color: [u8; 3],
+    },
+    /// 亮半个周期、灭半个周期
+    Blink {
+        color: [u8; 3],
+        period_ms: u32,
+    },
+    /// 一个周期内从灭渐变到最亮再渐变到灭
+    Breathe {
+        color: [u8; 3],
+        period_ms: u32,
+    },
+    /// 彩虹色，灯带上的像素依次错开
+    Rainbow {
+        period_ms: u32,
+    },
+}
+
+impl LedPattern {
+    /// 是否需要不停地刷新，静止的图案只需要写一次
+    pub fn is_animated(&self) -> bool {
+        matches!(
+            self,
+            LedPattern::Blink { .. } | LedPattern::Breathe { .. } | LedPattern::Rainbow { .. }
+        )
+    }
+
+    /// 计算 `elapsed_ms` 时刻每个像素的颜色，还没有做亮度和 gamma 校正
+    pub fn render(&self, elapsed_ms: u64, pixels: &mut [RGB8]) {
+        match *self {
+            LedPattern::Off => pixels.fill(RGB8::default()),
+            LedPattern::Solid { color } => pixels.fill(color.into()),
+            LedPattern::Blink { color, period_ms } => {
+                let on = phase(elapsed_ms, period_ms) < 128;
+                pixels.fill(if on { color.into() } else { RGB8::default() });
+            }
+            LedPattern::Breathe { color, period_ms } => {
+                let phase = phase(elapsed_ms, period_ms);
+                // 三角波，0 -> 255 -> 0
+                let level = if phase < 128 {
+                    phase * 2
+                } else {
+                    (255 - phase) * 2
+                };
+                pixels.fill(scale(color.into(), level as u8));
+            }
+            LedPattern::Rainbow { period_ms } => {
+                let phase = phase(elapsed_ms, period_ms);
+                let count = pixels.len() as u32;
+                for (i, pixel) in pixels.iter_mut().enumerate() {
+                    let hue = (phase + i as u32 * 256 / count) % 256;
+                    *pixel = wheel(hue as u8);
+                }
+            }
+        }
+    }
+}
+
+/// 当前时刻在周期里的位置，0-255。先用 u64 取模，开机很久之后也不会跳变
+fn phase(elapsed_ms: u64, period_ms: u32) -> u32 {
+    if period_ms == 0 {
+        return 0;
+    }
+    let period_ms = period_ms as u64;
+    (elapsed_ms % period_ms * 256 / period_ms) as u32
+}
+
+/// 色环，0 红 -> 85 绿 -> 170 蓝 -> 255 回到红
+fn wheel(hue: u8) -> RGB8 {
+    // 255 / 85 = 3，归到最后一段，算出来正好回到红色
+    let sector = (hue / 85).min(2);
+    let offset = (hue - sector * 85) * 3;
+    match sector {
+        0 => RGB8::new(255 - offset, offset, 0),
+        1 => RGB8::new(0, 255 - offset, offset),
+        _ => RGB8::new(offset, 0, 255 - offset),
+    }
+}
+
+fn scale(color: RGB8, level: u8) -> RGB8 {
+    let scale = |c: u8| (c as u16 * level as u16 / 255) as u8;
+    RGB8::new(scale(color.r), scale(color.g), scale(color.b))
+}
+
+fn gamma(value: u8) -> u8 {
+    ((value as f32 / 255.0).powf(GAMMA) * 255.0 + 0.5) as u8
+}
+
+/// 按亮度（0-100）缩放后做 gamma 校正，得到实际写到灯上的值
+pub fn correct(color: RGB8, brightness: u8) -> RGB8 {
+    let level = (brightness.min(100) as u16 * 255 / 100) as u8;
+    let color = scale(color, level);
+    RGB8::new(gamma(color.r), gamma(color.g), gamma(color.b))
+}
+
+/// 状态灯的设置，保存在 `display` 分组里。每种状态的图案都可以改
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LedSettings {
+    /// 0-100
+    pub brightness: u8,
+    pub idle: LedPattern,
+    pub listening: LedPattern,
+    pub speaking: LedPattern,
+    /// 连接服务器、配网、启动中
+    pub connecting: LedPattern,
+    pub upgrading: LedPattern,
+    /// 网络断开、连接服务器失败
+    pub error: LedPattern,
+}
+
+impl Default for LedSettings {
+    fn default() -> Self {
+        Self {
+            brightness: 50,
+            idle: LedPattern::Off,
+            listening: LedPattern::Breathe {
+                color: [0, 0, 255],
+                period_ms: 2000,
+            },
+            speaking: LedPattern::Solid { color: [0, 255, 0] },
+            connecting: LedPattern::Blink {
+                color: [255, 120, 0],
+                period_ms: 1000,
+            },
+            upgrading: LedPattern::Rainbow { period_ms: 3000 },
+            error: LedPattern::Solid { color: [255, 0, 0] },
+        }
+    }
+}
+
+impl LedSettings {
+    pub fn pattern_for(&self, state: &DeviceState) -> LedPattern {
+        match state {
+            DeviceState::Idle | DeviceState::DeviceStateAudioTesting => self.idle,
+            DeviceState::Listening => self.listening,
+            DeviceState::Speaking => self.speaking,
+            DeviceState::Starting
+            | DeviceState::Activating
+            | DeviceState::WifiConfiguring
+            | DeviceState::Connecting => self.connecting,
+            DeviceState::Upgrading => self.upgrading,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const WHITE: RGB8 = RGB8::new(255, 255, 255);
+    const BLACK: RGB8 = RGB8::new(0, 0, 0);
+
+    fn render(pattern: LedPattern, elapsed_ms: u64, count: usize) -> Vec<RGB8> {
+        let mut pixels = vec![RGB8::new(1, 2, 3); count];
+        pattern.render(elapsed_ms, &mut pixels);
+        pixels
+    }
+
+    #[test]
+    fn blink_is_on_for_the_first_half() {
+        let blink = LedPattern::Blink {
+            color: [255, 120, 0],
+            period_ms: 1000,
+        };
+        let on = RGB8::new(255, 120, 0);
+
+        assert_eq!(render(blink, 0, 2), [on, on]);
+        assert_eq!(render(blink, 499, 2), [on, on]);
+        assert_eq!(render(blink, 500, 2), [BLACK, BLACK]);
+        assert_eq!(render(blink, 999, 2), [BLACK, BLACK]);
+        // 下一个周期
+        assert_eq!(render(blink, 1000, 2), [on, on]);
+    }
+
+    #[test]
+    fn breathe_is_a_triangle_wave() {
+        let breathe = LedPattern::Breathe {
+            color: [0, 0, 255],
+            period_ms: 2000,
+        };
+
+        assert_eq!(render(breathe, 0, 1), [BLACK]);
+        assert_eq!(render(breathe, 500, 1), [RGB8::new(0, 0, 128)]);
+        // 半个周期时最亮
+        assert_eq!(render(breathe, 1000, 1), [RGB8::new(0, 0, 254)]);
+        assert_eq!(render(breathe, 1500, 1), [RGB8::new(0, 0, 126)]);
+        assert_eq!(render(breathe, 1990, 1), [RGB8::new(0, 0, 2)]);
+        assert_eq!(render(breathe, 1999, 1), [BLACK]);
+    }
+
+    #[test]
+    fn rainbow_spreads_hue_over_pixels() {
+        let rainbow = LedPattern::Rainbow { period_ms: 3000 };
+
+        assert!(render(rainbow, 0, 0).is_empty());
+        assert_eq!(render(rainbow, 0, 1), [RGB8::new(255, 0, 0)]);
+        assert_eq!(
+            render(rainbow, 0, 3),
+            [
+                RGB8::new(255, 0, 0),
+                RGB8::new(0, 255, 0),
+                RGB8::new(0, 0, 255)
+            ]
+        );
+        // 半个周期后整体转过半个色环
+        assert_eq!(
+            render(rainbow, 1500, 3),
+            [
+                RGB8::new(0, 126, 129),
+                RGB8::new(129, 0, 126),
+                RGB8::new(129, 126, 0)
+            ]
+        );
+    }
+
+    #[test]
+    fn rainbow_wraps_back_to_red() {
+        // 周期 256ms 时第 255ms 的 hue 是 255
+        let rainbow = LedPattern::Rainbow { period_ms: 256 };
+        assert_eq!(render(rainbow, 254, 1), [RGB8::new(252, 0, 3)]);
+        assert_eq!(render(rainbow, 255, 1), [RGB8::new(255, 0, 0)]);
+    }
+
+    #[test]
+    fn phase_does_not_jump_after_u32_ms() {
+        let blink = LedPattern::Blink {
+            color: [255, 120, 0],
+            period_ms: 1000,
+        };
+        let on = RGB8::new(255, 120, 0);
+        // 大约 49.7 天后毫秒数超过 u32
+        let base = u32::MAX as u64 + 1;
+        assert_eq!(render(blink, base * 1000 + 250, 1), [on]);
+        assert_eq!(render(blink, base * 1000 + 750, 1), [BLACK]);
+    }
+
+    #[test]
+    fn zero_period_does_not_divide_by_zero() {
+        let color = [10, 20, 30];
+        let blink = LedPattern::Blink {
+            color,
+            period_ms: 0,
+        };
+        let breathe = LedPattern::Breathe {
+            color,
+            period_ms: 0,
+        };
+        assert_eq!(render(blink, 1234, 1), [color.into()]);
+        assert_eq!(render(breathe, 1234, 1), [BLACK]);
+        assert_eq!(
+            render(LedPattern::Rainbow { period_ms: 0 }, 1234, 1),
+            [RGB8::new(255, 0, 0)]
+        );
+    }
+
+    #[test]
+    fn static_patterns_fill_every_pixel() {
+        assert_eq!(render(LedPattern::Off, 0, 3), [BLACK; 3]);
+        assert_eq!(
+            render(LedPattern::Solid { color: [255; 3] }, 0, 3),
+            [WHITE; 3]
+        );
+        assert!(!LedPattern::Solid { color: [255; 3] }.is_animated());
+        assert!(LedPattern::Rainbow { period_ms: 1 }.is_animated());
+    }
+
+    #[test]
+    fn correct_keeps_end_points() {
+        assert_eq!(correct(BLACK, 100), BLACK);
+        assert_eq!(correct(WHITE, 0), BLACK);
+        assert_eq!(correct(WHITE, 100), WHITE);
+        // 超过 100 按 100 算
+        assert_eq!(correct(WHITE, 200), WHITE);
+    }
+
+    #[test]
+    fn correct_applies_gamma_after_brightness() {
+        // (128 / 255) ^ 2.2 * 255 ≈ 56
+        assert_eq!(correct(RGB8::new(128, 0, 255), 100), RGB8::new(56, 0, 255));
+        // 50% 亮度先缩放到 127，再做 gamma
+        assert_eq!(correct(WHITE, 50), RGB8::new(55, 55, 55));
+    }
+}
@@ -0,0 +1,110 @@
+#[cfg(target_os = "espidf")]
+use std::thread;
+use std::{
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+#[cfg(target_os = "espidf")]
+use anyhow::Result;
+#[cfg(target_os = "espidf")]
+use log::error;
+#[cfg(target_os = "espidf")]
+use rgb::RGB8;
+
+use crate::led::pattern::LedPattern;
+#[cfg(target_os = "espidf")]
+use crate::{
+    common::diagnostics,
+    led::{pattern::correct, WS2812RMT},
+};
+
+/// 动画的刷新间隔，大约 30 帧
+#[cfg(target_os = "espidf")]
+const FRAME_INTERVAL: Duration = Duration::from_millis(33);
+
+struct LedState {
+    pattern: LedPattern,
+    /// 出错时临时显示的图案，`None` 的截止时间表示一直显示到 `clear_error`
+    error: Option<(LedPattern, Option<Instant>)>,
+    brightness: u8,
+}
+
+// host-sim 下没有灯带，不会启动刷新任务
+#[cfg_attr(not(target_os = "espidf"), allow(dead_code))]
+impl LedState {
+    fn current(&mut self) -> (LedPattern, u8) {
+        if let Some((pattern, until)) = self.error {
+            match until {
+                Some(until) if Instant::now() >= until => self.error = None,
+                _ => return (pattern, self.brightness),
+            }
+        }
+        (self.pattern, self.brightness)
+    }
+}
+
+/// 状态灯服务，在自己的任务里刷新动画。可以 clone 之后在不同的地方使用
+#[derive(Clone)]
+pub struct LedService {
+    state: Arc<Mutex<LedState>>,
+}
+
+impl LedService {
+    /// 启动刷新任务，`num_pixels` 是灯带上的像素个数
+    #[cfg(target_os = "espidf")]
+    pub fn start(mut driver: WS2812RMT<'static>, num_pixels: usize) -> Result<Self> {
+        let state = Arc::new(Mutex::new(LedState {
+            pattern: LedPattern::Off,
+            error: None,
+            brightness: 50,
+        }));
+
+        let task_state = state.clone();
+        thread::Builder::new()
+            .name("led_task".into())
+            .stack_size(4 * 1024)
+            .spawn(move || {
+                let _task = diagnostics::register_current_task("led_task");
+                let started_at = Instant::now();
+                let mut pixels = vec![RGB8::default(); num_pixels.max(1)];
+                let mut shown: Option<(LedPattern, u8)> = None;
+                loop {
+                    let (pattern, brightness) = task_state.lock().unwrap().current();
+                    // 静止的图案只在变化时写一次
+                    if pattern.is_animated() || shown != Some((pattern, brightness)) {
+                        pattern.render(started_at.elapsed().as_millis() as u64, &mut pixels);
+                        for pixel in pixels.iter_mut() {
+                            *pixel = correct(*pixel, brightness);
+                        }
+                        if let Err(e) = driver.set_pixels(&pixels) {
+                            error!("Failed to update led: {:?}", e);
+                        }
+                        shown = Some((pattern, brightness));
+                    }
+                    thread::sleep(FRAME_INTERVAL);
+                }
+            })?;
+
+        Ok(Self { state })
+    }
+
+    pub fn set_pattern(&self, pattern: LedPattern) {
+        self.state.lock().unwrap().pattern = pattern;
+    }
+
+    /// 亮度 0-100
+    pub fn set_brightness(&self, brightness: u8) {
+        self.state.lock().unwrap().brightness = brightness.min(100);
+    }
+
+    /// 临时显示出错的图案，`duration` 为 `None` 时一直显示到调用 `clear_error`
+    pub fn show_error(&self, pattern: LedPattern, duration: Option<Duration>) {
+        let until = duration.map(|duration| Instant::now() + duration);
+        self.state.lock().unwrap().error = Some((pattern, until));
+    }
+
+    pub fn clear_error(&self) {
+        self.state.lock().unwrap().error = None;
+    }
+}
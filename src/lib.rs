@@ -2,12 +2,23 @@ pub mod application;
 pub mod audio;
 pub mod axp173;
 pub mod boards;
+pub mod camera;
+pub mod clock;
 pub mod common;
 pub mod display;
+pub mod firmware;
+#[cfg(target_os = "espidf")]
 pub mod i2s;
+#[cfg(target_os = "espidf")]
 pub mod lcd;
 pub mod led;
+pub mod logging;
+pub mod mcp;
+pub mod power;
 pub mod protocols;
+pub mod scheduler;
 pub mod setting;
+#[cfg(test)]
+mod test_support;
 pub mod utils;
 pub mod wifi;
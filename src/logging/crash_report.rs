@@ -0,0 +1,119 @@
+use std::sync::Mutex;
+
+#[cfg(target_os = "espidf")]
+use log::info;
+use log::warn;
+use serde::Serialize;
+
+use crate::{common::diagnostics, logging::tee_logger};
+
+/// 上一次运行是 panic 或者看门狗复位时生成，附在下一次的 hello 消息里发给服务器
+#[derive(Debug, Clone, Serialize)]
+pub struct CrashReport {
+    pub reset_reason: &'static str,
+    /// panic 时保存在 coredump 分区里的摘要，看门狗复位时一般没有
+    pub core_dump: Option<CoreDumpSummary>,
+    /// 上一次运行的最后几行日志
+    pub last_logs: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct CoreDumpSummary {
+    pub task: String,
+    pub pc: String,
+    /// 可以用 `xtensa-esp32s3-elf-addr2line -e xiaoxin_esp32 <地址...>` 查看对应的源码
+    pub backtrace: Vec<String>,
+    pub backtrace_corrupted: bool,
+    pub app_elf_sha256: String,
+}
+
+static PENDING: Mutex<Option<CrashReport>> = Mutex::new(None);
+
+/// 启动时调用，需要在 `tee_logger::init()` 之后
+pub fn init() {
+    let reset_reason = diagnostics::reset_reason();
+    if !matches!(
+        reset_reason,
+        "panic" | "interrupt_watchdog" | "task_watchdog" | "other_watchdog"
+    ) {
+        return;
+    }
+
+    let core_dump = read_core_dump_summary();
+    warn!(
+        "Last run crashed: {}, core dump: {:?}",
+        reset_reason, core_dump
+    );
+    *PENDING.lock().unwrap() = Some(CrashReport {
+        reset_reason,
+        core_dump,
+        last_logs: tee_logger::previous_boot_logs().to_vec(),
+    });
+}
+
+/// 取出还没有上报的崩溃信息，只会返回一次。同时擦除 coredump，避免下次启动重复上报
+pub fn take() -> Option<CrashReport> {
+    let report = PENDING.lock().unwrap().take()?;
+    if report.core_dump.is_some() {
+        erase_core_dump();
+    }
+    Some(report)
+}
+
+#[cfg(target_os = "espidf")]
+fn erase_core_dump() {
+    let ret = unsafe { esp_idf_sys::esp_core_dump_image_erase() };
+    if ret != esp_idf_sys::ESP_OK {
+        warn!("Failed to erase core dump: {}", ret);
+    }
+}
+
+/// host-sim 下没有 coredump 分区
+#[cfg(not(target_os = "espidf"))]
+fn erase_core_dump() {}
+
+#[cfg(not(target_os = "espidf"))]
+fn read_core_dump_summary() -> Option<CoreDumpSummary> {
+    None
+}
+
+#[cfg(target_os = "espidf")]
+fn read_core_dump_summary() -> Option<CoreDumpSummary> {
+    if unsafe { esp_idf_sys::esp_core_dump_image_check() } != esp_idf_sys::ESP_OK {
+        info!("No core dump found");
+        return None;
+    }
+
+    let mut summary: esp_idf_sys::esp_core_dump_summary_t = Default::default();
+    let ret = unsafe { esp_idf_sys::esp_core_dump_get_summary(&mut summary) };
+    if ret != esp_idf_sys::ESP_OK {
+        warn!("Failed to get core dump summary: {}", ret);
+        return None;
+    }
+
+    let task = summary
+        .exc_task
+        .iter()
+        .take_while(|&&c| c != 0)
+        .map(|&c| c as u8 as char)
+        .collect();
+    let bt_info = &summary.exc_bt_info;
+    let depth = (bt_info.depth as usize).min(bt_info.bt.len());
+    let app_elf_sha256 = summary
+        .app_elf_sha256
+        .iter()
+        .take_while(|&&c| c != 0)
+        .map(|&c| c as char)
+        .collect();
+
+    Some(CoreDumpSummary {
+        task,
+        pc: format!("0x{:08x}", summary.exc_pc),
+        backtrace: bt_info.bt[..depth]
+            .iter()
+            .map(|addr| format!("0x{:08x}", addr))
+            .collect(),
+        backtrace_corrupted: bt_info.corrupted,
+        app_elf_sha256,
+    })
+}
@@ -0,0 +1,196 @@
+use anyhow::{anyhow, Result};
+use esp_idf_svc::partition::EspPartition;
+use log::warn;
+
+/// 日志分区的 label，见 partitions/v1/16m.csv
+pub const LOG_PARTITION_LABEL: &str = "logs";
+
+const SECTOR_SIZE: usize = 4096;
+const SECTOR_MAGIC: u32 = 0x474F_4C58; // "XLOG"
+const SECTOR_HEADER_LEN: usize = 8;
+const RECORD_HEADER_LEN: usize = 2;
+/// 擦除后的 flash 全是 0xFF，读到这个长度说明后面没有记录了
+const RECORD_END: u16 = 0xFFFF;
+/// 单行日志最多保存这么多字节，超出的截断
+pub const MAX_RECORD_LEN: usize = 512;
+
+/// 保存在专用分区里的日志环形缓冲区，重启（包括 panic、看门狗复位）后还在。
+///
+/// 每个扇区开头是 magic 和递增的序号，后面是一条条 `[长度 u16][内容]`。
+/// 写满一个扇区就擦除下一个扇区继续写，序号最大的扇区是当前正在写的。
+pub struct FlashRing {
+    partition: EspPartition,
+    sectors: usize,
+    sector: usize,
+    seq: u32,
+    offset: usize,
+}
+
+impl FlashRing {
+    /// 打开日志分区，分区表里没有这个分区时返回 None
+    pub fn open() -> Result<Option<Self>> {
+        let Some(mut partition) = (unsafe { EspPartition::new(LOG_PARTITION_LABEL)? }) else {
+            return Ok(None);
+        };
+        let sectors = partition.size() / SECTOR_SIZE;
+        if sectors < 2 {
+            return Err(anyhow!("log partition is too small: {}", partition.size()));
+        }
+
+        // 找到序号最大的扇区，接着往后写
+        let mut buf = vec![0u8; SECTOR_SIZE];
+        let mut current: Option<(usize, u32, usize)> = None;
+        for sector in 0..sectors {
+            partition.read(sector * SECTOR_SIZE, &mut buf)?;
+            if let Some((seq, _, end)) = parse_sector(&buf) {
+                let newer = match current {
+                    Some((_, max_seq, _)) => seq > max_seq,
+                    None => true,
+                };
+                if newer {
+                    current = Some((sector, seq, end));
+                }
+            }
+        }
+
+        let mut ring = Self {
+            partition,
+            sectors,
+            sector: 0,
+            seq: 0,
+            offset: SECTOR_SIZE,
+        };
+        match current {
+            Some((sector, seq, end)) => {
+                ring.sector = sector;
+                ring.seq = seq;
+                ring.offset = end;
+            }
+            None => {
+                warn!("Log partition is empty or corrupted, formatting");
+                ring.start_sector(0, 1)?;
+            }
+        }
+        Ok(Some(ring))
+    }
+
+    /// 按写入顺序遍历所有日志，一次只读一个扇区，不会占用太多内存
+    pub fn for_each_line<F>(&mut self, mut f: F) -> Result<()>
+    where
+        F: FnMut(&str) -> Result<()>,
+    {
+        let mut buf = vec![0u8; SECTOR_SIZE];
+        for sector in self.sectors_by_seq()? {
+            self.partition.read(sector * SECTOR_SIZE, &mut buf)?;
+            if let Some((_, lines, _)) = parse_sector(&buf) {
+                for line in lines {
+                    f(&line)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// 最后 `count` 行日志，旧的在前
+    pub fn read_tail(&mut self, count: usize) -> Result<Vec<String>> {
+        let mut buf = vec![0u8; SECTOR_SIZE];
+        let mut tail = Vec::new();
+        for sector in self.sectors_by_seq()?.into_iter().rev() {
+            self.partition.read(sector * SECTOR_SIZE, &mut buf)?;
+            if let Some((_, lines, _)) = parse_sector(&buf) {
+                tail.splice(0..0, lines);
+            }
+            if tail.len() >= count {
+                break;
+            }
+        }
+        let skip = tail.len().saturating_sub(count);
+        Ok(tail.split_off(skip))
+    }
+
+    /// 有效扇区的编号，按写入顺序排列
+    fn sectors_by_seq(&mut self) -> Result<Vec<usize>> {
+        let mut header = [0u8; SECTOR_HEADER_LEN];
+        let mut sectors = Vec::new();
+        for sector in 0..self.sectors {
+            self.partition.read(sector * SECTOR_SIZE, &mut header)?;
+            if let Some((seq, _, _)) = parse_sector(&header) {
+                sectors.push((seq, sector));
+            }
+        }
+        sectors.sort_unstable();
+        Ok(sectors.into_iter().map(|(_, sector)| sector).collect())
+    }
+
+    pub fn append(&mut self, line: &str) -> Result<()> {
+        let record = encode_record(line);
+        if self.offset + record.len() > SECTOR_SIZE {
+            let next = (self.sector + 1) % self.sectors;
+            self.start_sector(next, self.seq.wrapping_add(1))?;
+        }
+        self.partition
+            .write(self.sector * SECTOR_SIZE + self.offset, &record)?;
+        self.offset += record.len();
+        Ok(())
+    }
+
+    /// 擦除整个分区
+    pub fn clear(&mut self) -> Result<()> {
+        self.partition.erase(0, self.sectors * SECTOR_SIZE)?;
+        self.start_sector(0, 1)
+    }
+
+    fn start_sector(&mut self, sector: usize, seq: u32) -> Result<()> {
+        let address = sector * SECTOR_SIZE;
+        self.partition.erase(address, SECTOR_SIZE)?;
+        let mut header = [0u8; SECTOR_HEADER_LEN];
+        header[..4].copy_from_slice(&SECTOR_MAGIC.to_le_bytes());
+        header[4..].copy_from_slice(&seq.to_le_bytes());
+        self.partition.write(address, &header)?;
+
+        self.sector = sector;
+        self.seq = seq;
+        self.offset = SECTOR_HEADER_LEN;
+        Ok(())
+    }
+}
+
+fn encode_record(line: &str) -> Vec<u8> {
+    let mut len = line.len().min(MAX_RECORD_LEN);
+    // 截断时不要把 UTF-8 字符切成两半
+    while !line.is_char_boundary(len) {
+        len -= 1;
+    }
+    let mut record = Vec::with_capacity(RECORD_HEADER_LEN + len);
+    record.extend_from_slice(&(len as u16).to_le_bytes());
+    record.extend_from_slice(&line.as_bytes()[..len]);
+    record
+}
+
+/// 解析一个扇区，返回 (序号, 日志, 下一条记录的写入位置)。不是日志扇区时返回 None
+fn parse_sector(buf: &[u8]) -> Option<(u32, Vec<String>, usize)> {
+    if buf.len() < SECTOR_HEADER_LEN
+        || u32::from_le_bytes(buf[..4].try_into().ok()?) != SECTOR_MAGIC
+    {
+        return None;
+    }
+    let seq = u32::from_le_bytes(buf[4..8].try_into().ok()?);
+
+    let mut lines = Vec::new();
+    let mut offset = SECTOR_HEADER_LEN;
+    while offset + RECORD_HEADER_LEN <= buf.len() {
+        let len = u16::from_le_bytes([buf[offset], buf[offset + 1]]);
+        if len == RECORD_END {
+            break;
+        }
+        let start = offset + RECORD_HEADER_LEN;
+        let end = start + len as usize;
+        if len as usize > MAX_RECORD_LEN || end > buf.len() {
+            // 写到一半断电了，后面的内容不可信，从这里开始不能再写，直接换下一个扇区
+            return Some((seq, lines, buf.len()));
+        }
+        lines.push(String::from_utf8_lossy(&buf[start..end]).into_owned());
+        offset = end;
+    }
+    Some((seq, lines, offset))
+}
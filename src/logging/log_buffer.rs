@@ -0,0 +1,82 @@
+use std::collections::VecDeque;
+
+use log::Level;
+
+#[derive(Debug, Clone)]
+pub struct LogLine {
+    /// 递增的序号，用来记录推送/写 flash 的位置
+    pub seq: u64,
+    pub level: Level,
+    pub text: String,
+}
+
+/// 内存里的日志环形缓冲区，按字节数限制大小，满了丢掉最旧的行
+pub struct LogBuffer {
+    lines: VecDeque<LogLine>,
+    bytes: usize,
+    capacity: usize,
+    next_seq: u64,
+}
+
+impl LogBuffer {
+    pub const fn new(capacity: usize) -> Self {
+        Self {
+            lines: VecDeque::new(),
+            bytes: 0,
+            capacity,
+            next_seq: 0,
+        }
+    }
+
+    pub fn push(&mut self, level: Level, text: String) -> u64 {
+        let seq = self.next_seq;
+        self.next_seq += 1;
+
+        self.bytes += text.len();
+        self.lines.push_back(LogLine { seq, level, text });
+        while self.bytes > self.capacity && self.lines.len() > 1 {
+            if let Some(line) = self.lines.pop_front() {
+                self.bytes -= line.text.len();
+            }
+        }
+        seq
+    }
+
+    /// 下一行日志的序号
+    pub fn next_seq(&self) -> u64 {
+        self.next_seq
+    }
+
+    /// 序号 >= `seq` 并且级别不低于 `level` 的日志，最多 `limit` 行。
+    /// 已经被挤出缓冲区的行直接跳过
+    pub fn since(&self, seq: u64, level: Level, limit: usize) -> Vec<LogLine> {
+        self.lines
+            .iter()
+            .filter(|line| line.seq >= seq && line.level <= level)
+            .take(limit)
+            .cloned()
+            .collect()
+    }
+
+    /// 导出所有级别不低于 `level` 的日志，每行一条
+    pub fn dump(&self, level: Level) -> String {
+        let mut text = String::with_capacity(self.bytes + self.lines.len());
+        for line in self.lines.iter().filter(|line| line.level <= level) {
+            text.push_str(&line.text);
+            text.push('\n');
+        }
+        text
+    }
+}
+
+/// 解析 "error"/"warn"/"info"/"debug"/"trace"，不认识的返回 None
+pub fn parse_level(level: &str) -> Option<Level> {
+    match level.to_ascii_lowercase().as_str() {
+        "error" | "e" => Some(Level::Error),
+        "warn" | "warning" | "w" => Some(Level::Warn),
+        "info" | "i" => Some(Level::Info),
+        "debug" | "d" => Some(Level::Debug),
+        "trace" | "verbose" | "v" => Some(Level::Trace),
+        _ => None,
+    }
+}
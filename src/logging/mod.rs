@@ -0,0 +1,5 @@
+pub mod crash_report;
+#[cfg(target_os = "espidf")]
+pub mod flash_ring;
+pub mod log_buffer;
+pub mod tee_logger;
@@ -0,0 +1,315 @@
+#[cfg(not(target_os = "espidf"))]
+use std::time::Instant;
+use std::{
+    sync::{mpsc::Sender, Mutex, MutexGuard, OnceLock},
+    thread,
+    time::Duration,
+};
+
+use anyhow::Result;
+#[cfg(target_os = "espidf")]
+use esp_idf_svc::log::{EspIdfLogFilter, EspIdfLogger, EspLogger};
+#[cfg(target_os = "espidf")]
+use log::error;
+use log::{info, Level, Log, Metadata, Record};
+
+#[cfg(target_os = "espidf")]
+use crate::logging::flash_ring::FlashRing;
+use crate::{
+    common::{diagnostics, event::AppEvent},
+    logging::log_buffer::{LogBuffer, LogLine},
+};
+
+/// 内存里最多保存这么多字节的日志
+const RAM_LOG_CAPACITY: usize = 16 * 1024;
+/// 写入 flash 的最低级别，debug 日志太多，全写进去 flash 很快就磨损了
+#[cfg(target_os = "espidf")]
+const FLASH_MIN_LEVEL: Level = Level::Info;
+#[cfg(target_os = "espidf")]
+const FLASH_FLUSH_INTERVAL: Duration = Duration::from_secs(5);
+/// 启动时从 flash 里取出的上一次运行的日志行数，崩溃后附在 hello 里
+#[cfg(target_os = "espidf")]
+const PREVIOUS_BOOT_TAIL: usize = 50;
+const LOG_STREAM_INTERVAL: Duration = Duration::from_secs(1);
+
+#[cfg(target_os = "espidf")]
+struct FlashState {
+    ring: FlashRing,
+    /// 下一次从这个序号开始写 flash
+    flushed_seq: u64,
+}
+
+/// 把日志同时输出到串口（`EspLogger`）、内存环形缓冲区和 flash 日志分区。
+/// host-sim 下输出到标准输出，没有 flash
+pub struct TeeLogger {
+    #[cfg(target_os = "espidf")]
+    inner: EspLogger,
+    ram: Mutex<LogBuffer>,
+    #[cfg(target_os = "espidf")]
+    flash: Mutex<Option<FlashState>>,
+}
+
+static LOGGER: TeeLogger = TeeLogger {
+    #[cfg(target_os = "espidf")]
+    inner: EspIdfLogger::new(EspIdfLogFilter::new()),
+    ram: Mutex::new(LogBuffer::new(RAM_LOG_CAPACITY)),
+    #[cfg(target_os = "espidf")]
+    flash: Mutex::new(None),
+};
+
+/// host-sim 下日志时间戳的起点
+#[cfg(not(target_os = "espidf"))]
+static STARTED_AT: OnceLock<Instant> = OnceLock::new();
+
+static PREVIOUS_BOOT_LOGS: OnceLock<Vec<String>> = OnceLock::new();
+
+impl Log for TeeLogger {
+    #[cfg(target_os = "espidf")]
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        self.inner.enabled(metadata)
+    }
+
+    #[cfg(not(target_os = "espidf"))]
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= log::max_level()
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+        #[cfg(target_os = "espidf")]
+        self.inner.log(record);
+
+        let text = format!(
+            "{} ({}) {}: {}",
+            level_marker(record.level()),
+            log_timestamp(),
+            record.target(),
+            record.args()
+        );
+        #[cfg(not(target_os = "espidf"))]
+        println!("{}", text);
+        if let Some(mut ram) = self.lock_ram() {
+            ram.push(record.level(), text);
+        }
+    }
+
+    fn flush(&self) {
+        flush_to_flash();
+    }
+}
+
+impl TeeLogger {
+    /// panic 时这个线程可能正拿着锁（比如在 `LogBuffer::push` 里 panic），
+    /// 这时只用 `try_lock`，拿不到就不写内存，否则 panic hook 会在写 flash 之前死锁
+    fn lock_ram(&self) -> Option<MutexGuard<'_, LogBuffer>> {
+        if thread::panicking() {
+            self.ram.try_lock().ok()
+        } else {
+            self.ram.lock().ok()
+        }
+    }
+}
+
+/// 启动以来的毫秒数
+#[cfg(target_os = "espidf")]
+fn log_timestamp() -> u32 {
+    unsafe { esp_idf_sys::esp_log_timestamp() }
+}
+
+#[cfg(not(target_os = "espidf"))]
+fn log_timestamp() -> u128 {
+    STARTED_AT.get_or_init(Instant::now).elapsed().as_millis()
+}
+
+fn level_marker(level: Level) -> &'static str {
+    match level {
+        Level::Error => "E",
+        Level::Warn => "W",
+        Level::Info => "I",
+        Level::Debug => "D",
+        Level::Trace => "V",
+    }
+}
+
+/// 代替 `EspLogger::initialize_default()`，需要在第一次打日志之前调用。
+///
+/// 有日志分区时先把上一次运行的最后几行读出来，再启动定时写 flash 的线程，
+/// 并在 panic 时把还没写入的日志写进 flash
+pub fn init() {
+    if let Err(e) = log::set_logger(&LOGGER) {
+        println!("Failed to set logger: {:?}", e);
+        return;
+    }
+    init_filter();
+    #[cfg(target_os = "espidf")]
+    open_flash();
+    // flash 里保存了好几次运行的日志，用这一行区分
+    info!(
+        "==== boot, reset reason: {} ====",
+        diagnostics::reset_reason()
+    );
+}
+
+#[cfg(target_os = "espidf")]
+fn init_filter() {
+    LOGGER.inner.filter().initialize();
+}
+
+/// host-sim 下用 `RUST_LOG` 设置级别，默认 info
+#[cfg(not(target_os = "espidf"))]
+fn init_filter() {
+    let level = std::env::var("RUST_LOG")
+        .ok()
+        .and_then(|level| level.parse().ok())
+        .unwrap_or(log::LevelFilter::Info);
+    log::set_max_level(level);
+}
+
+#[cfg(target_os = "espidf")]
+fn open_flash() {
+    match FlashRing::open() {
+        Ok(Some(mut ring)) => {
+            match ring.read_tail(PREVIOUS_BOOT_TAIL) {
+                Ok(lines) => {
+                    let _ = PREVIOUS_BOOT_LOGS.set(lines);
+                }
+                Err(e) => error!("Failed to read previous logs: {:?}", e),
+            }
+            *LOGGER.flash.lock().unwrap() = Some(FlashState {
+                ring,
+                flushed_seq: 0,
+            });
+            if let Err(e) = start_flush_thread() {
+                error!("Failed to start log flush thread: {:?}", e);
+            }
+            install_panic_hook();
+        }
+        Ok(None) => info!("No log partition, logs are kept in RAM only"),
+        Err(e) => error!("Failed to open log partition: {:?}", e),
+    }
+}
+
+#[cfg(target_os = "espidf")]
+fn start_flush_thread() -> Result<()> {
+    thread::Builder::new()
+        .name("log_flush".into())
+        .stack_size(4 * 1024)
+        .spawn(|| loop {
+            thread::sleep(FLASH_FLUSH_INTERVAL);
+            flush_to_flash();
+        })?;
+    Ok(())
+}
+
+#[cfg(target_os = "espidf")]
+fn install_panic_hook() {
+    let default_hook = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |panic_info| {
+        error!("{}", panic_info);
+        flush_to_flash();
+        default_hook(panic_info);
+    }));
+}
+
+/// 把还没写入的日志写进 flash。正在写的时候直接返回，避免 panic 时死锁
+#[cfg(target_os = "espidf")]
+pub fn flush_to_flash() {
+    let Ok(mut flash) = LOGGER.flash.try_lock() else {
+        return;
+    };
+    let Some(state) = flash.as_mut() else {
+        return;
+    };
+
+    let (lines, next_seq) = {
+        let Some(ram) = LOGGER.lock_ram() else {
+            return;
+        };
+        (
+            ram.since(state.flushed_seq, FLASH_MIN_LEVEL, usize::MAX),
+            ram.next_seq(),
+        )
+    };
+    state.flushed_seq = next_seq;
+
+    for line in lines {
+        if let Err(e) = state.ring.append(&line.text) {
+            // 这里不能用 error!，它写的日志下次还会再写一遍
+            println!("Failed to write log to flash: {:?}", e);
+            break;
+        }
+    }
+}
+
+#[cfg(not(target_os = "espidf"))]
+pub fn flush_to_flash() {}
+
+/// 启动定时器线程，每秒发送一次 `AppEvent::LogStreamTick`，用来把新日志推送给服务器
+pub fn start_stream_timer(sender: Sender<AppEvent>) -> Result<()> {
+    thread::Builder::new()
+        .name("log_stream".into())
+        .stack_size(2 * 1024)
+        .spawn(move || loop {
+            thread::sleep(LOG_STREAM_INTERVAL);
+            if sender.send(AppEvent::LogStreamTick).is_err() {
+                break;
+            }
+        })?;
+    Ok(())
+}
+
+/// 内存里序号 >= `seq`、级别不低于 `level` 的日志，最多 `limit` 行
+pub fn lines_since(seq: u64, level: Level, limit: usize) -> Vec<LogLine> {
+    LOGGER
+        .ram
+        .lock()
+        .map(|ram| ram.since(seq, level, limit))
+        .unwrap_or_default()
+}
+
+/// 下一行日志的序号，开始推送日志时从这里开始
+pub fn next_seq() -> u64 {
+    LOGGER.ram.lock().map(|ram| ram.next_seq()).unwrap_or(0)
+}
+
+/// 导出内存里的日志
+pub fn dump_ram(level: Level) -> String {
+    LOGGER
+        .ram
+        .lock()
+        .map(|ram| ram.dump(level))
+        .unwrap_or_default()
+}
+
+/// 按写入顺序遍历 flash 里的日志，没有日志分区时返回 false
+#[cfg(target_os = "espidf")]
+pub fn for_each_flash_line<F>(f: F) -> Result<bool>
+where
+    F: FnMut(&str) -> Result<()>,
+{
+    // 先把内存里的写进去，保证 flash 里是最新的
+    flush_to_flash();
+    let mut flash = LOGGER.flash.lock().unwrap();
+    match flash.as_mut() {
+        Some(state) => {
+            state.ring.for_each_line(f)?;
+            Ok(true)
+        }
+        None => Ok(false),
+    }
+}
+
+#[cfg(not(target_os = "espidf"))]
+pub fn for_each_flash_line<F>(_f: F) -> Result<bool>
+where
+    F: FnMut(&str) -> Result<()>,
+{
+    Ok(false)
+}
+
+/// 上一次运行的最后几行日志（启动时从 flash 读出）
+pub fn previous_boot_logs() -> &'static [String] {
+    PREVIOUS_BOOT_LOGS.get().map(Vec::as_slice).unwrap_or(&[])
+}
@@ -0,0 +1,147 @@
+use anyhow::Result;
+use log::{info, warn};
+use serde_json::{json, Value};
+
+const PROTOCOL_VERSION: &str = "2024-11-05";
+
+// JSON-RPC 错误码
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+const INTERNAL_ERROR: i64 = -32603;
+
+type ToolCallback = Box<dyn Fn(&Value) -> Result<Value> + Send + 'static>;
+
+/// 一个可以被服务器(大模型)调用的工具
+pub struct McpTool {
+    name: String,
+    description: String,
+    input_schema: Value,
+    callback: ToolCallback,
+}
+
+/// 设备端的 MCP server，对应 C++ 版本的 McpServer。
+///
+/// 服务器发来的 `{"type": "mcp", "payload": {...}}` 消息中，payload 是 JSON-RPC 2.0 请求，
+/// `handle_message` 返回需要发回的 payload，application 负责包装后通过协议发送。
+#[derive(Default)]
+pub struct McpServer {
+    tools: Vec<McpTool>,
+}
+
+impl McpServer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 注册一个工具。`input_schema` 是 JSON Schema 里的 properties，
+    /// `required` 列出必须的参数
+    pub fn add_tool<F>(
+        &mut self,
+        name: &str,
+        description: &str,
+        properties: Value,
+        required: &[&str],
+        callback: F,
+    ) where
+        F: Fn(&Value) -> Result<Value> + Send + 'static,
+    {
+        if self.tools.iter().any(|tool| tool.name == name) {
+            warn!("MCP tool {} already added", name);
+            return;
+        }
+        self.tools.push(McpTool {
+            name: name.to_string(),
+            description: description.to_string(),
+            input_schema: json!({
+                "type": "object",
+                "properties": properties,
+                "required": required,
+            }),
+            callback: Box::new(callback),
+        });
+    }
+
+    /// 处理一条 JSON-RPC 消息，通知类消息(没有 id)返回 None
+    pub fn handle_message(&self, message: &Value) -> Option<Value> {
+        if message["jsonrpc"] != "2.0" {
+            warn!("Invalid JSON-RPC message: {}", message);
+            return None;
+        }
+        let method = message["method"].as_str().unwrap_or_default();
+        let id = message.get("id")?.clone();
+        let params = &message["params"];
+
+        let result = match method {
+            "initialize" => Ok(json!({
+                "protocolVersion": PROTOCOL_VERSION,
+                "capabilities": { "tools": {} },
+                "serverInfo": {
+                    "name": env!("CARGO_PKG_NAME"),
+                    "version": env!("CARGO_PKG_VERSION"),
+                },
+            })),
+            "tools/list" => Ok(self.list_tools()),
+            "tools/call" => self.call_tool(params),
+            _ => Err((METHOD_NOT_FOUND, format!("Method not found: {}", method))),
+        };
+
+        Some(match result {
+            Ok(result) => json!({ "jsonrpc": "2.0", "id": id, "result": result }),
+            Err((code, message)) => json!({
+                "jsonrpc": "2.0",
+                "id": id,
+                "error": { "code": code, "message": message },
+            }),
+        })
+    }
+
+    fn list_tools(&self) -> Value {
+        let tools = self
+            .tools
+            .iter()
+            .map(|tool| {
+                json!({
+                    "name": tool.name,
+                    "description": tool.description,
+                    "inputSchema": tool.input_schema,
+                })
+            })
+            .collect::<Vec<_>>();
+        json!({ "tools": tools })
+    }
+
+    fn call_tool(&self, params: &Value) -> Result<Value, (i64, String)> {
+        let name = params["name"].as_str().unwrap_or_default();
+        let Some(tool) = self.tools.iter().find(|tool| tool.name == name) else {
+            return Err((INVALID_PARAMS, format!("Unknown tool: {}", name)));
+        };
+
+        let arguments = if params["arguments"].is_object() {
+            params["arguments"].clone()
+        } else {
+            json!({})
+        };
+        if let Some(required) = tool.input_schema["required"].as_array() {
+            for key in required.iter().filter_map(|key| key.as_str()) {
+                if arguments.get(key).is_none() {
+                    return Err((INVALID_PARAMS, format!("Missing argument: {}", key)));
+                }
+            }
+        }
+
+        info!("Call MCP tool: {} {}", name, arguments);
+        match (tool.callback)(&arguments) {
+            Ok(value) => {
+                let text = match value {
+                    Value::String(text) => text,
+                    value => value.to_string(),
+                };
+                Ok(json!({
+                    "content": [{ "type": "text", "text": text }],
+                    "isError": false,
+                }))
+            }
+            Err(e) => Err((INTERNAL_ERROR, e.to_string())),
+        }
+    }
+}
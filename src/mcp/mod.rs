@@ -0,0 +1 @@
+pub mod mcp_server;
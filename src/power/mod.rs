@@ -0,0 +1,3 @@
+pub mod pmic_irq;
+pub mod policy;
+pub mod power_manager;
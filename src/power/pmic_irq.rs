@@ -0,0 +1,284 @@
+use std::sync::mpsc::Sender;
+#[cfg(target_os = "espidf")]
+use std::{thread, time::Duration};
+
+use anyhow::{anyhow, Result};
+use embedded_hal::blocking::i2c::{Write, WriteRead};
+#[cfg(target_os = "espidf")]
+use esp_idf_hal::gpio::{AnyInputPin, Input, PinDriver, Pull};
+use log::info;
+#[cfg(target_os = "espidf")]
+use log::{error, warn};
+
+use crate::{
+    axp173::{Axp173, Irq, IrqStatus},
+    common::event::AppEvent,
+};
+
+/// AXP173 的 IRQ 引脚是开漏输出、低电平有效，这里按固定间隔采样它。
+#[cfg(target_os = "espidf")]
+const IRQ_POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// 应用层关心的中断和对应的事件，只打开这些中断
+const IRQ_EVENTS: [(Irq, PmicEvent); 5] = [
+    (Irq::ButtonShortPress, PmicEvent::PowerKeyShortPress),
+    (Irq::ButtonLongPress, PmicEvent::PowerKeyLongPress),
+    (Irq::VbusPluggedIn, PmicEvent::VbusPluggedIn),
+    (Irq::VbusUnplugged, PmicEvent::VbusUnplugged),
+    (Irq::BatteryOverheat, PmicEvent::BatteryOverheat),
+];
+
+/// 从 AXP173 中断寄存器里解析出来、应用层关心的事件
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PmicEvent {
+    PowerKeyShortPress,
+    PowerKeyLongPress,
+    VbusPluggedIn,
+    VbusUnplugged,
+    BatteryOverheat,
+}
+
+impl PmicEvent {
+    /// 把一次读取到的中断状态解码成事件列表，不涉及任何 I2C 操作，方便单独测试。
+    pub fn decode(status: &IrqStatus) -> Vec<PmicEvent> {
+        IRQ_EVENTS
+            .iter()
+            .filter(|(irq, _)| status.is_set(*irq))
+            .map(|(_, event)| *event)
+            .collect()
+    }
+
+    pub fn to_app_event(self) -> AppEvent {
+        match self {
+            PmicEvent::PowerKeyShortPress => AppEvent::PowerKeyClicked,
+            PmicEvent::PowerKeyLongPress => AppEvent::PowerKeyLongPressed,
+            PmicEvent::VbusPluggedIn => AppEvent::ChargingStateChanged(true),
+            PmicEvent::VbusUnplugged => AppEvent::ChargingStateChanged(false),
+            PmicEvent::BatteryOverheat => AppEvent::BatteryOverheat,
+        }
+    }
+}
+
+/// 读取 AXP173 的中断状态，把 PEK 按键、USB 插拔、电池过温转换成 `AppEvent`。
+///
+/// 驱动只依赖 embedded-hal 的 I2C trait，所以可以用一个 mock 的 I2C 总线来驱动 `poll_once`。
+pub struct PmicIrqService<I> {
+    axp173: Axp173<I>,
+    app_event_sender: Sender<AppEvent>,
+}
+
+impl<I, E> PmicIrqService<I>
+where
+    I: WriteRead<Error = E> + Write<Error = E>,
+    E: core::fmt::Debug,
+{
+    pub fn new(axp173: Axp173<I>, app_event_sender: Sender<AppEvent>) -> Self {
+        Self {
+            axp173,
+            app_event_sender,
+        }
+    }
+
+    /// 打开 `IRQ_EVENTS` 里的中断，关掉其它的。上电默认值里没有电池过温，
+    /// 而低电量之类不处理的中断会一直把 IRQ 引脚拉低
+    pub fn enable_irqs(&mut self) -> Result<()> {
+        let irqs = IRQ_EVENTS.map(|(irq, _)| irq);
+        self.axp173
+            .set_enabled_irqs(&irqs)
+            .map_err(|e| anyhow!("Failed to enable AXP173 IRQs: {:?}", e))
+    }
+
+    /// 启动前先清掉上电过程中残留的中断，否则开机按键会被当成一次短按。
+    pub fn clear_pending(&mut self) -> Result<()> {
+        self.axp173
+            .clear_all_irq()
+            .map_err(|e| anyhow!("Failed to clear AXP173 IRQs: {:?}", e))
+    }
+
+    /// 读取并清除一次中断状态，把解析出来的事件发送给 application。
+    pub fn poll_once(&mut self) -> Result<Vec<PmicEvent>> {
+        let status = self
+            .axp173
+            .read_irq_status()
+            .map_err(|e| anyhow!("Failed to read AXP173 IRQ status: {:?}", e))?;
+
+        if status.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        self.axp173
+            .clear_irq_status(&status)
+            .map_err(|e| anyhow!("Failed to clear AXP173 IRQ status: {:?}", e))?;
+
+        let events = PmicEvent::decode(&status);
+        for event in events.iter() {
+            info!("AXP173 event: {:?}", event);
+            self.app_event_sender.send(event.to_app_event())?;
+        }
+        Ok(events)
+    }
+}
+
+#[cfg(target_os = "espidf")]
+impl<I, E> PmicIrqService<I>
+where
+    I: WriteRead<Error = E> + Write<Error = E> + Send + 'static,
+    E: core::fmt::Debug,
+{
+    /// 在独立线程中监听 IRQ 引脚，引脚被拉低时才去读 I2C。
+    pub fn spawn(mut self, irq_pin: AnyInputPin<'static>) -> Result<()> {
+        // IRQ 是开漏输出，板子上有上拉电阻，这里再打开内部上拉，免得悬空时误触发
+        let irq: PinDriver<'static, Input> = PinDriver::input(irq_pin, Pull::Up)?;
+
+        self.enable_irqs()?;
+        if let Err(e) = self.clear_pending() {
+            warn!("{:?}", e);
+        }
+
+        thread::Builder::new()
+            .name("pmic_irq_task".into())
+            .stack_size(4 * 1024)
+            .spawn(move || loop {
+                if irq.is_low() {
+                    if let Err(e) = self.poll_once() {
+                        error!("PMIC IRQ poll error: {:?}", e);
+                    }
+                }
+                thread::sleep(IRQ_POLL_INTERVAL);
+            })?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::mpsc;
+
+    use super::*;
+    use crate::test_support::recording_i2c::{I2cOp, RecordingI2c};
+
+    const ADDR: u8 = 0x34;
+
+    #[test]
+    fn decode_each_status_bit() {
+        let cases = [
+            ([0x00, 0x00, 0x02, 0x00], PmicEvent::PowerKeyShortPress),
+            ([0x00, 0x00, 0x01, 0x00], PmicEvent::PowerKeyLongPress),
+            ([0x08, 0x00, 0x00, 0x00], PmicEvent::VbusPluggedIn),
+            ([0x04, 0x00, 0x00, 0x00], PmicEvent::VbusUnplugged),
+            ([0x00, 0x02, 0x00, 0x00], PmicEvent::BatteryOverheat),
+        ];
+        for (regs, event) in cases {
+            assert_eq!(PmicEvent::decode(&IrqStatus(regs)), vec![event]);
+        }
+    }
+
+    #[test]
+    fn decode_ignores_other_bits() {
+        assert!(PmicEvent::decode(&IrqStatus::default()).is_empty());
+        // ACIN、电池充满、低电量警告这些不关心
+        assert!(PmicEvent::decode(&IrqStatus([0x40, 0x04, 0x80, 0x01])).is_empty());
+    }
+
+    #[test]
+    fn decode_multiple_bits_in_table_order() {
+        let events = PmicEvent::decode(&IrqStatus([0x08, 0x02, 0x03, 0x00]));
+        assert_eq!(
+            events,
+            vec![
+                PmicEvent::PowerKeyShortPress,
+                PmicEvent::PowerKeyLongPress,
+                PmicEvent::VbusPluggedIn,
+                PmicEvent::BatteryOverheat,
+            ]
+        );
+    }
+
+    #[test]
+    fn read_irq_status_reads_four_registers() {
+        let bus = RecordingI2c::new();
+        bus.set_register(ADDR, 0x44, 0x08);
+        bus.set_register(ADDR, 0x46, 0x02);
+        let mut axp173 = Axp173::new(bus.clone());
+
+        let status = axp173.read_irq_status().unwrap();
+
+        assert_eq!(status, IrqStatus([0x08, 0x00, 0x02, 0x00]));
+        assert_eq!(
+            bus.ops(),
+            vec![I2cOp::Read {
+                addr: ADDR,
+                reg: 0x44,
+                len: 4
+            }]
+        );
+    }
+
+    #[test]
+    fn enable_irqs_writes_all_enable_registers() {
+        let bus = RecordingI2c::new();
+        // 上电默认值，不能只改其中几位
+        bus.set_register(ADDR, 0x40, 0xD8);
+        bus.set_register(ADDR, 0x41, 0xFF);
+        bus.set_register(ADDR, 0x42, 0x98);
+        bus.set_register(ADDR, 0x43, 0x01);
+        let (sender, _receiver) = mpsc::channel();
+        let mut service = PmicIrqService::new(Axp173::new(bus.clone()), sender);
+
+        service.enable_irqs().unwrap();
+
+        // VBUS 插入/拔出、电池过温、PEK 短按/长按
+        assert_eq!(
+            bus.writes(ADDR),
+            vec![(0x40, 0x0C), (0x41, 0x02), (0x42, 0x03), (0x43, 0x00)]
+        );
+    }
+
+    #[test]
+    fn clear_irq_status_writes_only_pending_registers() {
+        let bus = RecordingI2c::new();
+        let mut axp173 = Axp173::new(bus.clone());
+
+        axp173
+            .clear_irq_status(&IrqStatus([0x0C, 0x00, 0x01, 0x00]))
+            .unwrap();
+
+        // 写 1 清除，没有中断的寄存器不写，免得清掉读完之后才来的中断
+        assert_eq!(bus.writes(ADDR), vec![(0x44, 0x0C), (0x46, 0x01)]);
+    }
+
+    #[test]
+    fn poll_once_sends_app_events() {
+        let bus = RecordingI2c::new();
+        bus.set_register(ADDR, 0x44, 0x04);
+        bus.set_register(ADDR, 0x46, 0x02);
+        let (sender, receiver) = mpsc::channel();
+        let mut service = PmicIrqService::new(Axp173::new(bus.clone()), sender);
+
+        let events = service.poll_once().unwrap();
+
+        assert_eq!(
+            events,
+            vec![PmicEvent::PowerKeyShortPress, PmicEvent::VbusUnplugged]
+        );
+        assert_eq!(bus.writes(ADDR), vec![(0x44, 0x04), (0x46, 0x02)]);
+        assert!(matches!(receiver.try_recv(), Ok(AppEvent::PowerKeyClicked)));
+        assert!(matches!(
+            receiver.try_recv(),
+            Ok(AppEvent::ChargingStateChanged(false))
+        ));
+        assert!(receiver.try_recv().is_err());
+    }
+
+    #[test]
+    fn poll_once_without_irq_does_not_write() {
+        let bus = RecordingI2c::new();
+        let (sender, receiver) = mpsc::channel();
+        let mut service = PmicIrqService::new(Axp173::new(bus.clone()), sender);
+
+        assert!(service.poll_once().unwrap().is_empty());
+        assert!(bus.writes(ADDR).is_empty());
+        assert!(receiver.try_recv().is_err());
+    }
+}
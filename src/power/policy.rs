@@ -0,0 +1,288 @@
+use std::time::{Duration, Instant};
+
+use crate::common::enums::DeviceState;
+
+/// 当前所处的省电级别
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SleepLevel {
+    Active,
+    ModemSleep,
+    LightSleep,
+    DeepSleep,
+}
+
+/// 设备从 light sleep 中被什么唤醒
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WakeSource {
+    Timer, // 定时器到期，用于从 light sleep 升级到 deep sleep
+    Gpio,  // 按键或 AXP173 IRQ
+    Other,
+}
+
+/// 策略给出的动作，由 application 负责真正执行
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PowerTransition {
+    EnterModemSleep,
+    ExitModemSleep,
+    /// 参数是 light sleep 的最长时间，到期后需要被唤醒以进入 deep sleep
+    EnterLightSleep(Option<Duration>),
+    EnterDeepSleep,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PowerPolicyConfig {
+    /// 空闲时打开 Wi-Fi modem sleep
+    pub modem_sleep_when_idle: bool,
+    /// 空闲多久后进入 light sleep，None 表示不进入
+    pub light_sleep_timeout: Option<Duration>,
+    /// 空闲多久后进入 deep sleep，None 表示不进入
+    pub deep_sleep_timeout: Option<Duration>,
+}
+
+impl Default for PowerPolicyConfig {
+    fn default() -> Self {
+        Self {
+            modem_sleep_when_idle: true,
+            light_sleep_timeout: Some(Duration::from_secs(60)),
+            deep_sleep_timeout: Some(Duration::from_secs(10 * 60)),
+        }
+    }
+}
+
+/// 纯逻辑的省电策略：只根据设备状态、最近一次活动时间和传入的 `now` 做决定，
+/// 不访问任何硬件，所以可以在主机上直接测试。
+#[derive(Debug, Clone)]
+pub struct PowerPolicy {
+    config: PowerPolicyConfig,
+    last_activity: Instant,
+    idle: bool,
+    charging: bool,
+    level: SleepLevel,
+}
+
+impl PowerPolicy {
+    pub fn new(config: PowerPolicyConfig, now: Instant) -> Self {
+        Self {
+            config,
+            last_activity: now,
+            idle: false,
+            charging: false,
+            level: SleepLevel::Active,
+        }
+    }
+
+    pub fn level(&self) -> SleepLevel {
+        self.level
+    }
+
+    /// 按键、收到服务器消息等用户活动，重新开始计算空闲时间
+    pub fn on_activity(&mut self, now: Instant) {
+        self.last_activity = now;
+    }
+
+    /// 插着 USB 时最多进入 light sleep，不进入 deep sleep
+    pub fn set_charging(&mut self, charging: bool) {
+        self.charging = charging;
+    }
+
+    /// 设备状态变化时调用，只有 Idle 才允许省电
+    pub fn on_device_state(
+        &mut self,
+        state: &DeviceState,
+        now: Instant,
+    ) -> Option<PowerTransition> {
+        self.last_activity = now;
+        self.idle = *state == DeviceState::Idle;
+
+        if self.idle {
+            if self.config.modem_sleep_when_idle && self.level == SleepLevel::Active {
+                self.level = SleepLevel::ModemSleep;
+                return Some(PowerTransition::EnterModemSleep);
+            }
+        } else if self.level != SleepLevel::Active {
+            self.level = SleepLevel::Active;
+            return Some(PowerTransition::ExitModemSleep);
+        }
+        None
+    }
+
+    /// 从 light sleep 返回后调用。被定时器唤醒不算用户活动，下一次 `evaluate` 会进入 deep sleep。
+    pub fn on_wake(&mut self, source: WakeSource, now: Instant) {
+        self.level = if self.config.modem_sleep_when_idle {
+            SleepLevel::ModemSleep
+        } else {
+            SleepLevel::Active
+        };
+
+        if source != WakeSource::Timer {
+            self.last_activity = now;
+        }
+    }
+
+    /// 周期性调用，判断是否需要进入更深的睡眠
+    pub fn evaluate(&mut self, now: Instant) -> Option<PowerTransition> {
+        if !self.idle || self.level == SleepLevel::DeepSleep {
+            return None;
+        }
+
+        let idle_time = now.saturating_duration_since(self.last_activity);
+
+        if let Some(deep_sleep_timeout) = self.config.deep_sleep_timeout {
+            if !self.charging && idle_time >= deep_sleep_timeout {
+                self.level = SleepLevel::DeepSleep;
+                return Some(PowerTransition::EnterDeepSleep);
+            }
+        }
+
+        if let Some(light_sleep_timeout) = self.config.light_sleep_timeout {
+            if self.level != SleepLevel::LightSleep && idle_time >= light_sleep_timeout {
+                self.level = SleepLevel::LightSleep;
+                let max_duration = match self.config.deep_sleep_timeout {
+                    Some(deep_sleep_timeout) if !self.charging => {
+                        Some(deep_sleep_timeout.saturating_sub(idle_time))
+                    }
+                    _ => None,
+                };
+                return Some(PowerTransition::EnterLightSleep(max_duration));
+            }
+        }
+
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SECOND: Duration = Duration::from_secs(1);
+
+    fn idle_policy(now: Instant) -> PowerPolicy {
+        let mut policy = PowerPolicy::new(PowerPolicyConfig::default(), now);
+        assert_eq!(
+            policy.on_device_state(&DeviceState::Idle, now),
+            Some(PowerTransition::EnterModemSleep)
+        );
+        policy
+    }
+
+    #[test]
+    fn idle_timeout_goes_modem_light_then_deep_sleep() {
+        let start = Instant::now();
+        let mut policy = idle_policy(start);
+        assert_eq!(policy.level(), SleepLevel::ModemSleep);
+
+        assert_eq!(policy.evaluate(start + 59 * SECOND), None);
+        assert_eq!(
+            policy.evaluate(start + 60 * SECOND),
+            Some(PowerTransition::EnterLightSleep(Some(540 * SECOND)))
+        );
+        assert_eq!(policy.level(), SleepLevel::LightSleep);
+        // 已经在 light sleep 里了，不会再进一次
+        assert_eq!(policy.evaluate(start + 120 * SECOND), None);
+
+        assert_eq!(
+            policy.evaluate(start + 600 * SECOND),
+            Some(PowerTransition::EnterDeepSleep)
+        );
+        assert_eq!(policy.level(), SleepLevel::DeepSleep);
+        assert_eq!(policy.evaluate(start + 700 * SECOND), None);
+    }
+
+    #[test]
+    fn busy_device_never_sleeps() {
+        let start = Instant::now();
+        let mut policy = PowerPolicy::new(PowerPolicyConfig::default(), start);
+        assert_eq!(policy.on_device_state(&DeviceState::Listening, start), None);
+        assert_eq!(policy.evaluate(start + 3600 * SECOND), None);
+        assert_eq!(policy.level(), SleepLevel::Active);
+    }
+
+    #[test]
+    fn leaving_idle_exits_modem_sleep() {
+        let start = Instant::now();
+        let mut policy = idle_policy(start);
+        assert_eq!(
+            policy.on_device_state(&DeviceState::Speaking, start + SECOND),
+            Some(PowerTransition::ExitModemSleep)
+        );
+        assert_eq!(policy.level(), SleepLevel::Active);
+    }
+
+    #[test]
+    fn activity_restarts_idle_timer() {
+        let start = Instant::now();
+        let mut policy = idle_policy(start);
+        policy.on_activity(start + 50 * SECOND);
+        assert_eq!(policy.evaluate(start + 100 * SECOND), None);
+        assert!(policy.evaluate(start + 110 * SECOND).is_some());
+    }
+
+    #[test]
+    fn charging_blocks_deep_sleep() {
+        let start = Instant::now();
+        let mut policy = idle_policy(start);
+        policy.set_charging(true);
+
+        // 充电时 light sleep 没有时间上限，也不会进入 deep sleep
+        assert_eq!(
+            policy.evaluate(start + 60 * SECOND),
+            Some(PowerTransition::EnterLightSleep(None))
+        );
+        policy.on_wake(WakeSource::Timer, start + 700 * SECOND);
+        assert_eq!(
+            policy.evaluate(start + 700 * SECOND),
+            Some(PowerTransition::EnterLightSleep(None))
+        );
+
+        policy.set_charging(false);
+        assert_eq!(
+            policy.evaluate(start + 700 * SECOND),
+            Some(PowerTransition::EnterDeepSleep)
+        );
+    }
+
+    #[test]
+    fn timer_wake_keeps_idle_time() {
+        let start = Instant::now();
+        let mut policy = idle_policy(start);
+        assert!(policy.evaluate(start + 60 * SECOND).is_some());
+
+        policy.on_wake(WakeSource::Timer, start + 600 * SECOND);
+        assert_eq!(policy.level(), SleepLevel::ModemSleep);
+        assert_eq!(
+            policy.evaluate(start + 600 * SECOND),
+            Some(PowerTransition::EnterDeepSleep)
+        );
+    }
+
+    #[test]
+    fn gpio_wake_counts_as_activity() {
+        let start = Instant::now();
+        let mut policy = idle_policy(start);
+        assert!(policy.evaluate(start + 60 * SECOND).is_some());
+
+        policy.on_wake(WakeSource::Gpio, start + 600 * SECOND);
+        assert_eq!(policy.level(), SleepLevel::ModemSleep);
+        assert_eq!(policy.evaluate(start + 600 * SECOND), None);
+        assert_eq!(
+            policy.evaluate(start + 660 * SECOND),
+            Some(PowerTransition::EnterLightSleep(Some(540 * SECOND)))
+        );
+    }
+
+    #[test]
+    fn wake_without_modem_sleep_returns_to_active() {
+        let start = Instant::now();
+        let config = PowerPolicyConfig {
+            modem_sleep_when_idle: false,
+            ..Default::default()
+        };
+        let mut policy = PowerPolicy::new(config, start);
+        assert_eq!(policy.on_device_state(&DeviceState::Idle, start), None);
+        assert!(policy.evaluate(start + 60 * SECOND).is_some());
+
+        policy.on_wake(WakeSource::Other, start + 61 * SECOND);
+        assert_eq!(policy.level(), SleepLevel::Active);
+    }
+}
@@ -0,0 +1,107 @@
+use std::{
+    sync::mpsc::Sender,
+    thread,
+    time::{Duration, Instant},
+};
+
+use anyhow::Result;
+use log::{error, info};
+
+use crate::{
+    common::{enums::DeviceState, event::AppEvent},
+    power::policy::{PowerPolicy, PowerPolicyConfig, PowerTransition, WakeSource},
+    setting::settings::Settings,
+};
+
+const POWER_TICK_INTERVAL: Duration = Duration::from_secs(1);
+
+/// 包装 `PowerPolicy`，负责提供当前时间、读取配置和定时发送 `AppEvent::PowerTick`。
+/// 真正的睡眠动作由 application 调用 board 完成。
+pub struct PowerManager {
+    policy: PowerPolicy,
+}
+
+impl PowerManager {
+    pub fn new(config: PowerPolicyConfig) -> Self {
+        Self {
+            policy: PowerPolicy::new(config, Instant::now()),
+        }
+    }
+
+    /// 从设置的 power 分组读取超时时间（秒），0 表示关闭对应的睡眠
+    pub fn load_config() -> PowerPolicyConfig {
+        match Settings::load() {
+            Ok(settings) => PowerPolicyConfig {
+                modem_sleep_when_idle: settings.power.modem_sleep,
+                light_sleep_timeout: (settings.power.light_sleep_s > 0)
+                    .then(|| Duration::from_secs(settings.power.light_sleep_s as u64)),
+                deep_sleep_timeout: (settings.power.deep_sleep_s > 0)
+                    .then(|| Duration::from_secs(settings.power.deep_sleep_s as u64)),
+            },
+            Err(e) => {
+                error!("Failed to get power setting: {:?}", e);
+                PowerPolicyConfig::default()
+            }
+        }
+    }
+
+    /// 启动定时器线程，每秒发送一次 `AppEvent::PowerTick`
+    pub fn start(&self, sender: Sender<AppEvent>) -> Result<()> {
+        thread::Builder::new()
+            .name("power_tick".into())
+            .stack_size(2 * 1024)
+            .spawn(move || loop {
+                thread::sleep(POWER_TICK_INTERVAL);
+                if sender.send(AppEvent::PowerTick).is_err() {
+                    break;
+                }
+            })?;
+        Ok(())
+    }
+
+    pub fn on_activity(&mut self) {
+        self.policy.on_activity(Instant::now());
+    }
+
+    pub fn set_charging(&mut self, charging: bool) {
+        self.policy.set_charging(charging);
+    }
+
+    pub fn on_device_state(&mut self, state: &DeviceState) -> Option<PowerTransition> {
+        self.policy.on_device_state(state, Instant::now())
+    }
+
+    pub fn on_wake(&mut self, source: WakeSource) {
+        info!("Wake up from light sleep, source: {:?}", source);
+        self.policy.on_wake(source, Instant::now());
+    }
+
+    pub fn tick(&mut self) -> Option<PowerTransition> {
+        self.policy.evaluate(Instant::now())
+    }
+}
+
+/// 打印上一次的唤醒原因，从 deep sleep 唤醒实际上就是重新启动
+#[cfg(target_os = "espidf")]
+pub fn log_wakeup_cause() {
+    let cause = unsafe { esp_idf_sys::esp_sleep_get_wakeup_cause() };
+    match cause {
+        esp_idf_sys::esp_sleep_source_t_ESP_SLEEP_WAKEUP_UNDEFINED => {
+            info!("Power on, not a wakeup from deep sleep");
+        }
+        esp_idf_sys::esp_sleep_source_t_ESP_SLEEP_WAKEUP_EXT1 => {
+            info!("Wakeup from deep sleep by button or PMIC IRQ");
+        }
+        esp_idf_sys::esp_sleep_source_t_ESP_SLEEP_WAKEUP_TIMER => {
+            info!("Wakeup from deep sleep by timer");
+        }
+        _ => {
+            info!("Wakeup from deep sleep, cause: {}", cause);
+        }
+    }
+}
+
+#[cfg(not(target_os = "espidf"))]
+pub fn log_wakeup_cause() {
+    info!("Power on, not a wakeup from deep sleep");
+}
@@ -1,2 +1,4 @@
 pub mod protocol;
+#[cfg(feature = "host-sim")]
+pub mod scripted_protocol;
 pub mod websocket;
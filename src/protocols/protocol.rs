@@ -32,4 +32,9 @@ pub trait Protocol {
     fn send_start_linstening(&mut self, listening_mode: ListeningMode) -> Result<(), Error>;
 
     fn send_stop_listening(&mut self) -> Result<(), Error>;
+
+    fn send_wake_word_detected(&mut self, wake_word: &str) -> Result<(), Error>;
+
+    /// 发送 MCP 消息，payload 是 JSON-RPC 2.0 格式
+    fn send_mcp_message(&mut self, payload: &serde_json::Value) -> Result<(), Error>;
 }
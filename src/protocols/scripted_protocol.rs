@@ -0,0 +1,380 @@
+//! host-sim 用的协议替身：不连服务器，按脚本回放服务器的消息。
+//!
+//! 脚本是 JSON Lines，每行一条规则。设备发出的文本消息的 `type`（有 `state` 时是 `type.state`）
+//! 和规则的 `on` 相同时，按顺序执行 `send` 里的动作：
+//!
+//! ```text
+//! {"on": "hello", "send": [{"text": {"type": "hello", "transport": "websocket"}}]}
+//! {"on": "listen.start", "send": [{"delay_ms": 3000}, {"text": {"type": "stt", "text": "你好"}},
+//!     {"text": {"type": "tts", "state": "start"}}, {"audio": "sim/reply.wav"},
+//!     {"text": {"type": "tts", "state": "stop"}}]}
+//! ```
+//!
+//! 动作有 `text`（服务器发来的文本）、`audio`（16kHz 16 bit 的 WAV，编码成 opus 按 60ms 一包发出）、
+//! `delay_ms` 和 `"close"`（服务器断开）。`"once": true` 的规则只触发一次。
+//!
+//! 设置了环境变量 `XIAOXIN_SIM_TRANSCRIPT` 时，设备发出的文本消息每条一行写进这个文件，
+//! 可以在 Application 外面检查设备的行为。
+
+use std::{
+    fs::File,
+    io::Write,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        mpsc::Sender,
+        Arc, Mutex,
+    },
+    thread,
+    time::Duration,
+};
+
+use anyhow::{anyhow, Error, Result};
+use log::{error, info};
+use serde::Deserialize;
+
+use crate::{
+    audio::{
+        codec::{
+            opus::encoder::OpusAudioEncoder, types::AudioStreamPacket, AUDIO_INPUT_SAMPLE_RATE,
+            OPUS_FRAME_DURATION_MS,
+        },
+        wav::read_wav,
+    },
+    common::{
+        enums::{AbortReason, ListeningMode},
+        event::AppEvent,
+    },
+    protocols::{protocol::Protocol, websocket::message::ClientHelloMessage},
+};
+
+const DEFAULT_SCRIPT_PATH: &str = "sim/script.jsonl";
+
+#[derive(Debug, Deserialize)]
+struct ScriptRule {
+    on: String,
+    #[serde(default)]
+    once: bool,
+    send: Vec<ScriptAction>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum ScriptAction {
+    Text(serde_json::Value),
+    Audio(String),
+    DelayMs(u64),
+    Close,
+}
+
+pub struct ScriptedProtocol {
+    device_id: String,
+    sender: Sender<AppEvent>,
+    /// 规则和是否已经触发过
+    rules: Arc<Mutex<Vec<(ScriptRule, bool)>>>,
+    channel_opened: bool,
+    is_connected: bool,
+    /// 设备发出的文本消息，测试时用来检查设备的行为
+    sent_messages: Arc<Mutex<Vec<String>>>,
+    audio_packets_sent: Arc<AtomicUsize>,
+    transcript: Option<File>,
+}
+
+impl ScriptedProtocol {
+    /// 脚本路径由环境变量 `XIAOXIN_SIM_SCRIPT` 指定，默认是 `sim/script.jsonl`
+    pub fn new(device_id: &str, sender: Sender<AppEvent>) -> Self {
+        let path =
+            std::env::var("XIAOXIN_SIM_SCRIPT").unwrap_or_else(|_| DEFAULT_SCRIPT_PATH.to_string());
+        let rules = match load_script(&path) {
+            Ok(rules) => {
+                info!("Loaded {} rules from {}", rules.len(), path);
+                rules
+            }
+            Err(e) => {
+                error!("Failed to load server script {}: {:?}", path, e);
+                Vec::new()
+            }
+        };
+        let transcript = std::env::var("XIAOXIN_SIM_TRANSCRIPT")
+            .ok()
+            .and_then(|path| match File::create(&path) {
+                Ok(file) => Some(file),
+                Err(e) => {
+                    error!("Failed to create transcript {}: {:?}", path, e);
+                    None
+                }
+            });
+        Self {
+            device_id: device_id.to_string(),
+            sender,
+            rules: Arc::new(Mutex::new(
+                rules.into_iter().map(|rule| (rule, false)).collect(),
+            )),
+            channel_opened: false,
+            is_connected: false,
+            sent_messages: Arc::new(Mutex::new(Vec::new())),
+            audio_packets_sent: Arc::new(AtomicUsize::new(0)),
+            transcript,
+        }
+    }
+
+    pub fn sent_messages(&self) -> Arc<Mutex<Vec<String>>> {
+        self.sent_messages.clone()
+    }
+
+    pub fn audio_packets_sent(&self) -> usize {
+        self.audio_packets_sent.load(Ordering::Relaxed)
+    }
+
+    pub fn is_connected(&self) -> bool {
+        self.channel_opened && self.is_connected
+    }
+
+    pub fn send_hello_message(&mut self) -> Result<()> {
+        let message = ClientHelloMessage::new(None)?;
+        self.send_text(&message)
+    }
+
+    pub fn send_diagnostics(&mut self, report: &serde_json::Value) -> Result<()> {
+        let message = serde_json::json!({
+            "session_id": self.device_id,
+            "type": "diag",
+            "payload": report,
+        });
+        self.send_text(&message.to_string())
+    }
+
+    pub fn send_log_lines(&mut self, lines: &[String]) -> Result<()> {
+        let message = serde_json::json!({
+            "session_id": self.device_id,
+            "type": "log",
+            "lines": lines,
+        });
+        self.send_text(&message.to_string())
+    }
+
+    /// 找到匹配的规则，在单独的线程里执行，和真服务器一样异步地回消息
+    fn run_rules(&mut self, text: &str) {
+        let Ok(message) = serde_json::from_str::<serde_json::Value>(text) else {
+            return;
+        };
+        let Some(message_type) = message["type"].as_str() else {
+            return;
+        };
+        let key = match message["state"].as_str() {
+            Some(state) => format!("{}.{}", message_type, state),
+            None => message_type.to_string(),
+        };
+
+        let mut actions = Vec::new();
+        for (rule, fired) in self.rules.lock().unwrap().iter_mut() {
+            if (rule.on == key || rule.on == message_type) && !(rule.once && *fired) {
+                *fired = true;
+                actions.extend(rule.send.iter().cloned());
+            }
+        }
+        if actions.is_empty() {
+            return;
+        }
+
+        let sender = self.sender.clone();
+        let result = thread::Builder::new()
+            .name("sim_server".to_string())
+            .spawn(move || {
+                for action in actions {
+                    if let Err(e) = run_action(&sender, action) {
+                        error!("Failed to run script action: {:?}", e);
+                    }
+                }
+            });
+        if let Err(e) = result {
+            error!("Failed to spawn sim_server thread: {:?}", e);
+        }
+    }
+}
+
+fn load_script(path: &str) -> Result<Vec<ScriptRule>> {
+    let content = std::fs::read_to_string(path)?;
+    content
+        .lines()
+        .filter(|line| !line.trim().is_empty() && !line.trim_start().starts_with("//"))
+        .map(|line| {
+            serde_json::from_str(line).map_err(|e| anyhow!("bad script line {}: {}", line, e))
+        })
+        .collect()
+}
+
+fn run_action(sender: &Sender<AppEvent>, action: ScriptAction) -> Result<()> {
+    match action {
+        ScriptAction::Text(message) => {
+            sender.send(AppEvent::WebsocketTextMessageReceived(message.to_string()))?;
+        }
+        ScriptAction::Audio(path) => {
+            for payload in encode_wav(&path)? {
+                sender.send(AppEvent::AudioPacketReceived(AudioStreamPacket {
+                    sample_rate: AUDIO_INPUT_SAMPLE_RATE as i32,
+                    frame_duration: OPUS_FRAME_DURATION_MS as i32,
+                    timestamp: 0,
+                    payload,
+                }))?;
+                thread::sleep(Duration::from_millis(OPUS_FRAME_DURATION_MS as u64));
+            }
+        }
+        ScriptAction::DelayMs(ms) => thread::sleep(Duration::from_millis(ms)),
+        ScriptAction::Close => sender.send(AppEvent::WebSocketClosed)?,
+    }
+    Ok(())
+}
+
+/// 把 WAV 编码成 opus 包，声道数和应用里的解码器一致（双声道）
+fn encode_wav(path: &str) -> Result<Vec<Vec<u8>>> {
+    let wav = read_wav(path)?;
+    if wav.sample_rate != AUDIO_INPUT_SAMPLE_RATE {
+        return Err(anyhow!(
+            "{} must be {} Hz, got {} Hz",
+            path,
+            AUDIO_INPUT_SAMPLE_RATE,
+            wav.sample_rate
+        ));
+    }
+    let pcm: Vec<i16> = match wav.channels {
+        1 => wav.samples.iter().flat_map(|&s| [s, s]).collect(),
+        _ => wav.samples,
+    };
+
+    let mut encoder = OpusAudioEncoder::new(
+        AUDIO_INPUT_SAMPLE_RATE as i32,
+        2,
+        OPUS_FRAME_DURATION_MS as i32,
+    )?;
+    let mut packets = Vec::new();
+    let mut on_encoded = |packet: Vec<u8>| packets.push(packet);
+    encoder.encode(pcm, &mut on_encoded)?;
+    encoder.flush(&mut on_encoded)?;
+    Ok(packets)
+}
+
+impl Protocol for ScriptedProtocol {
+    fn send_text(&mut self, text: &str) -> Result<()> {
+        if !self.channel_opened {
+            info!("ScriptedProtocol: channel not opened, cannot send.");
+            return Ok(());
+        }
+        info!("ScriptedProtocol: -> {}", text);
+        self.sent_messages.lock().unwrap().push(text.to_string());
+        if let Some(transcript) = &mut self.transcript {
+            if let Err(e) = writeln!(transcript, "{}", text) {
+                error!("Failed to write transcript: {:?}", e);
+            }
+        }
+        self.run_rules(text);
+        Ok(())
+    }
+
+    fn send_audio(&mut self, _audio: &AudioStreamPacket) -> Result<()> {
+        if self.channel_opened {
+            self.audio_packets_sent.fetch_add(1, Ordering::Relaxed);
+        }
+        Ok(())
+    }
+
+    fn open_audio_channel(&mut self) -> Result<bool, Error> {
+        self.channel_opened = true;
+        self.is_connected = false;
+        self.sender.send(AppEvent::WebSocketConnected)?;
+        Ok(true)
+    }
+
+    fn close_audio_channel(&mut self) -> Result<(), Error> {
+        self.channel_opened = false;
+        self.is_connected = false;
+        Ok(())
+    }
+
+    // 服务器的消息直接以 AppEvent 发给应用，和 WebSocketProtocol 一样不走这两个回调
+    fn on_incoming_text<F>(&mut self, _handler: F) -> Result<(), Error>
+    where
+        F: FnMut(&str) -> Result<(), Error> + Send + 'static,
+    {
+        Ok(())
+    }
+
+    fn on_incoming_audio<F>(&mut self, _handler: F) -> Result<(), Error>
+    where
+        F: FnMut(&AudioStreamPacket) -> Result<(), Error> + Send + 'static,
+    {
+        Ok(())
+    }
+
+    fn on_network_error<F>(&mut self, _handler: F)
+    where
+        F: FnMut(&str) -> Result<()> + Send + 'static,
+    {
+    }
+
+    fn is_timeout(&self) -> bool {
+        false
+    }
+
+    fn set_connected(&mut self, connected: bool) {
+        self.is_connected = connected;
+    }
+
+    fn is_audio_channel_opened(&self) -> bool {
+        self.is_connected
+    }
+
+    fn send_abort_speaking(&mut self, reason: AbortReason) -> Result<(), Error> {
+        let message = match reason {
+            AbortReason::WakeWordDetected => serde_json::json!({
+                "session_id": self.device_id,
+                "type": "abort",
+                "reason": "wake_word_detected",
+            }),
+            _ => serde_json::json!({"session_id": self.device_id, "type": "abort"}),
+        };
+        self.send_text(&message.to_string())
+    }
+
+    fn send_start_linstening(&mut self, listening_mode: ListeningMode) -> Result<(), Error> {
+        let mode = match listening_mode {
+            ListeningMode::AutoStop => "auto",
+            ListeningMode::Realtime => "realtime",
+            ListeningMode::Manual => "manual",
+        };
+        let message = serde_json::json!({
+            "session_id": self.device_id,
+            "type": "listen",
+            "state": "start",
+            "mode": mode,
+        });
+        self.send_text(&message.to_string())
+    }
+
+    fn send_stop_listening(&mut self) -> Result<(), Error> {
+        let message = serde_json::json!({
+            "session_id": self.device_id,
+            "type": "listen",
+            "state": "stop",
+        });
+        self.send_text(&message.to_string())
+    }
+
+    fn send_wake_word_detected(&mut self, wake_word: &str) -> Result<(), Error> {
+        let message = serde_json::json!({
+            "session_id": self.device_id,
+            "type": "listen",
+            "state": "detect",
+            "text": wake_word,
+        });
+        self.send_text(&message.to_string())
+    }
+
+    fn send_mcp_message(&mut self, payload: &serde_json::Value) -> Result<(), Error> {
+        let message = serde_json::json!({
+            "session_id": self.device_id,
+            "type": "mcp",
+            "payload": payload,
+        });
+        self.send_text(&message.to_string())
+    }
+}
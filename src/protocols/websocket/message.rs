@@ -1,10 +1,13 @@
 use anyhow::Result;
 use serde_json::Value;
 
+use crate::logging::crash_report::CrashReport;
+
 pub struct ClientHelloMessage;
 
 impl ClientHelloMessage {
-    pub fn new() -> Result<String> {
+    /// 上一次运行崩溃了的话，把崩溃信息放在 `crash_report` 字段里
+    pub fn new(crash_report: Option<&CrashReport>) -> Result<String> {
         // let audio_format = "pcm";
         let audio_format = "opus";
 
@@ -25,7 +28,10 @@ impl ClientHelloMessage {
         }}"#,
             audio_format
         );
-        let hello: Value = serde_json::from_str(&body)?;
+        let mut hello: Value = serde_json::from_str(&body)?;
+        if let Some(crash_report) = crash_report {
+            hello["crash_report"] = serde_json::to_value(crash_report)?;
+        }
         // hello["feature"] = json!({ "an": "object" });
         println!("{:?}", hello);
         println!("{:?}", serde_json::to_string(&hello));
@@ -1,2 +1,3 @@
 pub mod message;
+#[cfg(target_os = "espidf")]
 pub mod ws_protocol;
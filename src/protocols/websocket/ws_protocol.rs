@@ -15,7 +15,11 @@ use crate::audio::codec::types::AudioStreamPacket;
 use crate::common::enums::{AbortReason, ListeningMode};
 use crate::common::event::{AppEvent, WsEvent};
 use crate::protocols::protocol::Protocol;
+use crate::logging::crash_report;
 use crate::protocols::websocket::message::ClientHelloMessage;
+use crate::setting::settings::Settings;
+
+const DEFAULT_WS_URL: &str = "ws://192.168.1.40:8000/xiaozhi/v1/";
 
 pub struct WebSocketProtocol {
     client: Option<Box<EspWebSocketClient<'static>>>,
@@ -75,7 +79,8 @@ impl WebSocketProtocol {
 
     pub fn send_hello_message(&mut self) -> Result<()> {
         info!("try to send client  hello message to server.");
-        let message = ClientHelloMessage::new()?;
+        let crash_report = crash_report::take();
+        let message = ClientHelloMessage::new(crash_report.as_ref())?;
         if let Some(client) = &mut self.client {
             match client.send(FrameType::Text(false), message.as_bytes()) {
                 Ok(_) => {}
@@ -87,6 +92,31 @@ impl WebSocketProtocol {
         Ok(())
     }
 
+    /// 把诊断信息推送给服务器，服务器不认识这个类型时会忽略
+    pub fn send_diagnostics(&mut self, report: &serde_json::Value) -> Result<()> {
+        let message = serde_json::json!({
+            "session_id": self.device_id,
+            "type": "diag",
+            "payload": report,
+        });
+        self.send_text(&message.to_string())
+    }
+
+    /// 推送日志。不能用 send_text，它会把整条消息打到日志里，下一次又被推送出去
+    pub fn send_log_lines(&mut self, lines: &[String]) -> Result<()> {
+        let message = serde_json::json!({
+            "session_id": self.device_id,
+            "type": "log",
+            "lines": lines,
+        });
+        if let Some(client) = &mut self.client {
+            if client.is_connected() {
+                client.send(FrameType::Text(false), message.to_string().as_bytes())?;
+            }
+        }
+        Ok(())
+    }
+
     // 当收到服务器端的 hello message 时，才认为连接成功。
     pub fn on_server_hello_msg(&mut self) {
         self.is_connected = true;
@@ -170,7 +200,11 @@ impl Protocol for WebSocketProtocol {
 
         let timeout = Duration::from_secs(10);
 
-        let ws_url = "ws://192.168.1.40:8000/xiaozhi/v1/";
+        // 服务器地址可以通过配网写入 NVS，没有配置时使用默认地址
+        let ws_url = Settings::load()
+            .ok()
+            .and_then(|settings| settings.server.ws_url)
+            .unwrap_or_else(|| DEFAULT_WS_URL.to_string());
         // let ws_url = "ws://192.168.1.121:8000/xiaozhi/v1/";
 
         let config = EspWebSocketClientConfig {
@@ -192,7 +226,7 @@ impl Protocol for WebSocketProtocol {
         let server_hello_received = self.server_hello_received.clone();
 
         self.client = Some(Box::new(EspWebSocketClient::new(
-            ws_url,
+            ws_url.as_str(),
             &config,
             timeout,
             move |event| {
@@ -429,6 +463,29 @@ impl Protocol for WebSocketProtocol {
         Ok(())
     }
 
+    fn send_wake_word_detected(&mut self, wake_word: &str) -> Result<(), Error> {
+        // std::string json = "{\"session_id\":\"" + session_id_ +
+        //                   "\",\"type\":\"listen\",\"state\":\"detect\",\"text\":\"" + wake_word + "\"}";
+        let message = serde_json::json!({
+            "session_id": self.device_id,
+            "type": "listen",
+            "state": "detect",
+            "text": wake_word,
+        });
+        self.send_text(&message.to_string())?;
+        Ok(())
+    }
+
+    fn send_mcp_message(&mut self, payload: &serde_json::Value) -> Result<(), Error> {
+        let message = serde_json::json!({
+            "session_id": self.device_id,
+            "type": "mcp",
+            "payload": payload,
+        });
+        self.send_text(&message.to_string())?;
+        Ok(())
+    }
+
     fn on_network_error<F>(&mut self, handler: F)
     where
         F: FnMut(&str) -> Result<()> + Send + 'static,
@@ -0,0 +1,514 @@
+//! 本地的闹钟、计时器和提醒，服务器断开时也能响。
+//!
+//! - `Scheduler` 是纯逻辑：添加、取消、找出到期的项目，时间从 `Clock` 取，可以在主机上换成假的时钟
+//! - 所有项目以 JSON 保存在 NVS 的 `scheduler` namespace 里，重启后继续有效
+//! - `service` 负责每秒检查一次、响铃，并注册 MCP 工具
+
+use std::time::Duration;
+
+use anyhow::Result;
+use log::{error, warn};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::{clock, setting::storage::SettingsStorage};
+
+pub mod service;
+
+const NAMESPACE: &str = "scheduler";
+const KEY: &str = "alarms";
+const SECONDS_PER_DAY: i64 = 24 * 60 * 60;
+/// 最多保存多少个项目，NVS 的空间有限
+pub const MAX_ALARMS: usize = 16;
+/// 计时器最长一天
+pub const MAX_TIMER: Duration = Duration::from_secs(SECONDS_PER_DAY as u64);
+/// 关机期间错过的项目，晚了这么久以内开机还会响，再晚就直接丢掉
+const MISSED_GRACE_SECS: i64 = 10 * 60;
+
+/// 时间来源。设备上用 `SystemClock`，测试时换成可以手动拨动的时钟
+pub trait Clock: Send {
+    /// 当前的 Unix 时间（秒），时间还没同步时返回 None
+    fn now(&self) -> Option<i64>;
+    /// 本地时间比 UTC 快多少秒，用来把 `HH:MM` 换算成 Unix 时间
+    fn utc_offset(&self) -> i64;
+}
+
+/// 系统时间，要等 SNTP 或服务器时间同步后才可用
+#[derive(Debug, Default, Clone, Copy)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> Option<i64> {
+        clock::now_secs()
+    }
+
+    fn utc_offset(&self) -> i64 {
+        clock::utc_offset().unwrap_or(0)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AlarmKind {
+    /// 倒计时，例如“10 分钟后叫我”
+    Timer,
+    /// 到点响的闹钟
+    Alarm,
+    /// 到点提醒一件事，`label` 是要提醒的内容
+    Reminder,
+}
+
+/// 什么时候响
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum When {
+    /// 从现在起多久以后
+    After(Duration),
+    /// 本地时间的几点几分，今天已经过了就是明天
+    At { hour: u8, minute: u8 },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Alarm {
+    pub id: u32,
+    pub kind: AlarmKind,
+    #[serde(default)]
+    pub label: String,
+    /// Unix 时间（秒）
+    pub fire_at: i64,
+    /// 每天同一时间再响，响过之后 `fire_at` 往后推一天
+    #[serde(default)]
+    pub repeat_daily: bool,
+}
+
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum SchedulerError {
+    #[error("Time is not synced yet")]
+    TimeNotSynced,
+    #[error("Duration must be between 1 second and 24 hours")]
+    InvalidDuration,
+    #[error("Invalid time {0:02}:{1:02}")]
+    InvalidTime(u8, u8),
+    #[error("Too many alarms, at most {MAX_ALARMS}")]
+    TooManyAlarms,
+}
+
+/// 保存在 NVS 里的格式
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct StoredAlarms {
+    next_id: u32,
+    alarms: Vec<Alarm>,
+}
+
+pub struct Scheduler<C> {
+    clock: C,
+    next_id: u32,
+    /// 按 `fire_at` 排序
+    alarms: Vec<Alarm>,
+}
+
+impl<C: Clock> Scheduler<C> {
+    pub fn new(clock: C) -> Self {
+        Self {
+            clock,
+            next_id: 1,
+            alarms: Vec::new(),
+        }
+    }
+
+    /// 读取保存的项目，数据坏了就从空的开始
+    pub fn load_from(clock: C, storage: &dyn SettingsStorage) -> Self {
+        let mut scheduler = Self::new(clock);
+        match storage.get_blob(NAMESPACE, KEY) {
+            Ok(Some(value)) => match serde_json::from_slice::<StoredAlarms>(&value) {
+                Ok(stored) => {
+                    scheduler.next_id = stored.next_id.max(1);
+                    scheduler.alarms = stored.alarms;
+                    scheduler.sort();
+                }
+                Err(e) => error!("Invalid saved alarms, ignore them: {:?}", e),
+            },
+            Ok(None) => {}
+            Err(e) => error!("Failed to load alarms: {:?}", e),
+        }
+        scheduler
+    }
+
+    pub fn save_to(&self, storage: &mut dyn SettingsStorage) -> Result<()> {
+        let stored = StoredAlarms {
+            next_id: self.next_id,
+            alarms: self.alarms.clone(),
+        };
+        storage.set_blob(NAMESPACE, KEY, &serde_json::to_vec(&stored)?)
+    }
+
+    pub fn clock(&self) -> &C {
+        &self.clock
+    }
+
+    pub fn alarms(&self) -> &[Alarm] {
+        &self.alarms
+    }
+
+    pub fn add(
+        &mut self,
+        kind: AlarmKind,
+        when: When,
+        repeat_daily: bool,
+        label: &str,
+    ) -> Result<Alarm, SchedulerError> {
+        let now = self.clock.now().ok_or(SchedulerError::TimeNotSynced)?;
+        if self.alarms.len() >= MAX_ALARMS {
+            return Err(SchedulerError::TooManyAlarms);
+        }
+
+        let fire_at = match when {
+            When::After(duration) => {
+                if duration.as_secs() == 0 || duration > MAX_TIMER {
+                    return Err(SchedulerError::InvalidDuration);
+                }
+                now + duration.as_secs() as i64
+            }
+            When::At { hour, minute } => {
+                if hour > 23 || minute > 59 {
+                    return Err(SchedulerError::InvalidTime(hour, minute));
+                }
+                next_local_time(now, self.clock.utc_offset(), hour, minute)
+            }
+        };
+
+        let alarm = Alarm {
+            id: self.next_id,
+            kind,
+            label: label.trim().to_string(),
+            fire_at,
+            repeat_daily,
+        };
+        self.next_id = self.next_id.wrapping_add(1).max(1);
+        self.alarms.push(alarm.clone());
+        self.sort();
+        Ok(alarm)
+    }
+
+    /// 取消一个项目，返回被取消的项目
+    pub fn cancel(&mut self, id: u32) -> Option<Alarm> {
+        let index = self.alarms.iter().position(|alarm| alarm.id == id)?;
+        Some(self.alarms.remove(index))
+    }
+
+    /// 取出所有到期的项目。只响一次的从列表里删掉，每天重复的推到下一次。
+    /// 关机期间错过太久的不再返回
+    pub fn take_due(&mut self) -> Vec<Alarm> {
+        let Some(now) = self.clock.now() else {
+            return Vec::new();
+        };
+
+        let mut due = Vec::new();
+        self.alarms.retain_mut(|alarm| {
+            if alarm.fire_at > now {
+                return true;
+            }
+            if now - alarm.fire_at <= MISSED_GRACE_SECS {
+                due.push(alarm.clone());
+            } else {
+                warn!("Missed alarm {} at {}", alarm.id, alarm.fire_at);
+            }
+            if !alarm.repeat_daily {
+                return false;
+            }
+            while alarm.fire_at <= now {
+                alarm.fire_at += SECONDS_PER_DAY;
+            }
+            true
+        });
+        self.sort();
+        due
+    }
+
+    /// 离下一个项目还有多久，用来限制睡眠的时间
+    pub fn next_due_in(&self) -> Option<Duration> {
+        let now = self.clock.now()?;
+        let alarm = self.alarms.first()?;
+        Some(Duration::from_secs((alarm.fire_at - now).max(0) as u64))
+    }
+
+    fn sort(&mut self) {
+        self.alarms.sort_by_key(|alarm| (alarm.fire_at, alarm.id));
+    }
+}
+
+/// 删除保存的所有项目，恢复出厂设置时调用
+pub fn clear(storage: &mut dyn SettingsStorage) -> Result<()> {
+    storage.remove(NAMESPACE, KEY)?;
+    Ok(())
+}
+
+/// 下一个本地时间 `hour:minute` 对应的 Unix 时间，正好是现在也算下一天。
+/// 只按当前的 UTC 偏移换算，不考虑中间的夏令时切换
+fn next_local_time(now: i64, utc_offset: i64, hour: u8, minute: u8) -> i64 {
+    let local_now = now + utc_offset;
+    let today = local_now - local_now.rem_euclid(SECONDS_PER_DAY);
+    let mut local_fire_at = today + hour as i64 * 3600 + minute as i64 * 60;
+    if local_fire_at <= local_now {
+        local_fire_at += SECONDS_PER_DAY;
+    }
+    local_fire_at - utc_offset
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::setting::storage::{MemoryStorage, StoredValue};
+
+    /// 2024-01-01 00:00:00 UTC，东八区是早上 8 点
+    const BASE: i64 = 1_704_067_200;
+    const HOUR: i64 = 3600;
+    const CST: i64 = 8 * HOUR;
+
+    /// 测试里直接改 `now` 来拨动时间
+    struct FakeClock {
+        now: Option<i64>,
+        utc_offset: i64,
+    }
+
+    impl Clock for FakeClock {
+        fn now(&self) -> Option<i64> {
+            self.now
+        }
+
+        fn utc_offset(&self) -> i64 {
+            self.utc_offset
+        }
+    }
+
+    fn scheduler(utc_offset: i64) -> Scheduler<FakeClock> {
+        Scheduler::new(FakeClock {
+            now: Some(BASE),
+            utc_offset,
+        })
+    }
+
+    fn at(hour: u8, minute: u8) -> When {
+        When::At { hour, minute }
+    }
+
+    fn after_secs(secs: u64) -> When {
+        When::After(Duration::from_secs(secs))
+    }
+
+    #[test]
+    fn at_later_today_fires_today() {
+        let mut scheduler = scheduler(CST);
+        let alarm = scheduler
+            .add(AlarmKind::Alarm, at(9, 30), false, "")
+            .unwrap();
+        assert_eq!(alarm.fire_at, BASE + HOUR + 30 * 60);
+    }
+
+    #[test]
+    fn at_already_passed_rolls_over_to_tomorrow() {
+        let mut scheduler = scheduler(CST);
+        let earlier = scheduler
+            .add(AlarmKind::Alarm, at(7, 0), false, "")
+            .unwrap();
+        assert_eq!(earlier.fire_at, BASE + 23 * HOUR);
+        // 正好是现在也算明天
+        let now = scheduler
+            .add(AlarmKind::Alarm, at(8, 0), false, "")
+            .unwrap();
+        assert_eq!(now.fire_at, BASE + 24 * HOUR);
+    }
+
+    #[test]
+    fn at_uses_utc_offset() {
+        let mut utc = scheduler(0);
+        let alarm = utc.add(AlarmKind::Alarm, at(9, 30), false, "").unwrap();
+        assert_eq!(alarm.fire_at, BASE + 9 * HOUR + 30 * 60);
+
+        // UTC-5 的本地时间还是 12 月 31 日晚上 7 点，9:30 是第二天的 UTC 14:30
+        let mut est = scheduler(-5 * HOUR);
+        let alarm = est.add(AlarmKind::Alarm, at(9, 30), false, "").unwrap();
+        assert_eq!(alarm.fire_at, BASE + 14 * HOUR + 30 * 60);
+    }
+
+    #[test]
+    fn add_rejects_invalid_input() {
+        let mut scheduler = scheduler(CST);
+        assert_eq!(
+            scheduler.add(AlarmKind::Timer, after_secs(0), false, ""),
+            Err(SchedulerError::InvalidDuration)
+        );
+        assert_eq!(
+            scheduler.add(AlarmKind::Timer, When::After(MAX_TIMER * 2), false, ""),
+            Err(SchedulerError::InvalidDuration)
+        );
+        assert_eq!(
+            scheduler.add(AlarmKind::Alarm, at(24, 0), false, ""),
+            Err(SchedulerError::InvalidTime(24, 0))
+        );
+        assert_eq!(
+            scheduler.add(AlarmKind::Alarm, at(7, 60), false, ""),
+            Err(SchedulerError::InvalidTime(7, 60))
+        );
+
+        for _ in 0..MAX_ALARMS {
+            scheduler
+                .add(AlarmKind::Timer, after_secs(60), false, "")
+                .unwrap();
+        }
+        assert_eq!(
+            scheduler.add(AlarmKind::Timer, after_secs(60), false, ""),
+            Err(SchedulerError::TooManyAlarms)
+        );
+
+        scheduler.clock.now = None;
+        scheduler.alarms.clear();
+        assert_eq!(
+            scheduler.add(AlarmKind::Timer, after_secs(60), false, ""),
+            Err(SchedulerError::TimeNotSynced)
+        );
+    }
+
+    #[test]
+    fn alarms_are_sorted_by_fire_time() {
+        let mut scheduler = scheduler(CST);
+        let later = scheduler
+            .add(AlarmKind::Timer, after_secs(600), false, "")
+            .unwrap();
+        let sooner = scheduler
+            .add(AlarmKind::Timer, after_secs(60), false, "")
+            .unwrap();
+
+        assert_eq!(scheduler.alarms(), [sooner.clone(), later]);
+        assert_eq!(scheduler.next_due_in(), Some(Duration::from_secs(60)));
+        assert_eq!(scheduler.cancel(sooner.id), Some(sooner));
+        assert_eq!(scheduler.next_due_in(), Some(Duration::from_secs(600)));
+        assert_eq!(scheduler.cancel(12345), None);
+    }
+
+    #[test]
+    fn take_due_removes_one_shot_alarms() {
+        let mut scheduler = scheduler(CST);
+        let timer = scheduler
+            .add(AlarmKind::Timer, after_secs(60), false, " 泡面 ")
+            .unwrap();
+        assert_eq!(timer.label, "泡面");
+
+        scheduler.clock.now = Some(BASE + 59);
+        assert!(scheduler.take_due().is_empty());
+
+        scheduler.clock.now = Some(BASE + 60);
+        assert_eq!(scheduler.take_due(), [timer]);
+        assert!(scheduler.alarms().is_empty());
+        assert!(scheduler.take_due().is_empty());
+    }
+
+    #[test]
+    fn take_due_advances_daily_alarms() {
+        let mut scheduler = scheduler(CST);
+        let alarm = scheduler.add(AlarmKind::Alarm, at(9, 0), true, "").unwrap();
+
+        scheduler.clock.now = Some(alarm.fire_at + 5);
+        assert_eq!(scheduler.take_due(), [alarm.clone()]);
+        assert_eq!(
+            scheduler.alarms()[0].fire_at,
+            alarm.fire_at + SECONDS_PER_DAY
+        );
+
+        // 关机了三天，错过的不再响，直接推到下一次
+        scheduler.clock.now = Some(alarm.fire_at + 3 * SECONDS_PER_DAY + HOUR);
+        assert!(scheduler.take_due().is_empty());
+        assert_eq!(
+            scheduler.alarms()[0].fire_at,
+            alarm.fire_at + 4 * SECONDS_PER_DAY
+        );
+    }
+
+    #[test]
+    fn take_due_drops_alarms_missed_for_too_long() {
+        let mut scheduler = scheduler(CST);
+        let in_grace = scheduler
+            .add(AlarmKind::Timer, after_secs(60), false, "")
+            .unwrap();
+        let missed = scheduler
+            .add(AlarmKind::Timer, after_secs(59), false, "")
+            .unwrap();
+
+        scheduler.clock.now = Some(BASE + 60 + MISSED_GRACE_SECS);
+        assert_eq!(scheduler.take_due(), [in_grace]);
+        assert!(scheduler.cancel(missed.id).is_none());
+    }
+
+    #[test]
+    fn take_due_waits_for_time_sync() {
+        let mut scheduler = scheduler(CST);
+        scheduler
+            .add(AlarmKind::Timer, after_secs(60), false, "")
+            .unwrap();
+
+        scheduler.clock.now = None;
+        assert!(scheduler.take_due().is_empty());
+        assert_eq!(scheduler.next_due_in(), None);
+        assert_eq!(scheduler.alarms().len(), 1);
+    }
+
+    #[test]
+    fn save_and_load_round_trip() {
+        let mut storage = MemoryStorage::new();
+        let mut scheduler = scheduler(CST);
+        scheduler
+            .add(AlarmKind::Alarm, at(7, 0), true, "起床")
+            .unwrap();
+        scheduler
+            .add(AlarmKind::Reminder, at(9, 0), false, "开会")
+            .unwrap();
+        scheduler
+            .add(AlarmKind::Timer, after_secs(60), false, "")
+            .unwrap();
+        scheduler.save_to(&mut storage).unwrap();
+
+        let mut loaded = Scheduler::load_from(
+            FakeClock {
+                now: Some(BASE),
+                utc_offset: CST,
+            },
+            &storage,
+        );
+        assert_eq!(loaded.alarms(), scheduler.alarms());
+        // id 接着之前的继续分配
+        let alarm = loaded
+            .add(AlarmKind::Timer, after_secs(60), false, "")
+            .unwrap();
+        assert_eq!(alarm.id, 4);
+    }
+
+    #[test]
+    fn load_ignores_invalid_data() {
+        let mut storage = MemoryStorage::new();
+        storage.insert(NAMESPACE, KEY, StoredValue::Blob(b"not json".to_vec()));
+
+        let scheduler = Scheduler::load_from(
+            FakeClock {
+                now: Some(BASE),
+                utc_offset: CST,
+            },
+            &storage,
+        );
+        assert!(scheduler.alarms().is_empty());
+        assert_eq!(scheduler.next_id, 1);
+    }
+
+    #[test]
+    fn clear_removes_saved_alarms() {
+        let mut storage = MemoryStorage::new();
+        let mut scheduler = scheduler(CST);
+        scheduler
+            .add(AlarmKind::Timer, after_secs(60), false, "")
+            .unwrap();
+        scheduler.save_to(&mut storage).unwrap();
+
+        clear(&mut storage).unwrap();
+        assert_eq!(storage.get(NAMESPACE, KEY), None);
+        // 没有保存过也不算错
+        clear(&mut storage).unwrap();
+    }
+}
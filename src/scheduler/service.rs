@@ -0,0 +1,243 @@
+use std::{
+    sync::{mpsc::Sender, Arc, Mutex},
+    thread,
+    time::{Duration, Instant},
+};
+
+use anyhow::{anyhow, bail, Result};
+use chrono::DateTime;
+use log::{error, info};
+use serde_json::{json, Value};
+
+use crate::{
+    common::event::AppEvent,
+    mcp::mcp_server::McpServer,
+    scheduler::{Alarm, AlarmKind, Clock, Scheduler, SystemClock, When},
+    setting::storage::NvsStorage,
+};
+
+const TICK_INTERVAL: Duration = Duration::from_secs(1);
+/// vibration.p3 大约 1 秒，每 2 秒播一次
+const RING_INTERVAL: Duration = Duration::from_secs(2);
+/// 没人按键的话响一分钟就停
+const RING_TIMEOUT: Duration = Duration::from_secs(60);
+
+struct Ringing {
+    alarm: Alarm,
+    started: Instant,
+    last_ring: Instant,
+}
+
+/// 每秒检查一次到期的项目，到期后发 `AppEvent::AlarmFired`，
+/// 之后每隔 `RING_INTERVAL` 发一次 `AppEvent::AlarmRing`，直到按键关掉或者超时（`AppEvent::AlarmStopped`）
+#[derive(Clone)]
+pub struct SchedulerService {
+    scheduler: Arc<Mutex<Scheduler<SystemClock>>>,
+    ringing: Arc<Mutex<Option<Ringing>>>,
+}
+
+impl SchedulerService {
+    /// 从 NVS 读取保存的项目
+    pub fn new() -> Self {
+        Self {
+            scheduler: Arc::new(Mutex::new(Scheduler::load_from(
+                SystemClock,
+                &NvsStorage::new(),
+            ))),
+            ringing: Arc::new(Mutex::new(None)),
+        }
+    }
+
+    pub fn start(&self, sender: Sender<AppEvent>) -> Result<()> {
+        let service = self.clone();
+        thread::Builder::new()
+            .name("scheduler".into())
+            .stack_size(4 * 1024)
+            .spawn(move || loop {
+                thread::sleep(TICK_INTERVAL);
+                if service.tick(&sender).is_err() {
+                    break;
+                }
+            })?;
+        Ok(())
+    }
+
+    fn tick(&self, sender: &Sender<AppEvent>) -> Result<()> {
+        let due = {
+            let mut scheduler = self.scheduler.lock().unwrap();
+            let due = scheduler.take_due();
+            if !due.is_empty() {
+                save(&scheduler);
+            }
+            due
+        };
+        for alarm in due {
+            info!("Alarm fired: {:?}", alarm);
+            let now = Instant::now();
+            *self.ringing.lock().unwrap() = Some(Ringing {
+                alarm: alarm.clone(),
+                started: now,
+                last_ring: now,
+            });
+            sender.send(AppEvent::AlarmFired(alarm))?;
+        }
+
+        let mut ringing = self.ringing.lock().unwrap();
+        if let Some(state) = ringing.as_mut() {
+            if state.started.elapsed() >= RING_TIMEOUT {
+                info!("Alarm {} timed out", state.alarm.id);
+                *ringing = None;
+                sender.send(AppEvent::AlarmStopped)?;
+            } else if state.last_ring.elapsed() >= RING_INTERVAL {
+                state.last_ring = Instant::now();
+                sender.send(AppEvent::AlarmRing)?;
+            }
+        }
+        Ok(())
+    }
+
+    pub fn is_ringing(&self) -> bool {
+        self.ringing.lock().unwrap().is_some()
+    }
+
+    /// 按键关掉正在响的项目
+    pub fn dismiss(&self) -> Option<Alarm> {
+        let alarm = self.ringing.lock().unwrap().take()?.alarm;
+        info!("Alarm {} dismissed", alarm.id);
+        Some(alarm)
+    }
+
+    /// 离下一个项目还有多久，睡眠前用来设置定时唤醒
+    pub fn next_due_in(&self) -> Option<Duration> {
+        self.scheduler.lock().unwrap().next_due_in()
+    }
+}
+
+impl Default for SchedulerService {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn save(scheduler: &Scheduler<SystemClock>) {
+    if let Err(e) = scheduler.save_to(&mut NvsStorage::new()) {
+        error!("Failed to save alarms: {:?}", e);
+    }
+}
+
+/// 给大模型看的格式，时间换算成本地时间
+fn describe<C: Clock>(scheduler: &Scheduler<C>, alarm: &Alarm) -> Value {
+    let clock = scheduler.clock();
+    let local_time = DateTime::from_timestamp(alarm.fire_at + clock.utc_offset(), 0)
+        .map(|time| time.naive_utc().format("%Y-%m-%d %H:%M:%S").to_string())
+        .unwrap_or_default();
+    let remaining = clock
+        .now()
+        .map(|now| (alarm.fire_at - now).max(0))
+        .unwrap_or(0);
+    json!({
+        "id": alarm.id,
+        "kind": alarm.kind,
+        "label": alarm.label,
+        "time": local_time,
+        "remaining_seconds": remaining,
+        "repeat_daily": alarm.repeat_daily,
+    })
+}
+
+/// `HH:MM`，24 小时制
+fn parse_time(time: &str) -> Result<(u8, u8)> {
+    let (hour, minute) = time
+        .trim()
+        .split_once(':')
+        .ok_or_else(|| anyhow!("time must be HH:MM: {}", time))?;
+    Ok((hour.trim().parse()?, minute.trim().parse()?))
+}
+
+/// 注册 `self.alarm.set_timer`、`self.alarm.list_alarms` 和 `self.alarm.cancel_alarm`
+pub fn add_alarm_tools(mcp_server: &mut McpServer, service: &SchedulerService) {
+    let scheduler = service.scheduler.clone();
+    mcp_server.add_tool(
+        "self.alarm.set_timer",
+        "Set a timer, an alarm or a reminder on the device. It rings locally even when the \
+         device is offline. Use `seconds` for a countdown (e.g. \"set a timer for 10 minutes\" \
+         is 600), or `time` for a local time of day in 24-hour `HH:MM` (e.g. \"wake me up at \
+         7:30\" is `07:30`; if the time has passed today it rings tomorrow). \
+         Args: `kind`: `timer`, `alarm` or `reminder`, defaults to `timer` with `seconds` and \
+         `alarm` with `time`; `label`: what it is for, e.g. the thing to remind; \
+         `repeat_daily`: ring at the same time every day. \
+         Returns the created item with its `id`.",
+        json!({
+            "seconds": { "type": "integer", "minimum": 1, "maximum": 86400 },
+            "time": { "type": "string", "description": "Local time of day, HH:MM" },
+            "kind": { "type": "string", "enum": ["timer", "alarm", "reminder"] },
+            "label": { "type": "string" },
+            "repeat_daily": { "type": "boolean" },
+        }),
+        &[],
+        move |arguments| {
+            let when = match (arguments["seconds"].as_u64(), arguments["time"].as_str()) {
+                (Some(seconds), None) => When::After(Duration::from_secs(seconds)),
+                (None, Some(time)) => {
+                    let (hour, minute) = parse_time(time)?;
+                    When::At { hour, minute }
+                }
+                _ => bail!("exactly one of `seconds` and `time` is required"),
+            };
+            let kind = match arguments["kind"].as_str() {
+                Some(kind) => serde_json::from_value::<AlarmKind>(json!(kind))?,
+                None if matches!(when, When::After(_)) => AlarmKind::Timer,
+                None => AlarmKind::Alarm,
+            };
+            let label = arguments["label"].as_str().unwrap_or_default();
+            let repeat_daily = arguments["repeat_daily"].as_bool().unwrap_or(false);
+
+            let mut scheduler = scheduler.lock().unwrap();
+            let alarm = scheduler.add(kind, when, repeat_daily, label)?;
+            save(&scheduler);
+            info!("Alarm added: {:?}", alarm);
+            Ok(describe(&scheduler, &alarm))
+        },
+    );
+
+    let scheduler = service.scheduler.clone();
+    mcp_server.add_tool(
+        "self.alarm.list_alarms",
+        "List the timers, alarms and reminders set on the device, soonest first. \
+         Use this before cancelling one to find its `id`.",
+        json!({}),
+        &[],
+        move |_| {
+            let scheduler = scheduler.lock().unwrap();
+            let alarms = scheduler
+                .alarms()
+                .iter()
+                .map(|alarm| describe(&scheduler, alarm))
+                .collect::<Vec<_>>();
+            Ok(json!(alarms))
+        },
+    );
+
+    let scheduler = service.scheduler.clone();
+    mcp_server.add_tool(
+        "self.alarm.cancel_alarm",
+        "Cancel a timer, alarm or reminder by the `id` returned from `self.alarm.list_alarms` \
+         or `self.alarm.set_timer`.",
+        json!({
+            "id": { "type": "integer" },
+        }),
+        &["id"],
+        move |arguments| {
+            let id = arguments["id"]
+                .as_u64()
+                .ok_or_else(|| anyhow!("id must be an integer"))?;
+            let mut scheduler = scheduler.lock().unwrap();
+            let alarm = scheduler
+                .cancel(id as u32)
+                .ok_or_else(|| anyhow!("No alarm with id {}", id))?;
+            save(&scheduler);
+            info!("Alarm cancelled: {:?}", alarm);
+            Ok(json!(true))
+        },
+    );
+}
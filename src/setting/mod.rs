@@ -1 +1,4 @@
+#[cfg(target_os = "espidf")]
 pub mod nvs_setting;
+pub mod settings;
+pub mod storage;
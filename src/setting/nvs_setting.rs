@@ -22,18 +22,22 @@ impl NvsSetting {
     }
 
     pub fn get_string(&self, key: &str) -> Option<String> {
-        // String values are limited in the IDF to 4000 bytes, but our buffer is shorter.
-        const MAX_STR_LEN: usize = 100;
-        let mut buffer: [u8; MAX_STR_LEN] = [0; MAX_STR_LEN];
-        match self.nvs.get_str(key, &mut buffer).unwrap() {
-            Some(v) => {
-                let value = Some(v.to_string());
-                return value;
-            }
-            None => {
+        let len = match self.nvs.str_len(key) {
+            Ok(Some(len)) => len,
+            Ok(None) => return None,
+            Err(e) => {
+                error!("failed to get the length of {}: {:?}", key, e);
                 return None;
             }
         };
+        let mut buffer = vec![0u8; len];
+        match self.nvs.get_str(key, &mut buffer) {
+            Ok(value) => value.map(|v| v.to_string()),
+            Err(e) => {
+                error!("failed to get string {}: {:?}", key, e);
+                None
+            }
+        }
     }
 
     pub fn set_string(&mut self, key: &str, value: &str) -> anyhow::Result<()> {
@@ -41,6 +45,25 @@ impl NvsSetting {
         Ok(())
     }
 
+    /// 大的值（例如 JSON）用 blob 保存，字符串最长只有 4000 字节
+    pub fn get_blob(&self, key: &str) -> anyhow::Result<Option<Vec<u8>>> {
+        let Some(len) = self.nvs.blob_len(key)? else {
+            return Ok(None);
+        };
+        let mut buffer = vec![0u8; len];
+        let value = self.nvs.get_blob(key, &mut buffer)?.map(|v| v.to_vec());
+        Ok(value)
+    }
+
+    pub fn set_blob(&mut self, key: &str, value: &[u8]) -> anyhow::Result<()> {
+        self.nvs.set_blob(key, value)?;
+        Ok(())
+    }
+
+    pub fn remove(&mut self, key: &str) -> anyhow::Result<bool> {
+        Ok(self.nvs.remove(key)?)
+    }
+
     pub fn get_i32(&self, key: &str) -> Option<i32> {
         match self.nvs.get_i32(key) {
             Ok(value) => value,
@@ -0,0 +1,682 @@
+use anyhow::{anyhow, bail, Result};
+use log::{error, info, warn};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+use crate::{
+    clock::DEFAULT_TIMEZONE,
+    led::pattern::LedSettings,
+    scheduler,
+    setting::storage::{NvsStorage, SettingsStorage},
+    wifi::ssid_manager::{SsidItem, StaticIpConfig, WifiAuth},
+};
+
+/// 当前的设置格式版本，修改了格式就加一，并在 `MIGRATIONS` 里加上迁移函数
+pub const SETTINGS_VERSION: u8 = 1;
+
+const VERSION_NAMESPACE: &str = "settings";
+const VERSION_KEY: &str = "version";
+/// 每个分组保存在自己的 namespace 里，key 都是这个
+const GROUP_KEY: &str = "data";
+
+/// `MIGRATIONS[n]` 把版本 n 的数据迁移到版本 n + 1
+const MIGRATIONS: &[fn(&mut dyn SettingsStorage) -> Result<()>] = &[migrate_v0_to_v1];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SettingsGroup {
+    Wifi,
+    Audio,
+    Display,
+    Server,
+    Power,
+    Security,
+}
+
+impl SettingsGroup {
+    pub const ALL: [SettingsGroup; 6] = [
+        SettingsGroup::Wifi,
+        SettingsGroup::Audio,
+        SettingsGroup::Display,
+        SettingsGroup::Server,
+        SettingsGroup::Power,
+        SettingsGroup::Security,
+    ];
+
+    pub fn namespace(&self) -> &'static str {
+        match self {
+            SettingsGroup::Wifi => "wifi",
+            SettingsGroup::Audio => "audio",
+            SettingsGroup::Display => "display",
+            SettingsGroup::Server => "server",
+            SettingsGroup::Power => "power",
+            SettingsGroup::Security => "security",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct WifiSettings {
+    pub networks: Vec<SsidItem>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AudioSettings {
+    pub output_volume: u8,
+}
+
+impl Default for AudioSettings {
+    fn default() -> Self {
+        Self { output_volume: 30 }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DisplaySettings {
+    /// 背光亮度，0-100
+    pub brightness: u8,
+    /// 状态灯
+    pub led: LedSettings,
+    /// 时区，POSIX TZ 格式，例如 `CST-8`
+    pub timezone: String,
+}
+
+impl Default for DisplaySettings {
+    fn default() -> Self {
+        Self {
+            brightness: 100,
+            led: LedSettings::default(),
+            timezone: DEFAULT_TIMEZONE.to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ServerSettings {
+    /// websocket 服务器地址，None 时使用固件里的默认地址
+    pub ws_url: Option<String>,
+    /// OTA manifest 地址，None 时使用固件里的默认地址
+    pub ota_url: Option<String>,
+    /// 每隔多少秒把诊断信息推送给服务器，0 表示不推送
+    pub diag_interval_s: u32,
+    /// 拍照识图的地址，服务器在 MCP initialize 里下发时以下发的为准
+    pub vision_url: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PowerSettings {
+    pub modem_sleep: bool,
+    /// 空闲多少秒后进入 light sleep，0 表示不进入
+    pub light_sleep_s: u32,
+    /// 空闲多少秒后进入 deep sleep，0 表示不进入
+    pub deep_sleep_s: u32,
+}
+
+impl Default for PowerSettings {
+    fn default() -> Self {
+        Self {
+            modem_sleep: true,
+            light_sleep_s: 60,
+            deep_sleep_s: 10 * 60,
+        }
+    }
+}
+
+/// 安全相关的设置，不会被导出
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SecuritySettings {
+    /// 本地上传固件（`POST /ota`）用的 token，第一次使用时随机生成
+    pub ota_token: Option<String>,
+    /// 识图服务的 token，上传照片时放在 Authorization 里
+    pub vision_token: Option<String>,
+}
+
+/// 所有的设置。每个分组以 JSON blob 保存在对应的 NVS namespace 里，
+/// 缺少的分组或字段使用默认值。
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Settings {
+    pub wifi: WifiSettings,
+    pub audio: AudioSettings,
+    pub display: DisplaySettings,
+    pub server: ServerSettings,
+    pub power: PowerSettings,
+    pub security: SecuritySettings,
+}
+
+impl Settings {
+    /// 从 NVS 读取设置
+    pub fn load() -> Result<Self> {
+        Self::load_from(&mut NvsStorage::new())
+    }
+
+    /// 读取设置，如果保存的版本比较旧，先执行迁移
+    pub fn load_from(storage: &mut dyn SettingsStorage) -> Result<Self> {
+        migrate(storage)?;
+
+        Ok(Self {
+            wifi: load_group(storage, SettingsGroup::Wifi)?,
+            audio: load_group(storage, SettingsGroup::Audio)?,
+            display: load_group(storage, SettingsGroup::Display)?,
+            server: load_group(storage, SettingsGroup::Server)?,
+            power: load_group(storage, SettingsGroup::Power)?,
+            security: load_group(storage, SettingsGroup::Security)?,
+        })
+    }
+
+    /// 保存一个分组到 NVS
+    pub fn save(&self, group: SettingsGroup) -> Result<()> {
+        self.save_to(&mut NvsStorage::new(), group)
+    }
+
+    pub fn save_to(&self, storage: &mut dyn SettingsStorage, group: SettingsGroup) -> Result<()> {
+        let value = match group {
+            SettingsGroup::Wifi => serde_json::to_vec(&self.wifi)?,
+            SettingsGroup::Audio => serde_json::to_vec(&self.audio)?,
+            SettingsGroup::Display => serde_json::to_vec(&self.display)?,
+            SettingsGroup::Server => serde_json::to_vec(&self.server)?,
+            SettingsGroup::Power => serde_json::to_vec(&self.power)?,
+            SettingsGroup::Security => serde_json::to_vec(&self.security)?,
+        };
+        storage.set_blob(group.namespace(), GROUP_KEY, &value)
+    }
+
+    pub fn save_all_to(&self, storage: &mut dyn SettingsStorage) -> Result<()> {
+        for group in SettingsGroup::ALL {
+            self.save_to(storage, group)?;
+        }
+        Ok(())
+    }
+
+    /// 读取、修改并保存一个分组
+    pub fn update<F>(group: SettingsGroup, f: F) -> Result<Self>
+    where
+        F: FnOnce(&mut Settings),
+    {
+        let mut storage = NvsStorage::new();
+        let mut settings = Self::load_from(&mut storage)?;
+        f(&mut settings);
+        settings.save_to(&mut storage, group)?;
+        Ok(settings)
+    }
+}
+
+/// 导出的 WiFi 配置，不包含密码和账号
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ExportedNetwork {
+    pub ssid: String,
+    #[serde(default)]
+    pub auth: WifiAuth,
+    #[serde(default)]
+    pub hidden: bool,
+    #[serde(default)]
+    pub static_ip: Option<StaticIpConfig>,
+}
+
+/// 导出/导入用的格式，不包含任何密码，可以用来批量复制设备的配置
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SettingsExport {
+    pub version: u8,
+    #[serde(default)]
+    pub wifi: Vec<ExportedNetwork>,
+    #[serde(default)]
+    pub audio: AudioSettings,
+    #[serde(default)]
+    pub display: DisplaySettings,
+    #[serde(default)]
+    pub server: ServerSettings,
+    #[serde(default)]
+    pub power: PowerSettings,
+}
+
+/// 导入的结果，需要密码但本机没有保存过的 WiFi 会被跳过
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ImportReport {
+    pub imported_networks: Vec<String>,
+    pub skipped_networks: Vec<String>,
+}
+
+impl Settings {
+    pub fn export(&self) -> SettingsExport {
+        SettingsExport {
+            version: SETTINGS_VERSION,
+            wifi: self
+                .wifi
+                .networks
+                .iter()
+                .map(|network| ExportedNetwork {
+                    ssid: network.ssid.clone(),
+                    // Auto 会根据密码是否为空变化，导出时需要写明
+                    auth: network.effective_auth(),
+                    hidden: network.hidden,
+                    static_ip: network.static_ip.clone(),
+                })
+                .collect(),
+            audio: self.audio.clone(),
+            display: self.display.clone(),
+            server: self.server.clone(),
+            power: self.power.clone(),
+        }
+    }
+
+    /// 合并导入的配置。本机已保存的同名 WiFi 保留原来的密码；
+    /// 新的 WiFi 只有不需要密码时才会添加。
+    /// 比本机新的格式可能有不认识的字段，直接拒绝，不做部分导入
+    pub fn import(&mut self, export: SettingsExport) -> Result<ImportReport> {
+        if export.version > SETTINGS_VERSION {
+            bail!(
+                "Settings version {} is newer than {}, upgrade the firmware first",
+                export.version,
+                SETTINGS_VERSION
+            );
+        }
+        let mut report = ImportReport::default();
+
+        for exported in export.wifi {
+            let mut network = match self
+                .wifi
+                .networks
+                .iter()
+                .find(|network| network.ssid == exported.ssid)
+            {
+                Some(existing) => existing.clone(),
+                None => SsidItem::new(&exported.ssid, ""),
+            };
+            network.auth = exported.auth;
+            network.hidden = exported.hidden;
+            network.static_ip = exported.static_ip;
+
+            if network.validate().is_err() {
+                warn!("Skip importing wifi {}: missing credentials", exported.ssid);
+                report.skipped_networks.push(exported.ssid);
+                continue;
+            }
+
+            self.wifi.networks.retain(|item| item.ssid != network.ssid);
+            self.wifi.networks.push(network);
+            report.imported_networks.push(exported.ssid);
+        }
+
+        self.audio = export.audio;
+        self.display = export.display;
+        self.server = export.server;
+        self.power = export.power;
+        Ok(report)
+    }
+
+    /// 恢复出厂设置：所有分组写回默认值，包括已保存的 WiFi
+    pub fn factory_reset(storage: &mut dyn SettingsStorage) -> Result<()> {
+        info!("Factory reset settings");
+        Settings::default().save_all_to(storage)?;
+        storage.set_u8(VERSION_NAMESPACE, VERSION_KEY, SETTINGS_VERSION)?;
+        // 闹钟不在设置分组里，单独保存在 scheduler namespace
+        scheduler::clear(storage)?;
+        Ok(())
+    }
+
+    /// 从 JSON 导入并保存到 NVS
+    pub fn import_json(json: &[u8]) -> Result<ImportReport> {
+        let export = serde_json::from_slice::<SettingsExport>(json)?;
+        let mut storage = NvsStorage::new();
+        let mut settings = Self::load_from(&mut storage)?;
+        let report = settings.import(export)?;
+        settings.save_all_to(&mut storage)?;
+        Ok(report)
+    }
+}
+
+/// 没保存过的分组使用默认值。读取出错时返回错误，不能当成默认值，
+/// 否则之后保存任何一个分组都会用默认值覆盖掉真正的数据
+fn load_group<T: DeserializeOwned + Default>(
+    storage: &dyn SettingsStorage,
+    group: SettingsGroup,
+) -> Result<T> {
+    let value = storage
+        .get_blob(group.namespace(), GROUP_KEY)
+        .map_err(|e| anyhow!("Failed to read {} settings: {:?}", group.namespace(), e))?;
+    match value {
+        Some(value) => match serde_json::from_slice::<T>(&value) {
+            Ok(settings) => Ok(settings),
+            Err(e) => {
+                error!("Invalid {} settings, use default: {:?}", group.namespace(), e);
+                Ok(T::default())
+            }
+        },
+        None => Ok(T::default()),
+    }
+}
+
+/// 依次执行迁移，直到 `SETTINGS_VERSION`
+pub fn migrate(storage: &mut dyn SettingsStorage) -> Result<()> {
+    let version = storage
+        .get_u8(VERSION_NAMESPACE, VERSION_KEY)?
+        .unwrap_or(0);
+
+    if version > SETTINGS_VERSION {
+        // 降级固件时保留数据，能解析的字段照常使用
+        warn!(
+            "Settings version {} is newer than {}, skip migration",
+            version, SETTINGS_VERSION
+        );
+        return Ok(());
+    }
+
+    for from in version..SETTINGS_VERSION {
+        info!("Migrating settings from v{} to v{}", from, from + 1);
+        MIGRATIONS[from as usize](storage)?;
+        storage.set_u8(VERSION_NAMESPACE, VERSION_KEY, from + 1)?;
+    }
+    Ok(())
+}
+
+/// v0 是最早的格式：WiFi 列表是 "wifi/wifi_settings" 里的 JSON 字符串，
+/// 其它设置是分散的 u8/i32/字符串 key
+fn migrate_v0_to_v1(storage: &mut dyn SettingsStorage) -> Result<()> {
+    let mut settings = Settings::default();
+
+    if let Some(json) = storage.get_string("wifi", "wifi_settings")? {
+        match serde_json::from_str::<Vec<SsidItem>>(&json) {
+            Ok(networks) => settings.wifi.networks = networks,
+            Err(e) => error!("Failed to migrate wifi list: {:?}", e),
+        }
+    }
+
+    if let Some(volume) = storage.get_u8("audio", "output_volume")? {
+        if volume > 0 {
+            settings.audio.output_volume = volume;
+        }
+    }
+
+    settings.server.ws_url = storage.get_string("server", "ws_url")?;
+
+    if let Some(modem_sleep) = storage.get_u8("power", "modem_sleep")? {
+        settings.power.modem_sleep = modem_sleep != 0;
+    }
+    if let Some(secs) = storage.get_i32("power", "light_sleep_s")? {
+        settings.power.light_sleep_s = secs.max(0) as u32;
+    }
+    if let Some(secs) = storage.get_i32("power", "deep_sleep_s")? {
+        settings.power.deep_sleep_s = secs.max(0) as u32;
+    }
+
+    settings.save_all_to(storage)?;
+
+    for (namespace, key) in [
+        ("wifi", "wifi_settings"),
+        ("audio", "output_volume"),
+        ("server", "ws_url"),
+        ("power", "modem_sleep"),
+        ("power", "light_sleep_s"),
+        ("power", "deep_sleep_s"),
+    ] {
+        storage.remove(namespace, key)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::setting::storage::{MemoryStorage, StoredValue};
+
+    /// 指定的 namespace 读取时出错，模拟 NVS 读失败
+    struct FailingStorage {
+        inner: MemoryStorage,
+        failing_namespace: &'static str,
+    }
+
+    impl SettingsStorage for FailingStorage {
+        fn get_blob(&self, namespace: &str, key: &str) -> Result<Option<Vec<u8>>> {
+            if namespace == self.failing_namespace {
+                return Err(anyhow!("nvs read failed"));
+            }
+            self.inner.get_blob(namespace, key)
+        }
+
+        fn set_blob(&mut self, namespace: &str, key: &str, value: &[u8]) -> Result<()> {
+            self.inner.set_blob(namespace, key, value)
+        }
+
+        fn get_string(&self, namespace: &str, key: &str) -> Result<Option<String>> {
+            self.inner.get_string(namespace, key)
+        }
+
+        fn get_i32(&self, namespace: &str, key: &str) -> Result<Option<i32>> {
+            self.inner.get_i32(namespace, key)
+        }
+
+        fn get_u8(&self, namespace: &str, key: &str) -> Result<Option<u8>> {
+            self.inner.get_u8(namespace, key)
+        }
+
+        fn set_u8(&mut self, namespace: &str, key: &str, value: u8) -> Result<()> {
+            self.inner.set_u8(namespace, key, value)
+        }
+
+        fn remove(&mut self, namespace: &str, key: &str) -> Result<bool> {
+            self.inner.remove(namespace, key)
+        }
+    }
+
+    fn v0_storage() -> MemoryStorage {
+        let mut storage = MemoryStorage::new();
+        storage.insert(
+            "wifi",
+            "wifi_settings",
+            StoredValue::String(
+                r#"[{"ssid":"home","password":"12345678","last_connect_time":"2024-01-01"}]"#
+                    .to_string(),
+            ),
+        );
+        storage.insert("audio", "output_volume", StoredValue::U8(70));
+        storage.insert(
+            "server",
+            "ws_url",
+            StoredValue::String("wss://example.com/ws".to_string()),
+        );
+        storage.insert("power", "modem_sleep", StoredValue::U8(0));
+        storage.insert("power", "light_sleep_s", StoredValue::I32(120));
+        storage.insert("power", "deep_sleep_s", StoredValue::I32(-1));
+        storage
+    }
+
+    #[test]
+    fn migrate_v0_to_v1_moves_old_keys() {
+        let mut storage = v0_storage();
+
+        let settings = Settings::load_from(&mut storage).unwrap();
+
+        assert_eq!(settings.wifi.networks.len(), 1);
+        assert_eq!(settings.wifi.networks[0].ssid, "home");
+        assert_eq!(settings.wifi.networks[0].password, "12345678");
+        assert_eq!(settings.wifi.networks[0].last_connect_time, "2024-01-01");
+        assert_eq!(settings.audio.output_volume, 70);
+        assert_eq!(
+            settings.server.ws_url.as_deref(),
+            Some("wss://example.com/ws")
+        );
+        assert!(!settings.power.modem_sleep);
+        assert_eq!(settings.power.light_sleep_s, 120);
+        assert_eq!(settings.power.deep_sleep_s, 0);
+        assert_eq!(settings.display, DisplaySettings::default());
+
+        assert_eq!(
+            storage.get(VERSION_NAMESPACE, VERSION_KEY),
+            Some(&StoredValue::U8(SETTINGS_VERSION))
+        );
+        for (namespace, key) in [
+            ("wifi", "wifi_settings"),
+            ("audio", "output_volume"),
+            ("server", "ws_url"),
+            ("power", "modem_sleep"),
+            ("power", "light_sleep_s"),
+            ("power", "deep_sleep_s"),
+        ] {
+            assert_eq!(storage.get(namespace, key), None, "{}/{}", namespace, key);
+        }
+        for group in SettingsGroup::ALL {
+            assert!(storage.get(group.namespace(), GROUP_KEY).is_some());
+        }
+
+        // 迁移只做一次，再读一遍结果一样
+        assert_eq!(Settings::load_from(&mut storage).unwrap(), settings);
+    }
+
+    #[test]
+    fn migrate_v0_keeps_defaults_for_missing_or_bad_values() {
+        let mut storage = MemoryStorage::new();
+        storage.insert(
+            "wifi",
+            "wifi_settings",
+            StoredValue::String("not json".to_string()),
+        );
+        storage.insert("audio", "output_volume", StoredValue::U8(0));
+
+        let settings = Settings::load_from(&mut storage).unwrap();
+
+        assert_eq!(settings, Settings::default());
+        assert_eq!(storage.get("wifi", "wifi_settings"), None);
+    }
+
+    #[test]
+    fn current_version_is_not_migrated() {
+        let mut storage = v0_storage();
+        storage
+            .set_u8(VERSION_NAMESPACE, VERSION_KEY, SETTINGS_VERSION)
+            .unwrap();
+
+        let settings = Settings::load_from(&mut storage).unwrap();
+
+        assert_eq!(settings, Settings::default());
+        assert!(storage.get("audio", "output_volume").is_some());
+    }
+
+    #[test]
+    fn newer_version_keeps_data() {
+        let mut storage = MemoryStorage::new();
+        storage
+            .set_u8(VERSION_NAMESPACE, VERSION_KEY, SETTINGS_VERSION + 1)
+            .unwrap();
+        storage
+            .set_blob("audio", GROUP_KEY, br#"{"output_volume":55,"eq":"rock"}"#)
+            .unwrap();
+
+        let settings = Settings::load_from(&mut storage).unwrap();
+
+        assert_eq!(settings.audio.output_volume, 55);
+        assert_eq!(
+            storage.get(VERSION_NAMESPACE, VERSION_KEY),
+            Some(&StoredValue::U8(SETTINGS_VERSION + 1))
+        );
+    }
+
+    #[test]
+    fn save_and_load_round_trip() {
+        let mut storage = MemoryStorage::new();
+        let mut settings = Settings::load_from(&mut storage).unwrap();
+        settings
+            .wifi
+            .networks
+            .push(SsidItem::new("office", "password1"));
+        settings.audio.output_volume = 80;
+        settings.display.brightness = 40;
+        settings.display.timezone = "UTC0".to_string();
+        settings.server.ota_url = Some("https://example.com/ota.json".to_string());
+        settings.server.diag_interval_s = 300;
+        settings.power.light_sleep_s = 0;
+        settings.security.ota_token = Some("token".to_string());
+        settings.save_all_to(&mut storage).unwrap();
+
+        assert_eq!(Settings::load_from(&mut storage).unwrap(), settings);
+    }
+
+    #[test]
+    fn save_one_group_keeps_others() {
+        let mut storage = MemoryStorage::new();
+        let mut settings = Settings::load_from(&mut storage).unwrap();
+        settings.audio.output_volume = 10;
+        settings
+            .save_to(&mut storage, SettingsGroup::Audio)
+            .unwrap();
+        settings.power.modem_sleep = false;
+        settings
+            .save_to(&mut storage, SettingsGroup::Power)
+            .unwrap();
+
+        let loaded = Settings::load_from(&mut storage).unwrap();
+        assert_eq!(loaded.audio.output_volume, 10);
+        assert!(!loaded.power.modem_sleep);
+    }
+
+    #[test]
+    fn factory_reset_clears_settings_and_alarms() {
+        let mut storage = MemoryStorage::new();
+        let mut settings = Settings::load_from(&mut storage).unwrap();
+        settings.audio.output_volume = 10;
+        settings.save_all_to(&mut storage).unwrap();
+        storage
+            .set_blob("scheduler", "alarms", br#"{"next_id":2,"alarms":[]}"#)
+            .unwrap();
+
+        Settings::factory_reset(&mut storage).unwrap();
+
+        let loaded = Settings::load_from(&mut storage).unwrap();
+        assert_eq!(
+            loaded.audio.output_volume,
+            Settings::default().audio.output_volume
+        );
+        assert_eq!(storage.get("scheduler", "alarms"), None);
+    }
+
+    #[test]
+    fn invalid_group_uses_default() {
+        let mut storage = MemoryStorage::new();
+        storage
+            .set_u8(VERSION_NAMESPACE, VERSION_KEY, SETTINGS_VERSION)
+            .unwrap();
+        storage.set_blob("audio", GROUP_KEY, b"{").unwrap();
+        storage
+            .set_blob("power", GROUP_KEY, br#"{"deep_sleep_s":30}"#)
+            .unwrap();
+
+        let settings = Settings::load_from(&mut storage).unwrap();
+
+        assert_eq!(settings.audio, AudioSettings::default());
+        // 缺少的字段用默认值
+        assert_eq!(settings.power.deep_sleep_s, 30);
+        assert_eq!(settings.power.light_sleep_s, 60);
+    }
+
+    #[test]
+    fn import_rejects_newer_version() {
+        let mut settings = Settings::default();
+        let mut export = Settings::default().export();
+        export.audio.output_volume = 90;
+        export.version = SETTINGS_VERSION + 1;
+
+        assert!(settings.import(export.clone()).is_err());
+        assert_eq!(settings, Settings::default());
+
+        export.version = SETTINGS_VERSION;
+        settings.import(export).unwrap();
+        assert_eq!(settings.audio.output_volume, 90);
+    }
+
+    #[test]
+    fn read_error_is_not_treated_as_default() {
+        let mut inner = MemoryStorage::new();
+        inner
+            .set_u8(VERSION_NAMESPACE, VERSION_KEY, SETTINGS_VERSION)
+            .unwrap();
+        let mut storage = FailingStorage {
+            inner,
+            failing_namespace: "wifi",
+        };
+
+        assert!(Settings::load_from(&mut storage).is_err());
+    }
+}
@@ -0,0 +1,181 @@
+use std::collections::HashMap;
+#[cfg(not(target_os = "espidf"))]
+use std::sync::{Mutex, MutexGuard, OnceLock};
+
+use anyhow::Result;
+
+#[cfg(target_os = "espidf")]
+use crate::setting::nvs_setting::NvsSetting;
+
+/// 设置的底层存储。设备上用 NVS，主机上测试时用 `MemoryStorage`。
+///
+/// 新的设置都以 blob 保存；字符串、i32 和 u8 只用于读取旧版本写入的数据做迁移。
+pub trait SettingsStorage {
+    fn get_blob(&self, namespace: &str, key: &str) -> Result<Option<Vec<u8>>>;
+    fn set_blob(&mut self, namespace: &str, key: &str, value: &[u8]) -> Result<()>;
+
+    fn get_string(&self, namespace: &str, key: &str) -> Result<Option<String>>;
+    fn get_i32(&self, namespace: &str, key: &str) -> Result<Option<i32>>;
+    fn get_u8(&self, namespace: &str, key: &str) -> Result<Option<u8>>;
+    fn set_u8(&mut self, namespace: &str, key: &str, value: u8) -> Result<()>;
+
+    /// 删除一个 key，返回是否真的存在
+    fn remove(&mut self, namespace: &str, key: &str) -> Result<bool>;
+}
+
+/// NVS 存储，每个 namespace 在访问时打开。
+///
+/// host-sim 下没有 NVS，所有 `NvsStorage` 共用进程里的一份 `MemoryStorage`，退出后就没了
+#[derive(Default)]
+pub struct NvsStorage {}
+
+impl NvsStorage {
+    pub fn new() -> Self {
+        Self {}
+    }
+}
+
+#[cfg(target_os = "espidf")]
+impl SettingsStorage for NvsStorage {
+    fn get_blob(&self, namespace: &str, key: &str) -> Result<Option<Vec<u8>>> {
+        NvsSetting::new(namespace)?.get_blob(key)
+    }
+
+    fn set_blob(&mut self, namespace: &str, key: &str, value: &[u8]) -> Result<()> {
+        NvsSetting::new(namespace)?.set_blob(key, value)
+    }
+
+    fn get_string(&self, namespace: &str, key: &str) -> Result<Option<String>> {
+        Ok(NvsSetting::new(namespace)?.get_string(key))
+    }
+
+    fn get_i32(&self, namespace: &str, key: &str) -> Result<Option<i32>> {
+        Ok(NvsSetting::new(namespace)?.get_i32(key))
+    }
+
+    fn get_u8(&self, namespace: &str, key: &str) -> Result<Option<u8>> {
+        Ok(NvsSetting::new(namespace)?.get_u8(key))
+    }
+
+    fn set_u8(&mut self, namespace: &str, key: &str, value: u8) -> Result<()> {
+        NvsSetting::new(namespace)?.set_u8(key, value)
+    }
+
+    fn remove(&mut self, namespace: &str, key: &str) -> Result<bool> {
+        NvsSetting::new(namespace)?.remove(key)
+    }
+}
+
+#[cfg(not(target_os = "espidf"))]
+fn sim_nvs() -> MutexGuard<'static, MemoryStorage> {
+    static NVS: OnceLock<Mutex<MemoryStorage>> = OnceLock::new();
+    NVS.get_or_init(Default::default).lock().unwrap()
+}
+
+#[cfg(not(target_os = "espidf"))]
+impl SettingsStorage for NvsStorage {
+    fn get_blob(&self, namespace: &str, key: &str) -> Result<Option<Vec<u8>>> {
+        sim_nvs().get_blob(namespace, key)
+    }
+
+    fn set_blob(&mut self, namespace: &str, key: &str, value: &[u8]) -> Result<()> {
+        sim_nvs().set_blob(namespace, key, value)
+    }
+
+    fn get_string(&self, namespace: &str, key: &str) -> Result<Option<String>> {
+        sim_nvs().get_string(namespace, key)
+    }
+
+    fn get_i32(&self, namespace: &str, key: &str) -> Result<Option<i32>> {
+        sim_nvs().get_i32(namespace, key)
+    }
+
+    fn get_u8(&self, namespace: &str, key: &str) -> Result<Option<u8>> {
+        sim_nvs().get_u8(namespace, key)
+    }
+
+    fn set_u8(&mut self, namespace: &str, key: &str, value: u8) -> Result<()> {
+        sim_nvs().set_u8(namespace, key, value)
+    }
+
+    fn remove(&mut self, namespace: &str, key: &str) -> Result<bool> {
+        sim_nvs().remove(namespace, key)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum StoredValue {
+    Blob(Vec<u8>),
+    String(String),
+    I32(i32),
+    U8(u8),
+}
+
+/// 内存中的存储，用于在主机上运行设置和迁移的代码
+#[derive(Debug, Default, Clone)]
+pub struct MemoryStorage {
+    values: HashMap<(String, String), StoredValue>,
+}
+
+impl MemoryStorage {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 直接写入一个值，用来模拟旧版本固件写入的数据
+    pub fn insert(&mut self, namespace: &str, key: &str, value: StoredValue) {
+        self.values
+            .insert((namespace.to_string(), key.to_string()), value);
+    }
+
+    pub fn get(&self, namespace: &str, key: &str) -> Option<&StoredValue> {
+        self.values.get(&(namespace.to_string(), key.to_string()))
+    }
+}
+
+impl SettingsStorage for MemoryStorage {
+    fn get_blob(&self, namespace: &str, key: &str) -> Result<Option<Vec<u8>>> {
+        match self.get(namespace, key) {
+            Some(StoredValue::Blob(value)) => Ok(Some(value.clone())),
+            _ => Ok(None),
+        }
+    }
+
+    fn set_blob(&mut self, namespace: &str, key: &str, value: &[u8]) -> Result<()> {
+        self.insert(namespace, key, StoredValue::Blob(value.to_vec()));
+        Ok(())
+    }
+
+    fn get_string(&self, namespace: &str, key: &str) -> Result<Option<String>> {
+        match self.get(namespace, key) {
+            Some(StoredValue::String(value)) => Ok(Some(value.clone())),
+            _ => Ok(None),
+        }
+    }
+
+    fn get_i32(&self, namespace: &str, key: &str) -> Result<Option<i32>> {
+        match self.get(namespace, key) {
+            Some(StoredValue::I32(value)) => Ok(Some(*value)),
+            _ => Ok(None),
+        }
+    }
+
+    fn get_u8(&self, namespace: &str, key: &str) -> Result<Option<u8>> {
+        match self.get(namespace, key) {
+            Some(StoredValue::U8(value)) => Ok(Some(*value)),
+            _ => Ok(None),
+        }
+    }
+
+    fn set_u8(&mut self, namespace: &str, key: &str, value: u8) -> Result<()> {
+        self.insert(namespace, key, StoredValue::U8(value));
+        Ok(())
+    }
+
+    fn remove(&mut self, namespace: &str, key: &str) -> Result<bool> {
+        Ok(self
+            .values
+            .remove(&(namespace.to_string(), key.to_string()))
+            .is_some())
+    }
+}
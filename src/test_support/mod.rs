@@ -0,0 +1,2 @@
+//! 单元测试共用的替身，只在 `cargo test` 时编译。
+pub mod recording_i2c;
@@ -0,0 +1,132 @@
+//! 记录所有读写的假 I2C 总线，用来在主机上检查 codec、PMIC 驱动读写寄存器的顺序。
+//!
+//! 每个地址有一份寄存器表，写进去的值读的时候能读回来，没写过的读出 0，
+//! 也可以先用 `set_register` 填上芯片的默认值。克隆出来的总线共用同一份记录，
+//! 把一份交给驱动，留一份在外面检查。
+
+use std::{
+    collections::HashMap,
+    convert::Infallible,
+    sync::{Arc, Mutex},
+};
+
+use embedded_hal::blocking::i2c::{Write, WriteRead};
+
+/// 总线上的一次操作
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum I2cOp {
+    /// 从 `reg` 开始连续写，寄存器地址自动加一
+    Write {
+        addr: u8,
+        reg: u8,
+        data: Vec<u8>,
+    },
+    Read {
+        addr: u8,
+        reg: u8,
+        len: usize,
+    },
+}
+
+#[derive(Debug, Default)]
+struct Bus {
+    registers: HashMap<(u8, u8), u8>,
+    ops: Vec<I2cOp>,
+}
+
+#[derive(Debug, Default, Clone)]
+pub struct RecordingI2c {
+    bus: Arc<Mutex<Bus>>,
+}
+
+impl RecordingI2c {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 直接设置寄存器的值，不记录，用来模拟芯片的默认值
+    pub fn set_register(&self, addr: u8, reg: u8, value: u8) {
+        self.bus
+            .lock()
+            .unwrap()
+            .registers
+            .insert((addr, reg), value);
+    }
+
+    pub fn register(&self, addr: u8, reg: u8) -> u8 {
+        let bus = self.bus.lock().unwrap();
+        bus.registers.get(&(addr, reg)).copied().unwrap_or(0)
+    }
+
+    pub fn ops(&self) -> Vec<I2cOp> {
+        self.bus.lock().unwrap().ops.clone()
+    }
+
+    /// 只看写操作，拆成一个个 `(寄存器, 值)`
+    pub fn writes(&self, addr: u8) -> Vec<(u8, u8)> {
+        let mut writes = Vec::new();
+        for op in self.bus.lock().unwrap().ops.iter() {
+            if let I2cOp::Write {
+                addr: op_addr,
+                reg,
+                data,
+            } = op
+            {
+                if *op_addr == addr {
+                    for (i, value) in data.iter().enumerate() {
+                        writes.push((reg.wrapping_add(i as u8), *value));
+                    }
+                }
+            }
+        }
+        writes
+    }
+
+    /// 清掉记录，寄存器的值保留
+    pub fn clear(&self) {
+        self.bus.lock().unwrap().ops.clear();
+    }
+}
+
+impl Write for RecordingI2c {
+    type Error = Infallible;
+
+    fn write(&mut self, addr: u8, bytes: &[u8]) -> Result<(), Self::Error> {
+        let Some((&reg, data)) = bytes.split_first() else {
+            return Ok(());
+        };
+        let mut bus = self.bus.lock().unwrap();
+        for (i, value) in data.iter().enumerate() {
+            bus.registers
+                .insert((addr, reg.wrapping_add(i as u8)), *value);
+        }
+        bus.ops.push(I2cOp::Write {
+            addr,
+            reg,
+            data: data.to_vec(),
+        });
+        Ok(())
+    }
+}
+
+impl WriteRead for RecordingI2c {
+    type Error = Infallible;
+
+    fn write_read(&mut self, addr: u8, bytes: &[u8], buffer: &mut [u8]) -> Result<(), Self::Error> {
+        let reg = bytes.first().copied().unwrap_or(0);
+        let mut bus = self.bus.lock().unwrap();
+        for (i, value) in buffer.iter_mut().enumerate() {
+            *value = bus
+                .registers
+                .get(&(addr, reg.wrapping_add(i as u8)))
+                .copied()
+                .unwrap_or(0);
+        }
+        bus.ops.push(I2cOp::Read {
+            addr,
+            reg,
+            len: buffer.len(),
+        });
+        Ok(())
+    }
+}
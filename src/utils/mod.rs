@@ -1,3 +1,7 @@
 pub mod bits;
+#[cfg(target_os = "espidf")]
 pub mod ffi;
+#[cfg(target_os = "espidf")]
 pub mod md5;
+pub mod sha256;
+pub mod task;
@@ -0,0 +1,73 @@
+#[cfg(target_os = "espidf")]
+use std::mem::MaybeUninit;
+
+#[cfg(target_os = "espidf")]
+use anyhow::{anyhow, Result};
+#[cfg(target_os = "espidf")]
+use esp_idf_sys::{
+    mbedtls_sha256_context, mbedtls_sha256_finish, mbedtls_sha256_free, mbedtls_sha256_init,
+    mbedtls_sha256_starts, mbedtls_sha256_update,
+};
+
+/// 流式计算 SHA-256，用于边下载固件边校验，不需要把整个固件放在内存里
+#[cfg(target_os = "espidf")]
+pub struct Sha256 {
+    ctx: Box<mbedtls_sha256_context>,
+}
+
+#[cfg(target_os = "espidf")]
+impl Sha256 {
+    pub fn new() -> Result<Self> {
+        // context 里有内部指针（硬件加速时），放在 Box 里避免被移动
+        let mut ctx =
+            Box::new(unsafe { MaybeUninit::<mbedtls_sha256_context>::zeroed().assume_init() });
+        unsafe {
+            mbedtls_sha256_init(ctx.as_mut());
+            // 第二个参数 0 表示 SHA-256，1 表示 SHA-224
+            let ret = mbedtls_sha256_starts(ctx.as_mut(), 0);
+            if ret != 0 {
+                mbedtls_sha256_free(ctx.as_mut());
+                return Err(anyhow!("mbedtls_sha256_starts failed: {}", ret));
+            }
+        }
+        Ok(Self { ctx })
+    }
+
+    pub fn update(&mut self, data: &[u8]) -> Result<()> {
+        let ret = unsafe { mbedtls_sha256_update(self.ctx.as_mut(), data.as_ptr(), data.len()) };
+        if ret != 0 {
+            return Err(anyhow!("mbedtls_sha256_update failed: {}", ret));
+        }
+        Ok(())
+    }
+
+    pub fn finish(mut self) -> Result<[u8; 32]> {
+        let mut output = [0u8; 32];
+        let ret = unsafe { mbedtls_sha256_finish(self.ctx.as_mut(), output.as_mut_ptr()) };
+        if ret != 0 {
+            return Err(anyhow!("mbedtls_sha256_finish failed: {}", ret));
+        }
+        Ok(output)
+    }
+}
+
+#[cfg(target_os = "espidf")]
+impl Drop for Sha256 {
+    fn drop(&mut self) {
+        unsafe { mbedtls_sha256_free(self.ctx.as_mut()) };
+    }
+}
+
+/// 和 `calc_md5_builtin` 一样，返回小写的十六进制字符串
+#[cfg(target_os = "espidf")]
+pub fn calc_sha256_builtin(data: &[u8]) -> Result<String> {
+    let mut sha256 = Sha256::new()?;
+    sha256.update(data)?;
+    Ok(to_hex(&sha256.finish()?))
+}
+
+pub fn to_hex(data: &[u8]) -> String {
+    data.iter()
+        .map(|b| format!("{:02x}", b))
+        .collect::<String>()
+}
@@ -0,0 +1,58 @@
+use std::ffi::CStr;
+
+use anyhow::Result;
+
+pub type TaskClosure = Box<dyn FnOnce() + Send + 'static>;
+
+/// 创建一个固定在 `core` 上运行的任务。
+///
+/// 板子上直接用 FreeRTOS 的 `xTaskCreatePinnedToCore`，栈大小按字节算；
+/// host-sim 下没有优先级和核心，就是一个普通线程
+#[cfg(target_os = "espidf")]
+pub fn spawn_pinned(
+    name: &'static CStr,
+    stack_size: u32,
+    priority: u32,
+    core: i32,
+    task: TaskClosure,
+) -> Result<()> {
+    use std::{ffi::c_void, ptr};
+
+    use anyhow::bail;
+
+    use crate::utils::ffi::c_task_trampoline;
+
+    let task_ptr = Box::into_raw(Box::new(task));
+    let res = unsafe {
+        esp_idf_sys::xTaskCreatePinnedToCore(
+            Some(c_task_trampoline),
+            name.as_ptr(),
+            stack_size,
+            task_ptr as *mut c_void,
+            priority,
+            ptr::null_mut(),
+            core,
+        )
+    };
+    if res != 1 {
+        // 创建失败时收回闭包，否则会泄漏
+        drop(unsafe { Box::from_raw(task_ptr) });
+        bail!("Failed to create task {:?}: {}", name, res);
+    }
+    Ok(())
+}
+
+#[cfg(not(target_os = "espidf"))]
+pub fn spawn_pinned(
+    name: &'static CStr,
+    stack_size: u32,
+    _priority: u32,
+    _core: i32,
+    task: TaskClosure,
+) -> Result<()> {
+    std::thread::Builder::new()
+        .name(name.to_string_lossy().into_owned())
+        .stack_size(stack_size as usize)
+        .spawn(task)?;
+    Ok(())
+}
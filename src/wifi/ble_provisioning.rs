@@ -0,0 +1,544 @@
+//! 通过 BLE GATT 配网，供配套 App 使用。
+//!
+//! 服务包含四个特征值：
+//! - scan: 写入任意值开始扫描，扫描完成后读取得到 `GET /scan` 同样格式的 JSON
+//! - credentials: 写入 `{"ssid": "...", "password": "..."}`，设备会先测试连接，成功后保存。
+//!   也可以带上 `auth`、`identity`、`hidden`、`static_ip`，格式和保存在 NVS 里的 `SsidItem` 一样
+//! - server_url: 读写 websocket 服务器地址
+//! - status: 可读，可订阅通知，内容为 `{"state": "...", "message": "..."}`
+//!
+//! credentials 和 server_url 只允许加密的连接读写，连接后设备会主动发起配对并绑定，
+//! 否则附近的任何蓝牙设备都能不配对就读到 WiFi 密码、改掉服务器地址。
+//! 设备没有输入和确认的手段，只能用 Just Works 配对。
+
+use std::{
+    collections::HashMap,
+    sync::{
+        mpsc::{channel, Sender},
+        Arc, Mutex,
+    },
+    thread,
+};
+
+use anyhow::Result;
+use enumset::enum_set;
+use esp_idf_hal::modem::BluetoothModemPeripheral;
+use esp_idf_svc::{
+    bt::{
+        ble::{
+            gap::{
+                AdvConfiguration, AuthenticationRequest, BleEncryption, BleGapEvent, EspBleGap,
+                IOCapabilities, KeyMask, SecurityConfiguration,
+            },
+            gatt::{
+                server::{ConnectionId, EspGatts, GattsEvent},
+                AutoResponse, GattCharacteristic, GattDescriptor, GattId, GattInterface,
+                GattServiceId, GattStatus, Handle, Permission, Property,
+            },
+        },
+        Ble, BtDriver, BtStatus, BtUuid,
+    },
+    eventloop::EspSystemEventLoop,
+    wifi::EspWifi,
+};
+use esp_idf_sys::{EspError, ESP_FAIL};
+use log::{error, info, warn};
+use serde::Serialize;
+
+use crate::{
+    common::restart::schedule_restart,
+    setting::settings::{Settings, SettingsGroup},
+    wifi::{
+        ssid_manager::{SsidItem, SsidMananger},
+        wifi_driver::{scan_access_points, try_connect},
+    },
+};
+
+const APP_ID: u16 = 0;
+const DEVICE_NAME: &str = "xiaozhi_prov";
+const MAX_VALUE_LEN: usize = 512;
+
+pub const SERVICE_UUID: u128 = 0x7c1e0001_6d9a_4b1c_9f3e_2a8b5c4d3e2f;
+pub const SCAN_CHARACTERISTIC_UUID: u128 = 0x7c1e0002_6d9a_4b1c_9f3e_2a8b5c4d3e2f;
+pub const CREDENTIALS_CHARACTERISTIC_UUID: u128 = 0x7c1e0003_6d9a_4b1c_9f3e_2a8b5c4d3e2f;
+pub const SERVER_URL_CHARACTERISTIC_UUID: u128 = 0x7c1e0004_6d9a_4b1c_9f3e_2a8b5c4d3e2f;
+pub const STATUS_CHARACTERISTIC_UUID: u128 = 0x7c1e0005_6d9a_4b1c_9f3e_2a8b5c4d3e2f;
+const CCCD_UUID: u16 = 0x2902;
+
+type ProvBtDriver = BtDriver<'static, Ble>;
+type ProvGap = Arc<EspBleGap<'static, Ble, Arc<ProvBtDriver>>>;
+type ProvGatts = Arc<EspGatts<'static, Ble, Arc<ProvBtDriver>>>;
+
+#[derive(Serialize)]
+struct ProvisioningStatus<'a> {
+    state: &'a str,
+    message: &'a str,
+}
+
+/// 耗时的扫描和连接放到单独的线程里做，不能阻塞蓝牙协议栈的回调
+enum ProvisioningCommand {
+    Scan,
+    Connect(SsidItem),
+}
+
+#[derive(Default)]
+struct State {
+    gatt_if: Option<GattInterface>,
+    service_handle: Option<Handle>,
+    scan_handle: Option<Handle>,
+    credentials_handle: Option<Handle>,
+    server_url_handle: Option<Handle>,
+    status_handle: Option<Handle>,
+    status_cccd_handle: Option<Handle>,
+    /// 已连接的设备，以及它们是否订阅了 status 通知
+    connections: HashMap<ConnectionId, bool>,
+    /// 长写(prepare write)的数据先缓存起来，收到 ExecWrite 后再处理
+    prepared_writes: HashMap<ConnectionId, (Handle, Vec<u8>)>,
+}
+
+#[derive(Clone)]
+pub struct BleProvisioning {
+    gap: ProvGap,
+    gatts: ProvGatts,
+    state: Arc<Mutex<State>>,
+    command_sender: Sender<ProvisioningCommand>,
+}
+
+impl BleProvisioning {
+    pub fn start(
+        modem: impl BluetoothModemPeripheral + 'static,
+        wifi: Arc<Mutex<EspWifi<'static>>>,
+        sysloop: EspSystemEventLoop,
+    ) -> Result<Self> {
+        let bt = Arc::new(BtDriver::new(modem, None)?);
+        let (command_sender, command_receiver) = channel::<ProvisioningCommand>();
+
+        let server = Self {
+            gap: Arc::new(EspBleGap::new(bt.clone())?),
+            gatts: Arc::new(EspGatts::new(bt)?),
+            state: Arc::new(Mutex::new(State::default())),
+            command_sender,
+        };
+
+        let worker = server.clone();
+        thread::Builder::new()
+            .name("ble_prov_task".into())
+            .stack_size(8 * 1024)
+            .spawn(move || {
+                for command in command_receiver {
+                    worker.handle_command(command, &wifi, &sysloop);
+                }
+            })?;
+
+        let gap_server = server.clone();
+        server.gap.subscribe(move |event| {
+            gap_server.check_esp_status(gap_server.on_gap_event(event));
+        })?;
+
+        let gatts_server = server.clone();
+        server.gatts.subscribe(move |(gatt_if, event)| {
+            gatts_server.check_esp_status(gatts_server.on_gatts_event(gatt_if, event));
+        })?;
+
+        server.gatts.register_app(APP_ID)?;
+        info!("BLE provisioning started, device name: {}", DEVICE_NAME);
+
+        Ok(server)
+    }
+
+    fn handle_command(
+        &self,
+        command: ProvisioningCommand,
+        wifi: &Arc<Mutex<EspWifi<'static>>>,
+        sysloop: &EspSystemEventLoop,
+    ) {
+        match command {
+            ProvisioningCommand::Scan => {
+                self.set_status("scanning", "");
+                let result = {
+                    let mut esp_wifi = wifi.lock().unwrap();
+                    scan_access_points(&mut esp_wifi, sysloop)
+                };
+                match result {
+                    Ok(mut records) => {
+                        // 一个特征值最多 512 字节，放不下的弱信号 AP 就不返回了
+                        let mut json = serde_json::to_vec(&records).unwrap_or_default();
+                        while json.len() > MAX_VALUE_LEN && !records.is_empty() {
+                            records.pop();
+                            json = serde_json::to_vec(&records).unwrap_or_default();
+                        }
+                        if let Some(handle) = self.state.lock().unwrap().scan_handle {
+                            self.check_esp_status(self.gatts.set_attr(handle, &json));
+                        }
+                        self.set_status("scan_done", "");
+                    }
+                    Err(e) => {
+                        error!("BLE provisioning scan failed: {:?}", e);
+                        self.set_status("scan_failed", &e.to_string());
+                    }
+                }
+            }
+            ProvisioningCommand::Connect(credentials) => {
+                info!("BLE provisioning: testing wifi {}", credentials.ssid);
+                self.set_status("connecting", &credentials.ssid);
+                let result = {
+                    let mut esp_wifi = wifi.lock().unwrap();
+                    try_connect(&mut esp_wifi, sysloop, &credentials)
+                };
+                match result {
+                    Ok(ip_info) => {
+                        if let Err(e) = SsidMananger::get_instance().add_network(credentials) {
+                            error!("添加新wifi失败: {:?}", e);
+                            self.set_status("failed", &e.to_string());
+                            return;
+                        }
+                        self.set_status("connected", &ip_info.ip.to_string());
+                        schedule_restart();
+                    }
+                    Err(e) => {
+                        error!("BLE provisioning connect failed: {:?}", e);
+                        self.set_status("failed", &e.to_string());
+                    }
+                }
+            }
+        }
+    }
+
+    /// 更新 status 特征值，并通知所有订阅了的设备
+    fn set_status(&self, state: &str, message: &str) {
+        let value = serde_json::to_vec(&ProvisioningStatus { state, message }).unwrap_or_default();
+
+        let state = self.state.lock().unwrap();
+        let (Some(gatt_if), Some(status_handle)) = (state.gatt_if, state.status_handle) else {
+            return;
+        };
+
+        self.check_esp_status(self.gatts.set_attr(status_handle, &value));
+        for (conn_id, subscribed) in state.connections.iter() {
+            if *subscribed {
+                self.check_esp_status(self.gatts.notify(gatt_if, *conn_id, status_handle, &value));
+            }
+        }
+    }
+
+    fn on_gap_event(&self, event: BleGapEvent) -> Result<(), EspError> {
+        match event {
+            BleGapEvent::AdvertisingConfigured(status) => {
+                self.check_bt_status(status)?;
+                self.gap.start_advertising()?;
+            }
+            BleGapEvent::AuthenticationComplete { bd_addr, status } => {
+                if matches!(status, BtStatus::Success) {
+                    info!("BLE provisioning: {} paired", bd_addr);
+                } else {
+                    warn!("BLE provisioning: pairing with {} failed", bd_addr);
+                }
+            }
+            _ => (),
+        }
+        Ok(())
+    }
+
+    fn on_gatts_event(&self, gatt_if: GattInterface, event: GattsEvent) -> Result<(), EspError> {
+        match event {
+            GattsEvent::ServiceRegistered { status, app_id } => {
+                self.check_gatt_status(status)?;
+                if app_id == APP_ID {
+                    self.create_service(gatt_if)?;
+                }
+            }
+            GattsEvent::ServiceCreated {
+                status,
+                service_handle,
+                ..
+            } => {
+                self.check_gatt_status(status)?;
+                self.state.lock().unwrap().service_handle = Some(service_handle);
+                self.gatts.start_service(service_handle)?;
+                self.add_characteristics(service_handle)?;
+            }
+            GattsEvent::CharacteristicAdded {
+                status,
+                attr_handle,
+                service_handle,
+                char_uuid,
+            } => {
+                self.check_gatt_status(status)?;
+                self.register_characteristic(service_handle, attr_handle, char_uuid)?;
+            }
+            GattsEvent::DescriptorAdded {
+                status,
+                attr_handle,
+                descr_uuid,
+                ..
+            } => {
+                self.check_gatt_status(status)?;
+                if descr_uuid == BtUuid::uuid16(CCCD_UUID) {
+                    self.state.lock().unwrap().status_cccd_handle = Some(attr_handle);
+                }
+            }
+            GattsEvent::PeerConnected { conn_id, addr, .. } => {
+                info!("BLE provisioning: {} connected", addr);
+                self.state.lock().unwrap().connections.insert(conn_id, false);
+                self.gap.set_conn_params_conf(addr, 10, 20, 0, 400)?;
+                // 主动发起配对，加密后才能读写 credentials 和 server_url
+                self.gap.set_encryption(addr, BleEncryption::EncryptionNoMitm)?;
+            }
+            GattsEvent::PeerDisconnected { conn_id, addr, .. } => {
+                info!("BLE provisioning: {} disconnected", addr);
+                {
+                    let mut state = self.state.lock().unwrap();
+                    state.connections.remove(&conn_id);
+                    state.prepared_writes.remove(&conn_id);
+                }
+                // 重新广播，允许 App 再次连接
+                self.set_adv_conf()?;
+            }
+            GattsEvent::Write {
+                conn_id,
+                addr,
+                handle,
+                offset,
+                is_prep,
+                value,
+                ..
+            } => {
+                if is_prep {
+                    let mut state = self.state.lock().unwrap();
+                    let entry = state
+                        .prepared_writes
+                        .entry(conn_id)
+                        .or_insert_with(|| (handle, Vec::new()));
+                    if offset as usize == entry.1.len() && entry.1.len() < MAX_VALUE_LEN {
+                        entry.1.extend_from_slice(value);
+                    } else {
+                        warn!("Unexpected prepare write offset {} from {}", offset, addr);
+                    }
+                } else {
+                    self.on_write(conn_id, handle, value);
+                }
+            }
+            GattsEvent::ExecWrite {
+                conn_id, canceled, ..
+            } => {
+                let prepared = self.state.lock().unwrap().prepared_writes.remove(&conn_id);
+                if let (false, Some((handle, value))) = (canceled, prepared) {
+                    self.on_write(conn_id, handle, &value);
+                }
+            }
+            _ => (),
+        }
+
+        Ok(())
+    }
+
+    fn on_write(&self, conn_id: ConnectionId, handle: Handle, value: &[u8]) {
+        let (scan_handle, credentials_handle, server_url_handle, status_cccd_handle) = {
+            let state = self.state.lock().unwrap();
+            (
+                state.scan_handle,
+                state.credentials_handle,
+                state.server_url_handle,
+                state.status_cccd_handle,
+            )
+        };
+
+        if Some(handle) == status_cccd_handle {
+            if value.len() == 2 {
+                let subscribed = u16::from_le_bytes([value[0], value[1]]) & 0x01 != 0;
+                self.state
+                    .lock()
+                    .unwrap()
+                    .connections
+                    .insert(conn_id, subscribed);
+            }
+        } else if Some(handle) == scan_handle {
+            self.send_command(ProvisioningCommand::Scan);
+        } else if Some(handle) == credentials_handle {
+            match serde_json::from_slice::<SsidItem>(value) {
+                Ok(credentials) => self.send_command(ProvisioningCommand::Connect(credentials)),
+                Err(_) => self.set_status("failed", "invalid credentials"),
+            }
+        } else if Some(handle) == server_url_handle {
+            let url = String::from_utf8_lossy(value).trim().to_string();
+            if !url.starts_with("ws://") && !url.starts_with("wss://") {
+                self.set_status("failed", "invalid server url");
+                return;
+            }
+            match Settings::update(SettingsGroup::Server, |settings| {
+                settings.server.ws_url = Some(url.clone());
+            }) {
+                Ok(_) => {
+                    info!("BLE provisioning: server url set to {}", url);
+                    self.set_status("server_url_saved", &url);
+                }
+                Err(e) => {
+                    error!("Failed to save server url: {:?}", e);
+                    self.set_status("failed", &e.to_string());
+                }
+            }
+        }
+    }
+
+    fn send_command(&self, command: ProvisioningCommand) {
+        if let Err(e) = self.command_sender.send(command) {
+            error!("Failed to send provisioning command: {:?}", e);
+        }
+    }
+
+    fn set_adv_conf(&self) -> Result<(), EspError> {
+        self.gap.set_adv_conf(&AdvConfiguration {
+            include_name: true,
+            include_txpower: true,
+            flag: 2,
+            service_uuid: Some(BtUuid::uuid128(SERVICE_UUID)),
+            ..Default::default()
+        })
+    }
+
+    fn create_service(&self, gatt_if: GattInterface) -> Result<(), EspError> {
+        self.state.lock().unwrap().gatt_if = Some(gatt_if);
+
+        self.gap.set_device_name(DEVICE_NAME)?;
+        self.gap.set_security_conf(&SecurityConfiguration {
+            auth_req_mode: AuthenticationRequest::SecureBonding,
+            io_capabilities: IOCapabilities::NoInputNoOutput,
+            initiator_key: Some(KeyMask::EncryptionKey | KeyMask::IdentityResolvingKey),
+            responder_key: Some(KeyMask::EncryptionKey | KeyMask::IdentityResolvingKey),
+            max_key_size: Some(16),
+            ..Default::default()
+        })?;
+        self.set_adv_conf()?;
+        // 1 个服务 + 4 个特征值(各占 2 个 handle) + 1 个 CCCD
+        self.gatts.create_service(
+            gatt_if,
+            &GattServiceId {
+                id: GattId {
+                    uuid: BtUuid::uuid128(SERVICE_UUID),
+                    inst_id: 0,
+                },
+                is_primary: true,
+            },
+            12,
+        )?;
+        Ok(())
+    }
+
+    fn add_characteristics(&self, service_handle: Handle) -> Result<(), EspError> {
+        self.gatts.add_characteristic(
+            service_handle,
+            &GattCharacteristic {
+                uuid: BtUuid::uuid128(SCAN_CHARACTERISTIC_UUID),
+                permissions: enum_set!(Permission::Read | Permission::Write),
+                properties: enum_set!(Property::Read | Property::Write),
+                max_len: MAX_VALUE_LEN,
+                auto_rsp: AutoResponse::ByGatt,
+            },
+            b"[]",
+        )?;
+
+        self.gatts.add_characteristic(
+            service_handle,
+            &GattCharacteristic {
+                uuid: BtUuid::uuid128(CREDENTIALS_CHARACTERISTIC_UUID),
+                permissions: enum_set!(Permission::WriteEncrypted),
+                properties: enum_set!(Property::Write),
+                max_len: MAX_VALUE_LEN,
+                auto_rsp: AutoResponse::ByGatt,
+            },
+            &[],
+        )?;
+
+        let server_url = Settings::load()
+            .ok()
+            .and_then(|settings| settings.server.ws_url)
+            .unwrap_or_default();
+        self.gatts.add_characteristic(
+            service_handle,
+            &GattCharacteristic {
+                uuid: BtUuid::uuid128(SERVER_URL_CHARACTERISTIC_UUID),
+                permissions: enum_set!(Permission::ReadEncrypted | Permission::WriteEncrypted),
+                properties: enum_set!(Property::Read | Property::Write),
+                max_len: MAX_VALUE_LEN,
+                auto_rsp: AutoResponse::ByGatt,
+            },
+            server_url.as_bytes(),
+        )?;
+
+        // status 必须最后添加，CCCD 描述符会挂在最近添加的特征值上
+        self.gatts.add_characteristic(
+            service_handle,
+            &GattCharacteristic {
+                uuid: BtUuid::uuid128(STATUS_CHARACTERISTIC_UUID),
+                permissions: enum_set!(Permission::Read),
+                properties: enum_set!(Property::Read | Property::Notify),
+                max_len: MAX_VALUE_LEN,
+                auto_rsp: AutoResponse::ByGatt,
+            },
+            br#"{"state":"idle","message":""}"#,
+        )?;
+
+        Ok(())
+    }
+
+    fn register_characteristic(
+        &self,
+        service_handle: Handle,
+        attr_handle: Handle,
+        char_uuid: BtUuid,
+    ) -> Result<(), EspError> {
+        let is_status = {
+            let mut state = self.state.lock().unwrap();
+            if state.service_handle != Some(service_handle) {
+                return Ok(());
+            }
+
+            if char_uuid == BtUuid::uuid128(SCAN_CHARACTERISTIC_UUID) {
+                state.scan_handle = Some(attr_handle);
+            } else if char_uuid == BtUuid::uuid128(CREDENTIALS_CHARACTERISTIC_UUID) {
+                state.credentials_handle = Some(attr_handle);
+            } else if char_uuid == BtUuid::uuid128(SERVER_URL_CHARACTERISTIC_UUID) {
+                state.server_url_handle = Some(attr_handle);
+            } else if char_uuid == BtUuid::uuid128(STATUS_CHARACTERISTIC_UUID) {
+                state.status_handle = Some(attr_handle);
+            }
+            state.status_handle == Some(attr_handle)
+        };
+
+        if is_status {
+            self.gatts.add_descriptor(
+                service_handle,
+                &GattDescriptor {
+                    uuid: BtUuid::uuid16(CCCD_UUID),
+                    permissions: enum_set!(Permission::Read | Permission::Write),
+                },
+            )?;
+        }
+
+        Ok(())
+    }
+
+    fn check_esp_status(&self, status: Result<(), EspError>) {
+        if let Err(e) = status {
+            warn!("BLE provisioning error: {:?}", e);
+        }
+    }
+
+    fn check_bt_status(&self, status: BtStatus) -> Result<(), EspError> {
+        if !matches!(status, BtStatus::Success) {
+            warn!("Got status: {:?}", status);
+            Err(EspError::from_infallible::<ESP_FAIL>())
+        } else {
+            Ok(())
+        }
+    }
+
+    fn check_gatt_status(&self, status: GattStatus) -> Result<(), EspError> {
+        if !matches!(status, GattStatus::Ok) {
+            warn!("Got status: {:?}", status);
+            Err(EspError::from_infallible::<ESP_FAIL>())
+        } else {
+            Ok(())
+        }
+    }
+}
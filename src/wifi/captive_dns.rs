@@ -0,0 +1,268 @@
+use std::{
+    net::{Ipv4Addr, UdpSocket},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    thread::{self, JoinHandle},
+    time::Duration,
+};
+
+use anyhow::Result;
+use log::{error, info, warn};
+
+const DNS_PORT: u16 = 53;
+const DNS_HEADER_LEN: usize = 12;
+const MAX_DNS_PACKET_LEN: usize = 512;
+const ANSWER_TTL_SECS: u32 = 60;
+
+const QTYPE_A: u16 = 1;
+const QTYPE_ANY: u16 = 255;
+const QCLASS_IN: u16 = 1;
+
+/// 解析一个 DNS 查询，构造把所有 A 记录都指向 `ip` 的应答。
+///
+/// 只处理标准查询的第一个问题；不是合法查询时返回 None，调用方直接丢弃即可。
+/// 这是一个纯函数，不依赖 socket，方便用抓包得到的数据来验证。
+pub fn build_response(query: &[u8], ip: Ipv4Addr) -> Option<Vec<u8>> {
+    if query.len() < DNS_HEADER_LEN || query.len() > MAX_DNS_PACKET_LEN {
+        return None;
+    }
+
+    let flags = u16::from_be_bytes([query[2], query[3]]);
+    let is_response = flags & 0x8000 != 0;
+    let opcode = (flags >> 11) & 0x0F;
+    let qdcount = u16::from_be_bytes([query[4], query[5]]);
+    if is_response || opcode != 0 || qdcount == 0 {
+        return None;
+    }
+
+    // 问题部分: QNAME(若干 label，以 0 结束) + QTYPE + QCLASS
+    let mut pos = DNS_HEADER_LEN;
+    loop {
+        let label_len = *query.get(pos)? as usize;
+        if label_len == 0 {
+            pos += 1;
+            break;
+        }
+        // 查询报文里不应该出现压缩指针
+        if label_len & 0xC0 != 0 {
+            return None;
+        }
+        pos += 1 + label_len;
+    }
+    let question_end = pos + 4;
+    if question_end > query.len() {
+        return None;
+    }
+    let qtype = u16::from_be_bytes([query[pos], query[pos + 1]]);
+    let qclass = u16::from_be_bytes([query[pos + 2], query[pos + 3]]);
+
+    let answer = (qtype == QTYPE_A || qtype == QTYPE_ANY) && qclass == QCLASS_IN;
+
+    let mut response = Vec::with_capacity(question_end + 16);
+    // ID
+    response.extend_from_slice(&query[0..2]);
+    // QR=1, AA=1, 保留 RD，RCODE=0
+    let response_flags: u16 = 0x8000 | 0x0400 | (flags & 0x0100);
+    response.extend_from_slice(&response_flags.to_be_bytes());
+    // QDCOUNT, ANCOUNT, NSCOUNT, ARCOUNT
+    response.extend_from_slice(&1u16.to_be_bytes());
+    response.extend_from_slice(&(answer as u16).to_be_bytes());
+    response.extend_from_slice(&0u16.to_be_bytes());
+    response.extend_from_slice(&0u16.to_be_bytes());
+    // 原样带回第一个问题
+    response.extend_from_slice(&query[DNS_HEADER_LEN..question_end]);
+
+    if answer {
+        // NAME 使用压缩指针指向偏移 12 处的问题名称
+        response.extend_from_slice(&[0xC0, 0x0C]);
+        response.extend_from_slice(&QTYPE_A.to_be_bytes());
+        response.extend_from_slice(&QCLASS_IN.to_be_bytes());
+        response.extend_from_slice(&ANSWER_TTL_SECS.to_be_bytes());
+        response.extend_from_slice(&4u16.to_be_bytes());
+        response.extend_from_slice(&ip.octets());
+    }
+
+    Some(response)
+}
+
+/// SoftAP 模式下的 DNS 服务器，把所有域名都解析成 AP 自己的 IP，
+/// 手机连上热点后会自动弹出配网页面。drop 时停止。
+pub struct CaptiveDnsServer {
+    running: Arc<AtomicBool>,
+    handle: Option<JoinHandle<()>>,
+}
+
+impl CaptiveDnsServer {
+    pub fn start(ip: Ipv4Addr) -> Result<Self> {
+        let socket = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, DNS_PORT))?;
+        // 定时超时，这样 stop 时线程能退出
+        socket.set_read_timeout(Some(Duration::from_millis(500)))?;
+
+        let running = Arc::new(AtomicBool::new(true));
+        let running_clone = running.clone();
+
+        let handle = thread::Builder::new()
+            .name("captive_dns".into())
+            .stack_size(4 * 1024)
+            .spawn(move || {
+                let mut buf = [0u8; MAX_DNS_PACKET_LEN];
+                while running_clone.load(Ordering::Relaxed) {
+                    let (len, peer) = match socket.recv_from(&mut buf) {
+                        Ok(result) => result,
+                        Err(e)
+                            if e.kind() == std::io::ErrorKind::WouldBlock
+                                || e.kind() == std::io::ErrorKind::TimedOut =>
+                        {
+                            continue;
+                        }
+                        Err(e) => {
+                            error!("DNS recv error: {:?}", e);
+                            thread::sleep(Duration::from_millis(100));
+                            continue;
+                        }
+                    };
+
+                    match build_response(&buf[..len], ip) {
+                        Some(response) => {
+                            if let Err(e) = socket.send_to(&response, peer) {
+                                warn!("DNS send error: {:?}", e);
+                            }
+                        }
+                        None => {
+                            warn!("Ignore invalid DNS packet from {}", peer);
+                        }
+                    }
+                }
+                info!("Captive DNS server stopped");
+            })?;
+
+        info!("Captive DNS server started, all domains resolve to {}", ip);
+        Ok(Self {
+            running,
+            handle: Some(handle),
+        })
+    }
+}
+
+impl Drop for CaptiveDnsServer {
+    fn drop(&mut self) {
+        self.running.store(false, Ordering::Relaxed);
+        if let Some(handle) = self.handle.take() {
+            let _ = handle.join();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const AP_IP: Ipv4Addr = Ipv4Addr::new(192, 168, 71, 1);
+
+    /// `dig connectivitycheck.gstatic.com A` 抓到的查询，带一个 EDNS 的 OPT 记录
+    const A_QUERY: [u8; 58] = [
+        0x5c, 0x3e, 0x01, 0x20, 0x00, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x01, // header
+        0x11, b'c', b'o', b'n', b'n', b'e', b'c', b't', b'i', b'v', b'i', b't', b'y', b'c', b'h',
+        b'e', b'c', b'k', 0x07, b'g', b's', b't', b'a', b't', b'i', b'c', 0x03, b'c', b'o', b'm',
+        0x00, // QNAME
+        0x00, 0x01, 0x00, 0x01, // QTYPE=A, QCLASS=IN
+        0x00, 0x00, 0x29, 0x04, 0xd0, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, // OPT
+    ];
+    /// 问题部分在 `A_QUERY` 里的范围
+    const A_QUESTION: std::ops::Range<usize> = 12..47;
+
+    /// iOS 发的 `captive.apple.com AAAA` 查询
+    const AAAA_QUERY: [u8; 35] = [
+        0xa1, 0x07, 0x01, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, // header
+        0x07, b'c', b'a', b'p', b't', b'i', b'v', b'e', 0x05, b'a', b'p', b'p', b'l', b'e', 0x03,
+        b'c', b'o', b'm', 0x00, // QNAME
+        0x00, 0x1c, 0x00, 0x01, // QTYPE=AAAA, QCLASS=IN
+    ];
+
+    fn header(response: &[u8]) -> [u16; 6] {
+        let mut fields = [0u16; 6];
+        for (i, field) in fields.iter_mut().enumerate() {
+            *field = u16::from_be_bytes([response[i * 2], response[i * 2 + 1]]);
+        }
+        fields
+    }
+
+    #[test]
+    fn a_query_resolves_to_ap_ip() {
+        let response = build_response(&A_QUERY, AP_IP).unwrap();
+
+        // ID 不变，QR=1 AA=1 并保留 RD，一个问题一个回答，OPT 不带回去
+        assert_eq!(header(&response), [0x5c3e, 0x8500, 1, 1, 0, 0]);
+        assert_eq!(&response[12..47], &A_QUERY[A_QUESTION]);
+        assert_eq!(
+            &response[47..],
+            &[
+                0xc0, 0x0c, // 指向问题里的名称
+                0x00, 0x01, 0x00, 0x01, // A, IN
+                0x00, 0x00, 0x00, 0x3c, // TTL 60
+                0x00, 0x04, 192, 168, 71, 1,
+            ]
+        );
+    }
+
+    #[test]
+    fn aaaa_query_has_no_answer() {
+        let response = build_response(&AAAA_QUERY, AP_IP).unwrap();
+
+        assert_eq!(header(&response), [0xa107, 0x8500, 1, 0, 0, 0]);
+        assert_eq!(&response[12..], &AAAA_QUERY[12..]);
+    }
+
+    #[test]
+    fn truncated_packets_are_ignored() {
+        assert_eq!(build_response(&A_QUERY[..11], AP_IP), None);
+        // header 完整，但问题被截断在 QNAME 或者 QTYPE/QCLASS 里
+        assert_eq!(build_response(&A_QUERY[..20], AP_IP), None);
+        assert_eq!(build_response(&A_QUERY[..45], AP_IP), None);
+    }
+
+    #[test]
+    fn responses_are_ignored() {
+        let response = build_response(&A_QUERY, AP_IP).unwrap();
+        assert_eq!(build_response(&response, AP_IP), None);
+
+        let mut query = AAAA_QUERY;
+        query[2] |= 0x80;
+        assert_eq!(build_response(&query, AP_IP), None);
+    }
+
+    #[test]
+    fn non_standard_queries_are_ignored() {
+        // opcode=2（STATUS）
+        let mut query = AAAA_QUERY;
+        query[2] |= 0x10;
+        assert_eq!(build_response(&query, AP_IP), None);
+
+        // QDCOUNT=0
+        let mut query = AAAA_QUERY;
+        query[5] = 0;
+        assert_eq!(build_response(&query, AP_IP), None);
+
+        // QNAME 里出现压缩指针
+        let mut query = AAAA_QUERY;
+        query[12] = 0xc0;
+        assert_eq!(build_response(&query, AP_IP), None);
+    }
+
+    #[test]
+    fn multi_question_packet_answers_first_question_only() {
+        let mut query = Vec::from(&A_QUERY[..A_QUESTION.end]);
+        query[5] = 2; // QDCOUNT=2
+        query[11] = 0; // 去掉 OPT
+        query.extend_from_slice(&AAAA_QUERY[12..]);
+
+        let response = build_response(&query, AP_IP).unwrap();
+
+        assert_eq!(header(&response), [0x5c3e, 0x8500, 1, 1, 0, 0]);
+        assert_eq!(&response[12..47], &A_QUERY[A_QUESTION]);
+        assert_eq!(&response[47..49], &[0xc0, 0x0c]);
+        assert_eq!(response.len(), 47 + 16);
+    }
+}
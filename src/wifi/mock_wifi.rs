@@ -0,0 +1,75 @@
+use anyhow::{Error, Result};
+use log::info;
+
+use crate::wifi::wifi_driver::{AccessPointRecord, WifiStation};
+
+/// host-sim 用的假 WiFi，连接总是成功，网络由电脑提供
+pub struct MockWifi {
+    connected: bool,
+    power_save: bool,
+}
+
+impl MockWifi {
+    pub fn new() -> Self {
+        Self {
+            connected: false,
+            power_save: false,
+        }
+    }
+
+    pub fn power_save(&self) -> bool {
+        self.power_save
+    }
+}
+
+impl Default for MockWifi {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl WifiStation for MockWifi {
+    fn connect(&mut self, ssid: &str, _password: &str) -> Result<()> {
+        info!("Mock wifi connected to {}", ssid);
+        self.connected = true;
+        Ok(())
+    }
+
+    fn disconnect(&mut self) -> Result<()> {
+        self.connected = false;
+        Ok(())
+    }
+
+    fn is_connected(&self) -> Result<bool> {
+        Ok(self.connected)
+    }
+
+    fn get_mac_address(&self) -> Result<String> {
+        Ok("02:00:00:00:00:01".to_string())
+    }
+
+    fn get_ip_address(&self) -> Result<String> {
+        Ok("127.0.0.1".to_string())
+    }
+
+    fn get_available_access_points(&self) -> Result<Vec<AccessPointRecord>> {
+        Ok(vec![AccessPointRecord {
+            ssid: "host-sim".to_string(),
+            rssi: -40,
+            channel: 1,
+            auth_method: "WPA2Personal".to_string(),
+        }])
+    }
+
+    fn set_power_save(&mut self, enabled: bool) -> Result<()> {
+        self.power_save = enabled;
+        Ok(())
+    }
+
+    // 没有配网页面，不会有新的接入点
+    fn set_on_new_access_point_add_handler(
+        &mut self,
+        _on_new_access_point_add: Box<dyn FnMut(&str, &str) -> Result<(), Error> + Send + 'static>,
+    ) {
+    }
+}
@@ -1,2 +1,9 @@
+#[cfg(all(feature = "provisioning-ble", target_os = "espidf"))]
+pub mod ble_provisioning;
+pub mod captive_dns;
+#[cfg(feature = "host-sim")]
+pub mod mock_wifi;
 pub mod ssid_manager;
+#[cfg(target_os = "espidf")]
+pub mod station_manager;
 pub mod wifi_driver;
@@ -1,95 +1,320 @@
-use anyhow::{Ok, Result};
-use chrono::{DateTime, Utc};
-use esp_idf_svc::ping::Info;
-use log::{error, info};
+use std::{net::Ipv4Addr, sync::Mutex};
+
+use anyhow::Result;
+use chrono::{DateTime, FixedOffset, Utc};
+use log::error;
 use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::{
+    clock,
+    setting::settings::{Settings, SettingsGroup},
+};
+
+const MAX_SSID_LEN: usize = 32;
+const MIN_PASSPHRASE_LEN: usize = 8;
+const MAX_PASSWORD_LEN: usize = 64;
+const MAX_IDENTITY_LEN: usize = 128;
 
-use crate::setting::nvs_setting::NvsSetting;
+/// WiFi 的认证方式。旧版本保存的 JSON 没有这个字段，按 `Auto` 处理
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum WifiAuth {
+    /// 没有密码就是开放网络，否则按 WPA2 连接（同时兼容 WPA3 路由器）
+    #[default]
+    Auto,
+    Open,
+    Wpa2Personal,
+    Wpa3Personal,
+    Wpa2Wpa3Personal,
+    /// WPA2-Enterprise，PEAP/MSCHAPv2，需要 identity
+    Wpa2Enterprise,
+}
+
+/// 静态 IP 配置，不配置就使用 DHCP
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StaticIpConfig {
+    pub ip: Ipv4Addr,
+    pub gateway: Ipv4Addr,
+    /// 子网掩码长度，例如 24 表示 255.255.255.0
+    pub prefix_len: u8,
+    #[serde(default)]
+    pub dns: Option<Ipv4Addr>,
+}
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum WifiConfigError {
+    #[error("WiFi name is empty")]
+    EmptySsid,
+    #[error("WiFi name is longer than 32 bytes")]
+    SsidTooLong,
+    #[error("Password must be 8 to 64 characters")]
+    InvalidPasswordLength,
+    #[error("Identity is required for WPA2-Enterprise")]
+    MissingIdentity,
+    #[error("Identity is longer than 128 bytes")]
+    IdentityTooLong,
+    #[error("Invalid static IP config: {0}")]
+    InvalidStaticIp(&'static str),
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct SsidItem {
     pub ssid: String,
+    #[serde(default)]
     pub password: String,
+    #[serde(default)]
     pub last_connect_time: String,
+    #[serde(default)]
+    pub auth: WifiAuth,
+    /// WPA2-Enterprise 的用户名
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub identity: Option<String>,
+    /// 隐藏的 SSID 扫描不到，需要直接连接
+    #[serde(default)]
+    pub hidden: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub static_ip: Option<StaticIpConfig>,
+}
+
+impl SsidItem {
+    pub fn new(ssid: &str, password: &str) -> Self {
+        Self {
+            ssid: ssid.to_string(),
+            password: password.to_string(),
+            last_connect_time: String::new(),
+            auth: WifiAuth::Auto,
+            identity: None,
+            hidden: false,
+            static_ip: None,
+        }
+    }
+
+    /// 最后连接时间，时间同步之前连上的（空字符串）或解析失败时是 None
+    pub fn last_connected_at(&self) -> Option<DateTime<FixedOffset>> {
+        DateTime::parse_from_rfc3339(&self.last_connect_time).ok()
+    }
+
+    /// `Auto` 根据密码是否为空决定是开放网络还是 WPA2
+    pub fn effective_auth(&self) -> WifiAuth {
+        match self.auth {
+            WifiAuth::Auto if self.password.is_empty() => WifiAuth::Open,
+            WifiAuth::Auto => WifiAuth::Wpa2Personal,
+            auth => auth,
+        }
+    }
+
+    /// 检查各字段是否能放进 ESP-IDF 的配置里，连接和保存前都要调用
+    pub fn validate(&self) -> Result<(), WifiConfigError> {
+        if self.ssid.is_empty() {
+            return Err(WifiConfigError::EmptySsid);
+        }
+        if self.ssid.len() > MAX_SSID_LEN {
+            return Err(WifiConfigError::SsidTooLong);
+        }
+
+        match self.effective_auth() {
+            WifiAuth::Open | WifiAuth::Auto => {}
+            WifiAuth::Wpa2Personal | WifiAuth::Wpa3Personal | WifiAuth::Wpa2Wpa3Personal => {
+                if self.password.len() < MIN_PASSPHRASE_LEN
+                    || self.password.len() > MAX_PASSWORD_LEN
+                {
+                    return Err(WifiConfigError::InvalidPasswordLength);
+                }
+            }
+            WifiAuth::Wpa2Enterprise => {
+                match self.identity.as_deref() {
+                    None | Some("") => return Err(WifiConfigError::MissingIdentity),
+                    Some(identity) if identity.len() > MAX_IDENTITY_LEN => {
+                        return Err(WifiConfigError::IdentityTooLong)
+                    }
+                    _ => {}
+                }
+                if self.password.is_empty() || self.password.len() > MAX_PASSWORD_LEN {
+                    return Err(WifiConfigError::InvalidPasswordLength);
+                }
+            }
+        }
+
+        if let Some(static_ip) = &self.static_ip {
+            static_ip.validate()?;
+        }
+        Ok(())
+    }
+}
+
+impl StaticIpConfig {
+    pub fn netmask(&self) -> Ipv4Addr {
+        let bits = if self.prefix_len == 0 {
+            0
+        } else {
+            u32::MAX << (32 - self.prefix_len.min(32) as u32)
+        };
+        Ipv4Addr::from(bits)
+    }
+
+    pub fn validate(&self) -> Result<(), WifiConfigError> {
+        if self.prefix_len == 0 || self.prefix_len > 30 {
+            return Err(WifiConfigError::InvalidStaticIp("prefix length must be 1..=30"));
+        }
+        if self.ip.is_unspecified() || self.ip.is_broadcast() || self.ip.is_multicast() {
+            return Err(WifiConfigError::InvalidStaticIp("invalid address"));
+        }
+        let mask = u32::from(self.netmask());
+        if u32::from(self.ip) & mask != u32::from(self.gateway) & mask {
+            return Err(WifiConfigError::InvalidStaticIp(
+                "gateway is not in the same subnet",
+            ));
+        }
+        if u32::from(self.ip) & !mask == 0 || u32::from(self.ip) & !mask == !mask {
+            return Err(WifiConfigError::InvalidStaticIp(
+                "address is the network or broadcast address",
+            ));
+        }
+        Ok(())
+    }
 }
 
 pub struct SsidMananger {}
 
 const MAX_SSID_COUNT: usize = 10;
-const WIFI_SETTING_KEY: &str = "wifi_settings";
+
+/// 时间还没同步时连上的 WiFi，同步之后再记录连接时间
+static PENDING_TOUCH: Mutex<Option<String>> = Mutex::new(None);
+
+/// 时间没同步时是 1970 年，记下来也没法排序，返回 None
+fn now_rfc3339() -> Option<String> {
+    clock::time_synced().then(|| Utc::now().to_rfc3339())
+}
+
+/// 按最后连接时间倒序排序，没有连接时间的排在最后
+fn sort_recent_first(ssid_items: &mut [SsidItem]) {
+    ssid_items.sort_by(|a, b| b.last_connected_at().cmp(&a.last_connected_at()));
+}
+
 impl SsidMananger {
     pub fn get_instance() -> Self {
         Self {}
     }
     pub fn get_ssid_list(&self) -> Result<Vec<SsidItem>> {
-        let nvs = match NvsSetting::new("wifi") {
-            std::result::Result::Ok(nvs) => nvs,
+        let mut ssid_items = match Settings::load() {
+            std::result::Result::Ok(settings) => settings.wifi.networks,
             Err(e) => {
-                error!("failed to create nvs setting: {:?}", e);
-                return Err(e.into());
+                error!("failed to load wifi settings: {:?}", e);
+                return Err(e);
             }
         };
 
-        let mut ssid_items = Vec::new();
-
-        info!("try to get wifi_setting_json");
-        let wifi_setting_json = nvs.get_string(WIFI_SETTING_KEY);
-        info!("wifi_setting_json: {:?}", wifi_setting_json);
-
-        if let Some(json_string) = wifi_setting_json {
-            match serde_json::from_str::<Vec<SsidItem>>(&json_string) {
-                std::result::Result::Ok(mut items) => {
-                    items.sort_by(|a, b| {
-                        let a_time = DateTime::parse_from_rfc3339(&a.last_connect_time);
-                        let b_time = DateTime::parse_from_rfc3339(&b.last_connect_time);
-                        if a_time.is_ok() && b_time.is_ok() {
-                            //最后连接时间倒序排序
-                            b_time.unwrap().cmp(&a_time.unwrap())
-                        } else {
-                            std::cmp::Ordering::Equal
-                        }
-                    });
-                    ssid_items = items;
-                }
-                Err(_) => {}
-            }
-        }
+        sort_recent_first(&mut ssid_items);
 
         Ok(ssid_items)
     }
 
     pub fn add_ssid(&mut self, ssid: &str, password: &str) -> Result<()> {
+        self.add_network(SsidItem::new(ssid, password))
+    }
+
+    /// 保存完整的 WiFi 配置（认证方式、隐藏 SSID、静态 IP 等）
+    pub fn add_network(&mut self, mut network: SsidItem) -> Result<()> {
+        network.validate()?;
+
         let mut ssid_list = self.get_ssid_list()?;
+        // 同名的WiFi只保留最新的配置
+        ssid_list.retain(|item| item.ssid != network.ssid);
         if ssid_list.len() >= MAX_SSID_COUNT {
-            // 把列表按最后连接时间升序排序, 删除最老的
-            ssid_list.sort_by(|a, b| {
-                let a_time = DateTime::parse_from_rfc3339(&a.last_connect_time);
-                let b_time = DateTime::parse_from_rfc3339(&b.last_connect_time);
-                if a_time.is_ok() && b_time.is_ok() {
-                    a_time.unwrap().cmp(&b_time.unwrap())
-                } else {
-                    std::cmp::Ordering::Equal
-                }
-            });
-
-            ssid_list.remove(0);
+            // get_ssid_list 已经按最后连接时间倒序排好，最后一个最老，没有连接时间的也排在最后
+            ssid_list.pop();
         }
 
-        ssid_list.push(SsidItem {
-            ssid: ssid.to_string(),
-            password: password.to_string(),
-            last_connect_time: Utc::now().to_rfc3339(),
-        });
+        network.last_connect_time = now_rfc3339().unwrap_or_default();
+        ssid_list.push(network);
         self.save_to_nvs(&ssid_list)?;
         Ok(())
     }
 
-    fn save_to_nvs(&mut self, ssid_list: &[SsidItem]) -> Result<()> {
-        let mut nvs = NvsSetting::new("wifi")?;
+    /// 删除保存的WiFi，返回是否真的删除了
+    pub fn remove_ssid(&mut self, ssid: &str) -> Result<bool> {
+        let mut ssid_list = self.get_ssid_list()?;
+        let count = ssid_list.len();
+        ssid_list.retain(|item| item.ssid != ssid);
+        if ssid_list.len() == count {
+            return Ok(false);
+        }
+        self.save_to_nvs(&ssid_list)?;
+        Ok(true)
+    }
 
-        let json_string = serde_json::to_string(ssid_list)?;
-        nvs.set_string(WIFI_SETTING_KEY, &json_string)?;
+    /// 连接成功后更新最后连接时间，时间还没同步时等 `touch_pending_ssid` 再更新
+    pub fn touch_ssid(&mut self, ssid: &str) -> Result<()> {
+        let Some(now) = now_rfc3339() else {
+            *PENDING_TOUCH.lock().unwrap() = Some(ssid.to_string());
+            return Ok(());
+        };
+        let mut ssid_list = self.get_ssid_list()?;
+        if let Some(item) = ssid_list.iter_mut().find(|item| item.ssid == ssid) {
+            item.last_connect_time = now;
+            self.save_to_nvs(&ssid_list)?;
+        }
+        Ok(())
+    }
 
+    /// 时间同步后调用，补上同步之前连上的 WiFi 的连接时间
+    pub fn touch_pending_ssid(&mut self) -> Result<()> {
+        let ssid = PENDING_TOUCH.lock().unwrap().take();
+        match ssid {
+            Some(ssid) => self.touch_ssid(&ssid),
+            None => Ok(()),
+        }
+    }
+
+    fn save_to_nvs(&mut self, ssid_list: &[SsidItem]) -> Result<()> {
+        Settings::update(SettingsGroup::Wifi, |settings| {
+            settings.wifi.networks = ssid_list.to_vec();
+        })?;
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn item(ssid: &str, last_connect_time: &str) -> SsidItem {
+        SsidItem {
+            last_connect_time: last_connect_time.to_string(),
+            ..SsidItem::new(ssid, "")
+        }
+    }
+
+    fn ssids(items: &[SsidItem]) -> Vec<&str> {
+        items.iter().map(|item| item.ssid.as_str()).collect()
+    }
+
+    #[test]
+    fn last_connected_at_ignores_empty_and_invalid_time() {
+        assert!(item("a", "").last_connected_at().is_none());
+        assert!(item("a", "2024-01-01").last_connected_at().is_none());
+        assert!(item("a", "2024-01-01T08:00:00+08:00")
+            .last_connected_at()
+            .is_some());
+    }
+
+    #[test]
+    fn sorts_recent_first_and_unknown_last() {
+        let mut items = vec![
+            item("unknown", ""),
+            item("old", "2024-01-01T00:00:00Z"),
+            item("invalid", "yesterday"),
+            // 不同时区按同一时刻比较
+            item("new", "2024-03-01T08:00:00+08:00"),
+            item("middle", "2024-02-01T00:00:00Z"),
+        ];
+        sort_recent_first(&mut items);
+        assert_eq!(
+            ssids(&items),
+            ["new", "middle", "old", "unknown", "invalid"]
+        );
+        // 排序后最后一个就是添加新网络时要删掉的
+        assert_eq!(items.last().unwrap().ssid, "invalid");
+    }
+}
@@ -0,0 +1,423 @@
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc::{channel, Receiver, Sender},
+        Arc, Mutex,
+    },
+    thread,
+    time::Duration,
+};
+
+use anyhow::Result;
+use esp_idf_svc::{
+    eventloop::{EspSubscription, EspSystemEventLoop, System},
+    ipv4::IpInfo,
+    wifi::{EspWifi, WifiEvent},
+};
+use log::{error, info, warn};
+
+use crate::{
+    common::event::AppEvent,
+    wifi::{
+        ssid_manager::{SsidItem, SsidMananger},
+        wifi_driver::{scan_access_points, try_connect, AccessPointRecord},
+    },
+};
+
+/// 信号强度相差不到这么多 dB 的，认为差不多，按最近连接时间排序
+const RSSI_BUCKET_DB: i16 = 6;
+/// 重连连续失败这么多次后，回退到配网模式
+const MAX_RECONNECT_ATTEMPTS: u32 = 6;
+const RECONNECT_BASE_DELAY: Duration = Duration::from_secs(2);
+const RECONNECT_MAX_DELAY: Duration = Duration::from_secs(60);
+
+/// 在扫描结果里找到已保存的 WiFi，按信号强度和最近连接时间排序。
+///
+/// 信号强度先按 `RSSI_BUCKET_DB` 分档，档位高的优先；同一档里最近连接过的优先。
+/// 返回的 rssi 是扫描到的信号强度，隐藏的 SSID 是 `i8::MIN`。
+pub fn rank_networks(
+    saved: Vec<SsidItem>,
+    available: &[AccessPointRecord],
+) -> Vec<(SsidItem, i8)> {
+    let mut candidates: Vec<(SsidItem, i8)> = saved
+        .into_iter()
+        .filter_map(|item| {
+            match available.iter().find(|ap| ap.ssid == item.ssid) {
+                Some(ap) => Some((item, ap.rssi)),
+                // 隐藏的 SSID 扫描不到，放到最后直接尝试连接
+                None if item.hidden => Some((item, i8::MIN)),
+                None => None,
+            }
+        })
+        .collect();
+
+    candidates.sort_by(|(a, a_rssi), (b, b_rssi)| {
+        let a_bucket = (*a_rssi as i16).div_euclid(RSSI_BUCKET_DB);
+        let b_bucket = (*b_rssi as i16).div_euclid(RSSI_BUCKET_DB);
+        // 没有连接时间的(None)排在最后
+        b_bucket
+            .cmp(&a_bucket)
+            .then_with(|| b.last_connected_at().cmp(&a.last_connected_at()))
+    });
+
+    candidates
+}
+
+/// 指数退避，超过最大次数后返回 None
+#[derive(Debug, Clone)]
+pub struct ReconnectBackoff {
+    attempts: u32,
+    max_attempts: u32,
+    base: Duration,
+    max: Duration,
+}
+
+impl ReconnectBackoff {
+    pub fn new(max_attempts: u32, base: Duration, max: Duration) -> Self {
+        Self {
+            attempts: 0,
+            max_attempts,
+            base,
+            max,
+        }
+    }
+
+    /// 下一次重连前需要等待的时间
+    pub fn next_delay(&mut self) -> Option<Duration> {
+        if self.attempts >= self.max_attempts {
+            return None;
+        }
+        let delay = self
+            .base
+            .saturating_mul(1u32 << self.attempts.min(16))
+            .min(self.max);
+        self.attempts += 1;
+        Some(delay)
+    }
+
+    pub fn attempts(&self) -> u32 {
+        self.attempts
+    }
+
+    pub fn reset(&mut self) {
+        self.attempts = 0;
+    }
+}
+
+impl Default for ReconnectBackoff {
+    fn default() -> Self {
+        Self::new(
+            MAX_RECONNECT_ATTEMPTS,
+            RECONNECT_BASE_DELAY,
+            RECONNECT_MAX_DELAY,
+        )
+    }
+}
+
+/// 负责选择已保存的 WiFi 进行连接，断线后自动重连。
+///
+/// 网络连上、断开，以及重连多次失败需要重新配网时，通过 `AppEvent` 通知 application。
+pub struct StationManager {
+    wifi: Arc<Mutex<EspWifi<'static>>>,
+    sysloop: EspSystemEventLoop,
+    app_event_sender: Sender<AppEvent>,
+    /// 进入配网或主动断开时关掉，避免把这些断开当成掉线去重连
+    auto_reconnect: Arc<AtomicBool>,
+    subscription: Option<EspSubscription<'static, System>>,
+    /// 通知重连线程检查连接，和收到断开事件一样
+    disconnected_tx: Option<Sender<()>>,
+}
+
+impl StationManager {
+    pub fn new(
+        wifi: Arc<Mutex<EspWifi<'static>>>,
+        sysloop: EspSystemEventLoop,
+        app_event_sender: Sender<AppEvent>,
+    ) -> Self {
+        Self {
+            wifi,
+            sysloop,
+            app_event_sender,
+            auto_reconnect: Arc::new(AtomicBool::new(false)),
+            subscription: None,
+            disconnected_tx: None,
+        }
+    }
+
+    /// 扫描并按排序依次尝试已保存的 WiFi，返回是否连接成功
+    pub fn connect_best(&self) -> Result<bool> {
+        let result = connect_best(&self.wifi, &self.sysloop);
+        match &result {
+            Ok(Some(ip_info)) => {
+                self.send_event(AppEvent::NetworkConnected(ip_info.ip.to_string()));
+            }
+            Ok(None) => {}
+            Err(e) => {
+                error!("Failed to connect to saved wifi: {:?}", e);
+            }
+        }
+        Ok(result?.is_some())
+    }
+
+    pub fn set_auto_reconnect(&self, enabled: bool) {
+        self.auto_reconnect.store(enabled, Ordering::Relaxed);
+    }
+
+    /// 订阅 `WifiEvent::StaDisconnected`，掉线后按退避时间重连，
+    /// 连续失败 `MAX_RECONNECT_ATTEMPTS` 次后发送 `AppEvent::NetworkProvisioningRequired`。
+    pub fn start_monitor(&mut self) -> Result<()> {
+        if self.subscription.is_some() {
+            self.set_auto_reconnect(true);
+            return Ok(());
+        }
+
+        let (disconnected_tx, disconnected_rx) = channel::<()>();
+        self.disconnected_tx = Some(disconnected_tx.clone());
+        let auto_reconnect = self.auto_reconnect.clone();
+        let subscription = self.sysloop.subscribe::<WifiEvent, _>(move |event| {
+            if let WifiEvent::StaDisconnected(_) = event {
+                if auto_reconnect.load(Ordering::Relaxed) {
+                    let _ = disconnected_tx.send(());
+                }
+            }
+        })?;
+        self.subscription = Some(subscription);
+
+        let wifi = self.wifi.clone();
+        let sysloop = self.sysloop.clone();
+        let sender = self.app_event_sender.clone();
+        let auto_reconnect = self.auto_reconnect.clone();
+        thread::Builder::new()
+            .name("wifi_reconnect".into())
+            .stack_size(8 * 1024)
+            .spawn(move || {
+                reconnect_loop(disconnected_rx, wifi, sysloop, sender, auto_reconnect);
+            })?;
+
+        self.set_auto_reconnect(true);
+        Ok(())
+    }
+
+    /// light sleep 醒来后调用。睡眠期间掉线的话由重连线程按退避时间重连，
+    /// 不在这里连接，免得和重连线程同时操作 WiFi；配网中或者还没开始监控时什么都不做
+    pub fn check_connection(&self) {
+        if !self.auto_reconnect.load(Ordering::Relaxed) {
+            return;
+        }
+        if let Some(disconnected_tx) = &self.disconnected_tx {
+            // 重连线程会先检查是否已经连着
+            let _ = disconnected_tx.send(());
+        }
+    }
+
+    fn send_event(&self, event: AppEvent) {
+        if let Err(e) = self.app_event_sender.send(event) {
+            error!("Failed to send network event: {:?}", e);
+        }
+    }
+}
+
+fn connect_best(
+    wifi: &Arc<Mutex<EspWifi<'static>>>,
+    sysloop: &EspSystemEventLoop,
+) -> Result<Option<IpInfo>> {
+    let mut ssid_manager = SsidMananger::get_instance();
+    let saved = ssid_manager.get_ssid_list()?;
+    if saved.is_empty() {
+        info!("No saved wifi");
+        return Ok(None);
+    }
+
+    let mut esp_wifi = wifi.lock().unwrap();
+    let available = scan_access_points(&mut esp_wifi, sysloop)?;
+    let candidates = rank_networks(saved, &available);
+    info!(
+        "Wifi candidates: {:?}",
+        candidates
+            .iter()
+            .map(|(item, rssi)| format!("{}({}dBm)", item.ssid, rssi))
+            .collect::<Vec<_>>()
+    );
+
+    for (item, _) in candidates {
+        match try_connect(&mut esp_wifi, sysloop, &item) {
+            Ok(ip_info) => {
+                info!("Connected to {}", item.ssid);
+                if let Err(e) = ssid_manager.touch_ssid(&item.ssid) {
+                    warn!("Failed to update last connect time: {:?}", e);
+                }
+                return Ok(Some(ip_info));
+            }
+            Err(e) => {
+                warn!("Failed to connect to {}: {:?}", item.ssid, e);
+            }
+        }
+    }
+
+    Ok(None)
+}
+
+fn reconnect_loop(
+    disconnected_rx: Receiver<()>,
+    wifi: Arc<Mutex<EspWifi<'static>>>,
+    sysloop: EspSystemEventLoop,
+    sender: Sender<AppEvent>,
+    auto_reconnect: Arc<AtomicBool>,
+) {
+    let mut backoff = ReconnectBackoff::default();
+
+    while disconnected_rx.recv().is_ok() {
+        if wifi.lock().unwrap().is_connected().unwrap_or(false) {
+            continue;
+        }
+        warn!("Wifi disconnected, start reconnecting");
+        let _ = sender.send(AppEvent::NetworkDisconnected);
+
+        backoff.reset();
+        let reconnected = loop {
+            if !auto_reconnect.load(Ordering::Relaxed) {
+                break true;
+            }
+            let Some(delay) = backoff.next_delay() else {
+                break false;
+            };
+            thread::sleep(delay);
+
+            info!("Reconnecting wifi, attempt {}", backoff.attempts());
+            match connect_best(&wifi, &sysloop) {
+                Ok(Some(ip_info)) => {
+                    let _ = sender.send(AppEvent::NetworkConnected(ip_info.ip.to_string()));
+                    break true;
+                }
+                Ok(None) => {}
+                Err(e) => {
+                    warn!("Reconnect failed: {:?}", e);
+                }
+            }
+        };
+
+        // 重连过程中失败的尝试也会产生断开事件，丢掉
+        while disconnected_rx.try_recv().is_ok() {}
+
+        if !reconnected {
+            error!(
+                "Wifi reconnect failed after {} attempts, fall back to provisioning",
+                backoff.attempts()
+            );
+            auto_reconnect.store(false, Ordering::Relaxed);
+            let _ = sender.send(AppEvent::NetworkProvisioningRequired);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn saved(ssid: &str, last_connect_time: &str, hidden: bool) -> SsidItem {
+        SsidItem {
+            last_connect_time: last_connect_time.to_string(),
+            hidden,
+            ..SsidItem::new(ssid, "12345678")
+        }
+    }
+
+    fn ap(ssid: &str, rssi: i8) -> AccessPointRecord {
+        AccessPointRecord {
+            ssid: ssid.to_string(),
+            rssi,
+            channel: 1,
+            auth_method: "WPA2Personal".to_string(),
+        }
+    }
+
+    fn ranked(saved: Vec<SsidItem>, available: &[AccessPointRecord]) -> Vec<(String, i8)> {
+        rank_networks(saved, available)
+            .into_iter()
+            .map(|(item, rssi)| (item.ssid, rssi))
+            .collect()
+    }
+
+    #[test]
+    fn stronger_bucket_wins_over_recency() {
+        let networks = vec![
+            saved("weak", "2024-03-01T00:00:00Z", false),
+            saved("strong", "2024-01-01T00:00:00Z", false),
+        ];
+        // -60 和 -40 不在同一档
+        let available = [ap("weak", -60), ap("strong", -40)];
+        assert_eq!(
+            ranked(networks, &available),
+            [("strong".to_string(), -40), ("weak".to_string(), -60)]
+        );
+    }
+
+    #[test]
+    fn same_bucket_prefers_recently_connected() {
+        let networks = vec![
+            saved("never", "", false),
+            saved("old", "2024-01-01T00:00:00Z", false),
+            saved("new", "2024-03-01T00:00:00Z", false),
+        ];
+        // -37、-42 都在 [-42, -36) 这一档，-36 在上一档
+        let available = [ap("old", -37), ap("new", -42), ap("never", -37)];
+        assert_eq!(
+            ranked(networks.clone(), &available),
+            [
+                ("new".to_string(), -42),
+                ("old".to_string(), -37),
+                ("never".to_string(), -37)
+            ]
+        );
+
+        let available = [ap("old", -36), ap("new", -42), ap("never", -37)];
+        assert_eq!(ranked(networks, &available)[0].0, "old");
+    }
+
+    #[test]
+    fn hidden_ssid_is_tried_last_and_unseen_ssid_is_skipped() {
+        let networks = vec![
+            saved("hidden", "2024-03-01T00:00:00Z", true),
+            saved("away", "2024-03-01T00:00:00Z", false),
+            saved("far", "", false),
+        ];
+        let available = [ap("far", -90), ap("other", -30)];
+        assert_eq!(
+            ranked(networks, &available),
+            [("far".to_string(), -90), ("hidden".to_string(), i8::MIN)]
+        );
+        // 扫描到的隐藏网络按实际信号排序
+        let networks = vec![saved("hidden", "", true), saved("far", "", false)];
+        let available = [ap("far", -90), ap("hidden", -50)];
+        assert_eq!(ranked(networks, &available)[0], ("hidden".to_string(), -50));
+    }
+
+    #[test]
+    fn backoff_doubles_up_to_max_then_gives_up() {
+        let mut backoff = ReconnectBackoff::new(5, Duration::from_secs(2), Duration::from_secs(10));
+        let delays: Vec<_> = std::iter::from_fn(|| backoff.next_delay()).collect();
+        assert_eq!(delays, [2, 4, 8, 10, 10].map(Duration::from_secs));
+        assert_eq!(backoff.attempts(), 5);
+        assert_eq!(backoff.next_delay(), None);
+
+        backoff.reset();
+        assert_eq!(backoff.attempts(), 0);
+        assert_eq!(backoff.next_delay(), Some(Duration::from_secs(2)));
+    }
+
+    #[test]
+    fn backoff_does_not_overflow_with_many_attempts() {
+        let mut backoff =
+            ReconnectBackoff::new(40, Duration::from_secs(1), Duration::from_secs(60));
+        let delays: Vec<_> = std::iter::from_fn(|| backoff.next_delay()).collect();
+        assert_eq!(delays.len(), 40);
+        assert_eq!(delays[5], Duration::from_secs(32));
+        assert!(delays[6..]
+            .iter()
+            .all(|&delay| delay == Duration::from_secs(60)));
+
+        let mut backoff = ReconnectBackoff::default();
+        let attempts = std::iter::from_fn(|| backoff.next_delay()).count();
+        assert_eq!(attempts, MAX_RECONNECT_ATTEMPTS as usize);
+    }
+}
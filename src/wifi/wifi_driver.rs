@@ -1,20 +1,39 @@
-use std::sync::{Arc, Mutex};
+#[cfg(target_os = "espidf")]
+use std::{
+    collections::HashMap,
+    net::Ipv4Addr,
+    sync::{Arc, Mutex},
+};
 
-use anyhow::{bail, Error, Ok, Result};
+#[cfg(target_os = "espidf")]
+use anyhow::{bail, Ok};
+use anyhow::{Error, Result};
+#[cfg(target_os = "espidf")]
 use esp_idf_hal::modem::WifiModemPeripheral;
+#[cfg(target_os = "espidf")]
 use esp_idf_svc::{
     eventloop::EspSystemEventLoop,
+    handle::RawHandle,
     http::server::EspHttpServer,
-    ipv4::IpInfo,
-    nvs::{EspCustomNvsPartition, EspNvsPartition, NvsCustom},
+    ipv4::{self, IpInfo},
+    netif::{EspNetif, NetifConfiguration},
     wifi::{
         AccessPointConfiguration, AuthMethod, BlockingWifi, ClientConfiguration, Configuration,
-        EspWifi, WifiDeviceId,
+        EspWifi, ScanMethod, ScanSortMethod, WifiDeviceId,
     },
 };
+#[cfg(target_os = "espidf")]
 use log::{error, info};
+use serde::Serialize;
 
-use crate::common::httpd_server::{create_server, start_http_server};
+#[cfg(target_os = "espidf")]
+use crate::{
+    common::httpd_server::{create_server, start_http_server},
+    wifi::{
+        captive_dns::CaptiveDnsServer,
+        ssid_manager::{SsidItem, WifiAuth, WifiConfigError},
+    },
+};
 
 /// 定义 WiFi 模块必须具备的行为
 pub trait WifiStation {
@@ -34,11 +53,11 @@ pub trait WifiStation {
     /// 获取当前的 IP 地址 (可选，用于调试)
     fn get_ip_address(&self) -> Result<String>;
 
-    /// 获取可用的WIFI接入点列表
-    fn get_available_access_points(&self) -> Result<Vec<String>>;
+    /// 获取可用的WIFI接入点列表，按信号强度从强到弱排序
+    fn get_available_access_points(&self) -> Result<Vec<AccessPointRecord>>;
 
-    // 如果你需要省电管理，可以加这个
-    // fn set_power_save(&mut self, enabled: bool) -> Result<()>;
+    /// 打开或关闭 modem sleep 省电模式
+    fn set_power_save(&mut self, enabled: bool) -> Result<()>;
 
     fn set_on_new_access_point_add_handler(
         &mut self,
@@ -46,12 +65,23 @@ pub trait WifiStation {
     );
 }
 
+/// 扫描到的接入点，同时也是配网页面 `GET /scan` 返回的 JSON 格式
+#[derive(Debug, Clone, Serialize)]
+pub struct AccessPointRecord {
+    pub ssid: String,
+    pub rssi: i8,
+    pub channel: u8,
+    pub auth_method: String,
+}
+
+#[cfg(target_os = "espidf")]
 pub trait WifiAP {
     fn start_ap(&mut self, ssid: &str, password: &str) -> Result<IpInfo>;
     fn start_http_server(&mut self) -> Result<()>;
     fn stop_http_server(&mut self) -> Result<()>;
 }
 
+#[cfg(target_os = "espidf")]
 pub struct Esp32WifiDriver {
     // 使用 Arc<Mutex<>> 是为了线程安全，因为 EspWifi 可能需要在多处共享
     // 或者你可以直接持有 &mut EspWifi，取决于你的架构
@@ -63,8 +93,13 @@ pub struct Esp32WifiDriver {
         Option<Box<dyn FnMut(&str, &str) -> Result<(), Error> + Send + 'static>>,
 
     http_server: Option<EspHttpServer<'static>>,
+
+    /// SoftAP 的网关地址，以及把所有域名解析到这个地址的 DNS 服务器
+    ap_ip: Option<Ipv4Addr>,
+    dns_server: Option<CaptiveDnsServer>,
 }
 
+#[cfg(target_os = "espidf")]
 impl Esp32WifiDriver {
     pub fn new(
         modem: impl WifiModemPeripheral + 'static,
@@ -76,75 +111,25 @@ impl Esp32WifiDriver {
             sysloop: sysloop,
             on_new_access_point_add: None,
             http_server: None,
+            ap_ip: None,
+            dns_server: None,
         })
     }
+
+    pub fn esp_wifi(&self) -> Arc<Mutex<EspWifi<'static>>> {
+        self.wifi.clone()
+    }
+
+    pub fn sysloop(&self) -> EspSystemEventLoop {
+        self.sysloop.clone()
+    }
 }
 
+#[cfg(target_os = "espidf")]
 impl WifiStation for Esp32WifiDriver {
     fn connect(&mut self, ssid: &str, password: &str) -> Result<()> {
-        let mut auth_method = AuthMethod::WPA2Personal;
-        if ssid.is_empty() {
-            bail!("Missing WiFi name")
-        }
-        if password.is_empty() {
-            auth_method = AuthMethod::None;
-            info!("Wifi password is empty");
-        }
-
-        // let mut esp_wifi = EspWifi::new(modem, sysloop.clone(), Some(nvs))?;
         let mut esp_wifi = self.wifi.lock().unwrap();
-
-        let mut wifi = BlockingWifi::wrap(&mut *esp_wifi, self.sysloop.clone())?;
-
-        wifi.set_configuration(&Configuration::Client(ClientConfiguration::default()))?;
-
-        info!("Starting wifi...");
-
-        wifi.start()?;
-
-        info!("Scanning...");
-
-        let ap_infos = wifi.scan()?;
-
-        let ours = ap_infos.into_iter().find(|a| a.ssid == ssid);
-
-        let channel = if let Some(ours) = ours {
-            info!(
-                "Found configured access point {} on channel {}",
-                ssid, ours.channel
-            );
-            Some(ours.channel)
-        } else {
-            info!(
-            "Configured access point {} not found during scanning, will go with unknown channel",
-            ssid
-        );
-            None
-        };
-
-        wifi.set_configuration(&Configuration::Client(ClientConfiguration {
-            ssid: ssid
-                .try_into()
-                .expect("Could not parse the given SSID into WiFi config"),
-            password: password
-                .try_into()
-                .expect("Could not parse the given password into WiFi config"),
-            channel,
-            auth_method,
-            ..Default::default()
-        }))?;
-
-        info!("Connecting wifi...");
-
-        wifi.connect()?;
-
-        info!("Waiting for DHCP lease...");
-
-        wifi.wait_netif_up()?;
-
-        let ip_info = wifi.wifi().sta_netif().get_ip_info()?;
-
-        info!("Wifi DHCP info: {:?}", ip_info);
+        try_connect(&mut esp_wifi, &self.sysloop, &SsidItem::new(ssid, password))?;
         Ok(())
     }
 
@@ -168,24 +153,24 @@ impl WifiStation for Esp32WifiDriver {
     }
 
     fn get_ip_address(&self) -> Result<String> {
-        todo!()
+        let ip_info = self.wifi.lock().unwrap().sta_netif().get_ip_info()?;
+        Ok(ip_info.ip.to_string())
     }
 
-    fn get_available_access_points(&self) -> Result<Vec<String>> {
-        // let mut esp_wifi = EspWifi::new(modem, sysloop.clone(), Some(nvs))?;
+    fn get_available_access_points(&self) -> Result<Vec<AccessPointRecord>> {
         let mut esp_wifi = self.wifi.lock().unwrap();
+        scan_access_points(&mut esp_wifi, &self.sysloop)
+    }
 
-        let mut wifi = BlockingWifi::wrap(&mut *esp_wifi, self.sysloop.clone())?;
-
-        info!("Scanning...");
-
-        wifi.start()?;
-        let ap_infos = wifi.scan()?;
-        let ap_names = ap_infos
-            .into_iter()
-            .map(|a| a.ssid.to_string())
-            .collect::<Vec<String>>();
-        Ok(ap_names)
+    fn set_power_save(&mut self, enabled: bool) -> Result<()> {
+        let ps_type = if enabled {
+            esp_idf_sys::wifi_ps_type_t_WIFI_PS_MIN_MODEM
+        } else {
+            esp_idf_sys::wifi_ps_type_t_WIFI_PS_NONE
+        };
+        esp_idf_sys::esp!(unsafe { esp_idf_sys::esp_wifi_set_ps(ps_type) })?;
+        info!("Wifi power save: {}", enabled);
+        Ok(())
     }
 
     fn set_on_new_access_point_add_handler(
@@ -196,6 +181,7 @@ impl WifiStation for Esp32WifiDriver {
     }
 }
 
+#[cfg(target_os = "espidf")]
 impl WifiAP for Esp32WifiDriver {
     fn start_ap(&mut self, ssid: &str, password: &str) -> Result<IpInfo> {
         // 1. 初始化 EspWifi 驱动
@@ -213,12 +199,10 @@ impl WifiAP for Esp32WifiDriver {
 
         // 3. 构建 AP 配置
         let ap_config = AccessPointConfiguration {
-            ssid: ssid
-                .try_into()
-                .expect("Could not parse the given SSID into WiFi config"),
+            ssid: ssid.try_into().map_err(|_| WifiConfigError::SsidTooLong)?,
             password: password
                 .try_into()
-                .expect("Could not parse the given pasword into WiFi config"),
+                .map_err(|_| WifiConfigError::InvalidPasswordLength)?,
             auth_method,
             // 其他可选的高级配置：
             // ssid_hidden: false,       // 是否隐藏 SSID
@@ -227,8 +211,8 @@ impl WifiAP for Esp32WifiDriver {
             ..Default::default()
         };
 
-        // 4. 将配置包裹在 Configuration::AccessPoint 枚举中
-        let config = Configuration::AccessPoint(ap_config);
+        // 4. 使用 AP+STA 混合模式，这样配网页面才能扫描和测试连接附近的 WiFi
+        let config = Configuration::Mixed(ClientConfiguration::default(), ap_config);
         wifi.set_configuration(&config)?;
 
         // 5. 启动 WiFi (此时 WiFi 硬件开始工作)
@@ -251,6 +235,13 @@ impl WifiAP for Esp32WifiDriver {
         info!("IP Address: {}", ip_info.ip);
         info!("Subnet Mask: {}", ip_info.subnet.mask);
 
+        drop(wifi);
+        self.ap_ip = Some(ip_info.ip);
+        match CaptiveDnsServer::start(ip_info.ip) {
+            std::result::Result::Ok(dns_server) => self.dns_server = Some(dns_server),
+            Err(e) => error!("Failed to start captive DNS server: {:?}", e),
+        }
+
         Ok(ip_info)
     }
 
@@ -261,7 +252,13 @@ impl WifiAP for Esp32WifiDriver {
         // };
 
         let mut http_server = create_server()?;
-        start_http_server(&mut http_server)?;
+        let portal_ip = self.ap_ip.unwrap_or(Ipv4Addr::new(192, 168, 4, 1));
+        start_http_server(
+            &mut http_server,
+            self.wifi.clone(),
+            self.sysloop.clone(),
+            portal_ip,
+        )?;
 
         // let on_new_access_point_add = &self.on_new_access_point_add;
 
@@ -273,146 +270,201 @@ impl WifiAP for Esp32WifiDriver {
         if let Some(http_server) = self.http_server.take() {
             drop(http_server);
         }
+        if let Some(dns_server) = self.dns_server.take() {
+            drop(dns_server);
+        }
         Ok(())
     }
 }
 
-pub fn wifi(
-    ssid: &str,
-    pass: &str,
-    modem: impl WifiModemPeripheral + 'static,
-    sysloop: EspSystemEventLoop,
-) -> Result<Box<EspWifi<'static>>> {
-    let mut auth_method = AuthMethod::WPA2Personal;
-    if ssid.is_empty() {
-        bail!("Missing WiFi name")
-    }
-    if pass.is_empty() {
-        auth_method = AuthMethod::None;
-        info!("Wifi password is empty");
-    }
-    let nvs = EspNvsPartition::<esp_idf_svc::nvs::NvsDefault>::take()?;
-    let mut esp_wifi = EspWifi::new(modem, sysloop.clone(), Some(nvs))?;
-
-    let mut wifi = BlockingWifi::wrap(&mut esp_wifi, sysloop)?;
-
-    wifi.set_configuration(&Configuration::Client(ClientConfiguration::default()))?;
-
-    info!("Starting wifi...");
-
-    wifi.start()?;
+/// 扫描附近的接入点，同名的只保留信号最强的一个
+#[cfg(target_os = "espidf")]
+pub fn scan_access_points(
+    esp_wifi: &mut EspWifi<'static>,
+    sysloop: &EspSystemEventLoop,
+) -> Result<Vec<AccessPointRecord>> {
+    let mut wifi = BlockingWifi::wrap(esp_wifi, sysloop.clone())?;
 
     info!("Scanning...");
 
+    // 还没有配置过时(例如只开了 BLE 配网)，先切到 station 模式才能扫描
+    if matches!(wifi.get_configuration()?, Configuration::None) {
+        wifi.set_configuration(&Configuration::Client(ClientConfiguration::default()))?;
+    }
+    if !wifi.is_started()? {
+        wifi.start()?;
+    }
     let ap_infos = wifi.scan()?;
 
-    let ours = ap_infos.into_iter().find(|a| a.ssid == ssid);
-
-    let channel = if let Some(ours) = ours {
-        info!(
-            "Found configured access point {} on channel {}",
-            ssid, ours.channel
-        );
-        Some(ours.channel)
-    } else {
-        info!(
-            "Configured access point {} not found during scanning, will go with unknown channel",
-            ssid
-        );
-        None
-    };
-
-    wifi.set_configuration(&Configuration::Client(ClientConfiguration {
-        ssid: ssid
-            .try_into()
-            .expect("Could not parse the given SSID into WiFi config"),
-        password: pass
-            .try_into()
-            .expect("Could not parse the given password into WiFi config"),
-        channel,
-        auth_method,
-        ..Default::default()
-    }))?;
+    let mut records: HashMap<String, AccessPointRecord> = HashMap::new();
+    for ap in ap_infos {
+        if ap.ssid.is_empty() {
+            continue;
+        }
+        let record = AccessPointRecord {
+            ssid: ap.ssid.to_string(),
+            rssi: ap.signal_strength,
+            channel: ap.channel,
+            auth_method: match ap.auth_method {
+                Some(auth_method) => format!("{:?}", auth_method),
+                None => "Unknown".to_string(),
+            },
+        };
+        match records.get(&record.ssid) {
+            Some(existing) if existing.rssi >= record.rssi => {}
+            _ => {
+                records.insert(record.ssid.clone(), record);
+            }
+        }
+    }
 
-    info!("Connecting wifi...");
+    let mut records: Vec<AccessPointRecord> = records.into_values().collect();
+    records.sort_by(|a, b| b.rssi.cmp(&a.rssi));
+    Ok(records)
+}
 
-    wifi.connect()?;
+/// 按保存的配置连接 WiFi，配网页面也用它来测试配置是否正确。
+/// 会保留当前的 AP 配置，不影响已连接的手机。
+#[cfg(target_os = "espidf")]
+pub fn try_connect(
+    esp_wifi: &mut EspWifi<'static>,
+    sysloop: &EspSystemEventLoop,
+    network: &SsidItem,
+) -> Result<IpInfo> {
+    network.validate()?;
+    let client_config = client_configuration(network)?;
+
+    configure_enterprise(network)?;
+    configure_sta_netif(esp_wifi, network)?;
+
+    let mut wifi = BlockingWifi::wrap(esp_wifi, sysloop.clone())?;
+
+    let config = match wifi.get_configuration()? {
+        Configuration::AccessPoint(ap_config) | Configuration::Mixed(_, ap_config) => {
+            Configuration::Mixed(client_config, ap_config)
+        }
+        _ => Configuration::Client(client_config),
+    };
+    wifi.set_configuration(&config)?;
 
-    info!("Waiting for DHCP lease...");
+    if !wifi.is_started()? {
+        wifi.start()?;
+    }
 
-    wifi.wait_netif_up()?;
+    info!(
+        "Connecting to {} (auth: {:?}, hidden: {})",
+        network.ssid,
+        network.effective_auth(),
+        network.hidden
+    );
+    if let Err(e) = wifi.connect() {
+        let _ = wifi.disconnect();
+        bail!("Failed to connect to {}: {:?}", network.ssid, e);
+    }
+    // 连上了但拿不到 IP，也要断开，否则下次连接时 STA 还挂在这个网络上
+    if let Err(e) = wifi.wait_netif_up() {
+        let _ = wifi.disconnect();
+        bail!("Failed to get IP from {}: {:?}", network.ssid, e);
+    }
 
     let ip_info = wifi.wifi().sta_netif().get_ip_info()?;
-
-    info!("Wifi DHCP info: {:?}", ip_info);
-
-    Ok(Box::new(esp_wifi))
+    info!("Wifi IP info: {:?}", ip_info);
+    Ok(ip_info)
 }
 
-/// 配置并启动 WiFi AP 模式
-///
-/// `modem`: 外设的所有权 (Peripherals::take().unwrap().modem)
-/// `sysloop`: 系统事件循环
-/// `ssid`: 热点名称
-/// `password`: 热点密码 (如果为空，则为开放网络)
-pub fn start_ap<'a>(
-    modem: impl WifiModemPeripheral + 'a,
-    sysloop: EspSystemEventLoop,
-    ssid: &str,
-    password: &str,
-) -> Result<EspWifi<'a>> {
-    // 1. 初始化 EspWifi 驱动
-    let mut wifi = EspWifi::new(modem, sysloop.clone(), None)?;
-
-    // 2. 根据密码长度自动选择认证方式
-    let auth_method = if password.is_empty() {
-        info!("AP password is empty, setting to Open network.");
-        AuthMethod::None
-    } else if password.len() < 8 {
-        bail!("WiFi password must be at least 8 characters long");
+#[cfg(target_os = "espidf")]
+fn client_configuration(network: &SsidItem) -> Result<ClientConfiguration> {
+    let auth_method = match network.effective_auth() {
+        WifiAuth::Open | WifiAuth::Auto => AuthMethod::None,
+        // 这里的认证方式是最低要求，WPA2 也可以连接 WPA2/WPA3 混合模式的路由器
+        WifiAuth::Wpa2Personal => AuthMethod::WPA2Personal,
+        WifiAuth::Wpa3Personal => AuthMethod::WPA3Personal,
+        WifiAuth::Wpa2Wpa3Personal => AuthMethod::WPA2WPA3Personal,
+        WifiAuth::Wpa2Enterprise => AuthMethod::WPA2Enterprise,
+    };
+
+    // 企业网络的密码通过 EAP 设置，不放在 ClientConfiguration 里
+    let password = if network.effective_auth() == WifiAuth::Wpa2Enterprise {
+        ""
     } else {
-        AuthMethod::WPA2Personal
+        network.password.as_str()
     };
 
-    // 3. 构建 AP 配置
-    let ap_config = AccessPointConfiguration {
-        ssid: ssid
+    Ok(ClientConfiguration {
+        ssid: network
+            .ssid
+            .as_str()
             .try_into()
-            .expect("Could not parse the given SSID into WiFi config"),
+            .map_err(|_| WifiConfigError::SsidTooLong)?,
         password: password
             .try_into()
-            .expect("Could not parse the given pasword into WiFi config"),
+            .map_err(|_| WifiConfigError::InvalidPasswordLength)?,
         auth_method,
-        // 其他可选的高级配置：
-        // ssid_hidden: false,       // 是否隐藏 SSID
-        // max_connections: 4,       // 最大连接数 (ESP32 最大通常是 10)
-        // channel: 1,               // WiFi 信道 (1-13)
+        // 隐藏的 SSID 需要扫描所有信道
+        scan_method: if network.hidden {
+            ScanMethod::CompleteScan(ScanSortMethod::Signal)
+        } else {
+            ScanMethod::FastScan
+        },
         ..Default::default()
-    };
-
-    // 4. 将配置包裹在 Configuration::AccessPoint 枚举中
-    let config = Configuration::AccessPoint(ap_config);
-    wifi.set_configuration(&config)?;
-
-    // 5. 启动 WiFi (此时 WiFi 硬件开始工作)
-    info!("Starting WiFi in AP mode...");
-    wifi.start()?;
+    })
+}
 
-    // 6. AP 模式不需要 connect()，直接就是 Up 状态
-    // 但为了确保系统网络栈（LwIP）准备就绪，我们可以检查一下
-    if !wifi.is_up()? {
-        bail!("Failed to bring up WiFi AP");
+/// 设置或清除 WPA2-Enterprise (PEAP/MSCHAPv2) 的账号
+#[cfg(target_os = "espidf")]
+fn configure_enterprise(network: &SsidItem) -> Result<()> {
+    if network.effective_auth() != WifiAuth::Wpa2Enterprise {
+        esp_idf_sys::esp!(unsafe { esp_idf_sys::esp_wifi_sta_enterprise_disable() })?;
+        return Ok(());
     }
 
-    // 7. 获取并打印 ESP32 分配到的网关 IP 地址
-    // 默认情况下，ESP32 作为一个 DHCP 服务器，它的 IP 通常是 192.168.4.1
-    let netif = wifi.ap_netif();
-    let ip_info = netif.get_ip_info()?;
+    let identity = network.identity.as_deref().unwrap_or_default();
+    unsafe {
+        esp_idf_sys::esp!(esp_idf_sys::esp_eap_client_set_identity(
+            identity.as_ptr(),
+            identity.len() as i32
+        ))?;
+        esp_idf_sys::esp!(esp_idf_sys::esp_eap_client_set_username(
+            identity.as_ptr(),
+            identity.len() as i32
+        ))?;
+        esp_idf_sys::esp!(esp_idf_sys::esp_eap_client_set_password(
+            network.password.as_ptr(),
+            network.password.len() as i32
+        ))?;
+        esp_idf_sys::esp!(esp_idf_sys::esp_wifi_sta_enterprise_enable())?;
+    }
+    Ok(())
+}
 
-    info!("AP Started Successfully!");
-    info!("SSID: {}", ssid);
-    info!("IP Address: {}", ip_info.ip);
-    info!("Subnet Mask: {}", ip_info.subnet.mask);
+/// 根据是否配置了静态 IP，替换 station 的网络接口
+#[cfg(target_os = "espidf")]
+fn configure_sta_netif(esp_wifi: &mut EspWifi<'static>, network: &SsidItem) -> Result<()> {
+    let mut dhcp_status = esp_idf_sys::esp_netif_dhcp_status_t_ESP_NETIF_DHCP_INIT;
+    esp_idf_sys::esp!(unsafe {
+        esp_idf_sys::esp_netif_dhcpc_get_status(esp_wifi.sta_netif().handle(), &mut dhcp_status)
+    })?;
+    let dhcp_enabled = dhcp_status != esp_idf_sys::esp_netif_dhcp_status_t_ESP_NETIF_DHCP_STOPPED;
+
+    let ip_configuration = match &network.static_ip {
+        Some(static_ip) => ipv4::ClientConfiguration::Fixed(ipv4::ClientSettings {
+            ip: static_ip.ip,
+            subnet: ipv4::Subnet {
+                gateway: static_ip.gateway,
+                mask: ipv4::Mask(static_ip.prefix_len),
+            },
+            dns: static_ip.dns.or(Some(static_ip.gateway)),
+            secondary_dns: None,
+        }),
+        // 已经是 DHCP 了就不用换
+        None if dhcp_enabled => return Ok(()),
+        None => ipv4::ClientConfiguration::DHCP(Default::default()),
+    };
 
-    Ok(wifi)
+    let netif = EspNetif::new_with_conf(&NetifConfiguration {
+        ip_configuration: Some(ipv4::Configuration::Client(ip_configuration)),
+        ..NetifConfiguration::wifi_default_client()
+    })?;
+    esp_wifi.swap_netif_sta(netif)?;
+    Ok(())
 }
@@ -0,0 +1,167 @@
+//! 在模拟板子上启动完整的 Application，走一遍对话：唤醒 -> 聆听 -> 说话 -> 空闲。
+//!
+//! 需要编译成电脑上的程序：
+//! `cargo test --no-default-features --features host-sim --target x86_64-unknown-linux-gnu`
+#![cfg(feature = "host-sim")]
+
+use std::{
+    path::{Path, PathBuf},
+    thread,
+    time::{Duration, Instant},
+};
+
+use xiaoxin_esp32::{
+    application::Application,
+    audio::{
+        codec::AUDIO_INPUT_SAMPLE_RATE,
+        wav::{read_wav, WavWriter},
+    },
+    boards::mock_board,
+    common::sim_button,
+    logging::tee_logger,
+};
+
+/// MockBoard 的 boot 键
+const BOOT_BUTTON_GPIO: i32 = 0;
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+/// 第一次聆听时服务器识别出一句话，回复一段音频；之后的聆听不再回复
+const SCRIPT: &str = r#"
+{"on": "hello", "send": [{"text": {"type": "hello", "transport": "websocket"}}]}
+{"on": "listen.start", "once": true, "send": [{"delay_ms": 200}, {"text": {"type": "stt", "text": "你好"}}, {"text": {"type": "tts", "state": "start"}}, {"text": {"type": "tts", "state": "sentence_start", "text": "你好呀"}}, {"audio": "REPLY"}, {"text": {"type": "tts", "state": "stop"}}]}
+"#;
+
+fn sim_dir() -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("xiaoxin_host_sim_{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+fn wait_until<T>(what: &str, mut check: impl FnMut() -> Option<T>) -> T {
+    let started_at = Instant::now();
+    loop {
+        if let Some(value) = check() {
+            return value;
+        }
+        assert!(
+            started_at.elapsed() < TIMEOUT,
+            "timeout waiting for {}",
+            what
+        );
+        thread::sleep(Duration::from_millis(20));
+    }
+}
+
+/// 文本消息的 `type`，有 `state` 时是 `type.state`，和脚本里的 `on` 一样
+fn message_key(text: &str) -> String {
+    let message: serde_json::Value = serde_json::from_str(text).unwrap();
+    let message_type = message["type"].as_str().unwrap();
+    match message["state"].as_str() {
+        Some(state) => format!("{}.{}", message_type, state),
+        None => message_type.to_string(),
+    }
+}
+
+/// 设备发给服务器的消息
+fn transcript(path: &Path) -> Vec<String> {
+    std::fs::read_to_string(path)
+        .unwrap_or_default()
+        .lines()
+        .map(message_key)
+        .collect()
+}
+
+/// 等设备在第 `from` 条之后发出 `key`，返回这条消息的下一条的位置
+fn wait_for_message(path: &Path, from: usize, key: &str) -> usize {
+    wait_until(key, || {
+        transcript(path)
+            .iter()
+            .skip(from)
+            .position(|k| k == key)
+            .map(|i| from + i + 1)
+    })
+}
+
+#[test]
+fn wake_listen_speak_idle() {
+    let dir = sim_dir();
+    let reply = dir.join("reply.wav");
+    let script = dir.join("script.jsonl");
+    let speaker = dir.join("speaker.wav");
+    let transcript_path = dir.join("transcript.jsonl");
+
+    // 0.3 秒的回复，正好 5 帧
+    let mut writer = WavWriter::create(&reply, AUDIO_INPUT_SAMPLE_RATE, 1).unwrap();
+    let reply_samples = AUDIO_INPUT_SAMPLE_RATE as usize * 3 / 10;
+    let pcm: Vec<u8> = (0..reply_samples)
+        .flat_map(|_| 1000i16.to_le_bytes())
+        .collect();
+    writer.write(&pcm).unwrap();
+    drop(writer);
+    let reply_path = reply.to_str().unwrap().replace('\\', "/");
+    std::fs::write(&script, SCRIPT.replace("REPLY", &reply_path)).unwrap();
+
+    // 环境变量是整个进程共享的，这个文件里只有这一个测试
+    std::env::set_var("XIAOXIN_SIM_SCRIPT", &script);
+    std::env::set_var("XIAOXIN_SIM_MIC", dir.join("no_mic.wav"));
+    std::env::set_var("XIAOXIN_SIM_SPEAKER", &speaker);
+    std::env::set_var("XIAOXIN_SIM_TRANSCRIPT", &transcript_path);
+
+    // 测试失败时会打印出应用的日志
+    tee_logger::init();
+    // start() 一直在事件循环里，测试结束时随进程退出
+    thread::spawn(|| {
+        let mut application = Application::new().unwrap();
+        application.start().unwrap();
+    });
+
+    // 启动过程中单击 boot 键是重新配网，要等联网后进入空闲状态
+    let display = wait_until("board", mock_board::display_history);
+    wait_until("network", || {
+        display
+            .lock()
+            .unwrap()
+            .iter()
+            .any(|entry| entry == "status: 已连接")
+            .then_some(())
+    });
+
+    // 唤醒：单击 boot 键，打开通道开始聆听
+    sim_button::click(BOOT_BUTTON_GPIO);
+    let next = wait_for_message(&transcript_path, 0, "listen.start");
+    let next = wait_for_message(&transcript_path, next, "hello");
+
+    // 说话：服务器回复的音频写进了喇叭，说完之后自动回到聆听
+    let next = wait_for_message(&transcript_path, next, "listen.start");
+    let wav = wait_until("speaker output", || {
+        read_wav(&speaker)
+            .ok()
+            .filter(|wav| wav.samples.len() >= reply_samples * 2)
+    });
+    assert_eq!(wav.channels, 2);
+    assert!(wav.samples.iter().all(|&s| s > 0 && s <= 1000));
+
+    // 空闲：聆听时单击停止聆听
+    sim_button::click(BOOT_BUTTON_GPIO);
+    let next = wait_for_message(&transcript_path, next, "listen.stop");
+
+    // 只有空闲时单击才会重新开始聆听，通道还开着，不用重新 hello
+    sim_button::click(BOOT_BUTTON_GPIO);
+    let next = wait_for_message(&transcript_path, next, "listen.start");
+    let listen: Vec<String> = transcript(&transcript_path)[..next]
+        .iter()
+        .filter(|key| key.starts_with("listen."))
+        .cloned()
+        .collect();
+    assert_eq!(
+        listen,
+        [
+            "listen.start",
+            "listen.start",
+            "listen.stop",
+            "listen.start"
+        ]
+    );
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,161 @@
+//! 把固件上传到设备的 `POST /ota` 接口，用于调试或者没有升级服务器的场合。
+//!
+//! ```text
+//! cd tools/ota_upload
+//! cargo run --release --target x86_64-unknown-linux-gnu -- --device 192.168.1.88 --token 0123456789abcdef0123456789abcdef ../../target/xtensa-esp32s3-espidf/release/xiaoxin_esp32.bin
+//! ```
+//!
+//! 仓库根目录的 `.cargo/config.toml` 把 target 设成了 xtensa，所以要用 `--target` 指定电脑的 target（`rustc -vV` 里的 `host`）。
+//! token 在配网模式下显示在屏幕上，也可以用环境变量 `XIAOXIN_OTA_TOKEN` 传入。固件需要先用 `espflash save-image` 转成 bin 格式。
+
+use std::{
+    env, fs,
+    io::{self, Read, Write},
+    process::ExitCode,
+    time::{Duration, Instant},
+};
+
+use sha2::{Digest, Sha256};
+
+/// ESP 固件头的第一个字节
+const ESP_IMAGE_HEADER_MAGIC: u8 = 0xE9;
+const CHUNK_SIZE: usize = 16 * 1024;
+
+struct Args {
+    device: String,
+    token: String,
+    firmware: String,
+}
+
+fn usage() -> String {
+    "Usage: ota_upload --device <host[:port]> [--token <token>] <firmware.bin>".to_string()
+}
+
+fn parse_args() -> Result<Args, String> {
+    let mut device = None;
+    let mut token = env::var("XIAOXIN_OTA_TOKEN").ok();
+    let mut firmware = None;
+
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-d" | "--device" => device = args.next(),
+            "-t" | "--token" => token = args.next(),
+            "-h" | "--help" => return Err(usage()),
+            _ if arg.starts_with('-') => {
+                return Err(format!("Unknown option: {}\n{}", arg, usage()))
+            }
+            _ => firmware = Some(arg),
+        }
+    }
+
+    Ok(Args {
+        device: device.ok_or_else(usage)?,
+        token: token.ok_or_else(|| format!("Missing token\n{}", usage()))?,
+        firmware: firmware.ok_or_else(usage)?,
+    })
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// 按块读出固件，同时打印上传进度
+struct Progress<'a> {
+    image: &'a [u8],
+    sent: usize,
+    started_at: Instant,
+}
+
+impl Read for Progress<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let len = buf.len().min(CHUNK_SIZE).min(self.image.len() - self.sent);
+        if len == 0 {
+            return Ok(0);
+        }
+        buf[..len].copy_from_slice(&self.image[self.sent..self.sent + len]);
+        self.sent += len;
+
+        let elapsed = self.started_at.elapsed().as_secs_f64().max(0.001);
+        print!(
+            "\r{:3}%  {}/{} bytes  {:.1} KB/s",
+            self.sent * 100 / self.image.len(),
+            self.sent,
+            self.image.len(),
+            self.sent as f64 / 1024.0 / elapsed
+        );
+        let _ = io::stdout().flush();
+        Ok(len)
+    }
+}
+
+fn upload(args: &Args) -> Result<String, String> {
+    let image = fs::read(&args.firmware).map_err(|e| format!("{}: {}", args.firmware, e))?;
+    if image.first() != Some(&ESP_IMAGE_HEADER_MAGIC) {
+        return Err(format!(
+            "{} is not an ESP firmware image, convert it with `espflash save-image` first",
+            args.firmware
+        ));
+    }
+    let sha256 = to_hex(&Sha256::digest(&image));
+    println!(
+        "Firmware: {} ({} bytes, sha256 {})",
+        args.firmware,
+        image.len(),
+        sha256
+    );
+
+    let agent = ureq::AgentBuilder::new()
+        .timeout_connect(Duration::from_secs(10))
+        // 设备写完 flash 后还要校验，所以等待时间长一点
+        .timeout_read(Duration::from_secs(60))
+        .build();
+    let url = format!("http://{}/ota", args.device);
+    // 设备的 http 服务不支持 chunked 上传，必须带上 Content-Length
+    let result = agent
+        .post(&url)
+        .set("Authorization", &format!("Bearer {}", args.token))
+        .set("Content-Type", "application/octet-stream")
+        .set("Content-Length", &image.len().to_string())
+        .set("X-Firmware-Sha256", &sha256)
+        .send(Progress {
+            image: &image,
+            sent: 0,
+            started_at: Instant::now(),
+        });
+    println!();
+
+    match result {
+        Ok(response) => response
+            .into_string()
+            .map(|body| body.trim().to_string())
+            .map_err(|e| format!("Failed to read response: {}", e)),
+        Err(ureq::Error::Status(status, response)) => Err(format!(
+            "Device returned {}: {}",
+            status,
+            response.into_string().unwrap_or_default().trim()
+        )),
+        Err(e) => Err(format!("{}: {}", url, e)),
+    }
+}
+
+fn main() -> ExitCode {
+    let args = match parse_args() {
+        Ok(args) => args,
+        Err(e) => {
+            eprintln!("{}", e);
+            return ExitCode::from(2);
+        }
+    };
+
+    match upload(&args) {
+        Ok(body) => {
+            println!("Upload succeeded, device is restarting: {}", body);
+            ExitCode::SUCCESS
+        }
+        Err(e) => {
+            eprintln!("Upload failed: {}", e);
+            ExitCode::FAILURE
+        }
+    }
+}